@@ -0,0 +1,7 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Only the gRPC interface needs protoc
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/multimap.proto")?;
+
+    Ok(())
+}
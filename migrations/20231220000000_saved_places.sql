@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS saved_places (
+    id UUID PRIMARY KEY,
+    place_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    latitude DOUBLE PRECISION NOT NULL,
+    longitude DOUBLE PRECISION NOT NULL,
+    notes TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS saved_places_created_at_idx ON saved_places (created_at DESC);
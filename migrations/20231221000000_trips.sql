@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS trips (
+    id UUID PRIMARY KEY,
+    owner TEXT NOT NULL,
+    name TEXT NOT NULL,
+    origin_latitude DOUBLE PRECISION NOT NULL,
+    origin_longitude DOUBLE PRECISION NOT NULL,
+    destination_latitude DOUBLE PRECISION NOT NULL,
+    destination_longitude DOUBLE PRECISION NOT NULL,
+    waypoints JSONB NOT NULL DEFAULT '[]',
+    travel_mode TEXT NOT NULL,
+    encoded_polyline TEXT NOT NULL,
+    distance_meters DOUBLE PRECISION,
+    duration TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS trips_owner_idx ON trips (owner, created_at DESC);
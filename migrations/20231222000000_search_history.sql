@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS search_history (
+    id UUID PRIMARY KEY,
+    owner TEXT NOT NULL,
+    query TEXT NOT NULL,
+    chosen_place_id TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS search_history_owner_idx ON search_history (owner, created_at DESC);
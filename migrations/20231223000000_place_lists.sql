@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS place_lists (
+    id UUID PRIMARY KEY,
+    owner TEXT NOT NULL,
+    name TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (owner, name)
+);
+
+CREATE TABLE IF NOT EXISTS place_list_items (
+    list_id UUID NOT NULL REFERENCES place_lists (id) ON DELETE CASCADE,
+    saved_place_id UUID NOT NULL REFERENCES saved_places (id) ON DELETE CASCADE,
+    tags TEXT[] NOT NULL DEFAULT '{}',
+    added_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (list_id, saved_place_id)
+);
+
+CREATE INDEX IF NOT EXISTS place_list_items_tags_idx ON place_list_items USING GIN (tags);
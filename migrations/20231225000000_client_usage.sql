@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS client_usage (
+    identity TEXT NOT NULL,
+    period TEXT NOT NULL,
+    count BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (identity, period)
+);
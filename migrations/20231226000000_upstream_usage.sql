@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS upstream_usage (
+    day DATE NOT NULL,
+    sku TEXT NOT NULL,
+    calls BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (day, sku)
+);
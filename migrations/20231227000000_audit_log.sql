@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS audit_log (
+    id UUID PRIMARY KEY,
+    at TIMESTAMPTZ NOT NULL,
+    identity TEXT,
+    method TEXT NOT NULL,
+    endpoint TEXT NOT NULL,
+    params JSONB NOT NULL,
+    provider TEXT,
+    status INTEGER NOT NULL,
+    latency_ms BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_at_idx ON audit_log (at DESC);
+CREATE INDEX IF NOT EXISTS audit_log_identity_idx ON audit_log (identity, at DESC);
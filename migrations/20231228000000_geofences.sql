@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS geofences (
+    id UUID PRIMARY KEY,
+    owner TEXT NOT NULL,
+    name TEXT NOT NULL,
+    shape JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS geofences_owner_idx ON geofences (owner, created_at DESC);
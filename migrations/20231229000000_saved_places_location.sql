@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS saved_places_location_idx ON saved_places (latitude, longitude);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS shared_routes (
+    slug TEXT PRIMARY KEY,
+    owner TEXT,
+    title TEXT,
+    travel_mode TEXT NOT NULL,
+    encoded_polyline TEXT NOT NULL,
+    distance_meters DOUBLE PRECISION,
+    duration TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
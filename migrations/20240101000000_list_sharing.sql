@@ -0,0 +1,23 @@
+-- Bumped on every change to a list's items, clients send it back in If-Match
+ALTER TABLE place_lists ADD COLUMN IF NOT EXISTS version BIGINT NOT NULL DEFAULT 0;
+
+ALTER TABLE place_list_items ADD COLUMN IF NOT EXISTS added_by TEXT;
+
+CREATE TABLE IF NOT EXISTS place_list_members (
+    list_id UUID NOT NULL REFERENCES place_lists (id) ON DELETE CASCADE,
+    member TEXT NOT NULL,
+    role TEXT NOT NULL CHECK (role IN ('viewer', 'editor')),
+    joined_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (list_id, member)
+);
+
+CREATE INDEX IF NOT EXISTS place_list_members_member_idx ON place_list_members (member);
+
+-- Only a hash of the token is kept, the token itself is shown once to the list owner
+CREATE TABLE IF NOT EXISTS place_list_invites (
+    token_hash TEXT PRIMARY KEY,
+    list_id UUID NOT NULL REFERENCES place_lists (id) ON DELETE CASCADE,
+    role TEXT NOT NULL CHECK (role IN ('viewer', 'editor')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    expires_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,29 @@
+CREATE TABLE IF NOT EXISTS commutes (
+    id UUID PRIMARY KEY,
+    owner TEXT NOT NULL,
+    name TEXT NOT NULL,
+    origin_latitude DOUBLE PRECISION NOT NULL,
+    origin_longitude DOUBLE PRECISION NOT NULL,
+    destination_latitude DOUBLE PRECISION NOT NULL,
+    destination_longitude DOUBLE PRECISION NOT NULL,
+    travel_mode TEXT NOT NULL,
+    -- ISO weekdays, 1 is Monday
+    weekdays SMALLINT[] NOT NULL,
+    window_start TIME NOT NULL,
+    window_end TIME NOT NULL,
+    time_zone TEXT NOT NULL,
+    last_sampled_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS commutes_owner_idx ON commutes (owner, created_at DESC);
+
+CREATE TABLE IF NOT EXISTS commute_samples (
+    commute_id UUID NOT NULL REFERENCES commutes (id) ON DELETE CASCADE,
+    sampled_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    duration_seconds DOUBLE PRECISION NOT NULL,
+    -- The same route without traffic
+    static_duration_seconds DOUBLE PRECISION,
+    distance_meters DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (commute_id, sampled_at)
+);
@@ -0,0 +1,14 @@
+CREATE TABLE IF NOT EXISTS commute_alerts (
+    id UUID PRIMARY KEY,
+    commute_id UUID NOT NULL REFERENCES commutes (id) ON DELETE CASCADE,
+    -- 'webhook' posts to target, 'push' sends a notification to the device token in target
+    channel TEXT NOT NULL CHECK (channel IN ('webhook', 'push')),
+    target TEXT NOT NULL,
+    -- Signs webhook payloads
+    secret TEXT NOT NULL,
+    threshold_percent DOUBLE PRECISION NOT NULL,
+    last_notified_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS commute_alerts_commute_idx ON commute_alerts (commute_id);
@@ -0,0 +1,27 @@
+-- A trip repeats on ISO weekdays (1 is Monday) at a local time of its time zone
+ALTER TABLE trips ADD COLUMN IF NOT EXISTS recurrence_weekdays SMALLINT[];
+ALTER TABLE trips ADD COLUMN IF NOT EXISTS recurrence_time TIME;
+ALTER TABLE trips ADD COLUMN IF NOT EXISTS recurrence_time_zone TEXT;
+ALTER TABLE trips ADD COLUMN IF NOT EXISTS next_occurrence_at TIMESTAMPTZ;
+-- The departure the stored route was computed for, null when computed on demand
+ALTER TABLE trips ADD COLUMN IF NOT EXISTS route_departure_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS trips_next_occurrence_idx ON trips (next_occurrence_at)
+    WHERE next_occurrence_at IS NOT NULL;
+
+-- The first occurrence strictly after `after`, looking a week ahead covers every weekday
+CREATE OR REPLACE FUNCTION next_occurrence(
+    weekdays SMALLINT[],
+    at TIME,
+    time_zone TEXT,
+    after TIMESTAMPTZ
+) RETURNS TIMESTAMPTZ AS $$
+    SELECT min(occurrence)
+    FROM (
+        SELECT (((after AT TIME ZONE time_zone)::DATE + day) + at) AT TIME ZONE time_zone
+            AS occurrence
+        FROM generate_series(0, 7) AS day
+    ) candidates
+    WHERE occurrence > after
+      AND EXTRACT(ISODOW FROM occurrence AT TIME ZONE time_zone)::SMALLINT = ANY (weekdays)
+$$ LANGUAGE sql STABLE;
@@ -0,0 +1,59 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use reqwest::Client;
+use serde_json::{json, Value};
+
+/// Posts alerts to a webhook as Slack-compatible JSON (`text` plus the alert's details).
+/// Each alert fires at most once per cooldown so a sustained problem doesn't flood the channel.
+pub struct Webhook {
+    client: Client,
+    url: String,
+    cooldown: Duration,
+    last_fired: Mutex<HashMap<String, Instant>>,
+}
+
+impl Webhook {
+    pub fn new(client: Client, url: String, cooldown: Duration) -> Self {
+        Webhook {
+            client,
+            url,
+            cooldown,
+            last_fired: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sends in the background, so callers on the request path never wait on the webhook.
+    pub fn fire(&self, alert: &str, text: String, details: Value) {
+        {
+            let mut last_fired = self.last_fired.lock().unwrap();
+            let now = Instant::now();
+            if let Some(at) = last_fired.get(alert) {
+                if now.duration_since(*at) < self.cooldown {
+                    tracing::debug!(alert, "alert suppressed during cooldown");
+                    return;
+                }
+            }
+            last_fired.insert(alert.to_owned(), now);
+        }
+
+        tracing::warn!(alert, "{}", text);
+        let request = self.client.post(&self.url).json(&json!({
+            "text": text,
+            "alert": alert,
+            "details": details,
+        }));
+        tokio::spawn(async move {
+            match request.send().await {
+                Ok(response) if !response.status().is_success() => {
+                    tracing::error!(status = %response.status(), "alert webhook rejected alert");
+                }
+                Ok(_) => {}
+                Err(e) => tracing::error!(error = %e, "failed to send alert"),
+            }
+        });
+    }
+}
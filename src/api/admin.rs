@@ -0,0 +1,296 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    audit::AuditLog,
+    cache::{Cache, CacheStats},
+    db::api_keys::{self, ApiKey},
+    db::audit::AuditEntry,
+    db::saved_places,
+    error::AppError,
+    flags::{Flag, Rollout, RuleView},
+    identity::Identity,
+    middleware::{ClientQuota, Quotas},
+    upstream::EndpointStatus,
+    usage::UsageReport,
+    AppState,
+};
+
+use super::database;
+
+const API_KEY_LENGTH: usize = 40;
+
+#[derive(Debug, Deserialize)]
+pub struct InvalidateRequest {
+    pattern: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InvalidateResponse {
+    removed: u64,
+}
+
+fn enabled_cache(s: &AppState) -> Result<&dyn Cache, AppError> {
+    s.cache
+        .as_deref()
+        .ok_or_else(|| AppError::NotFound("Cache is disabled".into()))
+}
+
+pub async fn feature_flags(State(s): State<AppState>) -> Json<Vec<RuleView>> {
+    Json(s.flags.rules())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetFlagRequest {
+    rollout: String,
+    identity: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FlagQuery {
+    identity: Option<String>,
+}
+
+fn parse_flag(name: &str) -> Result<Flag, AppError> {
+    name.parse()
+        .map_err(|()| AppError::NotFound(format!("Unknown feature flag {}", name)))
+}
+
+/// Overrides the rule of a flag, for everyone or for one caller.
+pub async fn set_feature_flag(
+    State(s): State<AppState>,
+    Path(name): Path<String>,
+    Json(body): Json<SetFlagRequest>,
+) -> Result<Json<Vec<RuleView>>, AppError> {
+    let flag = parse_flag(&name)?;
+    let rollout: Rollout = body.rollout.parse().map_err(|()| {
+        AppError::Validation("rollout must be on, off or a percentage like 25%".into())
+    })?;
+
+    s.flags
+        .set_override((flag, body.identity.clone()), Some(rollout))
+        .await?;
+    tracing::info!(flag = %name, identity = ?body.identity, %rollout, "set feature flag");
+
+    Ok(Json(s.flags.rules()))
+}
+
+/// Removes an override, the rule from `FEATURE_FLAGS` applies again.
+pub async fn remove_feature_flag(
+    State(s): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<FlagQuery>,
+) -> Result<Json<Vec<RuleView>>, AppError> {
+    let flag = parse_flag(&name)?;
+
+    s.flags
+        .set_override((flag, query.identity.clone()), None)
+        .await?;
+    tracing::info!(flag = %name, identity = ?query.identity, "removed feature flag override");
+
+    Ok(Json(s.flags.rules()))
+}
+
+/// Reloads the configuration and keys, as SIGHUP does.
+pub async fn reload(State(s): State<AppState>) -> Result<StatusCode, AppError> {
+    s.reloader.reload().await.map_err(AppError::Validation)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn enabled_quotas(s: &AppState) -> Result<&Quotas, AppError> {
+    s.quotas
+        .as_deref()
+        .ok_or_else(|| AppError::NotFound("Quotas are disabled".into()))
+}
+
+pub async fn quota_overview(State(s): State<AppState>) -> Result<Json<Vec<ClientQuota>>, AppError> {
+    let quotas = enabled_quotas(&s)?;
+
+    Ok(Json(quotas.overview().await?))
+}
+
+pub async fn provider_status(State(s): State<AppState>) -> Json<Vec<EndpointStatus>> {
+    Json(s.upstream_metrics.status())
+}
+
+const DEFAULT_USAGE_DAYS: u64 = 30;
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UsageQuery {
+    #[validate(range(min = 1, max = 366))]
+    days: Option<u64>,
+}
+
+pub async fn usage_report(
+    State(s): State<AppState>,
+    Query(query): Query<UsageQuery>,
+) -> Result<Json<UsageReport>, AppError> {
+    query.validate()?;
+
+    Ok(Json(
+        s.usage
+            .report(query.days.unwrap_or(DEFAULT_USAGE_DAYS))
+            .await?,
+    ))
+}
+
+const DEFAULT_AUDIT_LIMIT: usize = 100;
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct AuditQuery {
+    #[validate(range(min = 1, max = 1000))]
+    limit: Option<usize>,
+    identity: Option<String>,
+}
+
+fn enabled_audit(s: &AppState) -> Result<&AuditLog, AppError> {
+    s.audit
+        .as_deref()
+        .ok_or_else(|| AppError::NotFound("Audit log is disabled".into()))
+}
+
+/// Most recent audit entries first.
+pub async fn audit_log(
+    State(s): State<AppState>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEntry>>, AppError> {
+    query.validate()?;
+    let audit = enabled_audit(&s)?;
+
+    Ok(Json(
+        audit
+            .recent(
+                query.identity.as_deref(),
+                query.limit.unwrap_or(DEFAULT_AUDIT_LIMIT),
+            )
+            .await?,
+    ))
+}
+
+pub async fn cache_stats(State(s): State<AppState>) -> Result<Json<CacheStats>, AppError> {
+    let cache = enabled_cache(&s)?;
+
+    Ok(Json(cache.stats().await))
+}
+
+pub async fn invalidate_cache(
+    State(s): State<AppState>,
+    Json(body): Json<InvalidateRequest>,
+) -> Result<Json<InvalidateResponse>, AppError> {
+    if body.pattern.trim().is_empty() {
+        return Err(AppError::Validation("pattern must not be empty".into()));
+    }
+    let cache = enabled_cache(&s)?;
+
+    let removed = cache.invalidate(&body.pattern).await;
+    tracing::info!(
+        "invalidated {} cache entries matching {}",
+        removed,
+        body.pattern
+    );
+
+    Ok(Json(InvalidateResponse { removed }))
+}
+
+pub async fn flush_cache(State(s): State<AppState>) -> Result<Json<InvalidateResponse>, AppError> {
+    let cache = enabled_cache(&s)?;
+
+    let removed = cache.flush().await;
+    tracing::info!("flushed {} cache entries", removed);
+
+    Ok(Json(InvalidateResponse { removed }))
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateApiKeyRequest {
+    #[validate(length(min = 1, max = 256))]
+    name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedApiKey {
+    #[serde(flatten)]
+    record: ApiKey,
+    /// Only returned once, the database keeps a hash
+    key: String,
+}
+
+pub async fn create_api_key(
+    State(s): State<AppState>,
+    Json(body): Json<CreateApiKeyRequest>,
+) -> Result<(StatusCode, Json<CreatedApiKey>), AppError> {
+    body.validate()?;
+    let pool = database(&s)?;
+
+    let key: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(API_KEY_LENGTH)
+        .map(char::from)
+        .collect();
+    let record = api_keys::insert(pool, &body.name, &Identity::from_api_key(&key).0).await?;
+    tracing::info!("created API key {} ({})", record.id, record.name);
+
+    Ok((StatusCode::CREATED, Json(CreatedApiKey { record, key })))
+}
+
+pub async fn list_api_keys(State(s): State<AppState>) -> Result<Json<Vec<ApiKey>>, AppError> {
+    let pool = database(&s)?;
+
+    Ok(Json(api_keys::list(pool).await?))
+}
+
+pub async fn revoke_api_key(
+    State(s): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let pool = database(&s)?;
+
+    if api_keys::revoke(pool, id).await? {
+        tracing::info!("revoked API key {}", id);
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound("API key not found".into()))
+    }
+}
+
+/// Who the places saved before accounts existed go to, an API key or a registered user.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClaimPlacesRequest {
+    api_key: Option<String>,
+    user_id: Option<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClaimedPlaces {
+    claimed: u64,
+}
+
+pub async fn claim_saved_places(
+    State(s): State<AppState>,
+    Json(body): Json<ClaimPlacesRequest>,
+) -> Result<Json<ClaimedPlaces>, AppError> {
+    let owner = match (body.api_key, body.user_id) {
+        (Some(key), None) if !key.is_empty() => Identity::from_api_key(&key),
+        (None, Some(user_id)) => Identity::from_user_id(&user_id.to_string()),
+        _ => {
+            return Err(AppError::Validation(
+                "One of apiKey and userId is required".into(),
+            ));
+        }
+    };
+    let pool = database(&s)?;
+
+    let claimed = saved_places::claim_unowned(pool, &owner.0).await?;
+    tracing::info!("gave {} unowned saved places to {}", claimed, owner.0);
+
+    Ok(Json(ClaimedPlaces { claimed }))
+}
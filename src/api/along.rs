@@ -0,0 +1,209 @@
+use axum::{extract::State, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use validator::Validate;
+
+use crate::{
+    cache::{self, CacheStatus},
+    db::trips::Coordinate,
+    error::AppError,
+    geo, upstream, usage, AppState,
+};
+
+use super::{
+    cached, duration_seconds, google_provider, store, validation, GooglePlace, ResponseMeta,
+    TravelMode, CONTENT_TYPE, DEFAULT_MAX_RESULTS, GOOGLE_API_KEY_HEADER, GOOGLE_FIELD_MASK_HEADER,
+    JSON_TYPE,
+};
+
+// The place fields of a text search, with the legs to and from each place
+const ALONG_FIELD_MASK: &str = "places.id,places.displayName,places.formattedAddress,\
+    places.location,places.rating,places.priceLevel,places.types,routingSummaries";
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct PlacesAlongRequest {
+    /// The route, as in `polyline.encodedPolyline` of a computed route
+    #[validate(length(min = 1, max = 100000))]
+    encoded_polyline: String,
+    /// What to stop at, e.g. "gas station"
+    #[validate(length(max = 512), custom = "validation::not_blank")]
+    text_query: String,
+    /// Driving when left out. Transit can't be searched along
+    travel_mode: Option<TravelMode>,
+    #[validate(range(min = 1, max = 20))]
+    max_results: Option<u8>,
+    /// Leaves out stops further off the route than this
+    #[validate(range(min = 1.0))]
+    max_detour_meters: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Leg {
+    duration: Option<String>,
+    distance_meters: Option<f64>,
+}
+
+// Google lists the start of the route to the place, then the place to the end
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct RoutingSummary {
+    #[serde(default)]
+    legs: Vec<Leg>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PlacesAlong {
+    #[serde(default)]
+    places: Vec<GooglePlace>,
+    /// In the order of `places`
+    #[serde(default)]
+    routing_summaries: Vec<RoutingSummary>,
+}
+
+/// A place along the route. The detour is estimated locally, the legs come from Google.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stop {
+    place: GooglePlace,
+    /// How far into the route the place is reached
+    distance_along_route_meters: f64,
+    /// From the closest point of the route, as the crow flies
+    off_route_meters: f64,
+    /// Leaving the route and coming back to it, twice `offRouteMeters`
+    detour_meters: f64,
+    /// From the start of the route to the place
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from_start_seconds: Option<f64>,
+    /// From the place to the end of the route
+    #[serde(skip_serializing_if = "Option::is_none")]
+    to_end_seconds: Option<f64>,
+    /// Both legs together, to compare with the route's own distance
+    #[serde(skip_serializing_if = "Option::is_none")]
+    via_place_meters: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlacesAlongResponse {
+    /// In the order they're passed
+    stops: Vec<Stop>,
+    meta: ResponseMeta,
+}
+
+fn along_body(body: &PlacesAlongRequest, start: Coordinate, travel_mode: TravelMode) -> Value {
+    json!({
+        "textQuery": body.text_query,
+        "maxResultCount": body.max_results.unwrap_or(DEFAULT_MAX_RESULTS),
+        "searchAlongRouteParameters": {
+            "polyline": { "encodedPolyline": body.encoded_polyline },
+        },
+        "routingParameters": {
+            "origin": { "latitude": start.latitude, "longitude": start.longitude },
+            "travelMode": travel_mode.as_str(),
+        },
+    })
+}
+
+/// Searches places along a route, e.g. gas stations on the way, with how far each one is
+/// into the route and off it.
+pub async fn places_along_route(
+    State(s): State<AppState>,
+    Json(body): Json<PlacesAlongRequest>,
+) -> Result<Json<PlacesAlongResponse>, AppError> {
+    body.validate()?;
+    let travel_mode = body.travel_mode.unwrap_or(TravelMode::Drive);
+    if travel_mode == TravelMode::Transit {
+        return Err(AppError::Validation(
+            "places can't be searched along a transit route".into(),
+        ));
+    }
+    let path = geo::polyline::decode(&body.encoded_polyline)
+        .filter(|path| path.len() >= 2)
+        .ok_or_else(|| AppError::Validation("encodedPolyline is not a valid route".into()))?;
+
+    let req = along_body(&body, path[0], travel_mode);
+    let (found, cache_status) = fetch_along(&s, &req).await?;
+
+    let mut summaries = found.routing_summaries.into_iter();
+    let mut stops: Vec<Stop> = found
+        .places
+        .into_iter()
+        .filter_map(|place| {
+            let summary = summaries.next().unwrap_or_default();
+            let projection = geo::project(&path, place.coordinate())?;
+            let leg = |i: usize| summary.legs.get(i);
+            let seconds = |leg: Option<&Leg>| leg?.duration.as_deref().and_then(duration_seconds);
+            let via_place_meters = match (leg(0), leg(1)) {
+                (Some(to), Some(from)) => Some(to.distance_meters? + from.distance_meters?),
+                _ => None,
+            };
+
+            Some(Stop {
+                distance_along_route_meters: projection.along_path_meters.round(),
+                off_route_meters: projection.off_path_meters.round(),
+                detour_meters: (2.0 * projection.off_path_meters).round(),
+                from_start_seconds: seconds(leg(0)),
+                to_end_seconds: seconds(leg(1)),
+                via_place_meters,
+                place: place.with_plus_code(),
+            })
+        })
+        .filter(|stop| {
+            body.max_detour_meters
+                .is_none_or(|max| stop.detour_meters <= max)
+        })
+        .collect();
+    stops.sort_by(|a, b| {
+        a.distance_along_route_meters
+            .total_cmp(&b.distance_along_route_meters)
+    });
+
+    Ok(Json(PlacesAlongResponse {
+        stops,
+        meta: ResponseMeta::new(cache_status),
+    }))
+}
+
+async fn fetch_along(s: &AppState, req: &Value) -> Result<(PlacesAlong, CacheStatus), AppError> {
+    let cache_key = cache::places_key(req, google_provider());
+    if let Some(found) = cached::<PlacesAlong>(s, &cache_key).await {
+        return Ok((found, CacheStatus::Hit));
+    }
+
+    let (status, body) = upstream::send_with_keys(
+        s.keys(),
+        &s.places_breaker,
+        &s.retry_policy,
+        &s.places_metrics,
+        |key| {
+            s.client_reqwest
+                .post(&s.urls.text_search)
+                .json(req)
+                .header(GOOGLE_FIELD_MASK_HEADER, ALONG_FIELD_MASK)
+                .header(CONTENT_TYPE, JSON_TYPE)
+                .header(GOOGLE_API_KEY_HEADER, key)
+        },
+    )
+    .await
+    .inspect_err(
+        |e| tracing::error!(error = %e, provider = "google-places", "upstream request failed"),
+    )?;
+
+    if !status.is_success() {
+        return Err(upstream::google_error::translate(
+            "google-places",
+            status,
+            &body,
+        ));
+    }
+    s.usage.record(usage::PLACES_TEXT_SEARCH);
+
+    let found = serde_json::from_slice::<PlacesAlong>(&body).map_err(|e| {
+        tracing::error!(error = %e, provider = "google-places", "failed to parse upstream response");
+        AppError::ParseError(e.to_string())
+    })?;
+    let cache_status = store(s, &cache_key, &found).await;
+
+    Ok((found, cache_status))
+}
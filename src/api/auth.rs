@@ -0,0 +1,94 @@
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Redirect, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::AppError, oauth::OAuth, AppState};
+
+#[derive(Debug, Deserialize)]
+pub struct LoginParams {
+    provider: String,
+    /// App URL to hand the session to, must be listed in `OAUTH_APP_REDIRECT_URLS`
+    redirect: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CallbackParams {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionResponse {
+    token: String,
+    token_type: &'static str,
+    expires_in: u64,
+}
+
+fn oauth(s: &AppState) -> Result<&OAuth, AppError> {
+    s.oauth
+        .as_deref()
+        .ok_or_else(|| AppError::NotFound("Login is disabled".into()))
+}
+
+pub async fn login(
+    State(s): State<AppState>,
+    Query(params): Query<LoginParams>,
+) -> Result<Response, AppError> {
+    let start = oauth(&s)?
+        .authorize_url(&params.provider, params.redirect)
+        .await?;
+
+    Ok((
+        [(header::SET_COOKIE, start.cookie)],
+        Redirect::to(&start.url),
+    )
+        .into_response())
+}
+
+pub async fn callback(
+    State(s): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<CallbackParams>,
+) -> Result<Response, AppError> {
+    if let Some(error) = params.error {
+        tracing::info!("login was not completed: {}", error);
+        return Err(AppError::Unauthorized);
+    }
+    let (Some(code), Some(state)) = (params.code, params.state) else {
+        return Err(AppError::Validation("Invalid request".into()));
+    };
+
+    let oauth = oauth(&s)?;
+    let cookie = headers
+        .get(header::COOKIE)
+        .and_then(|value| value.to_str().ok());
+    let session = oauth
+        .complete(&s.client_reqwest, cookie, &state, &code)
+        .await?;
+    let clear_cookie = [(header::SET_COOKIE, oauth.clear_state_cookie())];
+
+    // Apps receive the token in the fragment so it never reaches their server logs
+    if let Some(redirect) = session.redirect {
+        let url = format!(
+            "{}#token={}&tokenType=Bearer&expiresIn={}",
+            redirect, session.token, session.expires_in
+        );
+        return Ok((clear_cookie, Redirect::to(&url)).into_response());
+    }
+
+    Ok((
+        clear_cookie,
+        Json(SessionResponse {
+            token: session.token,
+            token_type: "Bearer",
+            expires_in: session.expires_in,
+        }),
+    )
+        .into_response())
+}
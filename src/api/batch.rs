@@ -0,0 +1,227 @@
+use std::{collections::BTreeMap, future::Future, sync::Arc};
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{
+    sync::{mpsc, Semaphore},
+    task::JoinSet,
+};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use validator::Validate;
+
+use crate::{error::AppError, job_store::ItemResult, middleware::in_current_environment, AppState};
+
+use super::{compute_routes, search_places, validation, waypoint, Location};
+
+/// Limits shared by every batch interface.
+#[derive(Clone, Copy, Debug)]
+pub struct BatchSettings {
+    pub max_items: usize,
+    pub concurrency: usize,
+}
+
+impl BatchSettings {
+    pub fn check_size(&self, items: usize) -> Result<(), AppError> {
+        if items > self.max_items {
+            return Err(AppError::Validation(format!(
+                "At most {} items per batch",
+                self.max_items
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct PlacesBatchRequest {
+    #[validate(length(min = 1, message = "must not be empty"))]
+    pub queries: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct RoutePair {
+    #[validate]
+    origin: Location,
+    #[validate]
+    destination: Location,
+    #[validate(custom = "validation::rfc3339")]
+    departure_time: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct RoutesBatchRequest {
+    // Pairs are validated one by one, an invalid pair only fails its own item
+    #[validate(length(min = 1, message = "must not be empty"))]
+    pub pairs: Vec<RoutePair>,
+}
+
+pub async fn search_item(s: AppState, query: String) -> Result<Value, AppError> {
+    let result = search_places(&s, query).await?;
+
+    serde_json::to_value(result).map_err(|e| AppError::ParseError(e.to_string()))
+}
+
+pub async fn route_item(s: AppState, pair: RoutePair) -> Result<Value, AppError> {
+    pair.validate()?;
+    let result = compute_routes(
+        &s,
+        waypoint(pair.origin.latitude, pair.origin.longitude),
+        waypoint(pair.destination.latitude, pair.destination.longitude),
+        pair.departure_time,
+    )
+    .await?;
+
+    serde_json::to_value(result).map_err(|e| AppError::ParseError(e.to_string()))
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlacesBatchResponse {
+    /// By query, repeated queries are only searched once
+    results: BTreeMap<String, ItemResult>,
+}
+
+/// Searches every query of the batch and answers once all are done. A failed query
+/// carries its own status and error, it doesn't fail the batch.
+pub async fn search_batch(
+    State(s): State<AppState>,
+    Json(body): Json<PlacesBatchRequest>,
+) -> Result<Json<PlacesBatchResponse>, AppError> {
+    body.validate()?;
+    s.batch.check_size(body.queries.len())?;
+
+    let mut queries = body.queries;
+    queries.sort();
+    queries.dedup();
+
+    let mut results = BTreeMap::new();
+    let keys = queries.clone();
+    run_batch(
+        queries,
+        s.batch.concurrency,
+        |query| search_item(s.clone(), query),
+        |index, item| {
+            results.insert(keys[index].clone(), item);
+        },
+    )
+    .await;
+
+    Ok(Json(PlacesBatchResponse { results }))
+}
+
+const NDJSON_TYPE: &str = "application/x-ndjson";
+
+#[derive(Debug, Serialize)]
+pub struct RoutesBatchResponse {
+    /// In the order of the pairs, `null` only for an item that crashed
+    results: Vec<Option<ItemResult>>,
+}
+
+/// One line of a streamed batch.
+#[derive(Debug, Serialize)]
+struct StreamedItem {
+    /// Position of the pair in the request
+    index: usize,
+    #[serde(flatten)]
+    item: ItemResult,
+}
+
+/// Computes the route of every pair and answers once all are done, e.g. ETAs to a list of
+/// saved places. A failed pair carries its own status and error, it doesn't fail the batch.
+/// With `Accept: application/x-ndjson` each pair is written as a line as soon as it's done
+/// instead, in completion order.
+pub async fn route_batch(
+    State(s): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<RoutesBatchRequest>,
+) -> Result<Response, AppError> {
+    body.validate()?;
+    s.batch.check_size(body.pairs.len())?;
+
+    let ndjson = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains(NDJSON_TYPE));
+    if ndjson {
+        return Ok(stream_route_batch(s, body.pairs));
+    }
+
+    let mut results = vec![None; body.pairs.len()];
+    run_batch(
+        body.pairs,
+        s.batch.concurrency,
+        |pair| route_item(s.clone(), pair),
+        |index, item| results[index] = Some(item),
+    )
+    .await;
+
+    Ok(Json(RoutesBatchResponse { results }).into_response())
+}
+
+fn stream_route_batch(s: AppState, pairs: Vec<RoutePair>) -> Response {
+    let (lines, receiver) = mpsc::unbounded_channel::<Result<String, serde_json::Error>>();
+    tokio::spawn(in_current_environment(async move {
+        let run = run_batch(
+            pairs,
+            s.batch.concurrency,
+            |pair| route_item(s.clone(), pair),
+            |index, item| {
+                let line =
+                    serde_json::to_string(&StreamedItem { index, item }).map(|line| line + "\n");
+                // The client is gone when this fails, the run is dropped right after
+                let _ = lines.send(line);
+            },
+        );
+        // Stops the remaining pairs once the client hangs up
+        tokio::select! {
+            _ = run => {}
+            _ = lines.closed() => {}
+        }
+    }));
+
+    (
+        [(header::CONTENT_TYPE, NDJSON_TYPE)],
+        Body::from_stream(UnboundedReceiverStream::new(receiver)),
+    )
+        .into_response()
+}
+
+/// Runs every item, at most `concurrency` at a time, and reports each outcome as soon as
+/// it completes. One item failing doesn't affect the others.
+pub async fn run_batch<T, F, Fut>(
+    items: Vec<T>,
+    concurrency: usize,
+    run: F,
+    mut report: impl FnMut(usize, ItemResult),
+) where
+    T: Send + 'static,
+    F: Fn(T) -> Fut,
+    Fut: Future<Output = Result<Value, AppError>> + Send + 'static,
+{
+    let permits = Arc::new(Semaphore::new(concurrency));
+    let mut tasks = JoinSet::new();
+    for (index, item) in items.into_iter().enumerate() {
+        let permits = permits.clone();
+        let work = run(item);
+        tasks.spawn(in_current_environment(async move {
+            // The semaphore is never closed
+            let _permit = permits.acquire().await.unwrap();
+            (index, work.await)
+        }));
+    }
+
+    while let Some(joined) = tasks.join_next().await {
+        match joined {
+            Ok((index, outcome)) => report(index, ItemResult::new(outcome)),
+            Err(e) => tracing::error!(error = %e, "batch item panicked"),
+        }
+    }
+}
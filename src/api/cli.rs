@@ -0,0 +1,127 @@
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{error::AppError, geo, AppState};
+
+use super::{compute_routes, search_places, waypoint};
+
+#[derive(Debug, Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    // Serving is the default, so `--seed` works without naming the subcommand
+    #[command(flatten)]
+    serve: ServeArgs,
+}
+
+impl Cli {
+    pub fn command(self) -> Command {
+        self.command.unwrap_or(Command::Serve(self.serve))
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the server, the default
+    Serve(ServeArgs),
+    /// Search places by text through the configured provider and print them as JSON
+    Geocode {
+        #[arg(required = true)]
+        query: Vec<String>,
+    },
+    /// Compute driving routes through the configured provider and print them as JSON. Each end
+    /// is `latitude,longitude`, a plus code or a search, whose first place is used
+    Route { from: String, to: String },
+    /// Print the points of an encoded polyline as JSON, without calling anything
+    DecodePolyline { polyline: String },
+}
+
+impl Command {
+    /// Whether this runs the server, rather than a command answering once and exiting.
+    pub fn serves(&self) -> bool {
+        matches!(self, Command::Serve(_))
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ServeArgs {
+    /// Add sample users, saved places and trips before serving, needs DATABASE_URL
+    #[arg(long)]
+    pub seed: bool,
+}
+
+fn print<T: Serialize>(value: &T) -> i32 {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => {
+            println!("{}", json);
+            0
+        }
+        Err(e) => {
+            eprintln!("failed to serialize the result: {}", e);
+            1
+        }
+    }
+}
+
+/// Runs a command that doesn't need the provider. `None` for the ones that do.
+pub fn run_offline(command: &Command) -> Option<i32> {
+    match command {
+        Command::DecodePolyline { polyline } => Some(match geo::polyline::decode(polyline) {
+            Some(points) => print(&points),
+            None => {
+                eprintln!("not a valid encoded polyline");
+                1
+            }
+        }),
+        _ => None,
+    }
+}
+
+// Coordinates or a plus code as given, anything else is searched and the first place taken
+async fn resolve(s: &AppState, end: &str) -> Result<Value, AppError> {
+    if let Some(point) = geo::plus_code::decode(end) {
+        return Ok(waypoint(point.latitude, point.longitude));
+    }
+    let coordinates = end.split_once(',').and_then(|(latitude, longitude)| {
+        let latitude: f64 = latitude.trim().parse().ok()?;
+        let longitude: f64 = longitude.trim().parse().ok()?;
+        ((-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude))
+            .then(|| waypoint(latitude, longitude))
+    });
+    if let Some(coordinates) = coordinates {
+        return Ok(coordinates);
+    }
+
+    let places = search_places(s, end.to_owned()).await?;
+    places
+        .places
+        .and_then(|places| places.into_iter().next())
+        .map(|place| json!({ "placeId": place.id }))
+        .ok_or_else(|| AppError::NotFound(format!("No place found for \"{}\"", end)))
+}
+
+/// Runs a one-off command against the provider layer, through the cache and key pool the
+/// server would use. Returns the exit code.
+pub async fn run(command: Command, s: &AppState) -> i32 {
+    let result = match command {
+        Command::Geocode { query } => search_places(s, query.join(" "))
+            .await
+            .map(|places| print(&places)),
+        Command::Route { from, to } => {
+            let routes = async {
+                let (origin, destination) = (resolve(s, &from).await?, resolve(s, &to).await?);
+                compute_routes(s, origin, destination, None).await
+            };
+            routes.await.map(|routes| print(&routes))
+        }
+        // Served or run before the state is built
+        Command::Serve(_) | Command::DecodePolyline { .. } => Ok(0),
+    };
+
+    result.unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        1
+    })
+}
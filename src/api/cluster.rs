@@ -0,0 +1,73 @@
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::{db::trips::Coordinate, error::AppError, geo};
+
+const DEFAULT_CELL_PIXELS: f64 = 60.0;
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct ClusterPoint {
+    id: String,
+    #[serde(flatten)]
+    #[validate]
+    location: Coordinate,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct ClusterRequest {
+    #[validate(range(max = 22, message = "must be within [0, 22]"))]
+    zoom: u8,
+    /// Width of a cluster cell on screen
+    #[validate(range(min = 1.0, max = 512.0, message = "must be within [1, 512]"))]
+    cell_pixels: Option<f64>,
+    // Points are checked in the handler, the derive can't combine this with nested validation
+    #[validate(length(min = 1, max = 10000, message = "must hold 1 to 10000 points"))]
+    points: Vec<ClusterPoint>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClusterGroup {
+    centroid: Coordinate,
+    count: usize,
+    member_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClusterResponse {
+    clusters: Vec<ClusterGroup>,
+}
+
+/// Clusters the given points for display at a zoom level, e.g. a user's saved places.
+pub async fn cluster_places(
+    Json(body): Json<ClusterRequest>,
+) -> Result<Json<ClusterResponse>, AppError> {
+    body.validate()?;
+    for point in &body.points {
+        point.validate()?;
+    }
+
+    let locations: Vec<Coordinate> = body.points.iter().map(|p| p.location).collect();
+    let cells = geo::cluster::grid(
+        &locations,
+        body.zoom,
+        body.cell_pixels.unwrap_or(DEFAULT_CELL_PIXELS),
+    );
+
+    let clusters = cells
+        .into_iter()
+        .map(|c| ClusterGroup {
+            centroid: c.centroid,
+            count: c.members.len(),
+            member_ids: c
+                .members
+                .into_iter()
+                .map(|i| body.points[i].id.clone())
+                .collect(),
+        })
+        .collect();
+
+    Ok(Json(ClusterResponse { clusters }))
+}
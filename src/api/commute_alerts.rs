@@ -0,0 +1,321 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng};
+use reqwest::redirect::Policy;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::PgPool;
+use url::{Host, Url};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    db::commutes::{self, Commute, CommuteAlert, Sample},
+    error::AppError,
+    identity::Identity,
+    AppState,
+};
+
+use super::{database, CONTENT_TYPE, JSON_TYPE};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const SECRET_LENGTH: usize = 32;
+const DEFAULT_THRESHOLD_PERCENT: f64 = 20.0;
+// The usual travel time is the median of this many days of samples
+const BASELINE_DAYS: i64 = 14;
+// Fewer samples don't say what usual is
+const MIN_BASELINE_SAMPLES: i64 = 6;
+// One notification per alert and hour, however long the traffic lasts
+const NOTIFY_COOLDOWN: Duration = Duration::from_secs(3600);
+const SIGNATURE_HEADER: &str = "X-Signature";
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+const ETA_CHANGED_EVENT: &str = "commute.eta_changed";
+
+/// Either a webhook or a push token.
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateAlertRequest {
+    /// HTTPS URL on a public address receiving a signed POST, redirects aren't followed
+    #[validate(url, length(max = 2048))]
+    webhook_url: Option<String>,
+    /// Device registration token for push notifications
+    #[validate(length(min = 1, max = 4096))]
+    push_token: Option<String>,
+    /// Notify when the travel time is this much longer or shorter than usual, 20 when
+    /// left out
+    #[validate(range(min = 1.0, max = 500.0))]
+    threshold_percent: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreatedAlert {
+    #[serde(flatten)]
+    alert: CommuteAlert,
+    /// Shown once, key of the hex HMAC-SHA256 of each webhook body sent in `X-Signature`
+    secret: String,
+}
+
+/// What a webhook receives, and the data of a push notification.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EtaChanged<'a> {
+    event: &'static str,
+    commute_id: Uuid,
+    name: &'a str,
+    sampled_at: DateTime<Utc>,
+    eta_seconds: f64,
+    baseline_seconds: f64,
+    /// Positive when slower than usual
+    deviation_percent: f64,
+    /// When to leave to arrive as a departure at the end of the window usually does
+    suggested_departure_time: DateTime<Utc>,
+}
+
+// Webhooks are called from inside our network, where anything but a public address could
+// be one of our own services
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let shared = ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64;
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || shared)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public(IpAddr::V4(mapped)),
+            None => {
+                let unique_local = ip.segments()[0] & 0xfe00 == 0xfc00;
+                let link_local = ip.segments()[0] & 0xffc0 == 0xfe80;
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    || unique_local
+                    || link_local)
+            }
+        },
+    }
+}
+
+/// Where a webhook is delivered to. Every address its host resolves to must be public,
+/// checked when the alert is created and again before each delivery.
+async fn webhook_address(url: &Url) -> Result<SocketAddr, AppError> {
+    let port = url.port_or_known_default().unwrap_or(443);
+    let addresses: Vec<SocketAddr> = match url.host() {
+        Some(Host::Domain(domain)) => tokio::net::lookup_host((domain, port))
+            .await
+            .map_err(|_| {
+                AppError::Validation(format!("webhookUrl host {} doesn't resolve", domain))
+            })?
+            .collect(),
+        Some(Host::Ipv4(ip)) => vec![SocketAddr::new(IpAddr::V4(ip), port)],
+        Some(Host::Ipv6(ip)) => vec![SocketAddr::new(IpAddr::V6(ip), port)],
+        None => return Err(AppError::Validation("webhookUrl needs a host".into())),
+    };
+
+    match addresses.first() {
+        Some(address) if addresses.iter().all(|a| is_public(a.ip())) => Ok(*address),
+        _ => Err(AppError::Validation(
+            "webhookUrl must resolve to public addresses only".into(),
+        )),
+    }
+}
+
+async fn owned_commute(s: &AppState, id: Uuid, identity: &Identity) -> Result<Commute, AppError> {
+    commutes::get(database(s)?, id, &identity.0)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Commute not found".into()))
+}
+
+pub async fn create_alert(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+    Json(body): Json<CreateAlertRequest>,
+) -> Result<(StatusCode, Json<CreatedAlert>), AppError> {
+    body.validate()?;
+    let (channel, target) = match (body.webhook_url, body.push_token) {
+        (Some(url), None) => match Url::parse(&url) {
+            Ok(parsed) if parsed.scheme() == "https" => {
+                webhook_address(&parsed).await?;
+                ("webhook", url)
+            }
+            _ => {
+                return Err(AppError::Validation(
+                    "webhookUrl must be an HTTPS URL".into(),
+                ))
+            }
+        },
+        (None, Some(_)) if s.fcm.is_none() => {
+            return Err(AppError::Validation(
+                "Push notifications are not configured".into(),
+            ))
+        }
+        (None, Some(token)) => ("push", token),
+        _ => {
+            return Err(AppError::Validation(
+                "Exactly one of webhookUrl and pushToken is required".into(),
+            ))
+        }
+    };
+    owned_commute(&s, id, &identity).await?;
+
+    let secret: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(SECRET_LENGTH)
+        .map(char::from)
+        .collect();
+    let alert = commutes::insert_alert(
+        database(&s)?,
+        id,
+        channel,
+        &target,
+        &secret,
+        body.threshold_percent.unwrap_or(DEFAULT_THRESHOLD_PERCENT),
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(CreatedAlert { alert, secret })))
+}
+
+pub async fn list_alerts(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<CommuteAlert>>, AppError> {
+    owned_commute(&s, id, &identity).await?;
+
+    Ok(Json(commutes::alerts(database(&s)?, id).await?))
+}
+
+pub async fn delete_alert(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path((id, alert_id)): Path<(Uuid, Uuid)>,
+) -> Result<StatusCode, AppError> {
+    owned_commute(&s, id, &identity).await?;
+
+    if commutes::delete_alert(database(&s)?, alert_id, id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound("Alert not found".into()))
+    }
+}
+
+/// The usual travel time of the commute, `None` until enough samples were taken.
+pub(super) async fn baseline(pool: &PgPool, commute_id: Uuid) -> Result<Option<f64>, AppError> {
+    let since = Utc::now() - ChronoDuration::days(BASELINE_DAYS);
+    let stats = commutes::stats(pool, commute_id, since).await?;
+
+    Ok(stats
+        .median_duration_seconds
+        .filter(|_| stats.samples >= MIN_BASELINE_SAMPLES))
+}
+
+/// Notifies the alerts of the commute the new sample deviates enough from `baseline` for.
+pub(super) async fn notify(
+    s: &AppState,
+    pool: &PgPool,
+    commute: &Commute,
+    sample: &Sample,
+    baseline: f64,
+) -> Result<(), AppError> {
+    let deviation_percent = (sample.duration_seconds - baseline) / baseline * 100.0;
+    let alerts =
+        commutes::claim_alerts(pool, commute.id, deviation_percent.abs(), NOTIFY_COOLDOWN).await?;
+    if alerts.is_empty() {
+        return Ok(());
+    }
+
+    let window_end = commutes::window_end_today(pool, commute.id).await?;
+    let shift = ChronoDuration::seconds((baseline - sample.duration_seconds).round() as i64);
+    let event = EtaChanged {
+        event: ETA_CHANGED_EVENT,
+        commute_id: commute.id,
+        name: &commute.name,
+        sampled_at: sample.sampled_at,
+        eta_seconds: sample.duration_seconds,
+        baseline_seconds: baseline,
+        deviation_percent: (deviation_percent * 10.0).round() / 10.0,
+        suggested_departure_time: (window_end + shift).max(Utc::now()),
+    };
+    for alert in alerts {
+        if let Err(e) = deliver(s, &alert, &event).await {
+            tracing::warn!(error = %e, alert = %alert.id, "failed to deliver commute alert");
+        }
+    }
+
+    Ok(())
+}
+
+async fn deliver(
+    s: &AppState,
+    alert: &CommuteAlert,
+    event: &EtaChanged<'_>,
+) -> Result<(), AppError> {
+    if alert.channel == "push" {
+        let Some(fcm) = &s.fcm else {
+            return Err(AppError::Unavailable);
+        };
+        let minutes = |seconds: f64| (seconds / 60.0).round();
+        let data = serde_json::to_value(event).map_err(|e| AppError::ParseError(e.to_string()))?;
+        return fcm
+            .send(
+                &s.client_reqwest,
+                &alert.target,
+                event.name,
+                &format!(
+                    "{} min right now, usually {} min",
+                    minutes(event.eta_seconds),
+                    minutes(event.baseline_seconds)
+                ),
+                data,
+            )
+            .await;
+    }
+
+    let url = Url::parse(&alert.target).map_err(|e| AppError::Validation(e.to_string()))?;
+    let address = webhook_address(&url).await?;
+    // Pinned to the address just checked, so the host can't resolve elsewhere in between.
+    // Redirects could lead anywhere and aren't followed
+    let mut client = reqwest::Client::builder()
+        .redirect(Policy::none())
+        .timeout(WEBHOOK_TIMEOUT);
+    if let Some(Host::Domain(domain)) = url.host() {
+        client = client.resolve(domain, address);
+    }
+    let client = client.build()?;
+
+    let body = serde_json::to_vec(event).map_err(|e| AppError::ParseError(e.to_string()))?;
+    let mut mac = HmacSha256::new_from_slice(alert.secret.as_bytes())
+        .map_err(|e| AppError::ParseError(e.to_string()))?;
+    mac.update(&body);
+    client
+        .post(url)
+        .header(CONTENT_TYPE, JSON_TYPE)
+        .header(
+            SIGNATURE_HEADER,
+            format!("sha256={}", hex::encode(mac.finalize().into_bytes())),
+        )
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
@@ -0,0 +1,299 @@
+use std::{str::FromStr, time::Duration};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{Duration as ChronoDuration, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    db::{
+        commutes::{self, Commute, NewCommute, Sample, SampleStats},
+        trips::Coordinate,
+    },
+    error::AppError,
+    identity::Identity,
+    upstream, usage, AppState,
+};
+
+use super::{
+    commute_alerts, database, duration_seconds, routes_body, validation, waypoint, TravelMode,
+    CONTENT_TYPE, GOOGLE_API_KEY_HEADER, GOOGLE_FIELD_MASK_HEADER, JSON_TYPE,
+};
+
+const SAMPLE_FIELD_MASK: &str = "routes.duration,routes.staticDuration,routes.distanceMeters";
+// How often the monitor looks for commutes due a sample
+const MONITOR_TICK: Duration = Duration::from_secs(30);
+// Commutes sampled per tick, the rest wait for the next one
+const SAMPLE_BATCH: i64 = 50;
+// Each commute costs a route computation per sample, so they're capped per owner
+const MAX_COMMUTES: i64 = 10;
+const DEFAULT_STATS_DAYS: i64 = 7;
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateCommuteRequest {
+    #[validate(length(min = 1, max = 256))]
+    name: String,
+    #[validate]
+    origin: Coordinate,
+    #[validate]
+    destination: Coordinate,
+    /// Driving when left out
+    travel_mode: Option<TravelMode>,
+    /// ISO weekdays to monitor, 1 is Monday
+    #[validate(length(min = 1, max = 7), custom = "validation::weekdays")]
+    weekdays: Vec<i16>,
+    /// Local time the window opens, e.g. "07:30"
+    window_start: NaiveTime,
+    /// Local time the window closes, later than `windowStart`
+    window_end: NaiveTime,
+    /// IANA time zone of the window, e.g. "Europe/Lisbon"
+    #[validate(length(min = 1, max = 64))]
+    time_zone: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct StatsQuery {
+    /// A week when left out
+    #[validate(range(min = 1, max = 90))]
+    days: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommuteStats {
+    commute: Commute,
+    /// The latest sample, absent until the window first opens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    current: Option<Sample>,
+    /// Over the requested days
+    stats: SampleStats,
+    /// Samples of the requested days, newest first
+    history: Vec<Sample>,
+}
+
+pub async fn create_commute(
+    State(s): State<AppState>,
+    identity: Identity,
+    Json(body): Json<CreateCommuteRequest>,
+) -> Result<(StatusCode, Json<Commute>), AppError> {
+    body.validate()?;
+    if body.window_start >= body.window_end {
+        return Err(AppError::Validation(
+            "windowEnd must be later than windowStart".into(),
+        ));
+    }
+    let pool = database(&s)?;
+    if !commutes::is_time_zone(pool, &body.time_zone).await? {
+        return Err(AppError::Validation(format!(
+            "Unknown time zone {}",
+            body.time_zone
+        )));
+    }
+    if commutes::count(pool, &identity.0).await? >= MAX_COMMUTES {
+        return Err(AppError::Validation(format!(
+            "At most {} commutes can be monitored",
+            MAX_COMMUTES
+        )));
+    }
+
+    let mut weekdays = body.weekdays;
+    weekdays.sort_unstable();
+    weekdays.dedup();
+    let commute = commutes::insert(
+        pool,
+        NewCommute {
+            owner: identity.0,
+            name: body.name,
+            origin: body.origin,
+            destination: body.destination,
+            travel_mode: body
+                .travel_mode
+                .unwrap_or(TravelMode::Drive)
+                .as_str()
+                .into(),
+            weekdays,
+            window_start: body.window_start,
+            window_end: body.window_end,
+            time_zone: body.time_zone,
+        },
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(commute)))
+}
+
+pub async fn list_commutes(
+    State(s): State<AppState>,
+    identity: Identity,
+) -> Result<Json<Vec<Commute>>, AppError> {
+    let pool = database(&s)?;
+
+    Ok(Json(commutes::list(pool, &identity.0).await?))
+}
+
+pub async fn get_commute(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Commute>, AppError> {
+    let pool = database(&s)?;
+
+    commutes::get(pool, id, &identity.0)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound("Commute not found".into()))
+}
+
+pub async fn delete_commute(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let pool = database(&s)?;
+
+    if commutes::delete(pool, id, &identity.0).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound("Commute not found".into()))
+    }
+}
+
+/// The latest travel time of the commute, with how it compares to the recent samples.
+pub async fn commute_stats(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<CommuteStats>, AppError> {
+    query.validate()?;
+    let pool = database(&s)?;
+    let commute = commutes::get(pool, id, &identity.0)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Commute not found".into()))?;
+
+    let since = Utc::now() - ChronoDuration::days(query.days.unwrap_or(DEFAULT_STATS_DAYS));
+    let current = commutes::latest_sample(pool, id).await?;
+    let history = commutes::samples(pool, id, since).await?;
+    let stats = commutes::stats(pool, id, since).await?;
+
+    Ok(Json(CommuteStats {
+        commute,
+        current,
+        stats,
+        history,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SampledRoute {
+    #[serde(default)]
+    distance_meters: f64,
+    duration: String,
+    static_duration: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SampledRoutes {
+    #[serde(default)]
+    routes: Vec<SampledRoute>,
+}
+
+// Live traffic is the point, so samples never come from the cache
+async fn fetch_sample(s: &AppState, req: &Value) -> Result<SampledRoute, AppError> {
+    let (status, body) = upstream::send_with_keys(
+        s.keys(),
+        &s.routes_breaker,
+        &s.retry_policy,
+        &s.routes_metrics,
+        |key| {
+            s.client_reqwest
+                .post(&s.urls.routes)
+                .json(req)
+                .header(GOOGLE_FIELD_MASK_HEADER, SAMPLE_FIELD_MASK)
+                .header(CONTENT_TYPE, JSON_TYPE)
+                .header(GOOGLE_API_KEY_HEADER, key)
+        },
+    )
+    .await?;
+
+    if !status.is_success() {
+        return Err(upstream::google_error::translate(
+            "google-routes",
+            status,
+            &body,
+        ));
+    }
+    s.usage.record(usage::routes_sku(req));
+
+    serde_json::from_slice::<SampledRoutes>(&body)
+        .map_err(|e| AppError::ParseError(e.to_string()))?
+        .routes
+        .into_iter()
+        .next()
+        .ok_or_else(|| AppError::NotFound("No route found for this commute".into()))
+}
+
+async fn sample(s: &AppState, pool: &PgPool, commute: &Commute) -> Result<Sample, AppError> {
+    let travel_mode = TravelMode::from_str(&commute.travel_mode)
+        .map_err(|_| AppError::Database(format!("unknown travel mode {}", commute.travel_mode)))?;
+    let mut req = routes_body(
+        waypoint(commute.origin.latitude, commute.origin.longitude),
+        waypoint(commute.destination.latitude, commute.destination.longitude),
+        Vec::new(),
+        travel_mode,
+        None,
+    );
+    req["computeAlternativeRoutes"] = json!(false);
+
+    let route = fetch_sample(s, &req).await?;
+    let duration = duration_seconds(&route.duration)
+        .ok_or_else(|| AppError::ParseError(format!("invalid duration {}", route.duration)))?;
+    let static_duration = route.static_duration.as_deref().and_then(duration_seconds);
+
+    // Taken before storing the sample so it isn't compared with itself
+    let baseline = commute_alerts::baseline(pool, commute.id).await?;
+    let sample = commutes::insert_sample(
+        pool,
+        commute.id,
+        duration,
+        static_duration,
+        route.distance_meters,
+    )
+    .await?;
+    if let Some(baseline) = baseline {
+        commute_alerts::notify(s, pool, commute, &sample, baseline).await?;
+    }
+
+    Ok(sample)
+}
+
+/// Samples every commute whose window is open, at most once per `every`.
+pub fn spawn_monitor(s: AppState, pool: PgPool, every: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(MONITOR_TICK);
+        loop {
+            interval.tick().await;
+            let due = match commutes::claim_due(&pool, every, SAMPLE_BATCH).await {
+                Ok(due) => due,
+                Err(e) => {
+                    tracing::warn!(error = %e, "failed to look up commutes to sample");
+                    continue;
+                }
+            };
+            for commute in due {
+                if let Err(e) = sample(&s, &pool, &commute).await {
+                    tracing::warn!(error = %e, commute = %commute.id, "failed to sample commute");
+                }
+            }
+        }
+    });
+}
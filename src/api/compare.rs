@@ -0,0 +1,276 @@
+use axum::{extract::State, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use validator::Validate;
+
+use crate::{cache, db::trips::Coordinate, error::AppError, upstream, usage, AppState};
+
+use super::{
+    cached, duration_seconds, google_provider, routes_body, store, validation, waypoint,
+    TravelMode, CONTENT_TYPE, GOOGLE_API_KEY_HEADER, GOOGLE_FIELD_MASK_HEADER, JSON_TYPE,
+};
+
+const COMPARE_FIELD_MASK: &str = "routes.distanceMeters,routes.duration,\
+    routes.polyline.encodedPolyline,routes.travelAdvisory.transitFare";
+const COMPARED_MODES: [TravelMode; 4] = [
+    TravelMode::Drive,
+    TravelMode::Transit,
+    TravelMode::Walk,
+    TravelMode::Bicycle,
+];
+// Currency of DRIVE_COST_PER_KM
+const DRIVE_COST_CURRENCY: &str = "USD";
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CompareRequest {
+    #[validate]
+    origin: Coordinate,
+    #[validate]
+    destination: Coordinate,
+    /// Now when left out
+    #[validate(custom = "validation::rfc3339")]
+    departure_time: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Cost {
+    currency_code: String,
+    amount: f64,
+    /// `fare` as quoted for transit, `distance` from DRIVE_COST_PER_KM, `free` otherwise
+    basis: &'static str,
+}
+
+/// One row of the comparison. Modes without a route have only `travelMode` and `error`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModeComparison {
+    travel_mode: TravelMode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration_seconds: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distance_meters: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cost: Option<Cost>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoded_polyline: Option<String>,
+    /// Error code when the mode couldn't be routed, e.g. `NOT_FOUND` without transit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'static str>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompareResponse {
+    /// Driving, transit, walking and cycling, in that order
+    modes: Vec<ModeComparison>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fastest: Option<TravelMode>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Money {
+    currency_code: String,
+    // int64 comes as a string
+    #[serde(default)]
+    units: Option<String>,
+    #[serde(default)]
+    nanos: i64,
+}
+
+impl Money {
+    fn amount(&self) -> f64 {
+        let units = self
+            .units
+            .as_deref()
+            .and_then(|u| u.parse::<f64>().ok())
+            .unwrap_or(0.0);
+
+        units + self.nanos as f64 / 1e9
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TravelAdvisory {
+    transit_fare: Option<Money>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ComparedRoute {
+    #[serde(default)]
+    distance_meters: f64,
+    duration: String,
+    polyline: ComparedPolyline,
+    #[serde(default)]
+    travel_advisory: TravelAdvisory,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ComparedPolyline {
+    encoded_polyline: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct ComparedRoutes {
+    #[serde(default)]
+    routes: Vec<ComparedRoute>,
+}
+
+fn cost(s: &AppState, travel_mode: TravelMode, route: &ComparedRoute) -> Option<Cost> {
+    match travel_mode {
+        TravelMode::Transit => route
+            .travel_advisory
+            .transit_fare
+            .as_ref()
+            .map(|fare| Cost {
+                currency_code: fare.currency_code.clone(),
+                amount: fare.amount(),
+                basis: "fare",
+            }),
+        TravelMode::Drive | TravelMode::TwoWheeler => Some(Cost {
+            currency_code: DRIVE_COST_CURRENCY.into(),
+            amount: (route.distance_meters / 1000.0 * s.drive_cost_per_km * 100.0).round() / 100.0,
+            basis: "distance",
+        }),
+        TravelMode::Walk | TravelMode::Bicycle => Some(Cost {
+            currency_code: DRIVE_COST_CURRENCY.into(),
+            amount: 0.0,
+            basis: "free",
+        }),
+    }
+}
+
+fn compare_body(body: &CompareRequest, travel_mode: TravelMode) -> Value {
+    let mut req = routes_body(
+        waypoint(body.origin.latitude, body.origin.longitude),
+        waypoint(body.destination.latitude, body.destination.longitude),
+        Vec::new(),
+        travel_mode,
+        body.departure_time.clone(),
+    );
+    // Only the best route of each mode is compared
+    req["computeAlternativeRoutes"] = json!(false);
+
+    req
+}
+
+async fn compare_mode(
+    s: &AppState,
+    body: &CompareRequest,
+    travel_mode: TravelMode,
+) -> Result<ModeComparison, AppError> {
+    let routes = fetch_compared(s, &compare_body(body, travel_mode)).await?;
+    let route = routes
+        .routes
+        .first()
+        .ok_or_else(|| AppError::NotFound("No route found between these points".into()))?;
+
+    Ok(ModeComparison {
+        travel_mode,
+        duration_seconds: duration_seconds(&route.duration),
+        distance_meters: Some(route.distance_meters),
+        cost: cost(s, travel_mode, route),
+        encoded_polyline: Some(route.polyline.encoded_polyline.clone()),
+        error: None,
+    })
+}
+
+fn failed(travel_mode: TravelMode, error: &AppError) -> ModeComparison {
+    ModeComparison {
+        travel_mode,
+        duration_seconds: None,
+        distance_meters: None,
+        cost: None,
+        encoded_polyline: None,
+        error: Some(error.code()),
+    }
+}
+
+/// Computes the trip by car, transit, on foot and by bike at once and compares their
+/// duration, distance and estimated cost. A mode without a route doesn't fail the others.
+pub async fn compare_routes(
+    State(s): State<AppState>,
+    Json(body): Json<CompareRequest>,
+) -> Result<Json<CompareResponse>, AppError> {
+    body.validate()?;
+
+    let [drive, transit, walk, bicycle] = COMPARED_MODES;
+    let (drive, transit, walk, bicycle) = tokio::join!(
+        compare_mode(&s, &body, drive),
+        compare_mode(&s, &body, transit),
+        compare_mode(&s, &body, walk),
+        compare_mode(&s, &body, bicycle),
+    );
+    let results = [drive, transit, walk, bicycle];
+
+    // Nothing to compare when no mode has a route. A provider failure says more than
+    // a missing route
+    if results.iter().all(Result::is_err) {
+        let mut errors: Vec<AppError> = results.into_iter().filter_map(Result::err).collect();
+        let telling = errors
+            .iter()
+            .position(|e| !matches!(e, AppError::NotFound(_)))
+            .unwrap_or(0);
+        return Err(errors.swap_remove(telling));
+    }
+    let modes: Vec<ModeComparison> = results
+        .into_iter()
+        .zip(COMPARED_MODES)
+        .map(|(result, travel_mode)| result.unwrap_or_else(|e| failed(travel_mode, &e)))
+        .collect();
+    let fastest = modes
+        .iter()
+        .filter_map(|m| Some((m.travel_mode, m.duration_seconds?)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(travel_mode, _)| travel_mode);
+
+    Ok(Json(CompareResponse { modes, fastest }))
+}
+
+async fn fetch_compared(s: &AppState, req: &Value) -> Result<ComparedRoutes, AppError> {
+    let cache_key = cache::routes_key(req, google_provider());
+    if let Some(routes) = cached::<ComparedRoutes>(s, &cache_key).await {
+        return Ok(routes);
+    }
+
+    let (status, body) = upstream::send_with_keys(
+        s.keys(),
+        &s.routes_breaker,
+        &s.retry_policy,
+        &s.routes_metrics,
+        |key| {
+            s.client_reqwest
+                .post(&s.urls.routes)
+                .json(req)
+                .header(GOOGLE_FIELD_MASK_HEADER, COMPARE_FIELD_MASK)
+                .header(CONTENT_TYPE, JSON_TYPE)
+                .header(GOOGLE_API_KEY_HEADER, key)
+        },
+    )
+    .await
+    .inspect_err(
+        |e| tracing::error!(error = %e, provider = "google-routes", "upstream request failed"),
+    )?;
+
+    if !status.is_success() {
+        return Err(upstream::google_error::translate(
+            "google-routes",
+            status,
+            &body,
+        ));
+    }
+    s.usage.record(usage::routes_sku(req));
+
+    let routes = serde_json::from_slice::<ComparedRoutes>(&body).map_err(|e| {
+        tracing::error!(error = %e, provider = "google-routes", "failed to parse upstream response");
+        AppError::ParseError(e.to_string())
+    })?;
+    store(s, &cache_key, &routes).await;
+
+    Ok(routes)
+}
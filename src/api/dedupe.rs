@@ -0,0 +1,62 @@
+use std::collections::HashSet;
+
+use crate::geo;
+
+use super::GooglePlace;
+
+// Share of character pairs two names need in common to be the same place
+const NAME_SIMILARITY: f64 = 0.7;
+
+/// Drops places that repeat an earlier one: a similar name within `radius_meters`. Of each
+/// group the record with the most details is kept, in the position of the first.
+pub(super) fn dedupe(places: Vec<GooglePlace>, radius_meters: f64) -> Vec<GooglePlace> {
+    let mut kept: Vec<(GooglePlace, HashSet<[char; 2]>)> = Vec::with_capacity(places.len());
+
+    for place in places {
+        let pairs = bigrams(&place.display_name.text);
+        let duplicate = kept.iter().position(|(other, other_pairs)| {
+            geo::haversine(place.coordinate(), other.coordinate()) <= radius_meters
+                && similarity(&pairs, other_pairs) >= NAME_SIMILARITY
+        });
+
+        match duplicate {
+            Some(i) if richness(&place) > richness(&kept[i].0) => kept[i].0 = place,
+            Some(_) => {}
+            None => kept.push((place, pairs)),
+        }
+    }
+
+    kept.into_iter().map(|(place, _)| place).collect()
+}
+
+// Punctuation, spacing and casing don't tell places apart, "Joe's Pizza" is "JOES PIZZA"
+fn bigrams(name: &str) -> HashSet<[char; 2]> {
+    let chars: Vec<char> = name
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect();
+
+    chars.windows(2).map(|w| [w[0], w[1]]).collect()
+}
+
+// Dice coefficient, names too short for a pair only match themselves
+fn similarity(a: &HashSet<[char; 2]>, b: &HashSet<[char; 2]>) -> f64 {
+    if a.is_empty() || b.is_empty() {
+        return if a == b { 1.0 } else { 0.0 };
+    }
+
+    2.0 * a.intersection(b).count() as f64 / (a.len() + b.len()) as f64
+}
+
+fn richness(place: &GooglePlace) -> usize {
+    [
+        !place.formatted_address.is_empty(),
+        place.display_name.language_code.is_some(),
+        place.price_level.is_some(),
+        place.rating.is_some(),
+    ]
+    .into_iter()
+    .filter(|present| *present)
+    .count()
+}
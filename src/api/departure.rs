@@ -0,0 +1,221 @@
+use axum::{extract::State, Json};
+use chrono::{DateTime, Duration as ChronoDuration, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use validator::Validate;
+
+use crate::{cache, db::trips::Coordinate, error::AppError, job_store::ItemResult, AppState};
+
+use super::{
+    batch::run_batch, cached, duration_seconds, fetch_routes, google_provider, routes_body, store,
+    validation, waypoint, GetRoutesReponse, TravelMode,
+};
+
+// Every sampled departure is a route computation
+const MAX_DEPARTURES: i64 = 24;
+const DEFAULT_STEP_MINUTES: i64 = 15;
+// Enough for travel times that change with the departure to settle
+const ARRIVAL_SEARCH_STEPS: usize = 5;
+// Arriving this much early is close enough
+const ARRIVAL_TOLERANCE_SECS: i64 = 120;
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct BestDepartureRequest {
+    #[validate]
+    origin: Coordinate,
+    #[validate]
+    destination: Coordinate,
+    /// Driving when left out
+    travel_mode: Option<TravelMode>,
+    /// Earliest departure, now when in the past
+    #[validate(custom = "validation::rfc3339")]
+    window_start: String,
+    /// Latest departure
+    #[validate(custom = "validation::rfc3339")]
+    window_end: String,
+    /// Time between two sampled departures, 15 when left out
+    #[validate(range(min = 5, max = 120))]
+    step_minutes: Option<i64>,
+}
+
+/// The fastest route when leaving at `departureTime`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Departure {
+    pub departure_time: DateTime<Utc>,
+    pub arrival_time: DateTime<Utc>,
+    pub duration_seconds: f64,
+    pub distance_meters: f64,
+    pub encoded_polyline: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BestDepartureResponse {
+    /// The sampled departure with the shortest travel time, the earliest on ties
+    best: Departure,
+    /// Every sampled departure in chronological order, failed ones with their error
+    departures: Vec<Option<ItemResult>>,
+}
+
+pub(super) fn timestamp(value: &str) -> DateTime<Utc> {
+    // Validated as RFC 3339 beforehand
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_else(|_| Utc::now())
+}
+
+// A minute ahead so a departure isn't in the past by the time Google sees it
+fn earliest_departure() -> DateTime<Utc> {
+    Utc::now() + ChronoDuration::minutes(1)
+}
+
+/// Routes leaving at `departure_time` through the cache, returning the fastest of them.
+pub(super) async fn depart_at(
+    s: &AppState,
+    origin: Coordinate,
+    destination: Coordinate,
+    travel_mode: TravelMode,
+    departure_time: DateTime<Utc>,
+) -> Result<Departure, AppError> {
+    let req = routes_body(
+        waypoint(origin.latitude, origin.longitude),
+        waypoint(destination.latitude, destination.longitude),
+        Vec::new(),
+        travel_mode,
+        Some(departure_time.to_rfc3339_opts(SecondsFormat::Secs, true)),
+    );
+    let cache_key = cache::routes_key(&req, google_provider());
+    let routes = match cached::<GetRoutesReponse>(s, &cache_key).await {
+        Some(routes) => routes,
+        None => {
+            let routes = fetch_routes(s, &req).await?;
+            store(s, &cache_key, &routes).await;
+            routes
+        }
+    };
+
+    routes
+        .routes
+        .into_iter()
+        .filter_map(|route| Some((duration_seconds(&route.duration)?, route)))
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(duration, route)| Departure {
+            departure_time,
+            arrival_time: departure_time + ChronoDuration::seconds(duration.round() as i64),
+            duration_seconds: duration,
+            distance_meters: f64::from(route.distance_meters),
+            encoded_polyline: route.polyline.encoded_polyline,
+        })
+        .ok_or_else(|| AppError::NotFound("No route found between these points".into()))
+}
+
+/// Searches the latest departure whose fastest route arrives by `arrival_time`, moving the
+/// departure by however much its arrival misses. Leaving now when even that is too late.
+pub(super) async fn departure_for_arrival(
+    s: &AppState,
+    origin: Coordinate,
+    destination: Coordinate,
+    travel_mode: TravelMode,
+    arrival_time: DateTime<Utc>,
+) -> Result<Departure, AppError> {
+    let now = earliest_departure();
+    if arrival_time <= now {
+        return Err(AppError::Validation(
+            "arrivalTime must be in the future".into(),
+        ));
+    }
+
+    let leaving_now = depart_at(s, origin, destination, travel_mode, now).await?;
+    let mut departure = leaving_now.clone();
+    let mut latest_on_time: Option<Departure> = None;
+    for _ in 0..ARRIVAL_SEARCH_STEPS {
+        let slack = arrival_time - departure.arrival_time;
+        if slack >= ChronoDuration::zero() {
+            if latest_on_time
+                .as_ref()
+                .is_none_or(|d| departure.departure_time > d.departure_time)
+            {
+                latest_on_time = Some(departure.clone());
+            }
+            if slack.num_seconds() <= ARRIVAL_TOLERANCE_SECS {
+                break;
+            }
+        }
+
+        let next = (departure.departure_time + slack).max(now);
+        if next == departure.departure_time {
+            break;
+        }
+        departure = depart_at(s, origin, destination, travel_mode, next).await?;
+    }
+
+    Ok(latest_on_time.unwrap_or(leaving_now))
+}
+
+/// Computes the trip for departures spread over a window and returns the one with the
+/// shortest travel time.
+pub async fn best_departure(
+    State(s): State<AppState>,
+    Json(body): Json<BestDepartureRequest>,
+) -> Result<Json<BestDepartureResponse>, AppError> {
+    body.validate()?;
+    let start = timestamp(&body.window_start).max(earliest_departure());
+    let end = timestamp(&body.window_end);
+    if end < start {
+        return Err(AppError::Validation(
+            "windowEnd must be later than windowStart and now".into(),
+        ));
+    }
+    let step = ChronoDuration::minutes(body.step_minutes.unwrap_or(DEFAULT_STEP_MINUTES));
+    let count = (end - start).num_seconds() / step.num_seconds() + 1;
+    if count > MAX_DEPARTURES {
+        return Err(AppError::Validation(format!(
+            "At most {} departures can be compared, widen stepMinutes or narrow the window",
+            MAX_DEPARTURES
+        )));
+    }
+
+    let travel_mode = body.travel_mode.unwrap_or(TravelMode::Drive);
+    let departures: Vec<DateTime<Utc>> = (0..count as i32).map(|i| start + step * i).collect();
+    let mut results = vec![None; departures.len()];
+    run_batch(
+        departures,
+        s.batch.concurrency,
+        |departure_time| {
+            let s = s.clone();
+            let (origin, destination) = (body.origin, body.destination);
+            async move {
+                let departure =
+                    depart_at(&s, origin, destination, travel_mode, departure_time).await?;
+                serde_json::to_value(departure).map_err(|e| AppError::ParseError(e.to_string()))
+            }
+        },
+        |index, item| results[index] = Some(item),
+    )
+    .await;
+
+    let best = results
+        .iter()
+        .flatten()
+        .filter_map(|item| item.result.clone())
+        .filter_map(|result: Value| serde_json::from_value::<Departure>(result).ok())
+        .fold(None, |best: Option<Departure>, departure| match best {
+            Some(best) if best.duration_seconds <= departure.duration_seconds => Some(best),
+            _ => Some(departure),
+        });
+    let Some(best) = best else {
+        let unroutable = results.iter().flatten().all(|item| item.status == 404);
+        return Err(if unroutable {
+            AppError::NotFound("No route found between these points".into())
+        } else {
+            AppError::Unavailable
+        });
+    };
+
+    Ok(Json(BestDepartureResponse {
+        best,
+        departures: results,
+    }))
+}
@@ -0,0 +1,58 @@
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::{db::trips::Coordinate, error::AppError, geo};
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct CoordinatePair {
+    #[validate]
+    from: Coordinate,
+    #[validate]
+    to: Coordinate,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct DistanceRequest {
+    // Pairs are checked in the handler, the derive can't combine this with nested validation
+    #[validate(length(min = 1, max = 1000, message = "must hold 1 to 1000 pairs"))]
+    pairs: Vec<CoordinatePair>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Distance {
+    haversine_meters: f64,
+    /// `null` for nearly antipodal points
+    vincenty_meters: Option<f64>,
+    /// Degrees clockwise from north
+    initial_bearing: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DistanceResponse {
+    /// In the order of the pairs
+    results: Vec<Distance>,
+}
+
+/// As-the-crow-flies distance and bearing between each pair, computed locally.
+pub async fn measure(
+    Json(body): Json<DistanceRequest>,
+) -> Result<Json<DistanceResponse>, AppError> {
+    body.validate()?;
+    for pair in &body.pairs {
+        pair.validate()?;
+    }
+
+    let results = body
+        .pairs
+        .iter()
+        .map(|p| Distance {
+            haversine_meters: geo::haversine(p.from, p.to),
+            vincenty_meters: geo::vincenty(p.from, p.to),
+            initial_bearing: geo::initial_bearing(p.from, p.to),
+        })
+        .collect();
+
+    Ok(Json(DistanceResponse { results }))
+}
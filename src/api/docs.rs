@@ -0,0 +1,82 @@
+use utoipa::{
+    openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use crate::{
+    cache::CacheStatus,
+    error::{ErrorBody, ErrorResponse, FieldError},
+};
+
+use super::{
+    dry_run::DryRun,
+    fuel::FuelCost,
+    tolls::TollCost,
+    transit::{TransitItinerary, TransitRide},
+    DisplayName, GetRouteRequestBody, GetRoutesReponse, GooglePlace, GooglePlacesReponse,
+    GooglePlacesRequest, Location, PlacesSearchResponse, Polyline, RankBy, ResponseMeta,
+    RoutesComputeResponse, RoutesResponse, Schedule, TravelMode, Viewport,
+};
+
+/// Served on `/openapi.json` and rendered by Swagger UI on `/docs`.
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Luda MultiMap"),
+    paths(super::get_places, super::get_routes),
+    components(schemas(
+        CacheStatus,
+        DisplayName,
+        DryRun,
+        ErrorBody,
+        ErrorResponse,
+        FieldError,
+        FuelCost,
+        GetRouteRequestBody,
+        GetRoutesReponse,
+        GooglePlace,
+        GooglePlacesReponse,
+        GooglePlacesRequest,
+        Location,
+        PlacesSearchResponse,
+        Polyline,
+        RankBy,
+        ResponseMeta,
+        RoutesComputeResponse,
+        RoutesResponse,
+        Schedule,
+        TollCost,
+        TransitItinerary,
+        TransitRide,
+        TravelMode,
+        Viewport,
+    )),
+    modifiers(&Credentials),
+    security((), ("api_key" = []), ("bearer" = [])),
+    tags(
+        (name = "places", description = "Place search"),
+        (name = "routes", description = "Route computation"),
+    )
+)]
+pub struct ApiDoc;
+
+// Either credential works when API authentication is enabled
+struct Credentials;
+
+impl Modify for Credentials {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-Api-Key"))),
+        );
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::{IntoParams, ToSchema};
+
+use super::{CONTENT_TYPE, GOOGLE_FIELD_MASK_HEADER, JSON_TYPE};
+
+// Query parameters about the call rather than the search. They don't make a query string
+// of their own, so a JSON body still goes with them
+pub(super) const CALL_PARAMETERS: [&str; 2] = ["dryRun", "dry_run"];
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "camelCase")]
+pub struct DryRunOption {
+    /// Answer with the request the provider would get, a `DryRun`, instead of sending it.
+    /// Nothing is cached or recorded
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// The provider request a call would make, headers without the API key.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DryRun {
+    provider: &'static str,
+    method: &'static str,
+    url: String,
+    #[schema(value_type = Object)]
+    headers: BTreeMap<&'static str, &'static str>,
+    #[schema(value_type = Object)]
+    body: Value,
+}
+
+impl DryRun {
+    /// A JSON POST the way every provider call is made.
+    pub(super) fn post(
+        provider: &'static str,
+        url: &str,
+        field_mask: &'static str,
+        body: Value,
+    ) -> Response {
+        let headers = BTreeMap::from([
+            (CONTENT_TYPE, JSON_TYPE),
+            (GOOGLE_FIELD_MASK_HEADER, field_mask),
+        ]);
+
+        Json(DryRun {
+            provider,
+            method: "POST",
+            url: url.to_owned(),
+            headers,
+            body,
+        })
+        .into_response()
+    }
+}
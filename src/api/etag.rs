@@ -0,0 +1,56 @@
+use axum::{
+    http::{
+        header::{CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+        HeaderMap, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+// Weak because compression changes the bytes on the wire but not the content
+pub fn etag_for<T: Serialize>(value: &T) -> Option<String> {
+    let bytes = serde_json::to_vec(value).ok()?;
+
+    Some(etag_of(&bytes))
+}
+
+/// The tag `etag_for` gives the value `bytes` is the JSON of.
+pub fn etag_of(bytes: &[u8]) -> String {
+    format!("W/\"{:x}\"", Sha256::digest(bytes))
+}
+
+fn opaque_tag(tag: &str) -> &str {
+    tag.trim().trim_start_matches("W/")
+}
+
+fn matches(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|tag| tag.trim() == "*" || opaque_tag(tag) == opaque_tag(etag))
+}
+
+/// Responds with `body` tagged with `etag`, or an empty 304 when the client already
+/// holds that version according to `If-None-Match`.
+pub fn conditional<T: Serialize>(headers: &HeaderMap, etag: Option<String>, body: T) -> Response {
+    match etag {
+        Some(etag) if matches(headers, &etag) => {
+            (StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response()
+        }
+        Some(etag) => ([(ETAG, etag)], Json(body)).into_response(),
+        None => Json(body).into_response(),
+    }
+}
+
+/// `conditional` for a body that's JSON already.
+pub fn conditional_raw(headers: &HeaderMap, etag: String, body: Vec<u8>) -> Response {
+    if matches(headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response();
+    }
+
+    ([(ETAG, etag)], [(CONTENT_TYPE, "application/json")], body).into_response()
+}
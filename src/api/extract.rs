@@ -0,0 +1,74 @@
+use axum::{
+    async_trait,
+    body::to_bytes,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::header::CONTENT_TYPE,
+};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+
+use crate::error::AppError;
+
+use super::{dry_run::CALL_PARAMETERS, version::VersionedQuery};
+
+// Far more than any search body needs
+const MAX_BODY_BYTES: usize = 64 * 1024;
+
+/// Parameters sent either as a JSON body or, as older clients do, in the query string.
+/// A request with a query string is read from it alone, so bodies those clients sent
+/// along, which used to be ignored, still are. Options of the call such as `dryRun` don't
+/// count as a query string.
+pub struct JsonOrQuery<T>(pub T);
+
+#[async_trait]
+impl<T: DeserializeOwned, S: Send + Sync> FromRequest<S> for JsonOrQuery<T> {
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let is_json = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("application/json"));
+        let (mut parts, body) = req.into_parts();
+
+        if is_json && !has_parameters(parts.uri.query().unwrap_or_default()) {
+            let bytes = to_bytes(body, MAX_BODY_BYTES)
+                .await
+                .map_err(|_| AppError::Validation("Request body is too large".into()))?;
+            if !bytes.is_empty() {
+                return serde_json::from_slice(&bytes)
+                    .map(JsonOrQuery)
+                    .map_err(|e| AppError::Validation(format!("Invalid JSON body: {}", e)));
+            }
+        }
+
+        let VersionedQuery(value) = VersionedQuery::from_request_parts(&mut parts, state).await?;
+        Ok(JsonOrQuery(value))
+    }
+}
+
+fn has_parameters(query: &str) -> bool {
+    url::form_urlencoded::parse(query.as_bytes()).any(|(key, _)| !CALL_PARAMETERS.contains(&&*key))
+}
+
+/// A list given as a JSON array or, in a query string, as comma separated values.
+pub fn comma_separated<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum List {
+        Values(Vec<String>),
+        Joined(String),
+    }
+
+    Ok(match List::deserialize(deserializer)? {
+        List::Values(values) => values,
+        List::Joined(joined) => joined
+            .split(',')
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(String::from)
+            .collect(),
+    })
+}
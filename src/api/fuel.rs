@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::error::AppError;
+
+use super::TravelMode;
+
+/// Prices per liter of each fuel, from `FUEL_PRICES`.
+#[derive(Debug)]
+pub struct FuelPrices {
+    prices: HashMap<String, f64>,
+    currency: String,
+}
+
+/// Consumption and price an estimate is made with.
+#[derive(Debug)]
+pub struct FuelRate {
+    liters_per_100km: f64,
+    price_per_liter: f64,
+    currency: String,
+}
+
+/// What driving a route burns and costs.
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FuelCost {
+    liters: f64,
+    currency_code: String,
+    amount: f64,
+}
+
+impl FuelPrices {
+    pub fn new(prices: HashMap<String, f64>, currency: String) -> Self {
+        FuelPrices { prices, currency }
+    }
+
+    /// The rate of the `/routes` fuel parameters, `None` without any of them. Consumption
+    /// goes with either a price or a fuel of the price table.
+    pub fn rate(
+        &self,
+        travel_mode: TravelMode,
+        consumption: Option<f64>,
+        price: Option<f64>,
+        fuel: Option<&str>,
+    ) -> Result<Option<FuelRate>, AppError> {
+        if consumption.is_none() && price.is_none() && fuel.is_none() {
+            return Ok(None);
+        }
+        if !travel_mode.is_motorized() {
+            return Err(AppError::Validation(
+                "Fuel costs are only estimated for DRIVE and TWO_WHEELER".into(),
+            ));
+        }
+        let liters_per_100km = consumption
+            .filter(|c| c.is_finite() && *c > 0.0 && *c <= 100.0)
+            .ok_or_else(|| {
+                AppError::Validation("fuelConsumption must be within (0, 100] liters".into())
+            })?;
+        let price_per_liter = match (price, fuel) {
+            (Some(price), _) if price.is_finite() && price >= 0.0 => price,
+            (Some(_), _) => {
+                return Err(AppError::Validation("fuelPrice can't be negative".into()));
+            }
+            (None, Some(fuel)) => *self
+                .prices
+                .get(&fuel.to_lowercase())
+                .ok_or_else(|| AppError::Validation(format!("No price for fuel {}", fuel)))?,
+            (None, None) => {
+                return Err(AppError::Validation(
+                    "fuelPrice or fuelType is required with fuelConsumption".into(),
+                ));
+            }
+        };
+
+        Ok(Some(FuelRate {
+            liters_per_100km,
+            price_per_liter,
+            currency: self.currency.clone(),
+        }))
+    }
+}
+
+impl FuelRate {
+    pub fn cost(&self, distance_meters: f64) -> FuelCost {
+        let liters = distance_meters / 100_000.0 * self.liters_per_100km;
+
+        FuelCost {
+            liters: (liters * 10.0).round() / 10.0,
+            currency_code: self.currency.clone(),
+            amount: (liters * self.price_per_liter * 100.0).round() / 100.0,
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    db::{
+        geofences::{self, Geofence},
+        trips::Coordinate,
+    },
+    error::AppError,
+    geo::fence::Shape,
+    identity::Identity,
+    AppState,
+};
+
+use super::{database, validation};
+
+const MAX_RADIUS_METERS: f64 = 100_000.0;
+const MAX_VERTICES: usize = 500;
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateGeofenceRequest {
+    #[validate(length(min = 1, max = 256))]
+    name: String,
+    shape: Shape,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CheckRequest {
+    #[validate(length(min = 1, max = 1000), custom = "validation::coordinates")]
+    points: Vec<Coordinate>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PointCheck {
+    /// Fences holding the point
+    inside: Vec<Uuid>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckResponse {
+    /// In the order of the points
+    results: Vec<PointCheck>,
+}
+
+fn check_shape(shape: &Shape) -> Result<(), AppError> {
+    match shape {
+        Shape::Circle {
+            center,
+            radius_meters,
+        } => {
+            center.validate()?;
+            if !(*radius_meters > 0.0 && *radius_meters <= MAX_RADIUS_METERS) {
+                return Err(AppError::Validation(format!(
+                    "radiusMeters must be within (0, {}]",
+                    MAX_RADIUS_METERS
+                )));
+            }
+        }
+        Shape::Polygon { vertices } => {
+            if !(3..=MAX_VERTICES).contains(&vertices.len()) {
+                return Err(AppError::Validation(format!(
+                    "A polygon needs 3 to {} vertices",
+                    MAX_VERTICES
+                )));
+            }
+            validation::coordinates(vertices).map_err(|e| {
+                AppError::Validation(format!("vertices: {}", e.message.unwrap_or_default()))
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn create_geofence(
+    State(s): State<AppState>,
+    identity: Identity,
+    Json(body): Json<CreateGeofenceRequest>,
+) -> Result<(StatusCode, Json<Geofence>), AppError> {
+    body.validate()?;
+    check_shape(&body.shape)?;
+    let pool = database(&s)?;
+
+    let fence = geofences::insert(pool, &identity.0, body.name.trim(), body.shape).await?;
+
+    Ok((StatusCode::CREATED, Json(fence)))
+}
+
+pub async fn list_geofences(
+    State(s): State<AppState>,
+    identity: Identity,
+) -> Result<Json<Vec<Geofence>>, AppError> {
+    let pool = database(&s)?;
+
+    Ok(Json(geofences::list(pool, &identity.0).await?))
+}
+
+pub async fn get_geofence(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Geofence>, AppError> {
+    let pool = database(&s)?;
+
+    geofences::get(pool, id, &identity.0)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound("Geofence not found".into()))
+}
+
+pub async fn delete_geofence(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let pool = database(&s)?;
+
+    if geofences::delete(pool, id, &identity.0).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound("Geofence not found".into()))
+    }
+}
+
+/// Tells for each point which of the caller's fences it falls in.
+pub async fn check_points(
+    State(s): State<AppState>,
+    identity: Identity,
+    Json(body): Json<CheckRequest>,
+) -> Result<Json<CheckResponse>, AppError> {
+    body.validate()?;
+    let fences = geofences::list(database(&s)?, &identity.0).await?;
+
+    let results = body
+        .points
+        .iter()
+        .map(|point| PointCheck {
+            inside: fences
+                .iter()
+                .filter(|f| f.shape.contains(*point))
+                .map(|f| f.id)
+                .collect(),
+        })
+        .collect();
+
+    Ok(Json(CheckResponse { results }))
+}
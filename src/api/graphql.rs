@@ -0,0 +1,231 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_graphql::{
+    dataloader::{DataLoader, Loader},
+    ComplexObject, Context, EmptyMutation, EmptySubscription, ErrorExtensions, InputObject, Object,
+    Schema, SimpleObject, ID,
+};
+use async_graphql_axum::{GraphQLRequest, GraphQLResponse};
+use axum::extract::State;
+use tokio::task::JoinSet;
+use validator::Validate;
+
+use crate::{cache, error::AppError, middleware::in_current_environment, AppState};
+
+use super::{
+    cached, fetch_place, google_provider, store, waypoint, GooglePlace, Location, RoutesResponse,
+};
+
+pub type GraphQlSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+// A query can't fan out into an unbounded number of upstream calls
+const MAX_DEPTH: usize = 8;
+const MAX_COMPLEXITY: usize = 200;
+
+/// Which upstream features the schema may use, mirroring `PLACES_ENABLED`/`ROUTES_ENABLED`.
+#[derive(Clone, Copy, Debug)]
+pub struct Features {
+    pub places: bool,
+    pub routes: bool,
+}
+
+pub fn schema(features: Features) -> GraphQlSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .data(features)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+// Same public message and code as the REST error body
+fn graphql_error(e: &AppError) -> async_graphql::Error {
+    let code = e.code();
+    async_graphql::Error::new(e.message()).extend_with(|_, ext| ext.set("code", code))
+}
+
+/// Loads place details, deduplicated per request. Google has no batch endpoint, so a
+/// batch becomes concurrent calls, each served from the cache when possible.
+struct PlaceLoader(AppState);
+
+impl Loader<String> for PlaceLoader {
+    type Value = GooglePlace;
+    type Error = Arc<AppError>;
+
+    async fn load(&self, ids: &[String]) -> Result<HashMap<String, GooglePlace>, Self::Error> {
+        let mut tasks = JoinSet::new();
+        for id in ids {
+            let s = self.0.clone();
+            let id = id.clone();
+            tasks.spawn(in_current_environment(async move {
+                let key = cache::place_key(&id, google_provider());
+                if let Some(place) = cached::<GooglePlace>(&s, &key).await {
+                    return (id, Ok(Some(place)));
+                }
+                let place = fetch_place(&s, &id).await;
+                if let Ok(Some(place)) = &place {
+                    store(&s, &key, place).await;
+                }
+                (id, place)
+            }));
+        }
+
+        let mut places = HashMap::new();
+        while let Some(joined) = tasks.join_next().await {
+            let (id, place) =
+                joined.map_err(|e| Arc::new(AppError::UpstreamError(e.to_string())))?;
+            if let Some(place) = place.map_err(Arc::new)? {
+                places.insert(id, place);
+            }
+        }
+
+        Ok(places)
+    }
+}
+
+/// A route endpoint, either a place or coordinates.
+#[derive(InputObject)]
+struct WaypointInput {
+    place_id: Option<ID>,
+    location: Option<Location>,
+}
+
+impl WaypointInput {
+    fn to_waypoint(&self) -> Result<serde_json::Value, AppError> {
+        match (&self.place_id, &self.location) {
+            (Some(id), None) => Ok(serde_json::json!({ "placeId": id.as_str() })),
+            (None, Some(location)) => {
+                location.validate()?;
+                Ok(waypoint(location.latitude, location.longitude))
+            }
+            _ => Err(AppError::Validation(
+                "A waypoint needs exactly one of placeId or location".into(),
+            )),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(complex)]
+struct RoutePlan {
+    routes: Vec<RoutesResponse>,
+    #[graphql(skip)]
+    origin_place_id: Option<String>,
+    #[graphql(skip)]
+    destination_place_id: Option<String>,
+}
+
+#[ComplexObject]
+impl RoutePlan {
+    /// Details of the origin, when it was given as a place.
+    async fn origin(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<GooglePlace>> {
+        load_place(ctx, self.origin_place_id.clone()).await
+    }
+
+    /// Details of the destination, when it was given as a place.
+    async fn destination(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<GooglePlace>> {
+        load_place(ctx, self.destination_place_id.clone()).await
+    }
+}
+
+async fn load_place(
+    ctx: &Context<'_>,
+    id: Option<String>,
+) -> async_graphql::Result<Option<GooglePlace>> {
+    let Some(id) = id else {
+        return Ok(None);
+    };
+    enabled(ctx, |f| f.places, "Places").map_err(|e| graphql_error(&e))?;
+
+    ctx.data_unchecked::<DataLoader<PlaceLoader>>()
+        .load_one(id)
+        .await
+        .map_err(|e| graphql_error(&e))
+}
+
+fn enabled(ctx: &Context<'_>, feature: fn(&Features) -> bool, name: &str) -> Result<(), AppError> {
+    if feature(ctx.data_unchecked::<Features>()) {
+        Ok(())
+    } else {
+        Err(AppError::NotFound(format!("{} are disabled", name)))
+    }
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// Places matching a free text search.
+    async fn places(
+        &self,
+        ctx: &Context<'_>,
+        text_query: String,
+    ) -> async_graphql::Result<Vec<GooglePlace>> {
+        search_places(ctx, text_query)
+            .await
+            .map_err(|e| graphql_error(&e))
+    }
+
+    /// Details of one place, by its Google place id.
+    async fn place(&self, ctx: &Context<'_>, id: ID) -> async_graphql::Result<Option<GooglePlace>> {
+        load_place(ctx, Some(id.to_string())).await
+    }
+
+    /// Driving routes between two waypoints. Endpoints given as places can be expanded
+    /// to their details in the same query.
+    async fn routes(
+        &self,
+        ctx: &Context<'_>,
+        origin: WaypointInput,
+        destination: WaypointInput,
+        departure_time: Option<String>,
+    ) -> async_graphql::Result<RoutePlan> {
+        compute_routes(ctx, origin, destination, departure_time)
+            .await
+            .map_err(|e| graphql_error(&e))
+    }
+}
+
+async fn search_places(
+    ctx: &Context<'_>,
+    text_query: String,
+) -> Result<Vec<GooglePlace>, AppError> {
+    enabled(ctx, |f| f.places, "Places")?;
+    let result = super::search_places(ctx.data_unchecked::<AppState>(), text_query).await?;
+
+    Ok(result.places.unwrap_or_default())
+}
+
+async fn compute_routes(
+    ctx: &Context<'_>,
+    origin: WaypointInput,
+    destination: WaypointInput,
+    departure_time: Option<String>,
+) -> Result<RoutePlan, AppError> {
+    enabled(ctx, |f| f.routes, "Routes")?;
+    let result = super::compute_routes(
+        ctx.data_unchecked::<AppState>(),
+        origin.to_waypoint()?,
+        destination.to_waypoint()?,
+        departure_time,
+    )
+    .await?;
+
+    Ok(RoutePlan {
+        routes: result.routes,
+        origin_place_id: origin.place_id.map(|id| id.to_string()),
+        destination_place_id: destination.place_id.map(|id| id.to_string()),
+    })
+}
+
+/// Runs a query with a fresh place loader, so batching and deduplication are per request.
+pub async fn execute(State(s): State<AppState>, req: GraphQLRequest) -> GraphQLResponse {
+    let loader = DataLoader::new(PlaceLoader(s.clone()), |work| {
+        tokio::spawn(in_current_environment(work))
+    });
+    let schema = s.graphql.clone();
+
+    schema
+        .execute(req.into_inner().data(s).data(loader))
+        .await
+        .into()
+}
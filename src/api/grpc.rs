@@ -0,0 +1,251 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use tonic::{metadata::MetadataMap, transport::Server, Code, Request, Response, Status};
+use validator::Validate;
+
+use crate::{
+    error::AppError,
+    middleware::{Authenticator, RateLimiter},
+    AppState,
+};
+
+use super::{fetch_place, waypoint, GooglePlace, Location};
+
+pub mod proto {
+    tonic::include_proto!("multimap.v1");
+}
+
+use proto::{
+    compute_route_server::{ComputeRoute, ComputeRouteServer},
+    place_search_server::{PlaceSearch, PlaceSearchServer},
+    waypoint::Waypoint as WaypointKind,
+    ComputeRoutesRequest, ComputeRoutesResponse, GetPlaceRequest, LatLng, Place, Route,
+    SearchTextRequest, SearchTextResponse, Waypoint,
+};
+
+impl From<AppError> for Status {
+    fn from(e: AppError) -> Self {
+        let code = match &e {
+            AppError::Validation(_) | AppError::InvalidFields(_) => Code::InvalidArgument,
+            AppError::NotFound(_) => Code::NotFound,
+            AppError::Timeout => Code::DeadlineExceeded,
+            AppError::RateLimited { .. } => Code::ResourceExhausted,
+            AppError::Overloaded { .. } | AppError::Unavailable | AppError::UpstreamError(_) => {
+                Code::Unavailable
+            }
+            AppError::Unauthorized => Code::Unauthenticated,
+            AppError::Forbidden => Code::PermissionDenied,
+            AppError::PreconditionFailed(_) => Code::FailedPrecondition,
+            AppError::ParseError(_) | AppError::Database(_) => Code::Internal,
+        };
+
+        Status::new(code, e.message())
+    }
+}
+
+impl From<GooglePlace> for Place {
+    fn from(place: GooglePlace) -> Self {
+        Place {
+            id: place.id,
+            display_name: place.display_name.text,
+            language_code: place.display_name.language_code.unwrap_or_default(),
+            formatted_address: place.formatted_address,
+            price_level: place.price_level.unwrap_or_default(),
+            location: Some(LatLng {
+                latitude: place.location.latitude.into(),
+                longitude: place.location.longitude.into(),
+            }),
+        }
+    }
+}
+
+// The credentials of the HTTP headers, metadata keys are lowercase
+const AUTHORIZATION_METADATA: &str = "authorization";
+const API_KEY_METADATA: &str = "x-api-key";
+
+fn to_waypoint(waypoint_field: &str, w: Option<Waypoint>) -> Result<serde_json::Value, AppError> {
+    match w.and_then(|w| w.waypoint) {
+        Some(WaypointKind::PlaceId(id)) => Ok(serde_json::json!({ "placeId": id })),
+        Some(WaypointKind::Location(l)) => {
+            let location = Location {
+                latitude: l.latitude as f32,
+                longitude: l.longitude as f32,
+                geohash: None,
+                plus_code: None,
+            };
+            location.validate()?;
+            Ok(waypoint(location.latitude, location.longitude))
+        }
+        None => Err(AppError::Validation(format!(
+            "{} is required",
+            waypoint_field
+        ))),
+    }
+}
+
+/// Serves both gRPC services over the HTTP handlers' state, so both interfaces share the
+/// key pool, circuit breakers, retries and caches, as well as the credentials, quotas and
+/// rate limits.
+#[derive(Clone)]
+pub struct GrpcService {
+    state: AppState,
+    places_enabled: bool,
+    routes_enabled: bool,
+    auth: Option<Arc<Authenticator>>,
+    rate_limiter: Option<Arc<RateLimiter<IpAddr>>>,
+}
+
+impl GrpcService {
+    /// Applies the API's rate limit, authentication and quota to a call, in that order as
+    /// on HTTP.
+    async fn admit(&self, metadata: &MetadataMap, peer: Option<SocketAddr>) -> Result<(), Status> {
+        if let (Some(limiter), Some(peer)) = (&self.rate_limiter, peer) {
+            limiter
+                .check(peer.ip())
+                .map_err(|retry_after| AppError::RateLimited { retry_after })?;
+        }
+        let Some(auth) = &self.auth else {
+            return Ok(());
+        };
+
+        let value = |key: &str| metadata.get(key).and_then(|v| v.to_str().ok());
+        let bearer = value(AUTHORIZATION_METADATA).and_then(|v| v.strip_prefix("Bearer "));
+        let identity = auth
+            .identify_credentials(bearer, value(API_KEY_METADATA))
+            .await?;
+        if let Some(quotas) = &self.state.quotas {
+            match quotas.consume(&identity.0).await {
+                Ok(()) => {}
+                // As on HTTP, an unreachable database doesn't take the API down
+                Err(AppError::Database(e)) => {
+                    tracing::warn!("quota check failed, allowing request: {}", e);
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn places(&self) -> Result<&AppState, Status> {
+        if self.places_enabled {
+            Ok(&self.state)
+        } else {
+            Err(Status::unimplemented("Places are disabled"))
+        }
+    }
+
+    fn routes(&self) -> Result<&AppState, Status> {
+        if self.routes_enabled {
+            Ok(&self.state)
+        } else {
+            Err(Status::unimplemented("Routes are disabled"))
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl PlaceSearch for GrpcService {
+    async fn search_text(
+        &self,
+        request: Request<SearchTextRequest>,
+    ) -> Result<Response<SearchTextResponse>, Status> {
+        let s = self.places()?;
+        self.admit(request.metadata(), request.remote_addr())
+            .await?;
+        let result = super::search_places(s, request.into_inner().text_query).await?;
+
+        Ok(Response::new(SearchTextResponse {
+            places: result
+                .places
+                .unwrap_or_default()
+                .into_iter()
+                .map(Place::from)
+                .collect(),
+        }))
+    }
+
+    async fn get_place(
+        &self,
+        request: Request<GetPlaceRequest>,
+    ) -> Result<Response<Place>, Status> {
+        let s = self.places()?;
+        self.admit(request.metadata(), request.remote_addr())
+            .await?;
+        let place = fetch_place(s, &request.into_inner().id)
+            .await?
+            .ok_or_else(|| Status::not_found("Place not found"))?;
+
+        Ok(Response::new(place.into()))
+    }
+}
+
+#[tonic::async_trait]
+impl ComputeRoute for GrpcService {
+    async fn compute_routes(
+        &self,
+        request: Request<ComputeRoutesRequest>,
+    ) -> Result<Response<ComputeRoutesResponse>, Status> {
+        let s = self.routes()?;
+        self.admit(request.metadata(), request.remote_addr())
+            .await?;
+        let request = request.into_inner();
+        let result = super::compute_routes(
+            s,
+            to_waypoint("origin", request.origin)?,
+            to_waypoint("destination", request.destination)?,
+            Some(request.departure_time).filter(|t| !t.is_empty()),
+        )
+        .await?;
+
+        Ok(Response::new(ComputeRoutesResponse {
+            routes: result
+                .routes
+                .into_iter()
+                .map(|r| Route {
+                    distance_meters: r.distance_meters.into(),
+                    duration: r.duration,
+                    encoded_polyline: r.polyline.encoded_polyline,
+                })
+                .collect(),
+        }))
+    }
+}
+
+/// Serves gRPC on its own address until shutdown. Calls carry the HTTP credentials as
+/// `authorization` or `x-api-key` metadata.
+pub fn spawn(
+    addr: SocketAddr,
+    state: AppState,
+    places_enabled: bool,
+    routes_enabled: bool,
+    timeout: Duration,
+    auth: Option<Arc<Authenticator>>,
+    rate_limiter: Option<Arc<RateLimiter<IpAddr>>>,
+) {
+    let service = GrpcService {
+        state,
+        places_enabled,
+        routes_enabled,
+        auth,
+        rate_limiter,
+    };
+
+    tokio::spawn(async move {
+        tracing::debug!("gRPC listening on {}", addr);
+        let served = Server::builder()
+            .timeout(timeout)
+            .add_service(PlaceSearchServer::new(service.clone()))
+            .add_service(ComputeRouteServer::new(service))
+            .serve_with_shutdown(addr, crate::shutdown_signal())
+            .await;
+        if let Err(e) = served {
+            tracing::error!("gRPC server failed: {}", e);
+            std::process::exit(1);
+        }
+    });
+}
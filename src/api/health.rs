@@ -0,0 +1,165 @@
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+use serde_json::json;
+use tokio::sync::Mutex;
+
+use crate::{db, AppState};
+
+use super::{CONTENT_TYPE, GOOGLE_API_KEY_HEADER, GOOGLE_FIELD_MASK_HEADER, JSON_TYPE};
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+// An empty text query is rejected with 400 before billing, but only once the key is accepted
+const PROBE_FIELD_MASK: &str = "places.id";
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Check {
+    status: &'static str,
+    latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Check {
+    fn is_up(&self) -> bool {
+        self.status == "up"
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadinessReport {
+    status: &'static str,
+    checks: BTreeMap<&'static str, Check>,
+}
+
+/// Dependencies checked by `/readyz`. The Google probe is cached so frequent probes
+/// don't turn into a steady stream of upstream calls.
+pub struct Readiness {
+    google_ttl: Duration,
+    check_google: bool,
+    check_redis: bool,
+    google: Mutex<Option<(Instant, Check)>>,
+}
+
+impl Readiness {
+    pub fn new(google_ttl: Duration, check_google: bool, check_redis: bool) -> Self {
+        Readiness {
+            google_ttl,
+            check_google,
+            check_redis,
+            google: Mutex::new(None),
+        }
+    }
+
+    // Holding the lock across the probe makes concurrent callers share one upstream call
+    async fn google(&self, s: &AppState) -> Check {
+        let mut cached = self.google.lock().await;
+        if let Some((at, check)) = cached.as_ref() {
+            if at.elapsed() < self.google_ttl {
+                return check.clone();
+            }
+        }
+
+        let check = timed(probe_google(s)).await;
+        *cached = Some((Instant::now(), check.clone()));
+        check
+    }
+}
+
+async fn timed<F>(check: F) -> Check
+where
+    F: Future<Output = Result<(), String>>,
+{
+    let started = Instant::now();
+    let result = tokio::time::timeout(CHECK_TIMEOUT, check)
+        .await
+        .unwrap_or_else(|_| Err(format!("timed out after {:?}", CHECK_TIMEOUT)));
+
+    Check {
+        status: if result.is_ok() { "up" } else { "down" },
+        latency_ms: started.elapsed().as_millis() as u64,
+        error: result.err(),
+    }
+}
+
+async fn probe_google(s: &AppState) -> Result<(), String> {
+    let key = s.google_keys.pick().ok_or("no usable Google key")?;
+    let response = s
+        .client_reqwest
+        .post(&s.urls.text_search)
+        .json(&json!({}))
+        .header(GOOGLE_FIELD_MASK_HEADER, PROBE_FIELD_MASK)
+        .header(CONTENT_TYPE, JSON_TYPE)
+        .header(GOOGLE_API_KEY_HEADER, key.value())
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = response.status();
+    let body = response.bytes().await.map_err(|e| e.to_string())?;
+
+    // The pool learns about invalid or rate limited keys from the probe too
+    if s.google_keys.record(&key, status, &body) {
+        return Err(format!("Google key rejected with {}", status));
+    }
+    if status.is_success() || status == reqwest::StatusCode::BAD_REQUEST {
+        Ok(())
+    } else {
+        Err(format!("Google returned {}", status))
+    }
+}
+
+async fn ping_redis(s: &AppState) -> Result<(), String> {
+    // An unreachable Redis at startup leaves the caches disabled
+    let cache = s
+        .cache
+        .as_ref()
+        .or(s.stale_cache.as_ref())
+        .ok_or("not connected")?;
+
+    cache.ping().await
+}
+
+/// Up as long as the process can answer.
+pub async fn livez() -> (StatusCode, &'static str) {
+    (StatusCode::OK, "OK")
+}
+
+/// Ready when every configured dependency answers, 503 otherwise.
+pub async fn readyz(State(s): State<AppState>) -> (StatusCode, Json<ReadinessReport>) {
+    let readiness = &s.readiness;
+    let mut checks = BTreeMap::new();
+
+    if readiness.check_google {
+        checks.insert("google", readiness.google(&s).await);
+    }
+    if let Some(pool) = &s.db {
+        checks.insert(
+            "database",
+            timed(async { db::ping(pool).await.map_err(|e| e.to_string()) }).await,
+        );
+    }
+    if readiness.check_redis {
+        checks.insert("redis", timed(ping_redis(&s)).await);
+    }
+
+    let ready = checks.values().all(Check::is_up);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status,
+        Json(ReadinessReport {
+            status: if ready { "ready" } else { "not_ready" },
+            checks,
+        }),
+    )
+}
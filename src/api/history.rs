@@ -0,0 +1,92 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    db::history::{self, HistoryEntry},
+    error::AppError,
+    identity::Identity,
+    AppState,
+};
+
+use super::database;
+
+const DEFAULT_PAGE_SIZE: i64 = 20;
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct HistoryQuery {
+    #[validate(range(min = 1, max = 100))]
+    limit: Option<i64>,
+    #[validate(range(min = 0))]
+    offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryPage {
+    items: Vec<HistoryEntry>,
+    limit: i64,
+    offset: i64,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct ChoiceRequest {
+    #[validate(length(min = 1, max = 256))]
+    place_id: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteHistoryResponse {
+    removed: u64,
+}
+
+pub async fn list_history(
+    State(s): State<AppState>,
+    identity: Identity,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<HistoryPage>, AppError> {
+    query.validate()?;
+    let pool = database(&s)?;
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    let offset = query.offset.unwrap_or(0);
+
+    let items = history::list(pool, &identity.0, limit, offset).await?;
+
+    Ok(Json(HistoryPage {
+        items,
+        limit,
+        offset,
+    }))
+}
+
+pub async fn record_choice(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+    Json(body): Json<ChoiceRequest>,
+) -> Result<StatusCode, AppError> {
+    body.validate()?;
+    let pool = database(&s)?;
+
+    if history::set_choice(pool, &identity.0, id, &body.place_id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound("History entry not found".into()))
+    }
+}
+
+pub async fn delete_history(
+    State(s): State<AppState>,
+    identity: Identity,
+) -> Result<Json<DeleteHistoryResponse>, AppError> {
+    let pool = database(&s)?;
+
+    let removed = history::delete_all(pool, &identity.0).await?;
+
+    Ok(Json(DeleteHistoryResponse { removed }))
+}
@@ -0,0 +1,196 @@
+use axum::{extract::State, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use validator::Validate;
+
+use crate::{cache, db::trips::Coordinate, error::AppError, geo, upstream, usage, AppState};
+
+use super::{
+    cached, duration_seconds, google_provider, store, waypoint, TravelMode, CONTENT_TYPE,
+    GOOGLE_API_KEY_HEADER, GOOGLE_FIELD_MASK_HEADER, JSON_TYPE,
+};
+
+const ROUTE_MATRIX_FIELD_MASK: &str = "originIndex,destinationIndex,duration,condition";
+const ROUTE_EXISTS: &str = "ROUTE_EXISTS";
+// 16 directions of 6 samples each keep one matrix under Google's 100 element limit
+const BEARINGS: usize = 16;
+const RINGS: usize = 6;
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct IsochroneRequest {
+    #[validate]
+    origin: Coordinate,
+    #[validate(range(min = 1, max = 60, message = "must be within [1, 60]"))]
+    minutes: u32,
+    /// Driving when left out
+    travel_mode: Option<TravelMode>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IsochroneProperties {
+    minutes: u32,
+    travel_mode: TravelMode,
+    /// The outline joins sampled points, it isn't the exact reachable area
+    approximate: bool,
+}
+
+/// GeoJSON feature holding the reachable area as a polygon.
+#[derive(Debug, Serialize)]
+pub struct Isochrone {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    geometry: Value,
+    properties: IsochroneProperties,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct MatrixElement {
+    // Google leaves out zero values, so the first origin and destination have no index
+    #[serde(default)]
+    pub(super) origin_index: usize,
+    #[serde(default)]
+    pub(super) destination_index: usize,
+    duration: Option<String>,
+    condition: Option<String>,
+}
+
+impl MatrixElement {
+    /// Travel time, `None` when there's no route.
+    pub(super) fn seconds(&self) -> Option<f64> {
+        if self.condition.as_deref() != Some(ROUTE_EXISTS) {
+            return None;
+        }
+
+        self.duration.as_deref().and_then(duration_seconds)
+    }
+
+    fn reachable_within(&self, seconds: f64) -> bool {
+        self.seconds().is_some_and(|d| d <= seconds)
+    }
+}
+
+// Generous top speeds, so the outer samples are out of reach
+fn top_speed_kmh(mode: TravelMode) -> f64 {
+    match mode {
+        TravelMode::Drive => 100.0,
+        TravelMode::TwoWheeler => 80.0,
+        TravelMode::Transit => 60.0,
+        TravelMode::Bicycle => 25.0,
+        TravelMode::Walk => 6.0,
+    }
+}
+
+/// One computeRouteMatrix call through the cache. Google answers with one element per
+/// origin and destination pair, each billed on its own.
+pub(super) async fn fetch_matrix(
+    s: &AppState,
+    req: &Value,
+) -> Result<Vec<MatrixElement>, AppError> {
+    let cache_key = cache::routes_key(req, google_provider());
+    if let Some(elements) = cached::<Vec<MatrixElement>>(s, &cache_key).await {
+        return Ok(elements);
+    }
+
+    let (status, body) = upstream::send_with_keys(
+        s.keys(),
+        &s.routes_breaker,
+        &s.retry_policy,
+        &s.routes_metrics,
+        |key| {
+            s.client_reqwest
+                .post(&s.urls.route_matrix)
+                .json(req)
+                .header(GOOGLE_FIELD_MASK_HEADER, ROUTE_MATRIX_FIELD_MASK)
+                .header(CONTENT_TYPE, JSON_TYPE)
+                .header(GOOGLE_API_KEY_HEADER, key)
+        },
+    )
+    .await
+    .inspect_err(
+        |e| tracing::error!(error = %e, provider = "google-routes", "upstream request failed"),
+    )?;
+
+    if !status.is_success() {
+        return Err(upstream::google_error::translate(
+            "google-routes",
+            status,
+            &body,
+        ));
+    }
+
+    let elements = serde_json::from_slice::<Vec<MatrixElement>>(&body).map_err(|e| {
+        tracing::error!(error = %e, provider = "google-routes", "failed to parse upstream response");
+        AppError::ParseError(e.to_string())
+    })?;
+    for _ in &elements {
+        s.usage.record(usage::ROUTE_MATRIX_ELEMENT);
+    }
+    store(s, &cache_key, &elements).await;
+
+    Ok(elements)
+}
+
+/// Area reachable from a point within some minutes. Travel times are computed to points
+/// on rays around the origin, the outline joins the farthest reachable point of each ray.
+pub async fn isochrone(
+    State(s): State<AppState>,
+    Json(body): Json<IsochroneRequest>,
+) -> Result<Json<Isochrone>, AppError> {
+    body.validate()?;
+
+    let origin = body.origin;
+    let travel_mode = body.travel_mode.unwrap_or(TravelMode::Drive);
+    let limit = f64::from(body.minutes) * 60.0;
+    let radius = top_speed_kmh(travel_mode) / 3.6 * limit;
+
+    // Ray by ray, from the origin outwards
+    let samples: Vec<Coordinate> = (0..BEARINGS)
+        .flat_map(|b| {
+            let bearing = b as f64 * 360.0 / BEARINGS as f64;
+            (1..=RINGS)
+                .map(move |r| geo::destination(origin, bearing, radius * r as f64 / RINGS as f64))
+        })
+        .collect();
+
+    let req = json!({
+        "origins": [{ "waypoint": waypoint(origin.latitude, origin.longitude) }],
+        "destinations": samples
+            .iter()
+            .map(|c| json!({ "waypoint": waypoint(c.latitude, c.longitude) }))
+            .collect::<Vec<_>>(),
+        "travelMode": travel_mode.as_str(),
+    });
+    let elements = fetch_matrix(&s, &req).await?;
+
+    let mut reachable = vec![false; samples.len()];
+    for element in &elements {
+        if element.destination_index < samples.len() {
+            reachable[element.destination_index] = element.reachable_within(limit);
+        }
+    }
+
+    let mut outline: Vec<[f64; 2]> = (0..BEARINGS)
+        .map(|b| {
+            let ray = b * RINGS..(b + 1) * RINGS;
+            let farthest = ray
+                .rev()
+                .find(|&i| reachable[i])
+                .map_or(origin, |i| samples[i]);
+            [farthest.longitude, farthest.latitude]
+        })
+        .collect();
+    outline.push(outline[0]);
+
+    Ok(Json(Isochrone {
+        kind: "Feature",
+        geometry: json!({ "type": "Polygon", "coordinates": [outline] }),
+        properties: IsochroneProperties {
+            minutes: body.minutes,
+            travel_mode,
+            approximate: true,
+        },
+    }))
+}
@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
+    Json,
+};
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    error::AppError,
+    identity::Identity,
+    job_store::{Job, JobEvent, JobSnapshot},
+    middleware::in_current_environment,
+    AppState,
+};
+
+use super::{
+    batch::{self, PlacesBatchRequest, RoutesBatchRequest},
+    version::ApiVersion,
+};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobCreated {
+    id: Uuid,
+    total: usize,
+    events_url: String,
+}
+
+fn created(job: &Job, version: ApiVersion) -> (StatusCode, Json<JobCreated>) {
+    (
+        StatusCode::ACCEPTED,
+        Json(JobCreated {
+            id: job.id,
+            total: job.total,
+            events_url: format!("{}/jobs/{}/events", version.prefix(), job.id),
+        }),
+    )
+}
+
+/// Starts a batch of place searches in the background.
+pub async fn create_places_job(
+    State(s): State<AppState>,
+    identity: Option<Identity>,
+    version: ApiVersion,
+    Json(body): Json<PlacesBatchRequest>,
+) -> Result<(StatusCode, Json<JobCreated>), AppError> {
+    body.validate()?;
+    s.batch.check_size(body.queries.len())?;
+
+    let job = s
+        .jobs
+        .create("places", identity.map(|i| i.0), body.queries.len())
+        .await;
+    let runner = job.clone();
+    tokio::spawn(in_current_environment(async move {
+        let concurrency = s.batch.concurrency;
+        batch::run_batch(
+            body.queries,
+            concurrency,
+            |query| batch::search_item(s.clone(), query),
+            |index, item| runner.record(index, item),
+        )
+        .await;
+    }));
+
+    Ok(created(&job, version))
+}
+
+/// Starts a batch of route computations in the background.
+pub async fn create_routes_job(
+    State(s): State<AppState>,
+    identity: Option<Identity>,
+    version: ApiVersion,
+    Json(body): Json<RoutesBatchRequest>,
+) -> Result<(StatusCode, Json<JobCreated>), AppError> {
+    body.validate()?;
+    s.batch.check_size(body.pairs.len())?;
+
+    let job = s
+        .jobs
+        .create("routes", identity.map(|i| i.0), body.pairs.len())
+        .await;
+    let runner = job.clone();
+    tokio::spawn(in_current_environment(async move {
+        let concurrency = s.batch.concurrency;
+        batch::run_batch(
+            body.pairs,
+            concurrency,
+            |pair| batch::route_item(s.clone(), pair),
+            |index, item| runner.record(index, item),
+        )
+        .await;
+    }));
+
+    Ok(created(&job, version))
+}
+
+async fn find_job(
+    s: &AppState,
+    identity: Option<Identity>,
+    id: Uuid,
+) -> Result<Arc<Job>, AppError> {
+    s.jobs
+        .get(id, identity.as_ref().map(|i| i.0.as_str()))
+        .await
+        .ok_or_else(|| AppError::NotFound("Job not found".into()))
+}
+
+pub async fn get_job(
+    State(s): State<AppState>,
+    identity: Option<Identity>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<JobSnapshot>, AppError> {
+    Ok(Json(find_job(&s, identity, id).await?.snapshot()))
+}
+
+/// Streams the job as server-sent events: an `item` event per completed item, then `done`.
+/// Subscribing late replays what already happened first.
+pub async fn job_events(
+    State(s): State<AppState>,
+    identity: Option<Identity>,
+    Path(id): Path<Uuid>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, AppError> {
+    let job = find_job(&s, identity, id).await?;
+    let (past, live) = job.subscribe();
+    // A finished job has nothing live, a receiver without sender ends right away
+    let live = live.unwrap_or_else(|| broadcast::channel(1).1);
+
+    let events = tokio_stream::iter(past)
+        .chain(BroadcastStream::new(live).filter_map(Result::ok))
+        .map(|event: JobEvent| Event::default().event(event.name()).json_data(&event));
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
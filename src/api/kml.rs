@@ -0,0 +1,130 @@
+use std::fmt::Write;
+
+use axum::{
+    extract::{Path, State},
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
+use uuid::Uuid;
+
+use crate::{
+    db::lists::{self, ListedPlace},
+    error::AppError,
+    identity::Identity,
+    AppState,
+};
+
+use super::database;
+
+const KML_CONTENT_TYPE: &str = "application/vnd.google-earth.kml+xml";
+const PLACE_STYLE: &str = "place";
+const PLACE_ICON: &str = "https://maps.google.com/mapfiles/kml/paddle/red-circle.png";
+
+pub(super) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+// Notes first, then the list's tags for the place
+fn description(item: &ListedPlace) -> String {
+    let mut parts = Vec::new();
+    if let Some(notes) = item.place.notes.as_deref().filter(|n| !n.is_empty()) {
+        parts.push(notes.to_owned());
+    }
+    if !item.tags.is_empty() {
+        parts.push(format!("Tags: {}", item.tags.join(", ")));
+    }
+
+    parts.join("\n\n")
+}
+
+fn document(name: &str, items: &[ListedPlace]) -> String {
+    let mut kml = String::new();
+    // Writing to a String can't fail
+    let _ = write!(
+        kml,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <kml xmlns=\"http://www.opengis.net/kml/2.2\">\n\
+         <Document>\n\
+         <name>{}</name>\n\
+         <Style id=\"{}\"><IconStyle><scale>1.1</scale><Icon><href>{}</href></Icon></IconStyle></Style>\n",
+        escape(name),
+        PLACE_STYLE,
+        PLACE_ICON
+    );
+
+    for item in items {
+        let place = &item.place;
+        let _ = write!(
+            kml,
+            "<Placemark>\n\
+             <name>{}</name>\n\
+             <description>{}</description>\n\
+             <styleUrl>#{}</styleUrl>\n\
+             <ExtendedData><Data name=\"placeId\"><value>{}</value></Data></ExtendedData>\n\
+             <Point><coordinates>{},{},0</coordinates></Point>\n\
+             </Placemark>\n",
+            escape(&place.name),
+            escape(&description(item)),
+            PLACE_STYLE,
+            escape(&place.place_id),
+            place.longitude,
+            place.latitude
+        );
+    }
+    kml.push_str("</Document>\n</kml>\n");
+
+    kml
+}
+
+// Header safe file name derived from the list name
+fn file_name(name: &str) -> String {
+    let stem: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    format!("{}.kml", stem)
+}
+
+/// The list as a KML document, for import into Google Earth or My Maps.
+pub async fn export_list(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+) -> Result<Response, AppError> {
+    let pool = database(&s)?;
+    let list = lists::get(pool, id, &identity.0)
+        .await?
+        .ok_or_else(|| AppError::NotFound("List not found".into()))?;
+    let items = lists::items(pool, id).await?;
+
+    Ok((
+        [
+            (CONTENT_TYPE, KML_CONTENT_TYPE.to_owned()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", file_name(&list.name)),
+            ),
+        ],
+        document(&list.name, &items),
+    )
+        .into_response())
+}
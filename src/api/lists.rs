@@ -0,0 +1,338 @@
+use axum::{
+    extract::{Path, State},
+    http::{
+        header::{ETAG, IF_MATCH},
+        HeaderMap, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    db::{
+        lists::{self, Edit, ListMember, ListedPlace, PlaceList, Role},
+        saved_places,
+    },
+    error::AppError,
+    identity::Identity,
+    AppState,
+};
+
+use super::database;
+
+const MAX_TAGS: usize = 20;
+const MAX_TAG_LENGTH: usize = 50;
+const INVITE_TOKEN_LENGTH: usize = 32;
+const DEFAULT_INVITE_HOURS: i64 = 7 * 24;
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreateListRequest {
+    #[validate(length(min = 1, max = 256))]
+    name: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ListItemRequest {
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateInviteRequest {
+    /// `viewer` when left out
+    role: Option<Role>,
+    /// A week when left out
+    #[validate(range(min = 1, max = 720))]
+    expires_in_hours: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InviteResponse {
+    /// Shown once, whoever accepts it joins the list
+    token: String,
+    role: Role,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateMemberRequest {
+    role: Role,
+}
+
+// Tags are matched case-insensitively, so they're stored trimmed, lowercased and unique
+fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>, AppError> {
+    let mut normalized: Vec<String> = tags
+        .into_iter()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect();
+    normalized.sort();
+    normalized.dedup();
+
+    if normalized.len() > MAX_TAGS || normalized.iter().any(|t| t.len() > MAX_TAG_LENGTH) {
+        return Err(AppError::Validation(format!(
+            "At most {} tags of up to {} characters are allowed",
+            MAX_TAGS, MAX_TAG_LENGTH
+        )));
+    }
+
+    Ok(normalized)
+}
+
+// Lists someone has no access to don't exist as far as they can tell
+async fn ensure_role(
+    s: &AppState,
+    id: Uuid,
+    identity: &Identity,
+    needed: Role,
+) -> Result<PlaceList, AppError> {
+    let list = lists::get(database(s)?, id, &identity.0)
+        .await?
+        .ok_or_else(|| AppError::NotFound("List not found".into()))?;
+
+    match list.role() {
+        Some(role) if role >= needed => Ok(list),
+        _ => Err(AppError::Forbidden),
+    }
+}
+
+fn version_tag(version: i64) -> String {
+    format!("\"{}\"", version)
+}
+
+// The version from `If-Match`, edits without one always apply
+fn expected_version(headers: &HeaderMap) -> Result<Option<i64>, AppError> {
+    let Some(value) = headers.get(IF_MATCH) else {
+        return Ok(None);
+    };
+    let tag = value.to_str().unwrap_or_default().trim();
+    if tag == "*" {
+        return Ok(None);
+    }
+
+    tag.trim_start_matches("W/")
+        .trim_matches('"')
+        .parse()
+        .map(Some)
+        .map_err(|_| AppError::Validation("If-Match must be an ETag of this list".into()))
+}
+
+fn edited(edit: Edit, missing: &str) -> Result<Response, AppError> {
+    match edit {
+        Edit::Applied { version } => {
+            Ok((StatusCode::NO_CONTENT, [(ETAG, version_tag(version))]).into_response())
+        }
+        Edit::Stale => Err(AppError::PreconditionFailed(
+            "The list changed since it was read, fetch it again".into(),
+        )),
+        Edit::Missing => Err(AppError::NotFound(missing.into())),
+    }
+}
+
+fn invite_hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+pub async fn create_list(
+    State(s): State<AppState>,
+    identity: Identity,
+    Json(body): Json<CreateListRequest>,
+) -> Result<(StatusCode, Json<PlaceList>), AppError> {
+    body.validate()?;
+    let pool = database(&s)?;
+
+    let list = lists::create(pool, &identity.0, body.name.trim())
+        .await?
+        .ok_or_else(|| AppError::Validation("A list with this name already exists".into()))?;
+
+    Ok((StatusCode::CREATED, Json(list)))
+}
+
+pub async fn list_lists(
+    State(s): State<AppState>,
+    identity: Identity,
+) -> Result<Json<Vec<PlaceList>>, AppError> {
+    let pool = database(&s)?;
+
+    Ok(Json(lists::list(pool, &identity.0).await?))
+}
+
+/// Deleting a list removes it for every member too.
+pub async fn delete_list(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let pool = database(&s)?;
+
+    if lists::delete(pool, id, &identity.0).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound("List not found".into()))
+    }
+}
+
+/// The places in the list, tagged with the list version to send back in `If-Match`.
+pub async fn list_places(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+) -> Result<Response, AppError> {
+    let list = ensure_role(&s, id, &identity, Role::Viewer).await?;
+    let items = lists::items(database(&s)?, id).await?;
+
+    Ok(([(ETAG, version_tag(list.version))], Json(items)).into_response())
+}
+
+/// Editors add their own saved places. With `If-Match` the edit only applies to that
+/// version of the list, otherwise it is 412.
+pub async fn add_place(
+    State(s): State<AppState>,
+    identity: Identity,
+    headers: HeaderMap,
+    Path((id, place_id)): Path<(Uuid, Uuid)>,
+    body: Option<Json<ListItemRequest>>,
+) -> Result<Response, AppError> {
+    let tags = normalize_tags(body.map(|Json(b)| b).unwrap_or_default().tags)?;
+    let expected = expected_version(&headers)?;
+    ensure_role(&s, id, &identity, Role::Editor).await?;
+    let pool = database(&s)?;
+
+    if saved_places::get(pool, place_id, &identity.0)
+        .await?
+        .is_none()
+    {
+        return Err(AppError::NotFound("Saved place not found".into()));
+    }
+    let edit = lists::upsert_item(pool, id, place_id, &tags, &identity.0, expected).await?;
+
+    edited(edit, "List not found")
+}
+
+pub async fn remove_place(
+    State(s): State<AppState>,
+    identity: Identity,
+    headers: HeaderMap,
+    Path((id, place_id)): Path<(Uuid, Uuid)>,
+) -> Result<Response, AppError> {
+    let expected = expected_version(&headers)?;
+    ensure_role(&s, id, &identity, Role::Editor).await?;
+    let edit = lists::remove_item(database(&s)?, id, place_id, expected).await?;
+
+    edited(edit, "Place is not in this list")
+}
+
+pub async fn list_members(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Vec<ListMember>>, AppError> {
+    ensure_role(&s, id, &identity, Role::Viewer).await?;
+
+    Ok(Json(lists::members(database(&s)?, id).await?))
+}
+
+pub async fn update_member(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path((id, member)): Path<(Uuid, String)>,
+    Json(body): Json<UpdateMemberRequest>,
+) -> Result<Json<ListMember>, AppError> {
+    ensure_role(&s, id, &identity, Role::Owner).await?;
+    if body.role == Role::Owner {
+        return Err(AppError::Validation(
+            "Members can be viewers or editors".into(),
+        ));
+    }
+
+    lists::update_member(database(&s)?, id, &member, body.role)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound("Member not found".into()))
+}
+
+/// The owner removes anyone, members can remove themselves to leave the list.
+pub async fn remove_member(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path((id, member)): Path<(Uuid, String)>,
+) -> Result<StatusCode, AppError> {
+    let needed = if member == identity.0 {
+        Role::Viewer
+    } else {
+        Role::Owner
+    };
+    ensure_role(&s, id, &identity, needed).await?;
+
+    if lists::remove_member(database(&s)?, id, &member).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound("Member not found".into()))
+    }
+}
+
+/// A single use token the owner hands out, accepting it joins the list with `role`.
+pub async fn create_invite(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+    Json(body): Json<CreateInviteRequest>,
+) -> Result<(StatusCode, Json<InviteResponse>), AppError> {
+    body.validate()?;
+    let role = body.role.unwrap_or(Role::Viewer);
+    if role == Role::Owner {
+        return Err(AppError::Validation(
+            "Invites are for viewers or editors".into(),
+        ));
+    }
+    ensure_role(&s, id, &identity, Role::Owner).await?;
+
+    let token: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(INVITE_TOKEN_LENGTH)
+        .map(char::from)
+        .collect();
+    let expires_at =
+        Utc::now() + Duration::hours(body.expires_in_hours.unwrap_or(DEFAULT_INVITE_HOURS));
+    lists::create_invite(database(&s)?, &invite_hash(&token), id, role, expires_at).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        Json(InviteResponse {
+            token,
+            role,
+            expires_at,
+        }),
+    ))
+}
+
+pub async fn accept_invite(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(token): Path<String>,
+) -> Result<Json<PlaceList>, AppError> {
+    lists::accept_invite(database(&s)?, &invite_hash(&token), &identity.0)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound("Invite not found or expired".into()))
+}
+
+pub async fn places_by_tag(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(tag): Path<String>,
+) -> Result<Json<Vec<ListedPlace>>, AppError> {
+    let pool = database(&s)?;
+
+    Ok(Json(
+        lists::items_by_tag(pool, &identity.0, &tag.trim().to_lowercase()).await?,
+    ))
+}
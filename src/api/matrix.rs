@@ -0,0 +1,112 @@
+use axum::{
+    body::Body,
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use validator::Validate;
+
+use crate::{db::trips::Coordinate, error::AppError, upstream, usage, AppState};
+
+use super::{
+    validation, waypoint, TravelMode, CONTENT_TYPE, GOOGLE_API_KEY_HEADER,
+    GOOGLE_FIELD_MASK_HEADER, JSON_TYPE,
+};
+
+const MATRIX_FIELD_MASK: &str =
+    "originIndex,destinationIndex,status,condition,distanceMeters,duration,staticDuration";
+// Google's limits on origins times destinations
+const MAX_ELEMENTS: usize = 625;
+const MAX_TRANSIT_ELEMENTS: usize = 100;
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct MatrixRequest {
+    #[validate(length(min = 1, max = 50), custom = "validation::coordinates")]
+    origins: Vec<Coordinate>,
+    #[validate(length(min = 1, max = 625), custom = "validation::coordinates")]
+    destinations: Vec<Coordinate>,
+    /// Driving when left out
+    travel_mode: Option<TravelMode>,
+    /// Now when left out
+    #[validate(custom = "validation::rfc3339")]
+    departure_time: Option<String>,
+}
+
+fn matrix_body(body: &MatrixRequest, travel_mode: TravelMode) -> Value {
+    let endpoints = |coordinates: &[Coordinate]| -> Vec<Value> {
+        coordinates
+            .iter()
+            .map(|c| json!({ "waypoint": waypoint(c.latitude, c.longitude) }))
+            .collect()
+    };
+    let mut req = json!({
+        "origins": endpoints(&body.origins),
+        "destinations": endpoints(&body.destinations),
+        "travelMode": travel_mode.as_str(),
+    });
+    // TRAFFIC_AWARE_OPTIMAL would cap the matrix at 100 elements
+    if travel_mode.is_motorized() {
+        req["routingPreference"] = json!("TRAFFIC_AWARE");
+    }
+    if let Some(departure_time) = &body.departure_time {
+        req["departureTime"] = json!(departure_time);
+    }
+
+    req
+}
+
+/// Travel time and distance from every origin to every destination. Google's answer, a
+/// JSON array with one element per pair in the order they're computed, is streamed
+/// through as it arrives rather than buffered, so it isn't cached either.
+pub async fn route_matrix(
+    State(s): State<AppState>,
+    Json(body): Json<MatrixRequest>,
+) -> Result<Response, AppError> {
+    body.validate()?;
+    let travel_mode = body.travel_mode.unwrap_or(TravelMode::Drive);
+    let elements = body.origins.len() * body.destinations.len();
+    let max_elements = match travel_mode {
+        TravelMode::Transit => MAX_TRANSIT_ELEMENTS,
+        _ => MAX_ELEMENTS,
+    };
+    if elements > max_elements {
+        return Err(AppError::Validation(format!(
+            "At most {} origin and destination pairs per matrix",
+            max_elements
+        )));
+    }
+
+    let req = matrix_body(&body, travel_mode);
+    let response = upstream::stream_with_keys(
+        s.keys(),
+        &s.routes_breaker,
+        &s.retry_policy,
+        &s.routes_metrics,
+        |key| {
+            s.client_reqwest
+                .post(&s.urls.route_matrix)
+                .json(&req)
+                .header(GOOGLE_FIELD_MASK_HEADER, MATRIX_FIELD_MASK)
+                .header(CONTENT_TYPE, JSON_TYPE)
+                .header(GOOGLE_API_KEY_HEADER, key)
+        },
+    )
+    .await
+    .inspect_err(
+        |e| tracing::error!(error = %e, provider = "google-routes", "upstream request failed"),
+    )?;
+    // Every element is billed, whether or not the client reads it
+    for _ in 0..elements {
+        s.usage.record(usage::ROUTE_MATRIX_ELEMENT);
+    }
+
+    Ok((
+        [(header::CONTENT_TYPE, JSON_TYPE)],
+        Body::from_stream(response.bytes_stream()),
+    )
+        .into_response())
+}
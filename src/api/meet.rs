@@ -0,0 +1,209 @@
+use axum::{extract::State, Json};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use validator::Validate;
+
+use crate::{
+    cache::{self, CacheStatus},
+    db::trips::Coordinate,
+    error::AppError,
+    flags::Flag,
+    geo, AppState,
+};
+
+use super::{
+    cached, fetch_places, google_provider,
+    isochrone::{fetch_matrix, MatrixElement},
+    store, validation, waypoint, GooglePlace, GooglePlacesReponse, ResponseMeta, TravelMode,
+};
+
+// The centroid and two rings of 8 points around it. With up to 5 origins the candidate
+// matrix stays under Google's 100 element limit for transit
+const BEARINGS: usize = 8;
+const RINGS: usize = 2;
+const DEFAULT_VENUES: u8 = 5;
+// Venues are searched around the midpoint, within a third of the group's spread
+const MIN_SEARCH_RADIUS_METERS: f64 = 500.0;
+const MAX_SEARCH_RADIUS_METERS: f64 = 50_000.0;
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct MeetRequest {
+    /// Where each participant sets off from
+    #[validate(length(min = 2, max = 5), custom = "validation::coordinates")]
+    origins: Vec<Coordinate>,
+    /// What to meet at, e.g. "coffee"
+    #[validate(length(max = 512), custom = "validation::not_blank")]
+    text_query: String,
+    /// Driving when left out
+    travel_mode: Option<TravelMode>,
+    #[validate(range(min = 1, max = 10))]
+    max_results: Option<u8>,
+}
+
+/// A venue with how long each participant takes to get there.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Venue {
+    place: GooglePlace,
+    /// In the order of `origins`, `null` for a participant with no route there
+    eta_seconds: Vec<Option<f64>>,
+    /// The longest of the trips, what the venues are ranked by
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_eta_seconds: Option<f64>,
+    /// Between the longest and the shortest trip
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spread_seconds: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MeetResponse {
+    /// The point whose longest trip is the shortest, the venues are searched around it
+    midpoint: Coordinate,
+    /// Fairest first
+    venues: Vec<Venue>,
+    meta: ResponseMeta,
+}
+
+fn endpoints(coordinates: &[Coordinate]) -> Vec<Value> {
+    coordinates
+        .iter()
+        .map(|c| json!({ "waypoint": waypoint(c.latitude, c.longitude) }))
+        .collect()
+}
+
+// Travel times from every origin to every destination, one row per destination
+async fn travel_times(
+    s: &AppState,
+    origins: &[Coordinate],
+    destinations: &[Coordinate],
+    travel_mode: TravelMode,
+) -> Result<Vec<Vec<Option<f64>>>, AppError> {
+    let req = json!({
+        "origins": endpoints(origins),
+        "destinations": endpoints(destinations),
+        "travelMode": travel_mode.as_str(),
+    });
+    let elements: Vec<MatrixElement> = fetch_matrix(s, &req).await?;
+
+    let mut times = vec![vec![None; origins.len()]; destinations.len()];
+    for element in &elements {
+        if let Some(row) = times.get_mut(element.destination_index) {
+            if let Some(time) = row.get_mut(element.origin_index) {
+                *time = element.seconds();
+            }
+        }
+    }
+
+    Ok(times)
+}
+
+// Longest and shortest trip, `None` when someone can't get there
+fn extremes(times: &[Option<f64>]) -> Option<(f64, f64)> {
+    times
+        .iter()
+        .try_fold((f64::MIN, f64::MAX), |(max, min), time| {
+            time.map(|t| (max.max(t), min.min(t)))
+        })
+}
+
+/// Suggests where a group should meet. A midpoint balancing everyone's travel time is
+/// picked among points around the origins, then venues matching the query near it are
+/// ranked by the longest trip to them.
+pub async fn meet(
+    State(s): State<AppState>,
+    Json(body): Json<MeetRequest>,
+) -> Result<Json<MeetResponse>, AppError> {
+    body.validate()?;
+    let travel_mode = body.travel_mode.unwrap_or(TravelMode::Drive);
+
+    let count = body.origins.len() as f64;
+    let centroid = Coordinate {
+        latitude: body.origins.iter().map(|o| o.latitude).sum::<f64>() / count,
+        longitude: body.origins.iter().map(|o| o.longitude).sum::<f64>() / count,
+    };
+    let spread = body
+        .origins
+        .iter()
+        .map(|o| geo::haversine(centroid, *o))
+        .fold(0.0, f64::max);
+
+    let mut candidates = vec![centroid];
+    candidates.extend((0..BEARINGS).flat_map(|b| {
+        let bearing = b as f64 * 360.0 / BEARINGS as f64;
+        (1..=RINGS).map(move |r| {
+            geo::destination(centroid, bearing, spread * r as f64 / (RINGS + 1) as f64)
+        })
+    }));
+    let times = travel_times(&s, &body.origins, &candidates, travel_mode).await?;
+    // Ties go to the smaller spread, then to the earlier candidate, the centroid first
+    let midpoint = candidates
+        .iter()
+        .zip(&times)
+        .filter_map(|(candidate, times)| Some((*candidate, extremes(times)?)))
+        .min_by(|(_, (max_a, min_a)), (_, (max_b, min_b))| {
+            max_a
+                .total_cmp(max_b)
+                .then((max_a - min_a).total_cmp(&(max_b - min_b)))
+        })
+        .map(|(candidate, _)| candidate)
+        .ok_or_else(|| AppError::NotFound("No point can be reached from every origin".into()))?;
+
+    let radius = (spread / 3.0).clamp(MIN_SEARCH_RADIUS_METERS, MAX_SEARCH_RADIUS_METERS);
+    let req = json!({
+        "textQuery": body.text_query,
+        "maxResultCount": body.max_results.unwrap_or(DEFAULT_VENUES),
+        "locationBias": {
+            "circle": {
+                "center": { "latitude": midpoint.latitude, "longitude": midpoint.longitude },
+                "radius": radius.round(),
+            },
+        },
+    });
+    let cache_key = cache::places_key(&req, google_provider());
+    let (found, cache_status) = match cached::<GooglePlacesReponse>(&s, &cache_key).await {
+        Some(found) => (found, CacheStatus::Hit),
+        None => {
+            let fan_out = s.flags.enabled(Flag::ProviderFanOut, None);
+            let found = fetch_places(&s, &req, fan_out).await?;
+            let cache_status = store(&s, &cache_key, &found).await;
+            (found, cache_status)
+        }
+    };
+    let places = found.places.unwrap_or_default();
+
+    let mut venues: Vec<Venue> = if places.is_empty() {
+        Vec::new()
+    } else {
+        let coordinates: Vec<Coordinate> = places.iter().map(GooglePlace::coordinate).collect();
+        let times = travel_times(&s, &body.origins, &coordinates, travel_mode).await?;
+        places
+            .into_iter()
+            .zip(times)
+            .map(|(place, eta_seconds)| {
+                let extremes = extremes(&eta_seconds);
+                Venue {
+                    place,
+                    max_eta_seconds: extremes.map(|(max, _)| max),
+                    spread_seconds: extremes.map(|(max, min)| max - min),
+                    eta_seconds,
+                }
+            })
+            .collect()
+    };
+    // Venues someone can't reach go last
+    venues.sort_by(|a, b| match (a.max_eta_seconds, b.max_eta_seconds) {
+        (Some(a_max), Some(b_max)) => a_max.total_cmp(&b_max).then(
+            a.spread_seconds
+                .unwrap_or(0.0)
+                .total_cmp(&b.spread_seconds.unwrap_or(0.0)),
+        ),
+        (a_max, b_max) => b_max.is_some().cmp(&a_max.is_some()),
+    });
+
+    Ok(Json(MeetResponse {
+        midpoint,
+        venues,
+        meta: ResponseMeta::new(cache_status),
+    }))
+}
@@ -0,0 +1,12 @@
+use axum::{extract::State, http::header, response::IntoResponse};
+
+use crate::AppState;
+
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+pub async fn prometheus(State(s): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)],
+        s.upstream_metrics.render(),
+    )
+}
@@ -0,0 +1,278 @@
+use std::{io, net::SocketAddr};
+
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::net::TcpListener;
+
+use crate::{db::trips::Coordinate, geo};
+
+use super::PRICE_LEVELS;
+
+// Mock places are scattered around here, in Mountain View
+const CENTER: Coordinate = Coordinate {
+    latitude: 37.419734,
+    longitude: -122.0827784,
+};
+const SCATTER_METERS: f64 = 3000.0;
+const ID_PREFIX: &str = "mock_";
+// Straight lines are shorter than roads
+const DETOUR_FACTOR: f64 = 1.3;
+// Name, types and price level index of each fixture
+const FIXTURES: [(&str, &[&str], usize); 8] = [
+    ("Blue Bottle Coffee", &["cafe", "food"], 1),
+    (
+        "Green Leaf Vegetarian",
+        &["restaurant", "vegetarian_restaurant"],
+        2,
+    ),
+    ("Golden Gate Pizza", &["restaurant", "pizza_restaurant"], 1),
+    (
+        "Harbor Seafood House",
+        &["restaurant", "seafood_restaurant"],
+        3,
+    ),
+    ("Corner Bakery", &["bakery", "cafe"], 1),
+    ("City Park", &["park", "tourist_attraction"], 0),
+    ("Central Library", &["library"], 0),
+    ("Summit Steakhouse", &["restaurant", "steak_house"], 4),
+];
+
+/// Serves fixed answers in the shape of Google's Places and Routes APIs on a loopback port,
+/// for running without network access or keys. The same request always gets the same
+/// answer: places come from a hash of the query, routes are straight lines between the
+/// waypoints at a typical speed of the travel mode.
+pub async fn serve() -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, 0)).await?;
+    let addr = listener.local_addr()?;
+    // At the paths of LOCAL_PATHS
+    let app = Router::new()
+        .route("/places/search", post(text_search))
+        .route("/places/details/:id", get(place_details))
+        .route("/routes", post(compute_routes))
+        .route("/routes/matrix", post(compute_route_matrix));
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            tracing::error!(error = %e, "mock provider stopped");
+        }
+    });
+
+    Ok(addr)
+}
+
+fn digest(value: &str) -> [u8; 32] {
+    Sha256::digest(value.as_bytes()).into()
+}
+
+// Google's error body, so errors are translated the same way
+fn invalid_argument(message: &str) -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        Json(json!({
+            "error": {
+                "code": 400,
+                "message": message,
+                "status": "INVALID_ARGUMENT",
+            }
+        })),
+    )
+        .into_response()
+}
+
+// Ids carry everything the place is made of, so details answer without any state
+fn place_id(fixture: usize, point: Coordinate) -> String {
+    format!(
+        "{}{}_{}_{}",
+        ID_PREFIX,
+        fixture,
+        (point.latitude * 1e6).round() as i64,
+        (point.longitude * 1e6).round() as i64
+    )
+}
+
+fn parse_place_id(id: &str) -> Option<(usize, Coordinate)> {
+    let mut parts = id.strip_prefix(ID_PREFIX)?.split('_');
+    let fixture = parts.next()?.parse().ok().filter(|f| *f < FIXTURES.len())?;
+    let latitude = parts.next()?.parse::<i64>().ok()? as f64 / 1e6;
+    let longitude = parts.next()?.parse::<i64>().ok()? as f64 / 1e6;
+
+    Some((
+        fixture,
+        Coordinate {
+            latitude,
+            longitude,
+        },
+    ))
+}
+
+fn place(fixture: usize, point: Coordinate, included_type: Option<&str>) -> Value {
+    let (name, types, price) = FIXTURES[fixture];
+    let mut types: Vec<&str> = types.to_vec();
+    if let Some(included) = included_type.filter(|t| !types.contains(t)) {
+        types.insert(0, included);
+    }
+    let seed = digest(&place_id(fixture, point));
+
+    json!({
+        "id": place_id(fixture, point),
+        "displayName": { "text": name, "languageCode": "en" },
+        "formattedAddress": format!("{} Castro St, Mountain View, CA 94041, USA", u16::from(seed[0]) * 4 + 1),
+        "location": { "latitude": point.latitude, "longitude": point.longitude },
+        "rating": 3.0 + f64::from(seed[1] % 21) / 10.0,
+        "priceLevel": PRICE_LEVELS[price],
+        "types": types,
+    })
+}
+
+async fn text_search(Json(body): Json<Value>) -> Response {
+    let Some(query) = body["textQuery"].as_str() else {
+        return invalid_argument("textQuery is required");
+    };
+    // Google sends the count as a number or a string
+    let count = body["maxResultCount"]
+        .as_u64()
+        .or_else(|| body["maxResultCount"].as_str()?.parse().ok())
+        .unwrap_or(20)
+        .clamp(1, 20) as usize;
+    let included_type = body["includedType"].as_str();
+
+    let seed = digest(&query.to_lowercase());
+    let places: Vec<Value> = (0..count)
+        .map(|i| {
+            let (a, b) = (seed[i % 32], seed[(i * 7 + 3) % 32]);
+            let bearing = f64::from(a) / 256.0 * 360.0;
+            let distance = f64::from(b) / 256.0 * SCATTER_METERS;
+            let point = geo::destination(CENTER, bearing, distance);
+            let fixture = (usize::from(a) + i) % FIXTURES.len();
+            place(fixture, point, included_type)
+        })
+        .collect();
+
+    Json(json!({ "places": places })).into_response()
+}
+
+async fn place_details(Path(id): Path<String>) -> Response {
+    match parse_place_id(&id) {
+        Some((fixture, point)) => Json(place(fixture, point, None)).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(json!({
+                "error": { "code": 404, "message": "Place not found", "status": "NOT_FOUND" }
+            })),
+        )
+            .into_response(),
+    }
+}
+
+// Coordinates, or the id of a mock place
+fn waypoint_point(waypoint: &Value) -> Option<Coordinate> {
+    let lat_lng = &waypoint["location"]["latLng"];
+    if let (Some(latitude), Some(longitude)) =
+        (lat_lng["latitude"].as_f64(), lat_lng["longitude"].as_f64())
+    {
+        return Some(Coordinate {
+            latitude,
+            longitude,
+        });
+    }
+
+    parse_place_id(waypoint["placeId"].as_str()?).map(|(_, point)| point)
+}
+
+fn meters_per_second(travel_mode: &str) -> f64 {
+    let km_per_hour = match travel_mode {
+        "WALK" => 5.0,
+        "BICYCLE" => 15.0,
+        "TRANSIT" => 25.0,
+        "TWO_WHEELER" => 35.0,
+        _ => 40.0,
+    };
+    km_per_hour / 3.6
+}
+
+fn travel(from: Coordinate, to: Coordinate, travel_mode: &str) -> (f64, u64) {
+    let meters = geo::haversine(from, to) * DETOUR_FACTOR;
+    (
+        meters,
+        (meters / meters_per_second(travel_mode)).round() as u64,
+    )
+}
+
+async fn compute_routes(Json(body): Json<Value>) -> Response {
+    let travel_mode = body["travelMode"].as_str().unwrap_or("DRIVE");
+    let intermediates = body["intermediates"]
+        .as_array()
+        .cloned()
+        .unwrap_or_default();
+    let mut path = vec![body["origin"].clone()];
+    path.extend(intermediates.iter().cloned());
+    path.push(body["destination"].clone());
+    let Some(path) = path.iter().map(waypoint_point).collect::<Option<Vec<_>>>() else {
+        return invalid_argument("Waypoints must be coordinates or mock place ids");
+    };
+
+    let legs: Vec<(f64, u64)> = path
+        .windows(2)
+        .map(|pair| travel(pair[0], pair[1], travel_mode))
+        .collect();
+    let distance: f64 = legs.iter().map(|(meters, _)| meters).sum();
+    let duration: u64 = legs.iter().map(|(_, seconds)| seconds).sum();
+    let mut route = json!({
+        "distanceMeters": distance.round() as u64,
+        "duration": format!("{}s", duration),
+        "staticDuration": format!("{}s", duration),
+        "polyline": { "encodedPolyline": geo::polyline::encode(&path) },
+        "legs": legs
+            .iter()
+            .map(|(meters, seconds)| json!({
+                "distanceMeters": meters.round() as u64,
+                "duration": format!("{}s", seconds),
+            }))
+            .collect::<Vec<_>>(),
+    });
+    if body["optimizeWaypointOrder"].as_bool() == Some(true) {
+        route["optimizedIntermediateWaypointIndex"] =
+            json!((0..intermediates.len()).collect::<Vec<_>>());
+    }
+
+    Json(json!({ "routes": [route] })).into_response()
+}
+
+async fn compute_route_matrix(Json(body): Json<Value>) -> Response {
+    let travel_mode = body["travelMode"].as_str().unwrap_or("DRIVE");
+    let endpoints = |key: &str| -> Option<Vec<Coordinate>> {
+        body[key]
+            .as_array()?
+            .iter()
+            .map(|endpoint| waypoint_point(&endpoint["waypoint"]))
+            .collect()
+    };
+    let (Some(origins), Some(destinations)) = (endpoints("origins"), endpoints("destinations"))
+    else {
+        return invalid_argument("Waypoints must be coordinates or mock place ids");
+    };
+
+    let mut elements = Vec::with_capacity(origins.len() * destinations.len());
+    for (origin_index, origin) in origins.iter().enumerate() {
+        for (destination_index, destination) in destinations.iter().enumerate() {
+            let (meters, seconds) = travel(*origin, *destination, travel_mode);
+            elements.push(json!({
+                "originIndex": origin_index,
+                "destinationIndex": destination_index,
+                "status": {},
+                "condition": "ROUTE_EXISTS",
+                "distanceMeters": meters.round() as u64,
+                "duration": format!("{}s", seconds),
+                "staticDuration": format!("{}s", seconds),
+            }));
+        }
+    }
+
+    Json(elements).into_response()
+}
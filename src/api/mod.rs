@@ -1,16 +1,83 @@
-use serde::{Deserialize, Serialize};
-use serde_json::json;
-use std::collections::HashMap;
-
-use axum::{
-    extract::{Query, State},
-    http::StatusCode,
-    response::IntoResponse,
-    Json,
-};
+pub mod admin;
+pub mod along;
+pub mod auth;
+pub mod batch;
+pub mod cli;
+pub mod cluster;
+pub mod commute_alerts;
+pub mod commutes;
+pub mod compare;
+mod dedupe;
+pub mod departure;
+pub mod distance;
+pub mod docs;
+mod dry_run;
+mod etag;
+mod extract;
+pub mod fuel;
+pub mod geofences;
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod health;
+pub mod history;
+pub mod isochrone;
+pub mod jobs;
+pub mod kml;
+pub mod lists;
+pub mod matrix;
+pub mod meet;
+pub mod metrics;
+pub mod mock;
+mod place_types;
+pub mod planner;
+pub mod prewarm;
+pub mod quota;
+pub mod race;
+pub mod saved_places;
+pub mod share;
+pub mod tiles;
+mod tolls;
+pub mod tracking;
+mod transit;
+pub mod trips;
+pub mod users;
+mod validation;
+pub mod version;
+pub mod w3w;
+pub mod warmup;
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+use async_graphql::{InputObject, SimpleObject};
+use axum::{extract::State, http::HeaderMap, response::Response, Json};
+use chrono::{DateTime, Duration as ChronoDuration, SecondsFormat, Utc};
+use sqlx::PgPool;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
 use validator::Validate;
 
-use crate::AppState;
+use crate::{
+    cache::{self, CacheStatus},
+    db::{self, trips::Coordinate},
+    error::AppError,
+    flags::Flag,
+    geo,
+    identity::Identity,
+    middleware::{current_environment, Environment},
+    telemetry::Coordinates,
+    upstream::{self, google_error},
+    usage, AppState,
+};
+
+use dry_run::{DryRun, DryRunOption};
+use extract::JsonOrQuery;
+use fuel::{FuelCost, FuelPrices, FuelRate};
+use tolls::TollCost;
+use transit::TransitItinerary;
+use version::VersionedQuery;
 
 // curl -X POST -d '{
 //     "textQuery" : "Spicy Vegetarian Food in Sydney, Australia",
@@ -23,97 +90,815 @@ use crate::AppState;
 const CONTENT_TYPE: &str = "Content-type";
 const JSON_TYPE: &str = "application/json";
 const GOOGLE_FIELD_MASK_HEADER: &str = "X-Goog-FieldMask";
-const FIELD_MASK: &str = "places.id,places.displayName,places.formattedAddress,places.location";
+const FIELD_MASK: &str = "places.id,places.displayName,places.formattedAddress,places.location,\
+    places.rating,places.priceLevel,places.types";
+// Google's price levels, the index is the level clients filter by
+const PRICE_LEVELS: [&str; 5] = [
+    "PRICE_LEVEL_FREE",
+    "PRICE_LEVEL_INEXPENSIVE",
+    "PRICE_LEVEL_MODERATE",
+    "PRICE_LEVEL_EXPENSIVE",
+    "PRICE_LEVEL_VERY_EXPENSIVE",
+];
 const GOOGLE_API_KEY_HEADER: &str = "X-Goog-Api-Key";
-const GOOGLE_URL: &str = "https://places.googleapis.com/v1/places:searchText";
-const GOOGLE_ROUTES_URL: &str = "https://routes.googleapis.com/directions/v2:computeRoutes";
-const MAX_RESULT_COUNT_KEY: &str = "maxResultCount";
-const MAX_RESULT_COUNT_VALUE: &str = "10";
-const ROUTE_FIELD_MASK: &str =
-    "routes.duration,routes.distanceMeters,routes.polyline.encodedPolyline";
-
-#[derive(Debug, Deserialize, Serialize)]
+// Under GOOGLE_PLACES_ORIGIN and GOOGLE_ROUTES_ORIGIN
+const TEXT_SEARCH_PATH: &str = "/v1/places:searchText";
+const PLACE_DETAILS_PATH: &str = "/v1/places/";
+const PLACE_DETAILS_FIELD_MASK: &str = "id,displayName,formattedAddress,location,priceLevel";
+const MAX_PLACE_ID_LENGTH: usize = 256;
+const ROUTES_PATH: &str = "/directions/v2:computeRoutes";
+const ROUTE_MATRIX_PATH: &str = "/distanceMatrix/v2:computeRouteMatrix";
+const DEFAULT_MAX_RESULTS: u8 = 10;
+const ROUTE_FIELD_MASK: &str = "routes.duration,routes.distanceMeters,\
+    routes.polyline.encodedPolyline,routes.viewport,routes.travelAdvisory.tollInfo";
+// Lines, stops and times of the vehicles ridden, only asked for transit routes
+const TRANSIT_ROUTE_FIELD_MASK: &str = "routes.duration,routes.distanceMeters,\
+    routes.polyline.encodedPolyline,routes.viewport,routes.legs.steps.transitDetails";
+
+// Paths of the endpoints on the built-in mock and the cassette proxy, in the order of
+// ProviderUrls::all. Place details take the id after the path
+const LOCAL_PATHS: [&str; 4] = [
+    "/places/search",
+    "/places/details/",
+    "/routes",
+    "/routes/matrix",
+];
+
+/// Where provider calls go: Google or a stand-in at `GOOGLE_PLACES_ORIGIN` and
+/// `GOOGLE_ROUTES_ORIGIN`, or a loopback server standing in for it, the built-in
+/// mock with `MOCK_PROVIDERS` or the cassette proxy with `UPSTREAM_CASSETTE_MODE`.
+#[derive(Clone, Debug)]
+pub struct ProviderUrls {
+    text_search: String,
+    place_details: String,
+    routes: String,
+    route_matrix: String,
+}
+
+impl ProviderUrls {
+    pub fn google(places_origin: &str, routes_origin: &str) -> Self {
+        let (places_origin, routes_origin) = (
+            places_origin.trim_end_matches('/'),
+            routes_origin.trim_end_matches('/'),
+        );
+        ProviderUrls {
+            text_search: format!("{}{}", places_origin, TEXT_SEARCH_PATH),
+            place_details: format!("{}{}", places_origin, PLACE_DETAILS_PATH),
+            routes: format!("{}{}", routes_origin, ROUTES_PATH),
+            route_matrix: format!("{}{}", routes_origin, ROUTE_MATRIX_PATH),
+        }
+    }
+
+    pub fn local(addr: std::net::SocketAddr) -> Self {
+        let [text_search, place_details, routes, route_matrix] =
+            LOCAL_PATHS.map(|path| format!("http://{}{}", addr, path));
+        ProviderUrls {
+            text_search,
+            place_details,
+            routes,
+            route_matrix,
+        }
+    }
+
+    fn all(&self) -> [&String; 4] {
+        [
+            &self.text_search,
+            &self.place_details,
+            &self.routes,
+            &self.route_matrix,
+        ]
+    }
+
+    /// Local paths with the URL each forwards to, for the cassette proxy.
+    pub fn upstreams(&self) -> Vec<(String, String)> {
+        LOCAL_PATHS
+            .iter()
+            .zip(self.all())
+            .map(|(path, url)| (path.to_string(), url.clone()))
+            .collect()
+    }
+}
+
+fn database(s: &AppState) -> Result<&PgPool, AppError> {
+    s.db.as_ref().ok_or(AppError::Unavailable)
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, SimpleObject, ToSchema)]
+#[serde(rename_all = "camelCase")]
 struct DisplayName {
     text: String,
-    #[serde(rename = "languageCode")]
     language_code: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// A point. Requests can give a full `plusCode` instead of `latitude` and `longitude`, or
+/// the plus code alone as a string.
+#[derive(Debug, Clone, Deserialize, InputObject, Serialize, SimpleObject, ToSchema, Validate)]
+#[graphql(input_name = "LocationInput")]
+#[serde(try_from = "LocationInput")]
 pub struct Location {
+    #[validate(range(min = -90.0, max = 90.0, message = "must be within [-90, 90]"))]
+    #[schema(minimum = -90.0, maximum = 90.0, example = 37.419734)]
     latitude: f32,
+    #[validate(range(min = -180.0, max = 180.0, message = "must be within [-180, 180]"))]
+    #[schema(minimum = -180.0, maximum = 180.0, example = -122.0827784)]
     longitude: f32,
+    /// Only on place results, when `GEOHASH_PRECISION` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[graphql(skip)]
+    #[schema(read_only)]
+    geohash: Option<String>,
+    /// Open Location Code, always on place results
+    #[serde(rename = "plusCode", skip_serializing_if = "Option::is_none")]
+    #[graphql(skip)]
+    #[schema(example = "849VCWC8+R9")]
+    plus_code: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LocationInput {
+    PlusCode(String),
+    Fields(LocationFields),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LocationFields {
+    latitude: Option<f32>,
+    longitude: Option<f32>,
+    geohash: Option<String>,
+    plus_code: Option<String>,
+}
+
+impl TryFrom<LocationInput> for Location {
+    type Error = String;
+
+    fn try_from(input: LocationInput) -> Result<Self, Self::Error> {
+        let fields = match input {
+            LocationInput::PlusCode(code) => LocationFields {
+                latitude: None,
+                longitude: None,
+                geohash: None,
+                plus_code: Some(code),
+            },
+            LocationInput::Fields(fields) => fields,
+        };
+        let point = geo::plus_code::resolve(
+            fields.latitude.map(f64::from),
+            fields.longitude.map(f64::from),
+            fields.plus_code.as_deref(),
+        )?;
+
+        Ok(Location {
+            latitude: fields.latitude.unwrap_or(point.latitude as f32),
+            longitude: fields.longitude.unwrap_or(point.longitude as f32),
+            geohash: fields.geohash,
+            plus_code: fields.plus_code,
+        })
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, SimpleObject, ToSchema)]
+#[graphql(name = "Place")]
+#[serde(rename_all = "camelCase")]
 struct GooglePlace {
     id: String,
-    #[serde(rename = "formattedAddress")]
     formatted_address: String,
-    #[serde(rename = "priceLevel")]
+    /// From `PRICE_LEVEL_FREE` to `PRICE_LEVEL_VERY_EXPENSIVE`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     price_level: Option<String>,
-    #[serde(rename = "displayName")]
     display_name: DisplayName,
     location: Location,
+    /// Google place types, e.g. `cafe`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    types: Option<Vec<String>>,
+    /// Average user rating from 1 to 5
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rating: Option<f64>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct GooglePlacesReponse {
+impl GooglePlace {
+    // 0 for free up to 4 for very expensive
+    fn price_index(&self) -> Option<u8> {
+        let level = self.price_level.as_deref()?;
+        PRICE_LEVELS
+            .iter()
+            .position(|l| *l == level)
+            .map(|i| i as u8)
+    }
+
+    fn coordinate(&self) -> Coordinate {
+        Coordinate {
+            latitude: self.location.latitude.into(),
+            longitude: self.location.longitude.into(),
+        }
+    }
+
+    fn with_plus_code(mut self) -> Self {
+        self.location.plus_code = Some(geo::plus_code::encode(self.coordinate()));
+        self
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct GooglePlacesReponse {
     places: Option<Vec<GooglePlace>>,
 }
 
-#[derive(Deserialize, Validate)]
+impl GooglePlacesReponse {
+    // Unlike geohashes these don't depend on a setting, so they're cached with the places
+    fn with_plus_codes(mut self) -> Self {
+        self.places = self.places.map(|places| {
+            places
+                .into_iter()
+                .map(GooglePlace::with_plus_code)
+                .collect()
+        });
+        self
+    }
+
+    // Added after caching, so the cached copies don't depend on the precision
+    fn with_geohashes(mut self, precision: Option<usize>) -> Self {
+        if let Some(precision) = precision {
+            for place in self.places.iter_mut().flatten() {
+                let geohash = geo::geohash::encode(place.coordinate(), precision);
+                place.location.geohash = Some(geohash);
+            }
+        }
+
+        self
+    }
+
+    // Google rounds the rating filter to half stars, has no free price level and takes a
+    // single type, the exact filters are applied here
+    fn filtered(mut self, request: &GooglePlacesRequest) -> Self {
+        if let Some(places) = self.places.as_mut() {
+            places.retain(|place| {
+                let rating = request
+                    .min_rating
+                    .is_none_or(|min| place.rating.is_some_and(|r| r >= min));
+                let price = match (request.min_price_level, request.max_price_level) {
+                    (None, None) => true,
+                    (min, max) => place.price_index().is_some_and(|level| {
+                        level >= min.unwrap_or(0) && level <= max.unwrap_or(4)
+                    }),
+                };
+                let types = place.types.as_deref().unwrap_or_default();
+                let included = request.included_types.len() < 2
+                    || types.iter().any(|t| request.included_types.contains(t));
+                let excluded = types.iter().any(|t| request.excluded_types.contains(t));
+                rating && price && included && !excluded
+            });
+        }
+
+        self
+    }
+
+    // Also applied after caching, one cached search serves every ranking
+    fn ranked(mut self, rank_by: RankBy, reference: Option<Coordinate>) -> Self {
+        let Some(places) = self.places.as_mut() else {
+            return self;
+        };
+        match (rank_by, reference) {
+            (RankBy::Distance, Some(reference)) => places.sort_by(|a, b| {
+                let a = geo::haversine(reference, a.coordinate());
+                let b = geo::haversine(reference, b.coordinate());
+                a.total_cmp(&b)
+            }),
+            // Unrated places go last, ties keep Google's order
+            (RankBy::Rating, _) => places.sort_by(|a, b| {
+                let a = a.rating.unwrap_or(f64::NEG_INFINITY);
+                let b = b.rating.unwrap_or(f64::NEG_INFINITY);
+                b.total_cmp(&a)
+            }),
+            _ => {}
+        }
+
+        self
+    }
+}
+
+/// Order of place results. `distance` is from the `latitude` and `longitude` given with the
+/// search.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RankBy {
+    /// Google's order
+    #[default]
+    Relevance,
+    Distance,
+    Rating,
+}
+
+/// How the response was produced.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseMeta {
+    cache: CacheStatus,
+    /// Served from the stale copy because the provider failed
+    stale: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    history_id: Option<Uuid>,
+}
+
+impl ResponseMeta {
+    fn new(cache: CacheStatus) -> Self {
+        ResponseMeta {
+            cache,
+            stale: false,
+            history_id: None,
+        }
+    }
+
+    fn stale() -> Self {
+        ResponseMeta {
+            stale: true,
+            ..ResponseMeta::new(CacheStatus::Hit)
+        }
+    }
+
+    fn with_history(self, history_id: Option<Uuid>) -> Self {
+        ResponseMeta { history_id, ..self }
+    }
+}
+
+/// Provider part of the cache and coalescing keys. Sandbox keys may see other results,
+/// so their responses are never shared with production.
+fn google_provider() -> &'static str {
+    match current_environment() {
+        Environment::Production => "google",
+        Environment::Sandbox => "google-sandbox",
+    }
+}
+
+async fn cached<T: DeserializeOwned>(s: &AppState, key: &str) -> Option<T> {
+    cache::get_json(s.cache.as_deref()?, key).await
+}
+
+async fn cached_bytes(s: &AppState, key: &str) -> Option<Vec<u8>> {
+    s.cache.as_deref()?.get(key).await
+}
+
+async fn stale<T: DeserializeOwned>(s: &AppState, key: &str) -> Option<T> {
+    let value = cache::get_json(s.stale_cache.as_deref()?, key).await;
+    if value.is_some() {
+        tracing::info!(cache_key = %key, "serving stale response");
+    }
+
+    value
+}
+
+// Results also refresh the longer lived copy used for stale serving
+async fn store<T: Serialize>(s: &AppState, key: &str, value: &T) -> CacheStatus {
+    match serde_json::to_vec(value) {
+        Ok(bytes) => store_bytes(s, key, bytes).await,
+        Err(_) => CacheStatus::Bypass,
+    }
+}
+
+async fn store_bytes(s: &AppState, key: &str, bytes: Vec<u8>) -> CacheStatus {
+    if let Some(c) = s.stale_cache.as_deref() {
+        c.set(key, bytes.clone()).await;
+    }
+
+    match s.cache.as_deref() {
+        Some(c) => {
+            c.set(key, bytes).await;
+            CacheStatus::Miss
+        }
+        None => CacheStatus::Bypass,
+    }
+}
+
+/// Responds with a result that's JSON already, adding `meta` the way `#[serde(flatten)]`
+/// would lay it out. `None` when `result` isn't a JSON object, the typed path then takes
+/// over. Results are only ever written by this service, so a glance at the shape is enough.
+fn raw_response(headers: &HeaderMap, result: &[u8], meta: &ResponseMeta) -> Option<Response> {
+    let fields = result.strip_prefix(b"{")?.strip_suffix(b"}")?;
+    let meta = serde_json::to_vec(meta).ok()?;
+
+    let mut body = Vec::with_capacity(result.len() + meta.len() + 9);
+    body.push(b'{');
+    body.extend_from_slice(fields);
+    if !fields.is_empty() {
+        body.push(b',');
+    }
+    body.extend_from_slice(b"\"meta\":");
+    body.extend_from_slice(&meta);
+    body.push(b'}');
+
+    Some(etag::conditional_raw(headers, etag::etag_of(result), body))
+}
+
+// Client errors are the caller's fault and a stale answer would hide them
+fn is_upstream_failure<T>(result: &Result<T, AppError>) -> bool {
+    !matches!(
+        result,
+        Ok(_) | Err(AppError::Validation(_) | AppError::NotFound(_))
+    )
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PlacesSearchResponse {
+    #[serde(flatten)]
+    result: GooglePlacesReponse,
+    meta: ResponseMeta,
+}
+
+#[derive(Default, Deserialize, IntoParams, ToSchema, Validate)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "camelCase")]
 pub struct GooglePlacesRequest {
-    #[validate(does_not_contain = "undefined")]
+    /// Free text search, e.g. "Spicy Vegetarian Food in Sydney, Australia"
+    #[validate(
+        length(max = 512, message = "must be at most 512 characters"),
+        custom = "validation::not_blank",
+        does_not_contain(pattern = "undefined", message = "must not contain \"undefined\"")
+    )]
     text_query: String,
+    /// How many places to return, from 1 to 20. 10 when left out
+    #[validate(range(min = 1, max = 20))]
+    #[serde(default)]
+    max_results: Option<u8>,
+    #[serde(default)]
+    rank_by: RankBy,
+    /// Reference point for ranking by distance
+    #[validate(range(min = -90.0, max = 90.0, message = "must be within [-90, 90]"))]
+    #[serde(default)]
+    latitude: Option<f64>,
+    #[validate(range(min = -180.0, max = 180.0, message = "must be within [-180, 180]"))]
+    #[serde(default)]
+    longitude: Option<f64>,
+    /// Reference point as a full plus code, instead of `latitude` and `longitude`
+    #[validate(custom = "validation::plus_code")]
+    #[serde(default)]
+    plus_code: Option<String>,
+    /// Only places rated at least this, from 0 to 5
+    #[validate(range(min = 0.0, max = 5.0))]
+    #[serde(default)]
+    min_rating: Option<f64>,
+    /// Only places at least this pricey, from 0 (free) to 4 (very expensive)
+    #[validate(range(max = 4))]
+    #[serde(default)]
+    min_price_level: Option<u8>,
+    /// Only places at most this pricey, from 0 (free) to 4 (very expensive)
+    #[validate(range(max = 4))]
+    #[serde(default)]
+    max_price_level: Option<u8>,
+    /// Only places of one of these types, e.g. `cafe`. A JSON array or comma separated
+    #[validate(length(max = 50), custom = "validation::place_types")]
+    #[serde(default, deserialize_with = "extract::comma_separated")]
+    included_types: Vec<String>,
+    /// No places of these types, e.g. `lodging`
+    #[validate(length(max = 50), custom = "validation::place_types")]
+    #[serde(default, deserialize_with = "extract::comma_separated")]
+    excluded_types: Vec<String>,
+    /// Scope the search to a country, e.g. `au`
+    #[validate(custom = "validation::region_code")]
+    #[serde(default)]
+    region_code: Option<String>,
+    /// Only places of the included type rather than favoring it. Several included types
+    /// always filter strictly
+    #[serde(default)]
+    strict_type_filtering: bool,
+}
+
+impl GooglePlacesRequest {
+    fn max_results(&self) -> u8 {
+        self.max_results.unwrap_or(DEFAULT_MAX_RESULTS)
+    }
+
+    fn reference(&self) -> Option<Coordinate> {
+        match (self.latitude, self.longitude, &self.plus_code) {
+            (Some(latitude), Some(longitude), _) => Some(Coordinate {
+                latitude,
+                longitude,
+            }),
+            (None, None, Some(code)) => geo::plus_code::decode(code),
+            _ => None,
+        }
+    }
+
+    // Whether `filtered` and `ranked` leave Google's results as they are
+    fn is_passthrough(&self) -> bool {
+        self.rank_by == RankBy::Relevance
+            && self.min_rating.is_none()
+            && self.min_price_level.is_none()
+            && self.max_price_level.is_none()
+            && self.included_types.len() < 2
+            && self.excluded_types.is_empty()
+    }
+}
+
+// Recorded in the background so history never slows down or fails a search
+fn record_history(s: &AppState, identity: Option<Identity>, query: &str) -> Option<Uuid> {
+    let pool = s.db.clone()?;
+    let owner = identity?.0;
+    let id = Uuid::new_v4();
+    let query = query.to_owned();
+
+    tokio::spawn(async move {
+        if let Err(e) = db::history::insert(&pool, id, &owner, &query).await {
+            tracing::error!(error = %e, "failed to record search history");
+        }
+    });
+
+    Some(id)
 }
 
+/// Searches places by free text, given as a JSON body or in the query string.
+#[utoipa::path(
+    post,
+    path = "/v2/places",
+    tag = "places",
+    params(GooglePlacesRequest, DryRunOption),
+    request_body(
+        content = GooglePlacesRequest,
+        description = "Alternative to the query string, ignored when a query string is sent"
+    ),
+    responses(
+        (status = 200, description = "Matching places", body = PlacesSearchResponse),
+        (status = 304, description = "Unchanged since the `If-None-Match` ETag"),
+        (status = 400, description = "Invalid query", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 429, description = "Rate limited or over quota", body = ErrorResponse),
+        (status = 502, description = "Provider error", body = ErrorResponse),
+        (status = 503, description = "Provider unavailable", body = ErrorResponse),
+        (status = 504, description = "Provider timed out", body = ErrorResponse),
+    )
+)]
 pub async fn get_places(
     State(s): State<AppState>,
-    params: Query<GooglePlacesRequest>,
-) -> impl IntoResponse {
+    identity: Option<Identity>,
+    headers: HeaderMap,
+    VersionedQuery(call): VersionedQuery<DryRunOption>,
+    params: JsonOrQuery<GooglePlacesRequest>,
+) -> Result<Response, AppError> {
     let p = params.0;
 
-    if p.validate().is_err() {
-        return (StatusCode::BAD_REQUEST, "Invalid request").into_response();
+    p.validate()?;
+    let reference = p.reference();
+    if p.rank_by == RankBy::Distance && reference.is_none() {
+        return Err(AppError::Validation(
+            "latitude and longitude, or plusCode, are required to rank by distance".into(),
+        ));
+    }
+    if p.strict_type_filtering && p.included_types.is_empty() {
+        return Err(AppError::Validation(
+            "strictTypeFiltering needs includedTypes".into(),
+        ));
+    }
+    if let (Some(min), Some(max)) = (p.min_price_level, p.max_price_level) {
+        if min > max {
+            return Err(AppError::Validation(
+                "minPriceLevel must not be above maxPriceLevel".into(),
+            ));
+        }
+    }
+    let present = |result: GooglePlacesReponse| {
+        result
+            .with_geohashes(s.geohash_precision)
+            .filtered(&p)
+            .ranked(p.rank_by, reference)
+    };
+
+    let body = text_search_body(&p);
+    if call.dry_run {
+        return Ok(DryRun::post(
+            "google-places",
+            &s.urls.text_search,
+            FIELD_MASK,
+            body,
+        ));
     }
 
-    let mut map = HashMap::new();
-    map.insert("textQuery", p.text_query);
-    map.insert(MAX_RESULT_COUNT_KEY, MAX_RESULT_COUNT_VALUE.into());
+    let fan_out = s.flags.enabled(Flag::ProviderFanOut, identity.as_ref());
+    let stale_if_error = s.flags.enabled(Flag::StaleIfError, identity.as_ref());
+    let history_id = record_history(&s, identity, &p.text_query);
+    let cache_key = cache::places_key(&body, google_provider());
+    // Results nothing is done to go out as they're cached, without parsing them again
+    let passthrough = p.is_passthrough() && s.geohash_precision.is_none();
+    let hit = cached_bytes(&s, &cache_key).await;
+    if let Some(hit) = hit.as_deref().filter(|_| passthrough) {
+        let meta = ResponseMeta::new(CacheStatus::Hit).with_history(history_id);
+        if let Some(response) = raw_response(&headers, hit, &meta) {
+            return Ok(response);
+        }
+    }
+    if let Some(cached) = hit.and_then(|hit| serde_json::from_slice(&hit).ok()) {
+        let cached = present(cached);
+        let tag = etag::etag_for(&cached);
+        return Ok(etag::conditional(
+            &headers,
+            tag,
+            PlacesSearchResponse {
+                result: cached,
+                meta: ResponseMeta::new(CacheStatus::Hit).with_history(history_id),
+            },
+        ));
+    }
 
-    // We should add locationBias https://developers.google.com/maps/documentation/places/web-service/text-search#location-bias
-    let request = s
-        .client_reqwest
-        .post(GOOGLE_URL)
-        .json(&map)
-        .header(GOOGLE_FIELD_MASK_HEADER, FIELD_MASK)
-        .header(CONTENT_TYPE, JSON_TYPE)
-        .header(GOOGLE_API_KEY_HEADER, s.google_key)
-        .send()
-        .await;
-
-    match request {
-        Ok(google_req) => match google_req.json::<GooglePlacesReponse>().await {
-            Ok(google_places) => Json(google_places).into_response(),
-            Err(e) => {
-                println!("Error parsing response from Google Places API: {}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Something went wrong. Try again later",
-                )
-                    .into_response()
+    let fetched = fetch_places(&s, &body, fan_out).await;
+    if stale_if_error && is_upstream_failure(&fetched) {
+        if let Some(stale) = stale::<GooglePlacesReponse>(&s, &cache_key).await {
+            let stale = present(stale);
+            let tag = etag::etag_for(&stale);
+            return Ok(etag::conditional(
+                &headers,
+                tag,
+                PlacesSearchResponse {
+                    result: stale,
+                    meta: ResponseMeta::stale().with_history(history_id),
+                },
+            ));
+        }
+    }
+    let google_places = fetched?;
+
+    if passthrough {
+        if let Ok(bytes) = serde_json::to_vec(&google_places) {
+            let cache_status = store_bytes(&s, &cache_key, bytes.clone()).await;
+            let meta = ResponseMeta::new(cache_status).with_history(history_id);
+            if let Some(response) = raw_response(&headers, &bytes, &meta) {
+                return Ok(response);
             }
+        }
+    }
+    let cache_status = store(&s, &cache_key, &google_places).await;
+
+    let google_places = present(google_places);
+    // Tagged on the result only, so hits and misses of the same content revalidate alike
+    let tag = etag::etag_for(&google_places);
+    Ok(etag::conditional(
+        &headers,
+        tag,
+        PlacesSearchResponse {
+            result: google_places,
+            meta: ResponseMeta::new(cache_status).with_history(history_id),
         },
-        Err(e) => {
-            println!("Error sending request to Google Places API: {}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Something went wrong. Try again later",
-            )
-                .into_response()
+    ))
+}
+
+fn text_search_body(request: &GooglePlacesRequest) -> Value {
+    let mut body = json!({
+        "textQuery": request.text_query,
+        "maxResultCount": request.max_results(),
+    });
+    if let Some(min_rating) = request.min_rating {
+        // Half stars only, rounded down so the exact filter still sees every match
+        body["minRating"] = json!((min_rating * 2.0).floor() / 2.0);
+    }
+    if request.min_price_level.is_some() || request.max_price_level.is_some() {
+        let min = usize::from(request.min_price_level.unwrap_or(0)).max(1);
+        let max = usize::from(request.max_price_level.unwrap_or(4));
+        // Free places can't be asked for, they're left to the exact filter
+        if min <= max {
+            body["priceLevels"] = json!(PRICE_LEVELS[min..=max]);
+        }
+    }
+    // Text Search filters by one type, more are filtered once the results are in
+    if let [included] = request.included_types.as_slice() {
+        body["includedType"] = json!(included);
+        if request.strict_type_filtering {
+            body["strictTypeFiltering"] = json!(true);
+        }
+    }
+    if let Some(region_code) = &request.region_code {
+        body["regionCode"] = json!(region_code.to_lowercase());
+    }
+
+    body
+}
+
+/// Calls Text Search without caching. Unsuccessful answers become the matching error.
+/// With `PLACES_RACE_URL` set and `fan_out`, the first of Google and that endpoint to find
+/// places answers. Identical searches in flight at the same time share one call.
+async fn fetch_places(
+    s: &AppState,
+    body: &Value,
+    fan_out: bool,
+) -> Result<GooglePlacesReponse, AppError> {
+    let key = cache::places_key(body, google_provider());
+    s.inflight.run(&key, race_places(s, body, fan_out)).await
+}
+
+async fn race_places(
+    s: &AppState,
+    body: &Value,
+    fan_out: bool,
+) -> Result<GooglePlacesReponse, AppError> {
+    let google = text_search(
+        s,
+        &s.urls.text_search,
+        s.keys(),
+        &s.places_breaker,
+        &s.places_metrics,
+        body,
+    );
+    // The race keys are production keys
+    let race = s
+        .places_race
+        .as_ref()
+        .filter(|_| fan_out && current_environment() == Environment::Production);
+    let mut google_places = match race {
+        Some(race) => {
+            let secondary =
+                text_search(s, &race.url, &race.keys, &race.breaker, &race.metrics, body);
+            race::first_success(google, secondary, |places: &GooglePlacesReponse| {
+                places.places.as_ref().is_some_and(|p| !p.is_empty())
+            })
+            .await?
         }
+        None => google.await?,
+    };
+    if s.place_dedupe_meters > 0.0 {
+        google_places.places = google_places
+            .places
+            .map(|places| dedupe::dedupe(places, s.place_dedupe_meters));
+    }
+
+    Ok(google_places.with_plus_codes())
+}
+
+async fn text_search(
+    s: &AppState,
+    url: &str,
+    keys: &upstream::KeyPool,
+    breaker: &upstream::CircuitBreaker,
+    metrics: &upstream::EndpointMetrics,
+    body: &Value,
+) -> Result<GooglePlacesReponse, AppError> {
+    let provider = breaker.name();
+    // We should add locationBias https://developers.google.com/maps/documentation/places/web-service/text-search#location-bias
+    let (status, body) = upstream::send_with_keys(keys, breaker, &s.retry_policy, metrics, |key| {
+        s.client_reqwest
+            .post(url)
+            .json(body)
+            .header(GOOGLE_FIELD_MASK_HEADER, FIELD_MASK)
+            .header(CONTENT_TYPE, JSON_TYPE)
+            .header(GOOGLE_API_KEY_HEADER, key)
+    })
+    .await
+    .inspect_err(|e| tracing::error!(error = %e, provider, "upstream request failed"))?;
+
+    if !status.is_success() {
+        return Err(google_error::translate(provider, status, &body));
+    }
+    s.usage.record(usage::PLACES_TEXT_SEARCH);
+
+    serde_json::from_slice::<GooglePlacesReponse>(&body).map_err(|e| {
+        tracing::error!(error = %e, provider, "failed to parse upstream response");
+        AppError::ParseError(e.to_string())
+    })
+}
+
+// Google place ids are URL safe base64-like strings
+fn is_place_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_PLACE_ID_LENGTH
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// Calls Place Details for one place. Unknown places are `None` rather than an error.
+async fn fetch_place(s: &AppState, id: &str) -> Result<Option<GooglePlace>, AppError> {
+    if !is_place_id(id) {
+        return Err(AppError::Validation("Invalid place id".into()));
+    }
+
+    let url = format!("{}{}", s.urls.place_details, id);
+    let (status, body) = upstream::send_with_keys(
+        s.keys(),
+        &s.places_breaker,
+        &s.retry_policy,
+        &s.places_metrics,
+        |key| {
+            s.client_reqwest
+                .get(&url)
+                .header(GOOGLE_FIELD_MASK_HEADER, PLACE_DETAILS_FIELD_MASK)
+                .header(GOOGLE_API_KEY_HEADER, key)
+        },
+    )
+    .await
+    .inspect_err(
+        |e| tracing::error!(error = %e, provider = "google-places", "upstream request failed"),
+    )?;
+
+    if status == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !status.is_success() {
+        return Err(google_error::translate("google-places", status, &body));
     }
+    s.usage.record(usage::PLACE_DETAILS);
+
+    serde_json::from_slice::<GooglePlace>(&body)
+        .map(|place| Some(place.with_plus_code()))
+        .map_err(|e| {
+            tracing::error!(error = %e, provider = "google-places", "failed to parse upstream response");
+            AppError::ParseError(e.to_string())
+        })
 }
 
 // curl -X POST -d '{
@@ -149,99 +934,599 @@ pub async fn get_places(
 //   -H 'X-Goog-FieldMask: routes.duration,routes.distanceMeters,routes.polyline.encodedPolyline' \
 //   'https://routes.googleapis.com/directions/v2:computeRoutes'
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+#[serde(rename_all = "camelCase")]
 pub struct GetRouteRequestBody {
-    #[serde(rename = "originLocation")]
+    #[validate]
     origin_location: Location,
-    #[serde(rename = "destinationLocation")]
+    #[validate]
     destination_location: Location,
-    #[serde(rename = "departureTime")]
-    departure_time: String,
+    /// Driving when left out
+    travel_mode: Option<TravelMode>,
+    /// Either this or `arrivalTime` is required
+    #[validate(custom = "validation::rfc3339")]
+    #[schema(format = DateTime, example = "2023-10-15T15:01:23Z")]
+    departure_time: Option<String>,
+    /// When to be at the destination. Transit schedules arrive by it, other modes search
+    /// the departure that does
+    #[validate(custom = "validation::rfc3339")]
+    #[schema(format = DateTime, example = "2023-10-15T16:00:00Z")]
+    arrival_time: Option<String>,
+}
+
+/// When to leave for the first route, answered for requests with an `arrivalTime`.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Schedule {
+    departure_time: DateTime<Utc>,
+    /// Estimated from the route duration
+    arrival_time: DateTime<Utc>,
+    /// Whether `arrivalTime` is met, false when leaving now is already too late
+    meets_arrival_time: bool,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject, ToSchema)]
+#[serde(rename_all = "camelCase")]
 pub struct Polyline {
-    #[serde(rename = "encodedPolyline")]
     encoded_polyline: String,
+    /// Decoded `encodedPolyline`, only with `decodePolyline=true`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[graphql(skip)]
+    #[schema(value_type = Option<Vec<Location>>)]
+    points: Option<Vec<Coordinate>>,
+}
+
+/// Box around a route, for fitting the map camera to it.
+#[derive(Debug, Clone, Deserialize, Serialize, SimpleObject, ToSchema)]
+pub struct Viewport {
+    /// South-west corner
+    low: Location,
+    /// North-east corner
+    high: Location,
+}
+
+impl Viewport {
+    fn of_polyline(encoded: &str) -> Option<Self> {
+        let path = geo::polyline::decode(encoded)?;
+        let (low, high) = geo::bounding_box(&path)?;
+        let location = |c: Coordinate| Location {
+            latitude: c.latitude as f32,
+            longitude: c.longitude as f32,
+            geohash: None,
+            plus_code: None,
+        };
+
+        Some(Viewport {
+            low: location(low),
+            high: location(high),
+        })
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, SimpleObject, ToSchema)]
+#[graphql(name = "Route")]
+#[serde(rename_all = "camelCase")]
 pub struct RoutesResponse {
-    #[serde(rename = "distanceMeters")]
     distance_meters: f32,
+    /// Seconds with an `s` suffix, e.g. "165s"
     duration: String,
     polyline: Polyline,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    viewport: Option<Viewport>,
+    /// Estimate for the `fuelConsumption` query parameter
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[graphql(skip)]
+    fuel_cost: Option<FuelCost>,
+    /// Estimated tolls of a `DRIVE` or `TWO_WHEELER` route, one per currency. Left out
+    /// without tolls, empty when Google can't price them
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[graphql(skip)]
+    tolls: Option<Vec<TollCost>>,
+    /// The rides of a `TRANSIT` route
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[graphql(skip)]
+    transit: Option<TransitItinerary>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
 pub struct GetRoutesReponse {
     routes: Vec<RoutesResponse>,
 }
 
-pub async fn get_routes(
-    State(s): State<AppState>,
-    Json(body): Json<GetRouteRequestBody>,
-) -> impl IntoResponse {
-    println!("body: {:?}", body);
-    let req = json!({
-        "origin":{
-            "location":{
-                "latLng":{
-                "latitude": body.origin_location.latitude,
-                "longitude": body.origin_location.longitude
-                }
-            }
-        },
-        "destination":{
-            "location":{
-                "latLng":{
-                "latitude": body.destination_location.latitude,
-                "longitude": body.destination_location.longitude
-                }
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RoutesComputeResponse {
+    #[serde(flatten)]
+    result: GetRoutesReponse,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    schedule: Option<Schedule>,
+    meta: ResponseMeta,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum TravelMode {
+    Drive,
+    Bicycle,
+    Walk,
+    TwoWheeler,
+    Transit,
+}
+
+impl TravelMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TravelMode::Drive => "DRIVE",
+            TravelMode::Bicycle => "BICYCLE",
+            TravelMode::Walk => "WALK",
+            TravelMode::TwoWheeler => "TWO_WHEELER",
+            TravelMode::Transit => "TRANSIT",
+        }
+    }
+
+    // Google rejects routing preferences and route modifiers for the other modes
+    fn is_motorized(&self) -> bool {
+        matches!(self, TravelMode::Drive | TravelMode::TwoWheeler)
+    }
+}
+
+impl FromStr for TravelMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "DRIVE" => Ok(TravelMode::Drive),
+            "BICYCLE" => Ok(TravelMode::Bicycle),
+            "WALK" => Ok(TravelMode::Walk),
+            "TWO_WHEELER" => Ok(TravelMode::TwoWheeler),
+            "TRANSIT" => Ok(TravelMode::Transit),
+            _ => Err(()),
+        }
+    }
+}
+
+fn waypoint<T: Serialize>(latitude: T, longitude: T) -> Value {
+    json!({
+        "location":{
+            "latLng":{
+            "latitude": latitude,
+            "longitude": longitude
             }
-        },
-        "departureTime": body.departure_time,
-        "travelMode": "DRIVE",
-        "routingPreference": "TRAFFIC_AWARE_OPTIMAL",
-        "computeAlternativeRoutes": true,
-        "routeModifiers": {
+        }
+    })
+}
+
+// Durations come as seconds with an `s` suffix, e.g. "165s"
+fn duration_seconds(duration: &str) -> Option<f64> {
+    duration.strip_suffix('s')?.parse().ok()
+}
+
+/// Builds a computeRoutes body. Alternatives are only requested without intermediates,
+/// which Google doesn't support together.
+fn routes_body(
+    origin: Value,
+    destination: Value,
+    intermediates: Vec<Value>,
+    travel_mode: TravelMode,
+    departure_time: Option<String>,
+) -> Value {
+    let mut req = json!({
+        "origin": origin,
+        "destination": destination,
+        "travelMode": travel_mode.as_str(),
+        "computeAlternativeRoutes": intermediates.is_empty(),
+        "languageCode": "en-US",
+        "units": "METRIC"
+    });
+
+    if travel_mode.is_motorized() {
+        req["routingPreference"] = json!("TRAFFIC_AWARE_OPTIMAL");
+        req["routeModifiers"] = json!({
           "avoidTolls": false,
           "avoidHighways": false,
           "avoidFerries": false
+        });
+        req["extraComputations"] = json!(["TOLLS"]);
+    }
+    if !intermediates.is_empty() {
+        req["intermediates"] = Value::Array(intermediates);
+    }
+    if let Some(departure_time) = departure_time {
+        req["departureTime"] = json!(departure_time);
+    }
+
+    req
+}
+
+fn is_transit(req: &Value) -> bool {
+    req["travelMode"] == TravelMode::Transit.as_str()
+}
+
+fn route_field_mask(req: &Value) -> &'static str {
+    if is_transit(req) {
+        TRANSIT_ROUTE_FIELD_MASK
+    } else {
+        ROUTE_FIELD_MASK
+    }
+}
+
+/// Calls computeRoutes without caching. Unsuccessful answers become the matching error.
+/// Identical requests in flight at the same time share one call.
+async fn fetch_routes(s: &AppState, req: &Value) -> Result<GetRoutesReponse, AppError> {
+    let key = cache::routes_key(req, google_provider());
+    s.inflight.run(&key, call_routes(s, req)).await
+}
+
+async fn call_routes(s: &AppState, req: &Value) -> Result<GetRoutesReponse, AppError> {
+    let (status, body) = upstream::send_with_keys(
+        s.keys(),
+        &s.routes_breaker,
+        &s.retry_policy,
+        &s.routes_metrics,
+        |key| {
+            s.client_reqwest
+                .post(&s.urls.routes)
+                .json(req)
+                .header(GOOGLE_FIELD_MASK_HEADER, route_field_mask(req))
+                .header(CONTENT_TYPE, JSON_TYPE)
+                .header(GOOGLE_API_KEY_HEADER, key)
         },
-        "languageCode": "en-US",
-        "units": "METRIC"
-    });
+    )
+    .await
+    .inspect_err(
+        |e| tracing::error!(error = %e, provider = "google-routes", "upstream request failed"),
+    )?;
 
-    let request = s
-        .client_reqwest
-        .post(GOOGLE_ROUTES_URL)
-        .json(&req)
-        .header(GOOGLE_FIELD_MASK_HEADER, ROUTE_FIELD_MASK)
-        .header(CONTENT_TYPE, JSON_TYPE)
-        .header(GOOGLE_API_KEY_HEADER, s.google_key)
-        .send()
-        .await;
-
-    match request {
-        Ok(google_req) => match google_req.json::<GetRoutesReponse>().await {
-            Ok(google_places) => Json(google_places).into_response(),
-            Err(e) => {
-                println!("Error parsing response from Google Routes API: {}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Something went wrong. Try again later",
-                )
-                    .into_response()
+    if !status.is_success() {
+        return Err(google_error::translate("google-routes", status, &body));
+    }
+    s.usage.record(usage::routes_sku(req));
+
+    let mut google_routes = serde_json::from_slice::<GetRoutesReponse>(&body).map_err(|e| {
+        tracing::error!(error = %e, provider = "google-routes", "failed to parse upstream response");
+        AppError::ParseError(e.to_string())
+    })?;
+    // Google leaves the viewport out now and then, the polyline has what's needed
+    for route in &mut google_routes.routes {
+        if route.viewport.is_none() {
+            route.viewport = Viewport::of_polyline(&route.polyline.encoded_polyline);
+        }
+    }
+    // Google's steps and advisories are summed up before caching, the cached routes don't
+    // keep them
+    if req["extraComputations"]
+        .as_array()
+        .is_some_and(|extra| extra.iter().any(|e| e == "TOLLS"))
+    {
+        match serde_json::from_slice::<tolls::Routes>(&body) {
+            Ok(advisories) => {
+                for (route, tolls) in google_routes.routes.iter_mut().zip(advisories.tolls()) {
+                    route.tolls = tolls;
+                }
             }
-        },
-        Err(e) => {
-            println!("Error sending request to Google Routes API: {}", e);
+            Err(e) => tracing::warn!(
+                error = %e,
+                provider = "google-routes",
+                "could not read the toll estimates"
+            ),
+        }
+    }
+    if is_transit(req) {
+        match serde_json::from_slice::<transit::Routes>(&body) {
+            Ok(transit) => {
+                for (route, itinerary) in google_routes.routes.iter_mut().zip(transit.itineraries())
+                {
+                    route.transit = Some(itinerary);
+                }
+            }
+            Err(e) => tracing::warn!(
+                error = %e,
+                provider = "google-routes",
+                "could not read the transit details"
+            ),
+        }
+    }
+
+    Ok(google_routes)
+}
+
+/// Text search through the cache, for the GraphQL and gRPC interfaces. Unlike the REST
+/// handler, stale copies aren't served when the provider fails.
+async fn search_places(s: &AppState, text_query: String) -> Result<GooglePlacesReponse, AppError> {
+    let request = GooglePlacesRequest {
+        text_query,
+        ..Default::default()
+    };
+    request.validate()?;
+
+    let body = text_search_body(&request);
+    let cache_key = cache::places_key(&body, google_provider());
+    if let Some(result) = cached::<GooglePlacesReponse>(s, &cache_key).await {
+        return Ok(result.with_geohashes(s.geohash_precision));
+    }
+
+    let fan_out = s.flags.enabled(Flag::ProviderFanOut, None);
+    let result = fetch_places(s, &body, fan_out).await?;
+    store(s, &cache_key, &result).await;
+
+    Ok(result.with_geohashes(s.geohash_precision))
+}
+
+/// Driving routes between two waypoints through the cache, the counterpart of
+/// `search_places` for routes.
+async fn compute_routes(
+    s: &AppState,
+    origin: Value,
+    destination: Value,
+    departure_time: Option<String>,
+) -> Result<GetRoutesReponse, AppError> {
+    if let Some(departure_time) = &departure_time {
+        validation::rfc3339(departure_time).map_err(|_| {
+            AppError::Validation("departureTime must be an RFC 3339 timestamp".into())
+        })?;
+    }
+
+    let req = routes_body(
+        origin,
+        destination,
+        Vec::new(),
+        TravelMode::Drive,
+        departure_time,
+    );
+    let cache_key = cache::routes_key(&req, google_provider());
+    if let Some(result) = cached::<GetRoutesReponse>(s, &cache_key).await {
+        return Ok(result);
+    }
+
+    let result = fetch_routes(s, &req).await?;
+    store(s, &cache_key, &result).await;
+
+    Ok(result)
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+#[serde(rename_all = "camelCase")]
+pub struct RoutesOptions {
+    /// Also return the points of each route's polyline
+    #[serde(default)]
+    decode_polyline: bool,
+    /// Liters per 100 kilometers, to estimate the fuel cost of each route
+    fuel_consumption: Option<f64>,
+    /// Per liter, in `FUEL_CURRENCY`
+    fuel_price: Option<f64>,
+    /// Priced from `FUEL_PRICES` when `fuelPrice` is left out, e.g. `diesel`
+    fuel_type: Option<String>,
+}
+
+impl RoutesOptions {
+    fn fuel_rate(
+        &self,
+        prices: &FuelPrices,
+        travel_mode: TravelMode,
+    ) -> Result<Option<FuelRate>, AppError> {
+        prices.rate(
+            travel_mode,
+            self.fuel_consumption,
+            self.fuel_price,
+            self.fuel_type.as_deref(),
+        )
+    }
+
+    // Applied after caching, so one cached result serves every combination of options
+    fn apply(&self, mut result: GetRoutesReponse, fuel: Option<&FuelRate>) -> GetRoutesReponse {
+        if let Some(fuel) = fuel {
+            for route in &mut result.routes {
+                route.fuel_cost = Some(fuel.cost(f64::from(route.distance_meters)));
+            }
+        }
+        if self.decode_polyline {
+            for route in &mut result.routes {
+                route.polyline.points = geo::polyline::decode(&route.polyline.encoded_polyline);
+                if route.polyline.points.is_none() {
+                    tracing::warn!(
+                        provider = "google-routes",
+                        "could not decode route polyline"
+                    );
+                }
+            }
+        }
+
+        result
+    }
+}
+
+// For the fastest route, leaving at `departure` or, without one, arriving at `arrival_time`
+fn schedule(
+    routes: &GetRoutesReponse,
+    arrival_time: DateTime<Utc>,
+    departure: Option<DateTime<Utc>>,
+) -> Option<Schedule> {
+    let seconds = routes
+        .routes
+        .iter()
+        .filter_map(|route| duration_seconds(&route.duration))
+        .min_by(f64::total_cmp)?;
+    let duration = ChronoDuration::seconds(seconds.round() as i64);
+    let (departure_time, arrives_at) = match departure {
+        Some(departure) => (departure, departure + duration),
+        None => (arrival_time - duration, arrival_time),
+    };
+
+    Some(Schedule {
+        departure_time,
+        arrival_time: arrives_at,
+        meets_arrival_time: arrives_at <= arrival_time,
+    })
+}
+
+/// Computes routes between two points, with alternatives, leaving or arriving at a time.
+#[utoipa::path(
+    post,
+    path = "/v2/routes",
+    tag = "routes",
+    params(RoutesOptions, DryRunOption),
+    request_body = GetRouteRequestBody,
+    responses(
+        (status = 200, description = "Computed routes", body = RoutesComputeResponse),
+        (status = 304, description = "Unchanged since the `If-None-Match` ETag"),
+        (status = 400, description = "Invalid body", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid credentials", body = ErrorResponse),
+        (status = 429, description = "Rate limited or over quota", body = ErrorResponse),
+        (status = 502, description = "Provider error", body = ErrorResponse),
+        (status = 503, description = "Provider unavailable", body = ErrorResponse),
+        (status = 504, description = "Provider timed out", body = ErrorResponse),
+    )
+)]
+pub async fn get_routes(
+    State(s): State<AppState>,
+    identity: Option<Identity>,
+    headers: HeaderMap,
+    VersionedQuery(options): VersionedQuery<RoutesOptions>,
+    VersionedQuery(call): VersionedQuery<DryRunOption>,
+    Json(body): Json<GetRouteRequestBody>,
+) -> Result<Response, AppError> {
+    body.validate()?;
+    tracing::debug!(
+        origin = %Coordinates(body.origin_location.latitude, body.origin_location.longitude),
+        destination = %Coordinates(
+            body.destination_location.latitude,
+            body.destination_location.longitude
+        ),
+        departure_time = ?body.departure_time,
+        arrival_time = ?body.arrival_time,
+        "computing routes"
+    );
+
+    let travel_mode = body.travel_mode.unwrap_or(TravelMode::Drive);
+    let fuel = options.fuel_rate(&s.fuel_prices, travel_mode)?;
+    let arrival_time = match (&body.departure_time, &body.arrival_time) {
+        (Some(_), None) => None,
+        (None, Some(arrival_time)) => Some(departure::timestamp(arrival_time)),
+        _ => {
+            return Err(AppError::Validation(
+                "Exactly one of departureTime and arrivalTime is required".into(),
+            ))
+        }
+    };
+    // Transit schedules take the arrival as is, other modes leave at the searched departure
+    if call.dry_run && arrival_time.is_some() && travel_mode != TravelMode::Transit {
+        return Err(AppError::Validation(
+            "dryRun needs departureTime outside transit, arrivals are searched with the provider"
+                .into(),
+        ));
+    }
+    let (departure_time, searched) = match arrival_time {
+        Some(arrival_time) if travel_mode != TravelMode::Transit => {
+            let location = |l: &Location| Coordinate {
+                latitude: f64::from(l.latitude),
+                longitude: f64::from(l.longitude),
+            };
+            let departure = departure::departure_for_arrival(
+                &s,
+                location(&body.origin_location),
+                location(&body.destination_location),
+                travel_mode,
+                arrival_time,
+            )
+            .await?;
+            let departure_time = departure.departure_time;
             (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Something went wrong. Try again later",
+                Some(departure_time.to_rfc3339_opts(SecondsFormat::Secs, true)),
+                Some(departure_time),
             )
-                .into_response()
         }
+        _ => (body.departure_time, None),
+    };
+
+    let mut req = routes_body(
+        waypoint(
+            body.origin_location.latitude,
+            body.origin_location.longitude,
+        ),
+        waypoint(
+            body.destination_location.latitude,
+            body.destination_location.longitude,
+        ),
+        Vec::new(),
+        travel_mode,
+        departure_time,
+    );
+    if let (TravelMode::Transit, Some(arrival_time)) = (travel_mode, &body.arrival_time) {
+        req["arrivalTime"] = json!(arrival_time);
     }
+    if call.dry_run {
+        return Ok(DryRun::post(
+            "google-routes",
+            &s.urls.routes,
+            route_field_mask(&req),
+            req,
+        ));
+    }
+    let schedule_of = |routes: &GetRoutesReponse| {
+        arrival_time.and_then(|arrival_time| schedule(routes, arrival_time, searched))
+    };
+
+    let cache_key = cache::routes_key(&req, google_provider());
+    // Routes nothing is added to go out as they're cached, without parsing them again
+    let passthrough = !options.decode_polyline && fuel.is_none() && arrival_time.is_none();
+    let hit = cached_bytes(&s, &cache_key).await;
+    if let Some(hit) = hit.as_deref().filter(|_| passthrough) {
+        if let Some(response) = raw_response(&headers, hit, &ResponseMeta::new(CacheStatus::Hit)) {
+            return Ok(response);
+        }
+    }
+    if let Some(cached) = hit.and_then(|hit| serde_json::from_slice(&hit).ok()) {
+        let cached = options.apply(cached, fuel.as_ref());
+        let tag = etag::etag_for(&cached);
+        return Ok(etag::conditional(
+            &headers,
+            tag,
+            RoutesComputeResponse {
+                schedule: schedule_of(&cached),
+                result: cached,
+                meta: ResponseMeta::new(CacheStatus::Hit),
+            },
+        ));
+    }
+
+    let fetched = fetch_routes(&s, &req).await;
+    if is_upstream_failure(&fetched) && s.flags.enabled(Flag::StaleIfError, identity.as_ref()) {
+        if let Some(stale) = stale::<GetRoutesReponse>(&s, &cache_key).await {
+            let stale = options.apply(stale, fuel.as_ref());
+            let tag = etag::etag_for(&stale);
+            return Ok(etag::conditional(
+                &headers,
+                tag,
+                RoutesComputeResponse {
+                    schedule: schedule_of(&stale),
+                    result: stale,
+                    meta: ResponseMeta::stale(),
+                },
+            ));
+        }
+    }
+    let google_routes = fetched?;
+
+    if passthrough {
+        if let Ok(bytes) = serde_json::to_vec(&google_routes) {
+            let cache_status = store_bytes(&s, &cache_key, bytes.clone()).await;
+            if let Some(response) = raw_response(&headers, &bytes, &ResponseMeta::new(cache_status))
+            {
+                return Ok(response);
+            }
+        }
+    }
+    let cache_status = store(&s, &cache_key, &google_routes).await;
+
+    let google_routes = options.apply(google_routes, fuel.as_ref());
+    let tag = etag::etag_for(&google_routes);
+    Ok(etag::conditional(
+        &headers,
+        tag,
+        RoutesComputeResponse {
+            schedule: schedule_of(&google_routes),
+            result: google_routes,
+            meta: ResponseMeta::new(cache_status),
+        },
+    ))
 }
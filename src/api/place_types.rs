@@ -0,0 +1,204 @@
+/// Place types Google accepts in search filters (Table A), sorted for binary search.
+/// https://developers.google.com/maps/documentation/places/web-service/place-types
+pub const PLACE_TYPES: &[&str] = &[
+    "accounting",
+    "administrative_area_level_1",
+    "administrative_area_level_2",
+    "airport",
+    "american_restaurant",
+    "amusement_center",
+    "amusement_park",
+    "aquarium",
+    "art_gallery",
+    "athletic_field",
+    "atm",
+    "auto_parts_store",
+    "bakery",
+    "bank",
+    "banquet_hall",
+    "bar",
+    "barbecue_restaurant",
+    "barber_shop",
+    "beauty_salon",
+    "bed_and_breakfast",
+    "bicycle_store",
+    "book_store",
+    "bowling_alley",
+    "brazilian_restaurant",
+    "breakfast_restaurant",
+    "brunch_restaurant",
+    "bus_station",
+    "bus_stop",
+    "cafe",
+    "campground",
+    "camping_cabin",
+    "car_dealer",
+    "car_rental",
+    "car_repair",
+    "car_wash",
+    "casino",
+    "cell_phone_store",
+    "cemetery",
+    "child_care_agency",
+    "chinese_restaurant",
+    "church",
+    "city_hall",
+    "clothing_store",
+    "coffee_shop",
+    "community_center",
+    "consultant",
+    "convenience_store",
+    "convention_center",
+    "cottage",
+    "country",
+    "courier_service",
+    "courthouse",
+    "cultural_center",
+    "dental_clinic",
+    "dentist",
+    "department_store",
+    "discount_store",
+    "doctor",
+    "dog_park",
+    "drugstore",
+    "electric_vehicle_charging_station",
+    "electrician",
+    "electronics_store",
+    "embassy",
+    "event_venue",
+    "extended_stay_hotel",
+    "farm",
+    "farmstay",
+    "fast_food_restaurant",
+    "ferry_terminal",
+    "fire_station",
+    "fitness_center",
+    "florist",
+    "french_restaurant",
+    "funeral_home",
+    "furniture_store",
+    "gas_station",
+    "gift_shop",
+    "golf_course",
+    "greek_restaurant",
+    "grocery_store",
+    "guest_house",
+    "gym",
+    "hair_care",
+    "hair_salon",
+    "hamburger_restaurant",
+    "hardware_store",
+    "heliport",
+    "hiking_area",
+    "hindu_temple",
+    "historical_landmark",
+    "home_goods_store",
+    "home_improvement_store",
+    "hospital",
+    "hostel",
+    "hotel",
+    "ice_cream_shop",
+    "indian_restaurant",
+    "indonesian_restaurant",
+    "insurance_agency",
+    "italian_restaurant",
+    "japanese_restaurant",
+    "jewelry_store",
+    "korean_restaurant",
+    "laundry",
+    "lawyer",
+    "lebanese_restaurant",
+    "library",
+    "light_rail_station",
+    "liquor_store",
+    "local_government_office",
+    "locality",
+    "locksmith",
+    "lodging",
+    "marina",
+    "market",
+    "meal_delivery",
+    "meal_takeaway",
+    "medical_lab",
+    "mediterranean_restaurant",
+    "mexican_restaurant",
+    "middle_eastern_restaurant",
+    "mosque",
+    "motel",
+    "movie_rental",
+    "movie_theater",
+    "moving_company",
+    "museum",
+    "national_park",
+    "night_club",
+    "painter",
+    "park",
+    "park_and_ride",
+    "parking",
+    "performing_arts_theater",
+    "pet_store",
+    "pharmacy",
+    "physiotherapist",
+    "pizza_restaurant",
+    "playground",
+    "plumber",
+    "police",
+    "post_office",
+    "postal_code",
+    "preschool",
+    "primary_school",
+    "private_guest_room",
+    "ramen_restaurant",
+    "real_estate_agency",
+    "resort_hotel",
+    "rest_stop",
+    "restaurant",
+    "roofing_contractor",
+    "rv_park",
+    "sandwich_shop",
+    "school",
+    "school_district",
+    "seafood_restaurant",
+    "secondary_school",
+    "shoe_store",
+    "shopping_mall",
+    "ski_resort",
+    "spa",
+    "spanish_restaurant",
+    "sporting_goods_store",
+    "sports_club",
+    "sports_complex",
+    "stadium",
+    "steak_house",
+    "storage",
+    "store",
+    "subway_station",
+    "supermarket",
+    "sushi_restaurant",
+    "swimming_pool",
+    "synagogue",
+    "tailor",
+    "taxi_stand",
+    "telecommunications_service_provider",
+    "thai_restaurant",
+    "tourist_attraction",
+    "train_station",
+    "transit_depot",
+    "transit_station",
+    "travel_agency",
+    "truck_stop",
+    "turkish_restaurant",
+    "university",
+    "vegan_restaurant",
+    "vegetarian_restaurant",
+    "veterinary_care",
+    "vietnamese_restaurant",
+    "visitor_center",
+    "wedding_venue",
+    "wholesaler",
+    "zoo",
+];
+
+pub fn is_place_type(value: &str) -> bool {
+    PLACE_TYPES.binary_search(&value).is_ok()
+}
@@ -0,0 +1,361 @@
+use axum::{extract::State, Json};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use validator::Validate;
+
+use crate::{cache, db::trips::Coordinate, error::AppError, upstream, usage, AppState};
+
+use super::{
+    cached, duration_seconds, fetch_place, google_provider, search_places, store, validation,
+    waypoint, GooglePlace, TravelMode, CONTENT_TYPE, GOOGLE_API_KEY_HEADER,
+    GOOGLE_FIELD_MASK_HEADER, JSON_TYPE,
+};
+
+const PLAN_FIELD_MASK: &str = "routes.distanceMeters,routes.duration,\
+    routes.polyline.encodedPolyline,routes.optimizedIntermediateWaypointIndex,\
+    routes.legs.distanceMeters,routes.legs.duration";
+
+/// A place given by Google place id, by a search resolved to its best match, or by
+/// coordinates. Exactly one is expected.
+#[derive(Debug, Deserialize, Serialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanPlace {
+    #[validate(length(min = 1, max = 256))]
+    place_id: Option<String>,
+    #[validate(length(min = 1, max = 512), custom = "validation::not_blank")]
+    query: Option<String>,
+    #[validate]
+    location: Option<Coordinate>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanStop {
+    #[serde(flatten)]
+    #[validate]
+    place: PlanPlace,
+    /// Time spent at the stop before moving on
+    #[serde(default)]
+    #[validate(range(max = 720, message = "must be at most 720"))]
+    stay_minutes: u32,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanRequest {
+    #[validate]
+    start: PlanPlace,
+    #[validate]
+    end: PlanPlace,
+    /// Visited in the order that makes the trip shortest. Google reorders at most 25
+    #[validate(length(min = 1, max = 25))]
+    stops: Vec<PlanStop>,
+    /// Driving when left out. Transit can't route through stops
+    travel_mode: Option<TravelMode>,
+    /// Now when left out
+    #[validate(custom = "validation::rfc3339")]
+    departure_time: Option<String>,
+    /// Latest acceptable arrival at the end
+    #[validate(custom = "validation::rfc3339")]
+    arrive_by: Option<String>,
+}
+
+/// A place of the itinerary with when it's reached and left.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedStop {
+    /// Position in the request's `stops`, absent for the start and end
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_index: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    place_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    location: Coordinate,
+    arrival: DateTime<Utc>,
+    departure: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlannedLeg {
+    distance_meters: f64,
+    duration_seconds: f64,
+    departure: DateTime<Utc>,
+    arrival: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Itinerary {
+    /// Start, stops in visiting order, then end
+    stops: Vec<PlannedStop>,
+    /// One less than the stops, `legs[i]` goes from `stops[i]` to `stops[i + 1]`
+    legs: Vec<PlannedLeg>,
+    total_distance_meters: f64,
+    /// Travel and time spent at stops
+    total_duration_seconds: f64,
+    arrival: DateTime<Utc>,
+    /// Whether `arrival` is no later than `arriveBy`, absent without it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    meets_deadline: Option<bool>,
+    encoded_polyline: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PlannedRoute {
+    #[serde(default)]
+    distance_meters: f64,
+    duration: String,
+    polyline: PlannedPolyline,
+    // Left out when nothing was reordered
+    #[serde(default)]
+    optimized_intermediate_waypoint_index: Vec<usize>,
+    #[serde(default)]
+    legs: Vec<RouteLeg>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PlannedPolyline {
+    encoded_polyline: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RouteLeg {
+    #[serde(default)]
+    distance_meters: f64,
+    duration: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct PlannedRoutes {
+    #[serde(default)]
+    routes: Vec<PlannedRoute>,
+}
+
+/// A request place once resolved to coordinates.
+struct Resolved {
+    place_id: Option<String>,
+    name: Option<String>,
+    location: Coordinate,
+}
+
+impl From<GooglePlace> for Resolved {
+    fn from(place: GooglePlace) -> Self {
+        Resolved {
+            location: place.coordinate(),
+            place_id: Some(place.id),
+            name: Some(place.display_name.text),
+        }
+    }
+}
+
+fn timestamp(value: &str) -> DateTime<Utc> {
+    // Validated as RFC 3339 beforehand
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_else(|_| Utc::now())
+}
+
+fn plus_seconds(time: DateTime<Utc>, seconds: f64) -> DateTime<Utc> {
+    time + ChronoDuration::milliseconds((seconds * 1000.0).round() as i64)
+}
+
+/// Resolves the start, end and stops, has Google order the stops for the shortest trip
+/// and returns the itinerary with an arrival and departure time at each place.
+pub async fn plan_trip(
+    State(s): State<AppState>,
+    Json(body): Json<PlanRequest>,
+) -> Result<Json<Itinerary>, AppError> {
+    body.validate()?;
+    let travel_mode = body.travel_mode.unwrap_or(TravelMode::Drive);
+    if travel_mode == TravelMode::Transit {
+        return Err(AppError::Validation(
+            "travelMode TRANSIT can't route through stops".into(),
+        ));
+    }
+    let departure = body
+        .departure_time
+        .as_deref()
+        .map_or_else(Utc::now, timestamp);
+
+    let start = resolve(&s, &body.start, "start").await?;
+    let end = resolve(&s, &body.end, "end").await?;
+    let mut stops = Vec::with_capacity(body.stops.len());
+    for (i, stop) in body.stops.iter().enumerate() {
+        stops.push(resolve(&s, &stop.place, &format!("stops[{}]", i)).await?);
+    }
+
+    let mut req = json!({
+        "origin": waypoint(start.location.latitude, start.location.longitude),
+        "destination": waypoint(end.location.latitude, end.location.longitude),
+        "intermediates": stops
+            .iter()
+            .map(|stop| waypoint(stop.location.latitude, stop.location.longitude))
+            .collect::<Vec<_>>(),
+        "travelMode": travel_mode.as_str(),
+        "optimizeWaypointOrder": true,
+        "languageCode": "en-US",
+        "units": "METRIC",
+    });
+    // Google doesn't optimize waypoints for the traffic aware optimal preference
+    if travel_mode.is_motorized() {
+        req["routingPreference"] = json!("TRAFFIC_AWARE");
+    }
+    // Google assumes now, leaving it out keeps the body cacheable
+    if let Some(departure_time) = &body.departure_time {
+        req["departureTime"] = json!(departure_time);
+    }
+    let route = fetch_plan(&s, &req)
+        .await?
+        .routes
+        .into_iter()
+        .next()
+        .ok_or_else(|| AppError::NotFound("No route found through these stops".into()))?;
+
+    // Without a reordering Google sends no index, the stops stay as given
+    let order: Vec<usize> = if route.optimized_intermediate_waypoint_index.len() == stops.len() {
+        route.optimized_intermediate_waypoint_index.clone()
+    } else {
+        (0..stops.len()).collect()
+    };
+    if route.legs.len() != order.len() + 1 {
+        return Err(AppError::ParseError(format!(
+            "expected {} legs, got {}",
+            order.len() + 1,
+            route.legs.len()
+        )));
+    }
+
+    let mut stops: Vec<Option<Resolved>> = stops.into_iter().map(Some).collect();
+    let mut visits: Vec<(Option<usize>, Resolved, u32)> = vec![(None, start, 0)];
+    for &i in &order {
+        let stop = stops
+            .get_mut(i)
+            .and_then(Option::take)
+            .ok_or_else(|| AppError::ParseError(format!("invalid waypoint index {}", i)))?;
+        visits.push((Some(i), stop, body.stops[i].stay_minutes));
+    }
+    visits.push((None, end, 0));
+
+    let mut planned = Vec::with_capacity(visits.len());
+    let mut legs = Vec::with_capacity(route.legs.len());
+    let mut time = departure;
+    for (n, (stop_index, place, stay_minutes)) in visits.into_iter().enumerate() {
+        let arrival = time;
+        if n > 0 {
+            // The start is left at the departure time
+            time = plus_seconds(time, f64::from(stay_minutes) * 60.0);
+        }
+        planned.push(PlannedStop {
+            stop_index,
+            place_id: place.place_id,
+            name: place.name,
+            location: place.location,
+            arrival,
+            departure: time,
+        });
+
+        if let Some(leg) = route.legs.get(n) {
+            let duration = duration_seconds(&leg.duration).unwrap_or_default();
+            let leg_departure = time;
+            time = plus_seconds(time, duration);
+            legs.push(PlannedLeg {
+                distance_meters: leg.distance_meters,
+                duration_seconds: duration,
+                departure: leg_departure,
+                arrival: time,
+            });
+        }
+    }
+    // The end has no stay, so it's left when reached
+    let arrival = planned.last().map_or(time, |stop| stop.arrival);
+
+    Ok(Json(Itinerary {
+        total_distance_meters: legs.iter().map(|leg| leg.distance_meters).sum(),
+        total_duration_seconds: (arrival - departure).num_milliseconds() as f64 / 1000.0,
+        meets_deadline: body.arrive_by.as_deref().map(|t| arrival <= timestamp(t)),
+        arrival,
+        stops: planned,
+        legs,
+        encoded_polyline: route.polyline.encoded_polyline,
+    }))
+}
+
+async fn resolve(s: &AppState, place: &PlanPlace, field: &str) -> Result<Resolved, AppError> {
+    match (&place.place_id, &place.query, place.location) {
+        (Some(id), None, None) => {
+            let key = cache::place_key(id, google_provider());
+            if let Some(place) = cached::<GooglePlace>(s, &key).await {
+                return Ok(place.into());
+            }
+            let found = fetch_place(s, id)
+                .await?
+                .ok_or_else(|| AppError::NotFound(format!("{} place not found", field)))?;
+            store(s, &key, &found).await;
+            Ok(found.into())
+        }
+        (None, Some(query), None) => search_places(s, query.clone())
+            .await?
+            .places
+            .and_then(|places| places.into_iter().next())
+            .map(Resolved::from)
+            .ok_or_else(|| AppError::NotFound(format!("No place found for {}", field))),
+        (None, None, Some(location)) => Ok(Resolved {
+            place_id: None,
+            name: None,
+            location,
+        }),
+        _ => Err(AppError::Validation(format!(
+            "{} needs exactly one of placeId, query and location",
+            field
+        ))),
+    }
+}
+
+async fn fetch_plan(s: &AppState, req: &Value) -> Result<PlannedRoutes, AppError> {
+    let cache_key = cache::routes_key(req, google_provider());
+    if let Some(routes) = cached::<PlannedRoutes>(s, &cache_key).await {
+        return Ok(routes);
+    }
+
+    let (status, body) = upstream::send_with_keys(
+        s.keys(),
+        &s.routes_breaker,
+        &s.retry_policy,
+        &s.routes_metrics,
+        |key| {
+            s.client_reqwest
+                .post(&s.urls.routes)
+                .json(req)
+                .header(GOOGLE_FIELD_MASK_HEADER, PLAN_FIELD_MASK)
+                .header(CONTENT_TYPE, JSON_TYPE)
+                .header(GOOGLE_API_KEY_HEADER, key)
+        },
+    )
+    .await
+    .inspect_err(
+        |e| tracing::error!(error = %e, provider = "google-routes", "upstream request failed"),
+    )?;
+
+    if !status.is_success() {
+        return Err(upstream::google_error::translate(
+            "google-routes",
+            status,
+            &body,
+        ));
+    }
+    s.usage.record(usage::routes_sku(req));
+
+    let routes = serde_json::from_slice::<PlannedRoutes>(&body).map_err(|e| {
+        tracing::error!(error = %e, provider = "google-routes", "failed to parse upstream response");
+        AppError::ParseError(e.to_string())
+    })?;
+    store(s, &cache_key, &routes).await;
+
+    Ok(routes)
+}
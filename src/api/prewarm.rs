@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use chrono::{Duration as ChronoDuration, Utc};
+use sqlx::PgPool;
+
+use crate::{cache, db, error::AppError, flags::Flag, AppState};
+
+use super::{fetch_places, google_provider, store, text_search_body, GooglePlacesRequest};
+
+// How long before the cached copies expire they're replaced
+const MAX_LEAD: Duration = Duration::from_secs(60);
+
+/// What the pre-warming task replays.
+#[derive(Clone, Copy, Debug)]
+pub struct PrewarmSettings {
+    /// Number of queries kept hot
+    pub top: i64,
+    /// How far back the search history is counted
+    pub window: Duration,
+    pub cache_ttl: Duration,
+}
+
+async fn refresh(s: &AppState, query: String) -> Result<(), AppError> {
+    let request = GooglePlacesRequest {
+        text_query: query,
+        ..Default::default()
+    };
+    let body = text_search_body(&request);
+    let fan_out = s.flags.enabled(Flag::ProviderFanOut, None);
+    let result = fetch_places(s, &body, fan_out).await?;
+    store(s, &cache::places_key(&body, google_provider()), &result).await;
+
+    Ok(())
+}
+
+/// Searches the most frequent recent queries again shortly before their cached copy
+/// expires, so they're always answered from the cache. Only the default search options
+/// are kept hot, and every instance running this spends its own upstream calls.
+pub fn spawn_prewarm(s: AppState, pool: PgPool, settings: PrewarmSettings) {
+    let lead = MAX_LEAD.min(settings.cache_ttl / 5);
+    let every = settings.cache_ttl - lead;
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(every);
+        loop {
+            interval.tick().await;
+            let window =
+                ChronoDuration::from_std(settings.window).unwrap_or(ChronoDuration::days(1));
+            let queries = match db::history::popular(&pool, Utc::now() - window, settings.top).await
+            {
+                Ok(queries) => queries,
+                Err(e) => {
+                    tracing::warn!(error = %e, "failed to look up popular queries");
+                    continue;
+                }
+            };
+            for query in queries {
+                if let Err(e) = refresh(&s, query).await {
+                    tracing::warn!(error = %e, "failed to pre-warm popular query");
+                }
+            }
+        }
+    });
+}
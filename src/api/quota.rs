@@ -0,0 +1,15 @@
+use axum::{extract::State, Json};
+
+use crate::{error::AppError, identity::Identity, middleware::QuotaStatus, AppState};
+
+pub async fn get_quota(
+    State(s): State<AppState>,
+    identity: Identity,
+) -> Result<Json<QuotaStatus>, AppError> {
+    let quotas = s
+        .quotas
+        .as_deref()
+        .ok_or_else(|| AppError::NotFound("Quotas are disabled".into()))?;
+
+    Ok(Json(quotas.status(&identity.0).await?))
+}
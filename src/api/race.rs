@@ -0,0 +1,70 @@
+use std::{future::Future, sync::Arc};
+
+use crate::{
+    error::AppError,
+    upstream::{CircuitBreaker, EndpointMetrics, KeyPool},
+};
+
+/// A second Text Search endpoint speaking the Places API (New), e.g. a proxy in another
+/// region, queried alongside Google when `PLACES_RACE_URL` is set.
+pub struct RaceProvider {
+    pub(super) url: String,
+    pub(super) keys: KeyPool,
+    pub(super) breaker: Arc<CircuitBreaker>,
+    pub(super) metrics: Arc<EndpointMetrics>,
+}
+
+impl RaceProvider {
+    pub fn new(
+        url: String,
+        keys: KeyPool,
+        breaker: Arc<CircuitBreaker>,
+        metrics: Arc<EndpointMetrics>,
+    ) -> Self {
+        RaceProvider {
+            url,
+            keys,
+            breaker,
+            metrics,
+        }
+    }
+}
+
+/// Runs both calls at once and returns the first successful answer `useful` accepts,
+/// dropping the other call. When neither is useful, the primary's answer wins unless only
+/// the secondary succeeded.
+pub(super) async fn first_success<T, P, S>(
+    primary: P,
+    secondary: S,
+    useful: impl Fn(&T) -> bool,
+) -> Result<T, AppError>
+where
+    P: Future<Output = Result<T, AppError>>,
+    S: Future<Output = Result<T, AppError>>,
+{
+    tokio::pin!(primary, secondary);
+    let mut primary_result = None;
+    let mut secondary_result = None;
+    while primary_result.is_none() || secondary_result.is_none() {
+        tokio::select! {
+            result = &mut primary, if primary_result.is_none() => {
+                if matches!(&result, Ok(value) if useful(value)) {
+                    return result;
+                }
+                primary_result = Some(result);
+            }
+            result = &mut secondary, if secondary_result.is_none() => {
+                if matches!(&result, Ok(value) if useful(value)) {
+                    return result;
+                }
+                secondary_result = Some(result);
+            }
+        }
+    }
+
+    match (primary_result, secondary_result) {
+        (Some(Ok(value)), _) | (_, Some(Ok(value))) => Ok(value),
+        (Some(Err(e)), _) => Err(e),
+        _ => Err(AppError::Unavailable),
+    }
+}
@@ -0,0 +1,92 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    db::saved_places::{self, NewSavedPlace, SavedPlace},
+    error::AppError,
+    identity::Identity,
+    AppState,
+};
+
+use super::database;
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct SavePlaceRequest {
+    #[validate(length(min = 1, max = 256))]
+    place_id: String,
+    #[validate(length(min = 1, max = 256))]
+    name: String,
+    #[validate(range(min = -90.0, max = 90.0))]
+    latitude: f64,
+    #[validate(range(min = -180.0, max = 180.0))]
+    longitude: f64,
+    #[validate(length(max = 2000))]
+    notes: Option<String>,
+}
+
+pub async fn create_saved_place(
+    State(s): State<AppState>,
+    identity: Identity,
+    Json(body): Json<SavePlaceRequest>,
+) -> Result<(StatusCode, Json<SavedPlace>), AppError> {
+    body.validate()?;
+    let pool = database(&s)?;
+
+    let place = saved_places::insert(
+        pool,
+        NewSavedPlace {
+            owner: identity.0,
+            place_id: body.place_id,
+            name: body.name,
+            latitude: body.latitude,
+            longitude: body.longitude,
+            notes: body.notes,
+        },
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(place)))
+}
+
+pub async fn list_saved_places(
+    State(s): State<AppState>,
+    identity: Identity,
+) -> Result<Json<Vec<SavedPlace>>, AppError> {
+    let pool = database(&s)?;
+
+    Ok(Json(saved_places::list(pool, &identity.0).await?))
+}
+
+pub async fn get_saved_place(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+) -> Result<Json<SavedPlace>, AppError> {
+    let pool = database(&s)?;
+
+    saved_places::get(pool, id, &identity.0)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound("Saved place not found".into()))
+}
+
+pub async fn delete_saved_place(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let pool = database(&s)?;
+
+    if saved_places::delete(pool, id, &identity.0).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound("Saved place not found".into()))
+    }
+}
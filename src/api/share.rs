@@ -0,0 +1,223 @@
+use std::fmt::Write;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{
+        header::{ACCEPT, CACHE_CONTROL},
+        HeaderMap, StatusCode,
+    },
+    response::{Html, IntoResponse, Response},
+    Json,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::{
+    db::shared_routes::{self, NewSharedRoute, SharedRoute},
+    error::AppError,
+    geo,
+    identity::Identity,
+    AppState,
+};
+
+use super::{database, kml::escape, TravelMode};
+
+const SLUG_LENGTH: usize = 8;
+// Random slugs collide about never, a few tries is plenty
+const SLUG_ATTEMPTS: usize = 3;
+// A shared route never changes
+const SHARED_CACHE_CONTROL: &str = "public, max-age=86400";
+const PREVIEW_WIDTH: f64 = 600.0;
+const PREVIEW_HEIGHT: f64 = 400.0;
+const PREVIEW_PADDING: f64 = 20.0;
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareRouteRequest {
+    #[validate(length(min = 1, max = 256))]
+    title: Option<String>,
+    /// Driving when left out
+    travel_mode: Option<TravelMode>,
+    #[validate(length(min = 1, max = 100000))]
+    encoded_polyline: String,
+    #[validate(range(min = 0.0))]
+    distance_meters: Option<f64>,
+    /// Seconds with an `s` suffix, e.g. "165s", as computed
+    #[validate(length(max = 32))]
+    duration: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareResponse {
+    /// Where the route can be fetched, relative to the server
+    path: String,
+    #[serde(flatten)]
+    route: SharedRoute,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SharedRouteQuery {
+    /// `html` or `json`, otherwise taken from the Accept header
+    format: Option<String>,
+}
+
+fn slug() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(SLUG_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+/// Stores a computed route under a short slug, served to anyone on `/r/:slug`.
+pub async fn share_route(
+    State(s): State<AppState>,
+    identity: Option<Identity>,
+    Json(body): Json<ShareRouteRequest>,
+) -> Result<(StatusCode, Json<ShareResponse>), AppError> {
+    body.validate()?;
+    if geo::polyline::decode(&body.encoded_polyline).is_none_or(|path| path.is_empty()) {
+        return Err(AppError::Validation(
+            "encodedPolyline is not a valid polyline".into(),
+        ));
+    }
+    let pool = database(&s)?;
+
+    let route = NewSharedRoute {
+        owner: identity.map(|identity| identity.0),
+        title: body.title,
+        travel_mode: body
+            .travel_mode
+            .unwrap_or(TravelMode::Drive)
+            .as_str()
+            .into(),
+        encoded_polyline: body.encoded_polyline,
+        distance_meters: body.distance_meters,
+        duration: body.duration,
+    };
+    for _ in 0..SLUG_ATTEMPTS {
+        if let Some(route) = shared_routes::insert(pool, &slug(), &route).await? {
+            return Ok((
+                StatusCode::CREATED,
+                Json(ShareResponse {
+                    path: format!("/r/{}", route.slug),
+                    route,
+                }),
+            ));
+        }
+    }
+
+    Err(AppError::Database("no free share slug".into()))
+}
+
+/// A shared route as JSON, or as an HTML page drawing it for browsers.
+pub async fn get_shared_route(
+    State(s): State<AppState>,
+    Path(slug): Path<String>,
+    Query(query): Query<SharedRouteQuery>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let pool = database(&s)?;
+    let route = shared_routes::get(pool, &slug)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Shared route not found".into()))?;
+
+    let html = match query.format.as_deref() {
+        Some("html") => true,
+        Some("json") => false,
+        Some(_) => return Err(AppError::Validation("format must be html or json".into())),
+        None => headers
+            .get(ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.contains("text/html")),
+    };
+    let headers = [(CACHE_CONTROL, SHARED_CACHE_CONTROL)];
+
+    if html {
+        Ok((headers, Html(preview(&route))).into_response())
+    } else {
+        Ok((headers, Json(route)).into_response())
+    }
+}
+
+fn summary(route: &SharedRoute) -> String {
+    let mut parts = vec![route.travel_mode.to_lowercase().replace('_', " ")];
+    if let Some(meters) = route.distance_meters {
+        parts.push(format!("{:.1} km", meters / 1000.0));
+    }
+    if let Some(seconds) = route
+        .duration
+        .as_deref()
+        .and_then(|d| d.strip_suffix('s'))
+        .and_then(|d| d.parse::<f64>().ok())
+    {
+        parts.push(format!("{} min", (seconds / 60.0).round()));
+    }
+
+    parts.join(" · ")
+}
+
+// The route drawn in Web Mercator, scaled to fit the preview with its aspect kept
+fn svg_path(route: &SharedRoute) -> String {
+    let points: Vec<(f64, f64)> = geo::polyline::decode(&route.encoded_polyline)
+        .unwrap_or_default()
+        .into_iter()
+        .map(geo::mercator)
+        .collect();
+    let (min_x, max_x, min_y, max_y) = points.iter().fold(
+        (f64::MAX, f64::MIN, f64::MAX, f64::MIN),
+        |(min_x, max_x, min_y, max_y), &(x, y)| {
+            (min_x.min(x), max_x.max(x), min_y.min(y), max_y.max(y))
+        },
+    );
+    let span = (max_x - min_x).max(max_y - min_y).max(f64::EPSILON);
+    let scale = (PREVIEW_WIDTH.min(PREVIEW_HEIGHT) - 2.0 * PREVIEW_PADDING) / span;
+    let offset_x = (PREVIEW_WIDTH - (max_x - min_x) * scale) / 2.0;
+    let offset_y = (PREVIEW_HEIGHT - (max_y - min_y) * scale) / 2.0;
+
+    let mut d = String::new();
+    for (i, (x, y)) in points.iter().enumerate() {
+        // Writing to a String can't fail
+        let _ = write!(
+            d,
+            "{}{:.1},{:.1} ",
+            if i == 0 { "M" } else { "L" },
+            offset_x + (x - min_x) * scale,
+            offset_y + (y - min_y) * scale,
+        );
+    }
+
+    d
+}
+
+fn preview(route: &SharedRoute) -> String {
+    let title = escape(route.title.as_deref().unwrap_or("Shared route"));
+    format!(
+        "<!DOCTYPE html>\n\
+         <html lang=\"en\">\n\
+         <head>\n\
+         <meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{title}</title>\n\
+         <meta property=\"og:title\" content=\"{title}\">\n\
+         <meta property=\"og:description\" content=\"{summary}\">\n\
+         </head>\n\
+         <body style=\"font-family: sans-serif; max-width: {width}px; margin: 2em auto\">\n\
+         <h1>{title}</h1>\n\
+         <p>{summary}</p>\n\
+         <svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {width} {height}\" \
+         style=\"width: 100%; background: #f4f4f4\">\n\
+         <path d=\"{path}\" fill=\"none\" stroke=\"#1a73e8\" stroke-width=\"4\" \
+         stroke-linejoin=\"round\" stroke-linecap=\"round\"/>\n\
+         </svg>\n\
+         </body>\n\
+         </html>\n",
+        title = title,
+        summary = escape(&summary(route)),
+        width = PREVIEW_WIDTH,
+        height = PREVIEW_HEIGHT,
+        path = svg_path(route),
+    )
+}
@@ -0,0 +1,77 @@
+use axum::{
+    extract::{Path, State},
+    http::header::CONTENT_TYPE,
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    db::{geofences, saved_places},
+    error::AppError,
+    geo::{
+        self,
+        mvt::{self, LayerBuilder, TileId},
+    },
+    identity::Identity,
+    AppState,
+};
+
+use super::database;
+
+const MVT_CONTENT_TYPE: &str = "application/vnd.mapbox-vector-tile";
+// Past this a tile is too dense to be useful, clients should zoom in
+const MAX_TILE_PLACES: i64 = 10_000;
+
+/// The caller's saved places and geofences as a Mapbox Vector Tile with a `places` and a
+/// `geofences` layer.
+pub async fn vector_tile(
+    State(s): State<AppState>,
+    identity: Option<Identity>,
+    Path((z, x, y)): Path<(u8, u32, u32)>,
+) -> Result<Response, AppError> {
+    let tile = TileId::new(z, x, y).ok_or_else(|| AppError::NotFound("No such tile".into()))?;
+    let pool = database(&s)?;
+    let (south_west, north_east) = tile.bounds();
+
+    let mut places = LayerBuilder::new(tile, "places");
+    let mut fences = LayerBuilder::new(tile, "geofences");
+    // Both layers hold the caller's own data, anonymous callers get an empty tile
+    if let Some(identity) = identity {
+        let owner = identity.0.as_str();
+        for place in
+            saved_places::within(pool, owner, south_west, north_east, MAX_TILE_PLACES).await?
+        {
+            let id = place.id.to_string();
+            places.point(
+                place.location(),
+                &[
+                    ("id", id.as_str()),
+                    ("placeId", place.place_id.as_str()),
+                    ("name", place.name.as_str()),
+                ],
+            );
+        }
+
+        for fence in geofences::list(pool, owner).await? {
+            let outline = fence.shape.outline();
+            let overlaps = geo::bounding_box(&outline).is_some_and(|(low, high)| {
+                low.latitude <= north_east.latitude
+                    && high.latitude >= south_west.latitude
+                    && low.longitude <= north_east.longitude
+                    && high.longitude >= south_west.longitude
+            });
+            if overlaps {
+                let id = fence.id.to_string();
+                fences.polygon(
+                    &outline,
+                    &[("id", id.as_str()), ("name", fence.name.as_str())],
+                );
+            }
+        }
+    }
+
+    Ok((
+        [(CONTENT_TYPE, MVT_CONTENT_TYPE)],
+        mvt::encode(vec![places, fences]),
+    )
+        .into_response())
+}
@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// What the tolls of a route are estimated to cost in one currency.
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TollCost {
+    currency_code: String,
+    amount: f64,
+}
+
+// computeRoutes with `routes.travelAdvisory.tollInfo`, left out of routes without tolls
+#[derive(Debug, Deserialize)]
+pub(super) struct Routes {
+    #[serde(default)]
+    routes: Vec<Route>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Route {
+    travel_advisory: Option<TravelAdvisory>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TravelAdvisory {
+    toll_info: Option<TollInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TollInfo {
+    #[serde(default)]
+    estimated_price: Vec<Money>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Money {
+    currency_code: String,
+    // int64 comes as a string
+    #[serde(default)]
+    units: Option<String>,
+    #[serde(default)]
+    nanos: i64,
+}
+
+impl Routes {
+    /// The tolls of each route, in the order of the routes. `None` for a route without
+    /// tolls, empty when it has some Google can't price.
+    pub(super) fn tolls(self) -> Vec<Option<Vec<TollCost>>> {
+        self.routes
+            .into_iter()
+            .map(|route| {
+                let toll_info = route.travel_advisory?.toll_info?;
+                Some(
+                    toll_info
+                        .estimated_price
+                        .into_iter()
+                        .map(Money::cost)
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+}
+
+impl Money {
+    fn cost(self) -> TollCost {
+        let units = self
+            .units
+            .as_deref()
+            .and_then(|u| u.parse::<f64>().ok())
+            .unwrap_or(0.0);
+        let amount = units + self.nanos as f64 / 1e9;
+
+        TollCost {
+            currency_code: self.currency_code,
+            amount: (amount * 100.0).round() / 100.0,
+        }
+    }
+}
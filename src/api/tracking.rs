@@ -0,0 +1,260 @@
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, State,
+    },
+    response::Response,
+};
+use serde::Serialize;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    db::trips::{self, Coordinate, Trip},
+    error::AppError,
+    geo::{self, Projection},
+    identity::Identity,
+    AppState,
+};
+
+use super::{database, fetch_routes, routes_body, waypoint, TravelMode};
+
+// Clients are expected to report at least every few seconds while navigating
+const IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+const ARRIVAL_METERS: f64 = 30.0;
+
+/// When a reported position counts as off route, and how often that may trigger a new route.
+#[derive(Clone, Copy, Debug)]
+pub struct TrackingSettings {
+    pub deviation_meters: f64,
+    pub reroute_interval: Duration,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum RouteReason {
+    Initial,
+    Deviation,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(
+    tag = "type",
+    rename_all = "lowercase",
+    rename_all_fields = "camelCase"
+)]
+enum ServerMessage<'a> {
+    Route {
+        reason: RouteReason,
+        encoded_polyline: &'a str,
+        distance_meters: f64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        duration: Option<&'a str>,
+    },
+    Progress {
+        off_route_meters: f64,
+        remaining_meters: f64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        eta_seconds: Option<u64>,
+        arrived: bool,
+    },
+    Error {
+        code: &'static str,
+        message: String,
+    },
+}
+
+/// The route currently followed, decoded once so each position is a cheap projection.
+struct ActiveRoute {
+    path: Vec<Coordinate>,
+    length: f64,
+    encoded_polyline: String,
+    distance_meters: f64,
+    duration: Option<String>,
+}
+
+impl ActiveRoute {
+    fn new(
+        encoded_polyline: String,
+        distance_meters: Option<f64>,
+        duration: Option<String>,
+    ) -> Result<Self, AppError> {
+        let path = geo::polyline::decode(&encoded_polyline)
+            .filter(|path| !path.is_empty())
+            .ok_or_else(|| AppError::ParseError("invalid route polyline".into()))?;
+        let length = geo::path_length(&path);
+
+        Ok(ActiveRoute {
+            distance_meters: distance_meters.unwrap_or(length),
+            path,
+            length,
+            encoded_polyline,
+            duration,
+        })
+    }
+
+    fn message(&self, reason: RouteReason) -> ServerMessage<'_> {
+        ServerMessage::Route {
+            reason,
+            encoded_polyline: &self.encoded_polyline,
+            distance_meters: self.distance_meters,
+            duration: self.duration.as_deref(),
+        }
+    }
+
+    fn project(&self, position: Coordinate) -> Projection {
+        // A decoded route always has a point, so there is always a projection
+        geo::project(&self.path, position).unwrap()
+    }
+
+    // The ETA scales the route's duration by the share of the route still ahead
+    fn progress(&self, position: Coordinate, projection: Projection) -> ServerMessage<'static> {
+        let remaining = (self.length - projection.along_path_meters).max(0.0);
+        let fraction = if self.length > 0.0 {
+            remaining / self.length
+        } else {
+            0.0
+        };
+        let seconds = self
+            .duration
+            .as_deref()
+            .and_then(|d| d.strip_suffix('s'))
+            .and_then(|d| d.parse::<f64>().ok());
+        let end = *self.path.last().unwrap();
+
+        ServerMessage::Progress {
+            off_route_meters: projection.off_path_meters,
+            remaining_meters: remaining,
+            eta_seconds: seconds.map(|s| (s * fraction).round() as u64),
+            arrived: geo::haversine(position, end) <= ARRIVAL_METERS,
+        }
+    }
+}
+
+/// Live navigation for a saved trip. The client sends its position as
+/// `{"latitude": .., "longitude": ..}` text messages and gets progress with an ETA back for
+/// each, plus a new route whenever it strays too far from the current one.
+pub async fn track_route(
+    ws: WebSocketUpgrade,
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(trip_id): Path<Uuid>,
+) -> Result<Response, AppError> {
+    let pool = database(&s)?;
+    let trip = trips::get(pool, trip_id, &identity.0)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Trip not found".into()))?;
+    let route = ActiveRoute::new(
+        trip.encoded_polyline.clone(),
+        trip.distance_meters,
+        trip.duration.clone(),
+    )?;
+
+    Ok(ws.on_upgrade(move |socket| track(socket, s, trip, route)))
+}
+
+async fn track(mut socket: WebSocket, s: AppState, trip: Trip, mut route: ActiveRoute) {
+    let settings = s.tracking;
+    let mut last_reroute: Option<Instant> = None;
+
+    if !send(&mut socket, &route.message(RouteReason::Initial)).await {
+        return;
+    }
+
+    loop {
+        let text = match tokio::time::timeout(IDLE_TIMEOUT, socket.recv()).await {
+            Ok(Some(Ok(Message::Text(text)))) => text,
+            Ok(Some(Ok(Message::Close(_)))) | Ok(None) => break,
+            // Pings are answered by axum, binary frames aren't part of the protocol
+            Ok(Some(Ok(_))) => continue,
+            Ok(Some(Err(e))) => {
+                tracing::debug!(error = %e, trip_id = %trip.id, "tracking socket failed");
+                break;
+            }
+            Err(_) => {
+                tracing::debug!(trip_id = %trip.id, "closing idle tracking socket");
+                break;
+            }
+        };
+
+        let Some(position) = serde_json::from_str::<Coordinate>(&text)
+            .ok()
+            .filter(|p| p.validate().is_ok())
+        else {
+            let error = ServerMessage::Error {
+                code: "INVALID_POSITION",
+                message: "Expected {\"latitude\": .., \"longitude\": ..}".into(),
+            };
+            if !send(&mut socket, &error).await {
+                break;
+            }
+            continue;
+        };
+
+        let mut projection = route.project(position);
+        let may_reroute = last_reroute.is_none_or(|at| at.elapsed() >= settings.reroute_interval);
+        if projection.off_path_meters > settings.deviation_meters && may_reroute {
+            last_reroute = Some(Instant::now());
+            match reroute(&s, &trip, position).await {
+                Ok(rerouted) => {
+                    route = rerouted;
+                    projection = route.project(position);
+                    if !send(&mut socket, &route.message(RouteReason::Deviation)).await {
+                        break;
+                    }
+                }
+                // The old route stays in use, the next deviating position tries again
+                Err(e) => {
+                    let error = ServerMessage::Error {
+                        code: e.code(),
+                        message: e.message(),
+                    };
+                    if !send(&mut socket, &error).await {
+                        break;
+                    }
+                }
+            }
+        }
+
+        if !send(&mut socket, &route.progress(position, projection)).await {
+            break;
+        }
+    }
+}
+
+// A new route from where the client is to the trip's destination
+async fn reroute(s: &AppState, trip: &Trip, position: Coordinate) -> Result<ActiveRoute, AppError> {
+    let travel_mode = TravelMode::from_str(&trip.travel_mode)
+        .map_err(|_| AppError::Database(format!("unknown travel mode {}", trip.travel_mode)))?;
+    let req = routes_body(
+        waypoint(position.latitude, position.longitude),
+        waypoint(trip.destination.latitude, trip.destination.longitude),
+        Vec::new(),
+        travel_mode,
+        None,
+    );
+
+    let google_routes = fetch_routes(s, &req).await?;
+    let route = google_routes
+        .routes
+        .into_iter()
+        .next()
+        .ok_or_else(|| AppError::NotFound("No route found from this position".into()))?;
+
+    ActiveRoute::new(
+        route.polyline.encoded_polyline,
+        Some(f64::from(route.distance_meters)),
+        Some(route.duration),
+    )
+}
+
+async fn send(socket: &mut WebSocket, message: &ServerMessage<'_>) -> bool {
+    // Serializing these messages can't fail
+    let text = serde_json::to_string(message).unwrap();
+    socket.send(Message::Text(text)).await.is_ok()
+}
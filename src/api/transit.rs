@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// The vehicles of a transit route, for rendering the itinerary.
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+pub struct TransitItinerary {
+    /// In the order they're taken, the walks between them left out
+    rides: Vec<TransitRide>,
+    /// Changes from one vehicle to the next
+    transfers: usize,
+}
+
+/// One vehicle ridden from a stop to another.
+#[derive(Clone, Debug, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TransitRide {
+    /// e.g. "Sacramento - Reno"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line_name: Option<String>,
+    /// What's on the vehicle, e.g. "28"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line_short_name: Option<String>,
+    /// e.g. `BUS`, `SUBWAY`, `HEAVY_RAIL`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vehicle_type: Option<String>,
+    /// Hex color of the line, e.g. "#f5a623"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+    /// Where the vehicle is headed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    headsign: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    departure_stop: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(format = DateTime)]
+    departure_time: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    arrival_stop: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(format = DateTime)]
+    arrival_time: Option<String>,
+    /// Stops from the departure to the arrival, the arrival counted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop_count: Option<u32>,
+}
+
+// computeRoutes with `routes.legs.steps.transitDetails`, walking steps have no details
+#[derive(Debug, Deserialize)]
+pub(super) struct Routes {
+    #[serde(default)]
+    routes: Vec<Route>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Route {
+    #[serde(default)]
+    legs: Vec<Leg>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Leg {
+    #[serde(default)]
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Step {
+    transit_details: Option<TransitDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TransitDetails {
+    #[serde(default)]
+    stop_details: StopDetails,
+    headsign: Option<String>,
+    #[serde(default)]
+    transit_line: TransitLine,
+    stop_count: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StopDetails {
+    departure_stop: Option<Stop>,
+    departure_time: Option<String>,
+    arrival_stop: Option<Stop>,
+    arrival_time: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Stop {
+    name: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TransitLine {
+    name: Option<String>,
+    name_short: Option<String>,
+    color: Option<String>,
+    vehicle: Option<Vehicle>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Vehicle {
+    #[serde(rename = "type")]
+    kind: Option<String>,
+}
+
+impl Routes {
+    /// The itinerary of each route, in the order of the routes.
+    pub(super) fn itineraries(self) -> Vec<TransitItinerary> {
+        self.routes.into_iter().map(Route::itinerary).collect()
+    }
+}
+
+impl Route {
+    fn itinerary(self) -> TransitItinerary {
+        let rides: Vec<TransitRide> = self
+            .legs
+            .into_iter()
+            .flat_map(|leg| leg.steps)
+            .filter_map(|step| step.transit_details)
+            .map(TransitDetails::ride)
+            .collect();
+
+        TransitItinerary {
+            transfers: rides.len().saturating_sub(1),
+            rides,
+        }
+    }
+}
+
+impl TransitDetails {
+    fn ride(self) -> TransitRide {
+        let stop_name = |stop: Option<Stop>| stop.and_then(|s| s.name);
+
+        TransitRide {
+            line_name: self.transit_line.name,
+            line_short_name: self.transit_line.name_short,
+            vehicle_type: self.transit_line.vehicle.and_then(|v| v.kind),
+            color: self.transit_line.color,
+            headsign: self.headsign,
+            departure_stop: stop_name(self.stop_details.departure_stop),
+            departure_time: self.stop_details.departure_time,
+            arrival_stop: stop_name(self.stop_details.arrival_stop),
+            arrival_time: self.stop_details.arrival_time,
+            stop_count: self.stop_count,
+        }
+    }
+}
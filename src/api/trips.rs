@@ -0,0 +1,291 @@
+use std::{str::FromStr, time::Duration};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Duration as ChronoDuration, NaiveTime, SecondsFormat, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::PgPool;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    cache,
+    db::{
+        commutes,
+        trips::{self, Coordinate, NewTrip, Trip},
+    },
+    error::AppError,
+    identity::Identity,
+    AppState,
+};
+
+use super::{
+    cached, database, fetch_routes, google_provider, routes_body, store, validation, waypoint,
+    GetRoutesReponse, TravelMode,
+};
+
+// Google accepts at most 25 intermediate waypoints per computeRoutes call
+const MAX_WAYPOINTS: usize = 25;
+// How often the precompute worker looks for upcoming occurrences
+const PRECOMPUTE_TICK: Duration = Duration::from_secs(60);
+const PRECOMPUTE_BATCH: i64 = 50;
+// A route precomputed for an occurrence still answers a recompute this long after it
+const PRECOMPUTED_GRACE_MINUTES: i64 = 15;
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct SaveTripRequest {
+    #[validate(length(min = 1, max = 256))]
+    name: String,
+    #[validate]
+    origin: Coordinate,
+    #[validate]
+    destination: Coordinate,
+    #[serde(default)]
+    #[validate(length(max = 25), custom = "validation::coordinates")]
+    waypoints: Vec<Coordinate>,
+    travel_mode: TravelMode,
+    #[validate(length(min = 1))]
+    encoded_polyline: String,
+    distance_meters: Option<f64>,
+    duration: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct RecurrenceRequest {
+    /// ISO weekdays the trip is taken on, 1 is Monday
+    #[validate(length(min = 1, max = 7), custom = "validation::weekdays")]
+    weekdays: Vec<i16>,
+    /// Local departure time, e.g. "08:00"
+    time: NaiveTime,
+    /// IANA time zone of `time`, e.g. "Europe/Lisbon"
+    #[validate(length(min = 1, max = 64))]
+    time_zone: String,
+}
+
+pub async fn create_trip(
+    State(s): State<AppState>,
+    identity: Identity,
+    Json(body): Json<SaveTripRequest>,
+) -> Result<(StatusCode, Json<Trip>), AppError> {
+    body.validate()?;
+    let pool = database(&s)?;
+
+    let trip = trips::insert(
+        pool,
+        NewTrip {
+            owner: identity.0,
+            name: body.name,
+            origin: body.origin,
+            destination: body.destination,
+            waypoints: body.waypoints,
+            travel_mode: body.travel_mode.as_str().into(),
+            encoded_polyline: body.encoded_polyline,
+            distance_meters: body.distance_meters,
+            duration: body.duration,
+        },
+    )
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(trip)))
+}
+
+pub async fn list_trips(
+    State(s): State<AppState>,
+    identity: Identity,
+) -> Result<Json<Vec<Trip>>, AppError> {
+    let pool = database(&s)?;
+
+    Ok(Json(trips::list_by_owner(pool, &identity.0).await?))
+}
+
+pub async fn get_trip(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Trip>, AppError> {
+    let pool = database(&s)?;
+
+    trips::get(pool, id, &identity.0)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound("Trip not found".into()))
+}
+
+pub async fn delete_trip(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let pool = database(&s)?;
+
+    if trips::delete(pool, id, &identity.0).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound("Trip not found".into()))
+    }
+}
+
+fn trip_body(trip: &Trip, departure_time: Option<String>) -> Result<Value, AppError> {
+    let travel_mode = TravelMode::from_str(&trip.travel_mode)
+        .map_err(|_| AppError::Database(format!("unknown travel mode {}", trip.travel_mode)))?;
+
+    Ok(routes_body(
+        waypoint(trip.origin.latitude, trip.origin.longitude),
+        waypoint(trip.destination.latitude, trip.destination.longitude),
+        trip.waypoints
+            .iter()
+            .take(MAX_WAYPOINTS)
+            .map(|c| waypoint(c.latitude, c.longitude))
+            .collect(),
+        travel_mode,
+        departure_time,
+    ))
+}
+
+/// Computes the stored trip again with current conditions and saves the best route. A route
+/// precomputed for an occurrence that's about now is returned as is.
+pub async fn recompute_trip(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Trip>, AppError> {
+    let pool = database(&s)?;
+    let trip = trips::get(pool, id, &identity.0)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Trip not found".into()))?;
+    let grace = ChronoDuration::minutes(PRECOMPUTED_GRACE_MINUTES);
+    if trip
+        .route_departure_at
+        .is_some_and(|departure| departure + grace >= Utc::now())
+    {
+        return Ok(Json(trip));
+    }
+
+    let google_routes = fetch_routes(&s, &trip_body(&trip, None)?).await?;
+    let route = google_routes
+        .routes
+        .first()
+        .ok_or_else(|| AppError::NotFound("No route found for this trip".into()))?;
+
+    trips::update_route(
+        pool,
+        id,
+        &identity.0,
+        &route.polyline.encoded_polyline,
+        Some(f64::from(route.distance_meters)),
+        Some(&route.duration),
+        None,
+    )
+    .await?
+    .map(Json)
+    .ok_or_else(|| AppError::NotFound("Trip not found".into()))
+}
+
+/// Makes the trip repeat, its route is then computed shortly before each occurrence.
+pub async fn set_recurrence(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+    Json(body): Json<RecurrenceRequest>,
+) -> Result<Json<Trip>, AppError> {
+    body.validate()?;
+    let pool = database(&s)?;
+    if !commutes::is_time_zone(pool, &body.time_zone).await? {
+        return Err(AppError::Validation(format!(
+            "Unknown time zone {}",
+            body.time_zone
+        )));
+    }
+
+    let mut weekdays = body.weekdays;
+    weekdays.sort_unstable();
+    weekdays.dedup();
+    trips::set_recurrence(pool, id, &identity.0, &weekdays, body.time, &body.time_zone)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound("Trip not found".into()))
+}
+
+pub async fn clear_recurrence(
+    State(s): State<AppState>,
+    identity: Identity,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Trip>, AppError> {
+    let pool = database(&s)?;
+
+    trips::clear_recurrence(pool, id, &identity.0)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound("Trip not found".into()))
+}
+
+// Goes through the cache, so a request for the same departure is answered from it too
+async fn precompute(
+    s: &AppState,
+    pool: &PgPool,
+    trip: &Trip,
+    occurrence: DateTime<Utc>,
+) -> Result<(), AppError> {
+    let req = trip_body(
+        trip,
+        Some(occurrence.to_rfc3339_opts(SecondsFormat::Secs, true)),
+    )?;
+    let cache_key = cache::routes_key(&req, google_provider());
+    let routes = match cached::<GetRoutesReponse>(s, &cache_key).await {
+        Some(routes) => routes,
+        None => {
+            let routes = fetch_routes(s, &req).await?;
+            store(s, &cache_key, &routes).await;
+            routes
+        }
+    };
+    let route = routes
+        .routes
+        .first()
+        .ok_or_else(|| AppError::NotFound("No route found for this trip".into()))?;
+
+    trips::update_route(
+        pool,
+        trip.id,
+        &trip.owner,
+        &route.polyline.encoded_polyline,
+        Some(f64::from(route.distance_meters)),
+        Some(&route.duration),
+        Some(occurrence),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Computes the route of every recurring trip `lead` before its next occurrence.
+pub fn spawn_precompute(s: AppState, pool: PgPool, lead: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PRECOMPUTE_TICK);
+        loop {
+            interval.tick().await;
+            let due = match trips::claim_due(&pool, lead, PRECOMPUTE_BATCH).await {
+                Ok(due) => due,
+                Err(e) => {
+                    tracing::warn!(error = %e, "failed to look up trips to precompute");
+                    continue;
+                }
+            };
+            for (trip, occurrence) in due {
+                // Missed while no instance was running, Google rejects past departures
+                if occurrence < Utc::now() {
+                    continue;
+                }
+                if let Err(e) = precompute(&s, &pool, &trip, occurrence).await {
+                    tracing::warn!(error = %e, trip = %trip.id, "failed to precompute trip");
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,195 @@
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use axum::{extract::State, http::StatusCode, Json};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    db::users::{self, User},
+    error::AppError,
+    identity::Identity,
+    session::Sessions,
+    AppState,
+};
+
+use super::database;
+
+// Verified against when the email is unknown, so that answers as slowly as a wrong password.
+// Argon2's default parameters, like the hashes of real accounts
+const DUMMY_PASSWORD_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$bXVsdGktbWFwLWR1bW15$oFefH9CQ3QE6kiZhkKp5IRqzEAjwQ0rN9h6Ir6lLvZc";
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterRequest {
+    #[validate(email, length(max = 320))]
+    email: String,
+    #[validate(length(min = 8, max = 256))]
+    password: String,
+    #[validate(length(min = 1, max = 256))]
+    display_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct LoginRequest {
+    #[validate(length(min = 1, max = 320))]
+    email: String,
+    #[validate(length(min = 1, max = 256))]
+    password: String,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct UpdateProfileRequest {
+    /// Left out or null clears the display name
+    #[validate(length(min = 1, max = 256))]
+    display_name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionResponse {
+    token: String,
+    token_type: &'static str,
+    expires_in: u64,
+    user: User,
+}
+
+fn sessions(s: &AppState) -> Result<&Sessions, AppError> {
+    s.sessions
+        .as_deref()
+        .ok_or_else(|| AppError::NotFound("Accounts are disabled".into()))
+}
+
+// Only sessions of registered users carry a user id, API keys and OAuth logins don't
+fn user_id(identity: &Identity) -> Result<Uuid, AppError> {
+    identity
+        .0
+        .strip_prefix("user:")
+        .and_then(|id| Uuid::parse_str(id).ok())
+        .ok_or(AppError::Forbidden)
+}
+
+fn session(sessions: &Sessions, user: User) -> Result<SessionResponse, AppError> {
+    Ok(SessionResponse {
+        token: sessions.issue(&user.id.to_string())?,
+        token_type: "Bearer",
+        expires_in: sessions.ttl().as_secs(),
+        user,
+    })
+}
+
+// Hashing takes tens of milliseconds of CPU on purpose, it runs off the async workers
+pub(crate) async fn hash_password(password: String) -> Result<String, AppError> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::encode_b64(&rand::thread_rng().gen::<[u8; 16]>())
+            .map_err(|e| AppError::ParseError(e.to_string()))?;
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| AppError::ParseError(e.to_string()))
+    })
+    .await
+    .map_err(|e| AppError::ParseError(e.to_string()))?
+}
+
+async fn verify_password(password: String, hash: String) -> Result<bool, AppError> {
+    tokio::task::spawn_blocking(move || {
+        let hash = PasswordHash::new(&hash).map_err(|e| AppError::Database(e.to_string()))?;
+        Ok(Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok())
+    })
+    .await
+    .map_err(|e| AppError::ParseError(e.to_string()))?
+}
+
+/// Creates an account and signs it in.
+pub async fn register(
+    State(s): State<AppState>,
+    Json(body): Json<RegisterRequest>,
+) -> Result<(StatusCode, Json<SessionResponse>), AppError> {
+    body.validate()?;
+    let sessions = sessions(&s)?;
+    let pool = database(&s)?;
+
+    let password_hash = hash_password(body.password).await?;
+    let user = users::insert(
+        pool,
+        body.email.trim(),
+        &password_hash,
+        body.display_name.as_deref(),
+    )
+    .await?
+    .ok_or_else(|| AppError::Validation("email is already registered".into()))?;
+    tracing::info!(user_id = %user.id, "registered user");
+
+    Ok((StatusCode::CREATED, Json(session(sessions, user)?)))
+}
+
+pub async fn login(
+    State(s): State<AppState>,
+    Json(body): Json<LoginRequest>,
+) -> Result<Json<SessionResponse>, AppError> {
+    body.validate()?;
+    let sessions = sessions(&s)?;
+    let pool = database(&s)?;
+
+    // Unknown emails and wrong passwords are told apart neither by status nor by message
+    let Some(credentials) = users::credentials(pool, body.email.trim()).await? else {
+        verify_password(body.password, DUMMY_PASSWORD_HASH.into()).await?;
+        return Err(AppError::Unauthorized);
+    };
+    if !verify_password(body.password, credentials.password_hash).await? {
+        return Err(AppError::Unauthorized);
+    }
+    let user = users::get(pool, credentials.id)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+
+    Ok(Json(session(sessions, user)?))
+}
+
+pub async fn get_profile(
+    State(s): State<AppState>,
+    identity: Identity,
+) -> Result<Json<User>, AppError> {
+    let pool = database(&s)?;
+
+    users::get(pool, user_id(&identity)?)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound("User not found".into()))
+}
+
+pub async fn update_profile(
+    State(s): State<AppState>,
+    identity: Identity,
+    Json(body): Json<UpdateProfileRequest>,
+) -> Result<Json<User>, AppError> {
+    body.validate()?;
+    let pool = database(&s)?;
+
+    users::update_profile(pool, user_id(&identity)?, body.display_name.as_deref())
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound("User not found".into()))
+}
+
+/// Deletes the account together with everything it owns.
+pub async fn delete_account(
+    State(s): State<AppState>,
+    identity: Identity,
+) -> Result<StatusCode, AppError> {
+    let pool = database(&s)?;
+
+    if users::delete(pool, user_id(&identity)?, &identity.0).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound("User not found".into()))
+    }
+}
@@ -0,0 +1,83 @@
+use chrono::DateTime;
+use validator::ValidationError;
+
+use crate::{db::trips::Coordinate, geo::plus_code};
+
+use super::place_types::is_place_type;
+
+pub fn not_blank(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        let mut error = ValidationError::new("not_blank");
+        error.message = Some("must not be blank".into());
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+pub fn rfc3339(value: &str) -> Result<(), ValidationError> {
+    if DateTime::parse_from_rfc3339(value).is_err() {
+        let mut error = ValidationError::new("rfc3339");
+        error.message = Some("must be an RFC 3339 timestamp".into());
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+// The derive can't combine a length check with nested validation on the same list
+pub fn coordinates(values: &[Coordinate]) -> Result<(), ValidationError> {
+    let in_range = |c: &Coordinate| {
+        (-90.0..=90.0).contains(&c.latitude) && (-180.0..=180.0).contains(&c.longitude)
+    };
+    if !values.iter().all(in_range) {
+        let mut error = ValidationError::new("coordinate");
+        error.message =
+            Some("latitudes must be within [-90, 90] and longitudes within [-180, 180]".into());
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+pub fn plus_code(value: &str) -> Result<(), ValidationError> {
+    if plus_code::decode(value).is_none() {
+        let mut error = ValidationError::new("plus_code");
+        error.message = Some("must be a full plus code, e.g. 849VCWC8+R9".into());
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+pub fn place_types(values: &[String]) -> Result<(), ValidationError> {
+    if let Some(unknown) = values.iter().find(|v| !is_place_type(v)) {
+        let mut error = ValidationError::new("place_type");
+        error.message = Some(format!("{} is not a supported place type", unknown).into());
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+// A CLDR region, e.g. "us" or "AU"
+pub fn region_code(value: &str) -> Result<(), ValidationError> {
+    if value.len() != 2 || !value.bytes().all(|b| b.is_ascii_alphabetic()) {
+        let mut error = ValidationError::new("region_code");
+        error.message = Some("must be a two letter region code".into());
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+// ISO weekdays, 1 is Monday
+pub fn weekdays(values: &[i16]) -> Result<(), ValidationError> {
+    if !values.iter().all(|d| (1..=7).contains(d)) {
+        let mut error = ValidationError::new("weekday");
+        error.message = Some("weekdays must be within [1, 7], 1 is Monday".into());
+        return Err(error);
+    }
+
+    Ok(())
+}
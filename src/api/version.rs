@@ -0,0 +1,94 @@
+use std::convert::Infallible;
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Query},
+    http::{request::Parts, Uri},
+};
+use serde::de::DeserializeOwned;
+
+use crate::error::AppError;
+
+/// API version a request came in through. Versions share handlers, which only branch on
+/// the version where a response shape changed, so older versions stay stable.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ApiVersion {
+    #[default]
+    V1,
+    /// camelCase query parameters, like every body and response
+    V2,
+}
+
+impl ApiVersion {
+    pub const ALL: [ApiVersion; 2] = [ApiVersion::V1, ApiVersion::V2];
+
+    fn of(parts: &Parts) -> Self {
+        parts
+            .extensions
+            .get::<ApiVersion>()
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            ApiVersion::V1 => "/v1",
+            ApiVersion::V2 => "/v2",
+        }
+    }
+}
+
+/// `first_second` to `firstSecond`, the naming of every field on the wire.
+pub fn camel_case(name: &str) -> String {
+    let mut words = name.split('_');
+    let mut camel = words.next().unwrap_or_default().to_owned();
+    for word in words {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            camel.extend(first.to_uppercase());
+            camel.push_str(chars.as_str());
+        }
+    }
+
+    camel
+}
+
+// Set by the version middleware, requests outside a versioned router get v1
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ApiVersion {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(ApiVersion::of(parts))
+    }
+}
+
+/// Query string parsed into `T`, whose fields are named in camelCase. v1 took snake_case
+/// parameters, which are renamed before parsing so v1 clients keep working.
+pub struct VersionedQuery<T>(pub T);
+
+#[async_trait]
+impl<T: DeserializeOwned, S: Send + Sync> FromRequestParts<S> for VersionedQuery<T> {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let query = parts.uri.query().unwrap_or_default();
+
+        let query = match ApiVersion::of(parts) {
+            ApiVersion::V1 => url::form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(
+                    url::form_urlencoded::parse(query.as_bytes())
+                        .map(|(key, value)| (camel_case(&key), value)),
+                )
+                .finish(),
+            ApiVersion::V2 => query.to_owned(),
+        };
+        let uri: Uri = format!("/?{}", query)
+            .parse()
+            .map_err(|_| AppError::Validation("Invalid query string".into()))?;
+
+        Query::try_from_uri(&uri)
+            .map(|Query(value)| VersionedQuery(value))
+            .map_err(|e| AppError::Validation(e.body_text()))
+    }
+}
@@ -0,0 +1,36 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::Deserialize;
+
+use crate::{
+    error::AppError,
+    what3words::{is_words, Square},
+    AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct ResolveQuery {
+    /// `index.home.raft`, with or without the leading `///`
+    words: String,
+}
+
+/// Coordinates and plus code of a what3words address.
+pub async fn resolve(
+    State(s): State<AppState>,
+    Query(query): Query<ResolveQuery>,
+) -> Result<Json<Square>, AppError> {
+    let what3words = s
+        .what3words
+        .as_deref()
+        .ok_or_else(|| AppError::NotFound("what3words is not configured".into()))?;
+    let words = query.words.trim().trim_start_matches("///");
+    if !is_words(words) {
+        return Err(AppError::Validation(
+            "words must be three words separated by dots, e.g. index.home.raft".into(),
+        ));
+    }
+
+    Ok(Json(what3words.resolve(words).await?))
+}
@@ -0,0 +1,36 @@
+use std::time::{Duration, Instant};
+
+use reqwest::Url;
+
+use crate::AppState;
+
+// Whatever the status, answering it takes an open connection. Without a key nothing is billed
+async fn touch(s: &AppState, endpoint: &str) {
+    let Ok(mut url) = Url::parse(endpoint) else {
+        return;
+    };
+    url.set_path("/");
+    let started = Instant::now();
+    match s.client_reqwest.head(url.clone()).send().await {
+        Ok(response) => tracing::debug!(
+            url = %url,
+            status = response.status().as_u16(),
+            latency_ms = started.elapsed().as_millis() as u64,
+            "upstream connection warm"
+        ),
+        Err(e) => tracing::warn!(error = %e, url = %url, "failed to warm upstream connection"),
+    }
+}
+
+/// Resolves and connects to the provider endpoints right away, then touches them every `every`
+/// so the pooled connections are never idle long enough to be closed. The first request
+/// after a quiet period then skips the DNS lookup and the TLS handshake.
+pub fn spawn_warmup(s: AppState, every: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(every);
+        loop {
+            interval.tick().await;
+            tokio::join!(touch(&s, &s.urls.text_search), touch(&s, &s.urls.routes));
+        }
+    });
+}
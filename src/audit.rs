@@ -0,0 +1,192 @@
+use std::{
+    cell::Cell,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde_json::{Map, Value};
+use sqlx::PgPool;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::mpsc,
+};
+
+use crate::{
+    db::{self, audit::AuditEntry},
+    error::AppError,
+};
+
+// Only the tail of an audit file is searched for recent entries
+const FILE_TAIL_BYTES: u64 = 4 * 1024 * 1024;
+const MAX_PARAM_LENGTH: usize = 256;
+const FILE_QUEUE_SIZE: usize = 1024;
+// Query parameters that may carry credentials are dropped
+const SECRET_PARAMS: &[&str] = &["key", "token", "secret", "password", "signature"];
+
+tokio::task_local! {
+    static PROVIDER: Cell<Option<&'static str>>;
+}
+
+/// Notes the upstream provider serving the current request for its audit record.
+pub fn note_provider(provider: &'static str) {
+    let _ = PROVIDER.try_with(|p| p.set(Some(provider)));
+}
+
+/// Runs `f` collecting the provider it calls, if any.
+pub async fn with_provider<F: std::future::Future>(f: F) -> (F::Output, Option<&'static str>) {
+    PROVIDER
+        .scope(Cell::new(None), async move {
+            let output = f.await;
+            (output, PROVIDER.with(Cell::get))
+        })
+        .await
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuditSink {
+    Off,
+    Database,
+    File,
+}
+
+impl FromStr for AuditSink {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(AuditSink::Off),
+            "database" => Ok(AuditSink::Database),
+            "file" => Ok(AuditSink::File),
+            _ => Err(()),
+        }
+    }
+}
+
+enum Sink {
+    Database(PgPool),
+    File {
+        path: PathBuf,
+        queue: mpsc::Sender<AuditEntry>,
+    },
+}
+
+/// Append-only record of API requests, kept in the database or a JSON lines file.
+pub struct AuditLog {
+    sink: Sink,
+}
+
+impl AuditLog {
+    pub fn database(pool: PgPool) -> Self {
+        AuditLog {
+            sink: Sink::Database(pool),
+        }
+    }
+
+    /// Entries are written by a single task so lines never interleave.
+    pub async fn file(path: PathBuf) -> std::io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await?;
+        let (queue, mut entries) = mpsc::channel::<AuditEntry>(FILE_QUEUE_SIZE);
+
+        tokio::spawn(async move {
+            while let Some(entry) = entries.recv().await {
+                let mut line = match serde_json::to_vec(&entry) {
+                    Ok(line) => line,
+                    Err(e) => {
+                        tracing::error!(error = %e, "failed to serialize audit entry");
+                        continue;
+                    }
+                };
+                line.push(b'\n');
+                if let Err(e) = file.write_all(&line).await {
+                    tracing::error!(error = %e, "failed to write audit entry");
+                }
+            }
+        });
+
+        Ok(AuditLog {
+            sink: Sink::File { path, queue },
+        })
+    }
+
+    // Never blocks the request: a full file queue drops the entry with an error log
+    pub fn record(&self, entry: AuditEntry) {
+        match &self.sink {
+            Sink::Database(pool) => {
+                let pool = pool.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = db::audit::insert(&pool, &entry).await {
+                        tracing::error!(error = %e, "failed to record audit entry");
+                    }
+                });
+            }
+            Sink::File { queue, .. } => {
+                if queue.try_send(entry).is_err() {
+                    tracing::error!("audit queue is full, dropping entry");
+                }
+            }
+        }
+    }
+
+    pub async fn recent(
+        &self,
+        identity: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<AuditEntry>, AppError> {
+        match &self.sink {
+            Sink::Database(pool) => Ok(db::audit::recent(pool, identity, limit as i64).await?),
+            Sink::File { path, .. } => {
+                let tail = read_tail(path).await.map_err(|e| {
+                    tracing::error!(error = %e, "failed to read audit file");
+                    AppError::Unavailable
+                })?;
+
+                Ok(tail
+                    .lines()
+                    .rev()
+                    .filter_map(|line| serde_json::from_str::<AuditEntry>(line).ok())
+                    .filter(|e| identity.is_none() || e.identity.as_deref() == identity)
+                    .take(limit)
+                    .collect())
+            }
+        }
+    }
+}
+
+async fn read_tail(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path).await?;
+    let len = file.metadata().await?.len();
+    let start = len.saturating_sub(FILE_TAIL_BYTES);
+    file.seek(SeekFrom::Start(start)).await?;
+
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).await?;
+    let text = String::from_utf8_lossy(&bytes);
+
+    // Starting mid-file, the first line is most likely partial
+    Ok(match (start, text.split_once('\n')) {
+        (0, _) => text.into_owned(),
+        (_, Some((_, rest))) => rest.to_owned(),
+        (_, None) => String::new(),
+    })
+}
+
+/// Query parameters as recorded: credentials dropped and long values cut short.
+pub fn sanitize_params(query: Option<&str>) -> Value {
+    let mut params = Map::new();
+    for (key, value) in url::form_urlencoded::parse(query.unwrap_or_default().as_bytes()) {
+        let lowered = key.to_ascii_lowercase();
+        if SECRET_PARAMS.iter().any(|s| lowered.contains(s)) {
+            continue;
+        }
+        let value: String = value.chars().take(MAX_PARAM_LENGTH).collect();
+        params.insert(key.into_owned(), Value::String(value));
+    }
+
+    Value::Object(params)
+}
@@ -0,0 +1,270 @@
+//! Drives a running instance at a fixed request rate and reports latency percentiles.
+//!
+//! Requests are sent on schedule whether or not earlier ones have answered, so a slow
+//! service shows up as latency instead of as a lower rate. Configured from the environment:
+//!
+//! ```text
+//! LOADTEST_URL=http://localhost:3000/v2/places LOADTEST_RPS=200 LOADTEST_DURATION_SECS=30 \
+//! LOADTEST_BODY='{"textQuery":"coffee in Lisbon"}' cargo run --release --bin loadtest
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    env,
+    process::ExitCode,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use reqwest::{header::CONTENT_TYPE, Client, Method};
+use tokio::{
+    sync::{mpsc, Semaphore},
+    time::MissedTickBehavior,
+};
+
+const API_KEY_HEADER: &str = "X-Api-Key";
+
+struct Settings {
+    url: String,
+    method: Method,
+    body: Option<String>,
+    api_key: Option<String>,
+    rps: u32,
+    duration: Duration,
+    warmup: Duration,
+    max_in_flight: usize,
+    timeout: Duration,
+}
+
+fn var<T: FromStr>(key: &str, default: T) -> Result<T, String> {
+    match env::var(key) {
+        Ok(value) => value
+            .parse()
+            .map_err(|_| format!("invalid value for {}: {}", key, value)),
+        Err(_) => Ok(default),
+    }
+}
+
+impl Settings {
+    fn from_env() -> Result<Self, String> {
+        let url = env::var("LOADTEST_URL").map_err(|_| "LOADTEST_URL is required".to_string())?;
+        // A body starting with @ is read from that file
+        let body = match env::var("LOADTEST_BODY").ok() {
+            Some(path) if path.starts_with('@') => Some(
+                std::fs::read_to_string(&path[1..])
+                    .map_err(|e| format!("failed to read {}: {}", &path[1..], e))?,
+            ),
+            body => body,
+        };
+        let method = match env::var("LOADTEST_METHOD") {
+            Ok(method) => Method::from_str(&method.to_uppercase())
+                .map_err(|_| format!("invalid value for LOADTEST_METHOD: {}", method))?,
+            Err(_) if body.is_some() => Method::POST,
+            Err(_) => Method::GET,
+        };
+        let rps = var("LOADTEST_RPS", 50)?;
+        if rps == 0 {
+            return Err("LOADTEST_RPS must be at least 1".into());
+        }
+
+        Ok(Settings {
+            url,
+            method,
+            body,
+            api_key: env::var("LOADTEST_API_KEY").ok(),
+            rps,
+            duration: Duration::from_secs(var("LOADTEST_DURATION_SECS", 30)?),
+            warmup: Duration::from_secs(var("LOADTEST_WARMUP_SECS", 0)?),
+            max_in_flight: var("LOADTEST_MAX_IN_FLIGHT", 1000)?,
+            timeout: Duration::from_secs(var("LOADTEST_TIMEOUT_SECS", 30)?),
+        })
+    }
+}
+
+enum Outcome {
+    Status(u16),
+    Failed(String),
+}
+
+struct Sample {
+    latency: Duration,
+    outcome: Outcome,
+}
+
+#[derive(Default)]
+struct Report {
+    latencies: Vec<Duration>,
+    statuses: BTreeMap<u16, usize>,
+    failures: BTreeMap<String, usize>,
+    // Not sent because too many requests were still in flight
+    skipped: usize,
+}
+
+impl Report {
+    fn record(&mut self, sample: Sample) {
+        self.latencies.push(sample.latency);
+        match sample.outcome {
+            Outcome::Status(status) => *self.statuses.entry(status).or_default() += 1,
+            Outcome::Failed(error) => *self.failures.entry(error).or_default() += 1,
+        }
+    }
+
+    // Nearest rank over sorted latencies
+    fn percentile(&self, p: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = ((p / 100.0) * self.latencies.len() as f64).ceil() as usize;
+        self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+    }
+
+    fn print(&mut self, settings: &Settings, elapsed: Duration) {
+        self.latencies.sort_unstable();
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let sent = self.latencies.len();
+        let ok: usize = self
+            .statuses
+            .iter()
+            .filter(|(status, _)| (200..400).contains(*status))
+            .map(|(_, count)| count)
+            .sum();
+
+        println!("{} {}", settings.method, settings.url);
+        println!(
+            "target {} rps, achieved {:.1} rps over {:.1}s",
+            settings.rps,
+            sent as f64 / elapsed.as_secs_f64(),
+            elapsed.as_secs_f64()
+        );
+        println!(
+            "requests {}, succeeded {} ({:.2}%), skipped {}",
+            sent,
+            ok,
+            ok as f64 * 100.0 / sent.max(1) as f64,
+            self.skipped
+        );
+        println!();
+        println!("latency (ms)");
+        for (label, p) in [
+            ("p50", 50.0),
+            ("p90", 90.0),
+            ("p95", 95.0),
+            ("p99", 99.0),
+            ("p99.9", 99.9),
+        ] {
+            println!("  {:<6}{:>10.2}", label, ms(self.percentile(p)));
+        }
+        println!(
+            "  {:<6}{:>10.2}",
+            "max",
+            ms(self.latencies.last().copied().unwrap_or_default())
+        );
+        println!();
+        println!("responses");
+        for (status, count) in &self.statuses {
+            println!("  {:<6}{:>10}", status, count);
+        }
+        for (error, count) in &self.failures {
+            println!("  {:>16} {}", count, error);
+        }
+    }
+}
+
+async fn send(client: &Client, settings: &Settings) -> Outcome {
+    let mut request = client.request(settings.method.clone(), &settings.url);
+    if let Some(key) = &settings.api_key {
+        request = request.header(API_KEY_HEADER, key);
+    }
+    if let Some(body) = &settings.body {
+        request = request
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.clone());
+    }
+    match request.send().await {
+        // Read the whole body so the latency covers what a client waits for
+        Ok(response) => {
+            let status = response.status().as_u16();
+            match response.bytes().await {
+                Ok(_) => Outcome::Status(status),
+                Err(e) => Outcome::Failed(format!("reading body: {}", e)),
+            }
+        }
+        Err(e) if e.is_timeout() => Outcome::Failed("timed out".into()),
+        Err(e) if e.is_connect() => Outcome::Failed("connection failed".into()),
+        Err(e) => Outcome::Failed(e.to_string()),
+    }
+}
+
+/// Sends requests at the configured rate for `duration` and waits for their answers.
+async fn run(client: &Client, settings: &Arc<Settings>, duration: Duration) -> (Report, Duration) {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let in_flight = Arc::new(Semaphore::new(settings.max_in_flight));
+    let mut report = Report::default();
+    let mut interval = tokio::time::interval(Duration::from_secs(1) / settings.rps);
+    // Catching up in bursts would skew the rate the service sees
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    let started = Instant::now();
+    while started.elapsed() < duration {
+        interval.tick().await;
+        let Ok(permit) = in_flight.clone().try_acquire_owned() else {
+            report.skipped += 1;
+            continue;
+        };
+        let (client, settings, tx) = (client.clone(), settings.clone(), tx.clone());
+        tokio::spawn(async move {
+            let sent = Instant::now();
+            let outcome = send(&client, &settings).await;
+            drop(permit);
+            let _ = tx.send(Sample {
+                latency: sent.elapsed(),
+                outcome,
+            });
+        });
+    }
+    // The rate is over the time spent sending, not waiting for the last answers
+    let elapsed = started.elapsed();
+    drop(tx);
+    while let Some(sample) = rx.recv().await {
+        report.record(sample);
+    }
+
+    (report, elapsed)
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let settings = match Settings::from_env() {
+        Ok(settings) => Arc::new(settings),
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let client = match Client::builder()
+        .timeout(settings.timeout)
+        .pool_max_idle_per_host(settings.max_in_flight)
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => {
+            eprintln!("failed to build the HTTP client: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if !settings.warmup.is_zero() {
+        eprintln!("warming up for {}s", settings.warmup.as_secs());
+        run(&client, &settings, settings.warmup).await;
+    }
+    eprintln!(
+        "sending {} rps for {}s",
+        settings.rps,
+        settings.duration.as_secs()
+    );
+    let (mut report, elapsed) = run(&client, &settings, settings.duration).await;
+    report.print(&settings, elapsed);
+
+    ExitCode::SUCCESS
+}
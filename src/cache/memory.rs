@@ -0,0 +1,111 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use moka::{future::Cache as MokaCache, Expiry};
+
+use super::{glob_match, Cache, CacheStats, HitCounter};
+
+// Like a time to live, but read at every insert so it can change while running
+struct SharedTtl(Arc<AtomicU64>);
+
+impl SharedTtl {
+    fn get(&self) -> Option<Duration> {
+        Some(Duration::from_millis(self.0.load(Ordering::Relaxed)))
+    }
+}
+
+impl Expiry<String, Vec<u8>> for SharedTtl {
+    fn expire_after_create(&self, _: &String, _: &Vec<u8>, _: Instant) -> Option<Duration> {
+        self.get()
+    }
+
+    fn expire_after_update(
+        &self,
+        _: &String,
+        _: &Vec<u8>,
+        _: Instant,
+        _: Option<Duration>,
+    ) -> Option<Duration> {
+        self.get()
+    }
+}
+
+pub struct MemoryCache {
+    entries: MokaCache<String, Vec<u8>>,
+    ttl_millis: Arc<AtomicU64>,
+    counter: HitCounter,
+}
+
+impl MemoryCache {
+    pub fn new(max_entries: u64, ttl: Duration) -> Self {
+        let ttl_millis = Arc::new(AtomicU64::new(ttl.as_millis() as u64));
+        MemoryCache {
+            entries: MokaCache::builder()
+                .max_capacity(max_entries)
+                .expire_after(SharedTtl(ttl_millis.clone()))
+                .build(),
+            ttl_millis,
+            counter: HitCounter::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl Cache for MemoryCache {
+    async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let value = self.entries.get(key).await;
+        self.counter.record(value.is_some());
+
+        value
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) {
+        self.entries.insert(key.to_owned(), value).await;
+    }
+
+    async fn stats(&self) -> CacheStats {
+        self.entries.run_pending_tasks().await;
+        let memory_bytes = self
+            .entries
+            .iter()
+            .map(|(k, v)| (k.len() + v.len()) as u64)
+            .sum();
+
+        self.counter
+            .stats("memory", self.entries.entry_count(), memory_bytes)
+    }
+
+    async fn invalidate(&self, pattern: &str) -> u64 {
+        let keys: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(k, _)| glob_match(pattern, k))
+            .map(|(k, _)| k)
+            .collect();
+
+        for key in &keys {
+            self.entries.invalidate(key.as_str()).await;
+        }
+
+        keys.len() as u64
+    }
+
+    async fn flush(&self) -> u64 {
+        self.entries.run_pending_tasks().await;
+        let count = self.entries.entry_count();
+        self.entries.invalidate_all();
+
+        count
+    }
+
+    fn set_ttl(&self, ttl: Duration) {
+        self.ttl_millis
+            .store(ttl.as_millis() as u64, Ordering::Relaxed);
+    }
+}
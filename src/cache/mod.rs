@@ -0,0 +1,184 @@
+mod memory;
+mod redis_cache;
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use crate::config::Config;
+
+pub use memory::MemoryCache;
+pub use redis_cache::RedisCache;
+
+/// Byte oriented cache shared by all handlers. Backends treat their own failures as
+/// misses so a broken cache never fails a request.
+#[async_trait]
+pub trait Cache: Send + Sync {
+    async fn get(&self, key: &str) -> Option<Vec<u8>>;
+    async fn set(&self, key: &str, value: Vec<u8>);
+    async fn stats(&self) -> CacheStats;
+    /// Removes entries whose key matches a glob `pattern` (`*` and `?` wildcards).
+    async fn invalidate(&self, pattern: &str) -> u64;
+    async fn flush(&self) -> u64;
+    /// Changes how long entries stored from now on are kept.
+    fn set_ttl(&self, ttl: Duration);
+
+    /// Checks the backend is reachable, for readiness probes.
+    async fn ping(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    backend: &'static str,
+    entries: u64,
+    hits: u64,
+    misses: u64,
+    hit_ratio: f64,
+    memory_bytes: u64,
+}
+
+#[derive(Debug, Default)]
+struct HitCounter {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl HitCounter {
+    fn record(&self, hit: bool) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn stats(&self, backend: &'static str, entries: u64, memory_bytes: u64) -> CacheStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+
+        CacheStats {
+            backend,
+            entries,
+            hits,
+            misses,
+            hit_ratio: if lookups == 0 {
+                0.0
+            } else {
+                hits as f64 / lookups as f64
+            },
+            memory_bytes,
+        }
+    }
+}
+
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    // Position of the last `*` and the text index it is currently matched up to
+    let mut star: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+#[derive(Clone, Copy, Debug, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheStatus {
+    Hit,
+    Miss,
+    Bypass,
+}
+
+/// Place searches are keyed by the query regardless of casing and whitespace, and by a hash
+/// of the rest of the upstream body.
+pub fn places_key(body: &serde_json::Value, provider: &str) -> String {
+    let query = body["textQuery"]
+        .as_str()
+        .unwrap_or_default()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase();
+    let mut options = body.clone();
+    if let Some(options) = options.as_object_mut() {
+        options.remove("textQuery");
+    }
+    let digest = Sha256::digest(options.to_string().as_bytes());
+
+    format!("places:{}:{:x}:{}", provider, digest, query)
+}
+
+pub fn place_key(id: &str, provider: &str) -> String {
+    format!("place:{}:{}", provider, id)
+}
+
+/// Route requests are keyed by a hash of the exact upstream body.
+pub fn routes_key(body: &serde_json::Value, provider: &str) -> String {
+    let digest = Sha256::digest(body.to_string().as_bytes());
+
+    format!("routes:{}:{:x}", provider, digest)
+}
+
+pub async fn get_json<T: DeserializeOwned>(cache: &dyn Cache, key: &str) -> Option<T> {
+    let bytes = cache.get(key).await?;
+
+    serde_json::from_slice(&bytes).ok()
+}
+
+pub async fn build(config: &Config) -> Option<Arc<dyn Cache>> {
+    if !config.cache_enabled {
+        return None;
+    }
+
+    backend(config, "cache", config.cache_ttl).await
+}
+
+/// Longer lived copies of successful results, served when the upstream provider fails.
+pub async fn build_stale(config: &Config) -> Option<Arc<dyn Cache>> {
+    if !config.stale_if_error_enabled {
+        return None;
+    }
+
+    backend(config, "stale", config.stale_ttl).await
+}
+
+/// Uses Redis when `REDIS_URL` is set so instances share entries, otherwise an
+/// in-process cache. An unreachable Redis disables caching instead of failing startup.
+async fn backend(config: &Config, namespace: &str, ttl: Duration) -> Option<Arc<dyn Cache>> {
+    match &config.redis_url {
+        Some(url) => match RedisCache::connect(url, namespace, ttl).await {
+            Ok(cache) => Some(Arc::new(cache)),
+            Err(e) => {
+                tracing::warn!("redis unavailable, {} cache disabled: {}", namespace, e);
+                None
+            }
+        },
+        None => Some(Arc::new(MemoryCache::new(config.cache_max_entries, ttl))),
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use redis::{aio::ConnectionManager, RedisError};
+
+use super::{Cache, CacheStats, HitCounter};
+
+// Keeps our entries apart from anything else stored in a shared Redis
+const KEY_PREFIX: &str = "multi-map";
+const SCAN_BATCH: u64 = 500;
+
+pub struct RedisCache {
+    conn: ConnectionManager,
+    prefix: String,
+    ttl_secs: AtomicU64,
+    counter: HitCounter,
+}
+
+impl RedisCache {
+    /// Entries are stored under `multi-map:<namespace>:` so several caches can share a Redis.
+    pub async fn connect(url: &str, namespace: &str, ttl: Duration) -> Result<Self, RedisError> {
+        let client = redis::Client::open(url)?;
+        let conn = ConnectionManager::new(client).await?;
+
+        Ok(RedisCache {
+            conn,
+            prefix: format!("{}:{}:", KEY_PREFIX, namespace),
+            ttl_secs: AtomicU64::new(ttl.as_secs()),
+            counter: HitCounter::default(),
+        })
+    }
+
+    async fn scan(&self, pattern: &str) -> Result<Vec<String>, RedisError> {
+        let mut conn = self.conn.clone();
+        let mut cursor = 0u64;
+        let mut keys = Vec::new();
+
+        loop {
+            let (next, batch) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(format!("{}{}", self.prefix, pattern))
+                .arg("COUNT")
+                .arg(SCAN_BATCH)
+                .query_async::<_, (u64, Vec<String>)>(&mut conn)
+                .await?;
+            keys.extend(batch);
+
+            if next == 0 {
+                return Ok(keys);
+            }
+            cursor = next;
+        }
+    }
+
+    async fn delete_matching(&self, pattern: &str) -> Result<u64, RedisError> {
+        let keys = self.scan(pattern).await?;
+        if keys.is_empty() {
+            return Ok(0);
+        }
+
+        let mut conn = self.conn.clone();
+        redis::cmd("DEL")
+            .arg(&keys)
+            .query_async::<_, u64>(&mut conn)
+            .await
+    }
+
+    async fn used_memory(&self) -> Result<u64, RedisError> {
+        let mut conn = self.conn.clone();
+        let info = redis::cmd("INFO")
+            .arg("memory")
+            .query_async::<_, String>(&mut conn)
+            .await?;
+
+        Ok(info
+            .lines()
+            .find_map(|line| line.strip_prefix("used_memory:"))
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(0))
+    }
+}
+
+#[async_trait]
+impl Cache for RedisCache {
+    async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let mut conn = self.conn.clone();
+
+        let value = match redis::cmd("GET")
+            .arg(format!("{}{}", self.prefix, key))
+            .query_async::<_, Option<Vec<u8>>>(&mut conn)
+            .await
+        {
+            Ok(value) => value,
+            Err(e) => {
+                tracing::warn!("redis GET {} failed: {}", key, e);
+                None
+            }
+        };
+        self.counter.record(value.is_some());
+
+        value
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>) {
+        let mut conn = self.conn.clone();
+
+        if let Err(e) = redis::cmd("SET")
+            .arg(format!("{}{}", self.prefix, key))
+            .arg(value)
+            .arg("EX")
+            .arg(self.ttl_secs.load(Ordering::Relaxed).max(1))
+            .query_async::<_, ()>(&mut conn)
+            .await
+        {
+            tracing::warn!("redis SET {} failed: {}", key, e);
+        }
+    }
+
+    // Entries are counted across the namespace, memory is the whole Redis instance
+    async fn stats(&self) -> CacheStats {
+        let entries = self
+            .scan("*")
+            .await
+            .map(|keys| keys.len() as u64)
+            .unwrap_or_else(|e| {
+                tracing::warn!("redis SCAN failed: {}", e);
+                0
+            });
+        let memory_bytes = self.used_memory().await.unwrap_or_else(|e| {
+            tracing::warn!("redis INFO failed: {}", e);
+            0
+        });
+
+        self.counter.stats("redis", entries, memory_bytes)
+    }
+
+    async fn ping(&self) -> Result<(), String> {
+        let mut conn = self.conn.clone();
+
+        redis::cmd("PING")
+            .query_async::<_, String>(&mut conn)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+    }
+
+    async fn invalidate(&self, pattern: &str) -> u64 {
+        self.delete_matching(pattern).await.unwrap_or_else(|e| {
+            tracing::warn!("redis invalidate {} failed: {}", pattern, e);
+            0
+        })
+    }
+
+    async fn flush(&self) -> u64 {
+        self.invalidate("*").await
+    }
+
+    fn set_ttl(&self, ttl: Duration) {
+        self.ttl_secs.store(ttl.as_secs(), Ordering::Relaxed);
+    }
+}
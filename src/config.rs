@@ -0,0 +1,950 @@
+use std::{
+    collections::HashMap,
+    env, fmt,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+
+use axum::http::{HeaderName, HeaderValue, Method};
+use ipnet::IpNet;
+
+use crate::{
+    audit::AuditSink,
+    flags::{self, Rules},
+    geo::geohash,
+    secrets::SecretsBackend,
+    telemetry::LogFormat,
+    upstream::{CassetteMode, Rotation},
+    usage::DEFAULT_PRICES,
+};
+
+const DEFAULT_BIND_ADDR: &str = "0.0.0.0:3000";
+const DEFAULT_GOOGLE_PLACES_ORIGIN: &str = "https://places.googleapis.com";
+const DEFAULT_GOOGLE_ROUTES_ORIGIN: &str = "https://routes.googleapis.com";
+const DEFAULT_WHAT3WORDS_ORIGIN: &str = "https://api.what3words.com";
+// Below the endpoint budgets so a slow upstream fails inside the handler, where it
+// can still fall back to a stale response
+const DEFAULT_UPSTREAM_TIMEOUT_MS: u64 = 4_000;
+// Well under the endpoint budgets, so requests that are slow but still succeed are flagged
+const DEFAULT_SLOW_REQUEST_THRESHOLD_MS: u64 = 2_000;
+const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 3_000;
+const DEFAULT_PLACES_TIMEOUT_MS: u64 = 5_000;
+const DEFAULT_ROUTES_TIMEOUT_MS: u64 = 10_000;
+const DEFAULT_BATCH_TIMEOUT_MS: u64 = 30_000;
+const DEFAULT_RETRY_MAX_ATTEMPTS: u32 = 3;
+const DEFAULT_RETRY_BASE_DELAY_MS: u64 = 100;
+const DEFAULT_RETRY_MAX_DELAY_MS: u64 = 2_000;
+const DEFAULT_BREAKER_FAILURE_THRESHOLD: u32 = 5;
+const DEFAULT_BREAKER_OPEN_SECS: u64 = 30;
+const DEFAULT_RATE_LIMIT_BURST: u32 = 20;
+const DEFAULT_RATE_LIMIT_PER_SEC: f64 = 5.0;
+const DEFAULT_CORS_ALLOWED_METHODS: &[&str] = &["GET", "POST", "DELETE"];
+const DEFAULT_CORS_ALLOWED_HEADERS: &[&str] = &[
+    "content-type",
+    "if-none-match",
+    "x-api-key",
+    "authorization",
+    "x-request-id",
+];
+const DEFAULT_CORS_MAX_AGE_SECS: u64 = 3_600;
+const DEFAULT_DATABASE_MAX_CONNECTIONS: u32 = 10;
+const DEFAULT_CACHE_TTL_SECS: u64 = 300;
+const DEFAULT_CACHE_MAX_ENTRIES: u64 = 10_000;
+const DEFAULT_STALE_TTL_SECS: u64 = 86_400;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Missing(&'static str),
+    Invalid { key: &'static str, value: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Missing(key) => write!(f, "missing required env var {}", key),
+            ConfigError::Invalid { key, value } => {
+                write!(f, "invalid value {:?} for env var {}", value, key)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub google_keys: Vec<String>,
+    pub google_sandbox_keys: Vec<String>,
+    pub sandbox_clients: Vec<String>,
+    pub mock_providers: bool,
+    pub cassette_mode: CassetteMode,
+    pub cassette_dir: PathBuf,
+    pub google_places_origin: String,
+    pub google_routes_origin: String,
+    pub places_race_url: Option<String>,
+    pub places_race_keys: Vec<String>,
+    pub what3words_key: Option<String>,
+    pub what3words_origin: String,
+    pub google_key_rotation: Rotation,
+    pub google_key_cooldown: Duration,
+    pub secrets_backend: SecretsBackend,
+    pub secrets_name: Option<String>,
+    pub secrets_refresh: Duration,
+    pub vault_addr: Option<String>,
+    pub vault_token: Option<String>,
+    pub bind_addr: SocketAddr,
+    pub tls_cert_path: Option<PathBuf>,
+    pub log_format: LogFormat,
+    pub log_redact_coordinates: bool,
+    pub otlp_endpoint: Option<String>,
+    pub metrics_enabled: bool,
+    pub docs_enabled: bool,
+    pub legacy_routes_enabled: bool,
+    pub graphql_enabled: bool,
+    pub grpc_bind_addr: Option<SocketAddr>,
+    pub geohash_precision: Option<usize>,
+    pub place_dedupe_meters: f64,
+    pub drive_cost_per_km: f64,
+    pub fuel_prices: HashMap<String, f64>,
+    pub fuel_currency: String,
+    pub route_deviation_meters: f64,
+    pub reroute_interval: Duration,
+    pub commute_sample_interval: Duration,
+    pub trip_precompute_lead: Duration,
+    pub batch_max_items: usize,
+    pub batch_concurrency: usize,
+    pub batch_timeout: Duration,
+    pub job_ttl: Duration,
+    pub slow_request_threshold: Duration,
+    pub load_shed_max_in_flight: usize,
+    pub concurrency_limit: usize,
+    pub route_concurrency_limits: HashMap<String, usize>,
+    pub load_shed_max_lag: Duration,
+    pub alert_webhook_url: Option<String>,
+    pub fcm_service_account_path: Option<PathBuf>,
+    pub alert_cooldown: Duration,
+    pub slow_request_alert_rate: f64,
+    pub slow_request_alert_window: Duration,
+    pub slow_request_alert_min_requests: u64,
+    pub error_rate_alert_threshold: f64,
+    pub error_rate_alert_window: Duration,
+    pub error_rate_alert_min_requests: u64,
+    pub usage_prices: HashMap<String, f64>,
+    pub feature_flags: Rules,
+    pub feature_flags_refresh: Duration,
+    pub audit_log: AuditSink,
+    pub audit_log_path: Option<PathBuf>,
+    pub otel_service_name: String,
+    pub tls_key_path: Option<PathBuf>,
+    pub upstream_timeout: Duration,
+    pub connect_timeout: Duration,
+    pub pool_max_idle_per_host: usize,
+    pub pool_idle_timeout: Duration,
+    pub tcp_keepalive: Duration,
+    pub http2_prior_knowledge: bool,
+    pub upstream_warmup_interval: Duration,
+    pub places_timeout: Duration,
+    pub routes_timeout: Duration,
+    pub retry_max_attempts: u32,
+    pub retry_base_delay: Duration,
+    pub retry_max_delay: Duration,
+    pub breaker_failure_threshold: u32,
+    pub breaker_open_duration: Duration,
+    pub ip_allowlist: Vec<IpNet>,
+    pub ip_denylist: Vec<IpNet>,
+    pub trusted_proxies: Vec<IpNet>,
+    pub rate_limit_enabled: bool,
+    pub rate_limit_burst: u32,
+    pub rate_limit_per_sec: f64,
+    pub cors_allowed_origins: Vec<String>,
+    pub cors_allowed_methods: Vec<String>,
+    pub cors_allowed_headers: Vec<String>,
+    pub cors_max_age: Duration,
+    pub compression_enabled: bool,
+    pub places_enabled: bool,
+    pub routes_enabled: bool,
+    pub cache_enabled: bool,
+    pub cache_ttl: Duration,
+    pub cache_max_entries: u64,
+    pub cache_prewarm_top: i64,
+    pub cache_prewarm_window: Duration,
+    pub redis_url: Option<String>,
+    pub stale_if_error_enabled: bool,
+    pub readiness_cache: Duration,
+    pub stale_ttl: Duration,
+    pub admin_token: Option<String>,
+    pub api_auth_enabled: bool,
+    pub client_api_keys: Vec<String>,
+    pub jwt_secret: Option<String>,
+    pub jwt_jwks_url: Option<String>,
+    pub jwt_issuer: Option<String>,
+    pub jwt_audience: Option<String>,
+    pub jwt_user_claim: String,
+    pub signing_clients: Vec<(String, String)>,
+    pub signing_tolerance: Duration,
+    pub oauth_callback_url: Option<String>,
+    pub oauth_app_redirect_urls: Vec<String>,
+    pub oauth_google_client_id: Option<String>,
+    pub oauth_google_client_secret: Option<String>,
+    pub oauth_github_client_id: Option<String>,
+    pub oauth_github_client_secret: Option<String>,
+    pub session_ttl: Duration,
+    pub users_enabled: bool,
+    pub quota_daily: Option<u64>,
+    pub quota_monthly: Option<u64>,
+    pub database_url: Option<String>,
+    pub database_max_connections: u32,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::from_vars(&environment())
+    }
+
+    /// Reads the settings from `vars` rather than the process environment.
+    pub fn from_vars(vars: &Vars) -> Result<Self, ConfigError> {
+        let secrets_backend = parse_or(vars, "SECRETS_BACKEND", SecretsBackend::Env)?;
+        let mock_providers = parse_or(vars, "MOCK_PROVIDERS", false)?;
+        let cassette_mode = parse_or(vars, "UPSTREAM_CASSETTE_MODE", CassetteMode::Off)?;
+        // With a secrets manager the keys are loaded at startup instead. Nothing offline
+        // reaches Google, so no key is needed then
+        let offline = mock_providers || cassette_mode == CassetteMode::Replay;
+        let google_keys = match secrets_backend {
+            SecretsBackend::Env if !offline => parse_list(&required(vars, "GOOGLE_PLACES_KEY")?),
+            _ => list_or(vars, "GOOGLE_PLACES_KEY", &[]),
+        };
+
+        let config = Config {
+            google_keys,
+            google_sandbox_keys: list_or(vars, "GOOGLE_SANDBOX_KEYS", &[]),
+            sandbox_clients: list_or(vars, "SANDBOX_CLIENTS", &[]),
+            mock_providers,
+            cassette_mode,
+            cassette_dir: PathBuf::from(
+                optional(vars, "UPSTREAM_CASSETTE_DIR").unwrap_or_else(|| "cassettes".into()),
+            ),
+            google_places_origin: optional(vars, "GOOGLE_PLACES_ORIGIN")
+                .unwrap_or_else(|| DEFAULT_GOOGLE_PLACES_ORIGIN.into()),
+            google_routes_origin: optional(vars, "GOOGLE_ROUTES_ORIGIN")
+                .unwrap_or_else(|| DEFAULT_GOOGLE_ROUTES_ORIGIN.into()),
+            places_race_url: optional(vars, "PLACES_RACE_URL"),
+            places_race_keys: list_or(vars, "PLACES_RACE_KEY", &[]),
+            what3words_key: optional(vars, "WHAT3WORDS_API_KEY"),
+            what3words_origin: optional(vars, "WHAT3WORDS_ORIGIN")
+                .unwrap_or_else(|| DEFAULT_WHAT3WORDS_ORIGIN.into()),
+            google_key_rotation: parse_or(vars, "GOOGLE_KEY_ROTATION", Rotation::RoundRobin)?,
+            google_key_cooldown: Duration::from_secs(parse_or(
+                vars,
+                "GOOGLE_KEY_COOLDOWN_SECS",
+                60,
+            )?),
+            secrets_backend,
+            secrets_name: optional(vars, "SECRETS_NAME"),
+            secrets_refresh: Duration::from_secs(parse_or(vars, "SECRETS_REFRESH_SECS", 300)?),
+            vault_addr: optional(vars, "VAULT_ADDR"),
+            vault_token: optional(vars, "VAULT_TOKEN"),
+            bind_addr: parse_or(vars, "BIND_ADDR", DEFAULT_BIND_ADDR.parse().unwrap())?,
+            tls_cert_path: optional(vars, "TLS_CERT_PATH").map(PathBuf::from),
+            log_format: parse_or(vars, "LOG_FORMAT", LogFormat::Text)?,
+            log_redact_coordinates: parse_or(vars, "LOG_REDACT_COORDINATES", false)?,
+            otlp_endpoint: optional(vars, "OTLP_ENDPOINT"),
+            metrics_enabled: parse_or(vars, "METRICS_ENABLED", true)?,
+            docs_enabled: parse_or(vars, "DOCS_ENABLED", true)?,
+            legacy_routes_enabled: parse_or(vars, "LEGACY_ROUTES_ENABLED", true)?,
+            graphql_enabled: parse_or(vars, "GRAPHQL_ENABLED", true)?,
+            grpc_bind_addr: parse_optional(vars, "GRPC_BIND_ADDR")?,
+            geohash_precision: parse_optional(vars, "GEOHASH_PRECISION")?,
+            place_dedupe_meters: parse_or(vars, "PLACE_DEDUPE_METERS", 50.0)?,
+            drive_cost_per_km: parse_or(vars, "DRIVE_COST_PER_KM", 0.25)?,
+            fuel_prices: fuel_prices(vars)?,
+            fuel_currency: optional(vars, "FUEL_CURRENCY").unwrap_or_else(|| "USD".into()),
+            route_deviation_meters: parse_or(vars, "ROUTE_DEVIATION_METERS", 50.0)?,
+            reroute_interval: Duration::from_secs(parse_or(vars, "REROUTE_MIN_INTERVAL_SECS", 15)?),
+            commute_sample_interval: Duration::from_secs(parse_or(
+                vars,
+                "COMMUTE_SAMPLE_INTERVAL_SECS",
+                600,
+            )?),
+            trip_precompute_lead: Duration::from_secs(parse_or(
+                vars,
+                "TRIP_PRECOMPUTE_LEAD_SECS",
+                900,
+            )?),
+            batch_max_items: parse_or(vars, "BATCH_MAX_ITEMS", 50)?,
+            batch_concurrency: parse_or(vars, "BATCH_CONCURRENCY", 4)?,
+            batch_timeout: Duration::from_millis(parse_or(
+                vars,
+                "BATCH_TIMEOUT_MS",
+                DEFAULT_BATCH_TIMEOUT_MS,
+            )?),
+            job_ttl: Duration::from_secs(parse_or(vars, "JOB_TTL_SECS", 3600)?),
+            load_shed_max_in_flight: parse_or(vars, "LOAD_SHED_MAX_IN_FLIGHT", 0)?,
+            concurrency_limit: parse_or(vars, "CONCURRENCY_LIMIT", 0)?,
+            route_concurrency_limits: route_concurrency_limits(vars)?,
+            load_shed_max_lag: Duration::from_millis(parse_or(vars, "LOAD_SHED_MAX_LAG_MS", 0)?),
+            slow_request_threshold: Duration::from_millis(parse_or(
+                vars,
+                "SLOW_REQUEST_THRESHOLD_MS",
+                DEFAULT_SLOW_REQUEST_THRESHOLD_MS,
+            )?),
+            alert_webhook_url: optional(vars, "ALERT_WEBHOOK_URL"),
+            fcm_service_account_path: optional(vars, "FCM_SERVICE_ACCOUNT_PATH").map(PathBuf::from),
+            alert_cooldown: Duration::from_secs(parse_or(vars, "ALERT_COOLDOWN_SECS", 300)?),
+            slow_request_alert_rate: parse_or(vars, "SLOW_REQUEST_ALERT_RATE", 0.1)?,
+            slow_request_alert_window: Duration::from_secs(parse_or(
+                vars,
+                "SLOW_REQUEST_ALERT_WINDOW_SECS",
+                60,
+            )?),
+            slow_request_alert_min_requests: parse_or(vars, "SLOW_REQUEST_ALERT_MIN_REQUESTS", 20)?,
+            error_rate_alert_threshold: parse_or(vars, "ERROR_RATE_ALERT_THRESHOLD", 0.05)?,
+            error_rate_alert_window: Duration::from_secs(parse_or(
+                vars,
+                "ERROR_RATE_ALERT_WINDOW_SECS",
+                300,
+            )?),
+            error_rate_alert_min_requests: parse_or(vars, "ERROR_RATE_ALERT_MIN_REQUESTS", 20)?,
+            usage_prices: usage_prices(vars)?,
+            feature_flags: feature_flags(vars)?,
+            feature_flags_refresh: Duration::from_secs(parse_or(
+                vars,
+                "FEATURE_FLAGS_REFRESH_SECS",
+                30,
+            )?),
+            audit_log: parse_or(vars, "AUDIT_LOG", AuditSink::Off)?,
+            audit_log_path: optional(vars, "AUDIT_LOG_PATH").map(PathBuf::from),
+            otel_service_name: optional(vars, "OTEL_SERVICE_NAME")
+                .unwrap_or_else(|| "multi-map-backend".into()),
+            tls_key_path: optional(vars, "TLS_KEY_PATH").map(PathBuf::from),
+            upstream_timeout: Duration::from_millis(parse_or(
+                vars,
+                "UPSTREAM_TIMEOUT_MS",
+                DEFAULT_UPSTREAM_TIMEOUT_MS,
+            )?),
+            connect_timeout: Duration::from_millis(parse_or(
+                vars,
+                "UPSTREAM_CONNECT_TIMEOUT_MS",
+                DEFAULT_CONNECT_TIMEOUT_MS,
+            )?),
+            pool_max_idle_per_host: parse_or(vars, "UPSTREAM_POOL_MAX_IDLE_PER_HOST", 32)?,
+            pool_idle_timeout: Duration::from_secs(parse_or(
+                vars,
+                "UPSTREAM_POOL_IDLE_TIMEOUT_SECS",
+                90,
+            )?),
+            tcp_keepalive: Duration::from_secs(parse_or(vars, "UPSTREAM_TCP_KEEPALIVE_SECS", 60)?),
+            http2_prior_knowledge: parse_or(vars, "UPSTREAM_HTTP2_PRIOR_KNOWLEDGE", false)?,
+            upstream_warmup_interval: Duration::from_secs(parse_or(
+                vars,
+                "UPSTREAM_WARMUP_INTERVAL_SECS",
+                45,
+            )?),
+            places_timeout: Duration::from_millis(parse_or(
+                vars,
+                "PLACES_TIMEOUT_MS",
+                DEFAULT_PLACES_TIMEOUT_MS,
+            )?),
+            routes_timeout: Duration::from_millis(parse_or(
+                vars,
+                "ROUTES_TIMEOUT_MS",
+                DEFAULT_ROUTES_TIMEOUT_MS,
+            )?),
+            retry_max_attempts: parse_or(vars, "RETRY_MAX_ATTEMPTS", DEFAULT_RETRY_MAX_ATTEMPTS)?,
+            retry_base_delay: Duration::from_millis(parse_or(
+                vars,
+                "RETRY_BASE_DELAY_MS",
+                DEFAULT_RETRY_BASE_DELAY_MS,
+            )?),
+            retry_max_delay: Duration::from_millis(parse_or(
+                vars,
+                "RETRY_MAX_DELAY_MS",
+                DEFAULT_RETRY_MAX_DELAY_MS,
+            )?),
+            breaker_failure_threshold: parse_or(
+                vars,
+                "BREAKER_FAILURE_THRESHOLD",
+                DEFAULT_BREAKER_FAILURE_THRESHOLD,
+            )?,
+            breaker_open_duration: Duration::from_secs(parse_or(
+                vars,
+                "BREAKER_OPEN_SECS",
+                DEFAULT_BREAKER_OPEN_SECS,
+            )?),
+            ip_allowlist: cidr_list(vars, "IP_ALLOWLIST")?,
+            ip_denylist: cidr_list(vars, "IP_DENYLIST")?,
+            trusted_proxies: cidr_list(vars, "TRUSTED_PROXIES")?,
+            rate_limit_enabled: parse_or(vars, "RATE_LIMIT_ENABLED", true)?,
+            rate_limit_burst: parse_or(vars, "RATE_LIMIT_BURST", DEFAULT_RATE_LIMIT_BURST)?,
+            rate_limit_per_sec: parse_or(vars, "RATE_LIMIT_PER_SEC", DEFAULT_RATE_LIMIT_PER_SEC)?,
+            cors_allowed_origins: list_or(vars, "CORS_ALLOWED_ORIGINS", &[]),
+            cors_allowed_methods: list_or(
+                vars,
+                "CORS_ALLOWED_METHODS",
+                DEFAULT_CORS_ALLOWED_METHODS,
+            ),
+            cors_allowed_headers: list_or(
+                vars,
+                "CORS_ALLOWED_HEADERS",
+                DEFAULT_CORS_ALLOWED_HEADERS,
+            ),
+            cors_max_age: Duration::from_secs(parse_or(
+                vars,
+                "CORS_MAX_AGE_SECS",
+                DEFAULT_CORS_MAX_AGE_SECS,
+            )?),
+            compression_enabled: parse_or(vars, "COMPRESSION_ENABLED", true)?,
+            places_enabled: parse_or(vars, "PLACES_ENABLED", true)?,
+            routes_enabled: parse_or(vars, "ROUTES_ENABLED", true)?,
+            cache_enabled: parse_or(vars, "CACHE_ENABLED", true)?,
+            cache_ttl: Duration::from_secs(parse_or(
+                vars,
+                "CACHE_TTL_SECS",
+                DEFAULT_CACHE_TTL_SECS,
+            )?),
+            cache_prewarm_top: parse_or(vars, "CACHE_PREWARM_TOP", 0)?,
+            cache_prewarm_window: Duration::from_secs(
+                parse_or(vars, "CACHE_PREWARM_WINDOW_HOURS", 24u64)? * 3600,
+            ),
+            cache_max_entries: parse_or(vars, "CACHE_MAX_ENTRIES", DEFAULT_CACHE_MAX_ENTRIES)?,
+            redis_url: optional(vars, "REDIS_URL"),
+            stale_if_error_enabled: parse_or(vars, "STALE_IF_ERROR_ENABLED", true)?,
+            readiness_cache: Duration::from_secs(parse_or(vars, "READINESS_CACHE_SECS", 30)?),
+            stale_ttl: Duration::from_secs(parse_or(
+                vars,
+                "STALE_TTL_SECS",
+                DEFAULT_STALE_TTL_SECS,
+            )?),
+            admin_token: optional(vars, "ADMIN_TOKEN"),
+            api_auth_enabled: parse_or(vars, "API_AUTH_ENABLED", true)?,
+            client_api_keys: list_or(vars, "CLIENT_API_KEYS", &[]),
+            jwt_secret: optional(vars, "JWT_SECRET"),
+            jwt_jwks_url: optional(vars, "JWT_JWKS_URL"),
+            jwt_issuer: optional(vars, "JWT_ISSUER"),
+            jwt_audience: optional(vars, "JWT_AUDIENCE"),
+            jwt_user_claim: optional(vars, "JWT_USER_CLAIM").unwrap_or_else(|| "sub".into()),
+            signing_clients: signing_clients(vars)?,
+            signing_tolerance: Duration::from_secs(parse_or(vars, "SIGNING_TOLERANCE_SECS", 300)?),
+            oauth_callback_url: optional(vars, "OAUTH_CALLBACK_URL"),
+            oauth_app_redirect_urls: list_or(vars, "OAUTH_APP_REDIRECT_URLS", &[]),
+            oauth_google_client_id: optional(vars, "OAUTH_GOOGLE_CLIENT_ID"),
+            oauth_google_client_secret: optional(vars, "OAUTH_GOOGLE_CLIENT_SECRET"),
+            oauth_github_client_id: optional(vars, "OAUTH_GITHUB_CLIENT_ID"),
+            oauth_github_client_secret: optional(vars, "OAUTH_GITHUB_CLIENT_SECRET"),
+            session_ttl: Duration::from_secs(parse_or(vars, "SESSION_TTL_SECS", 86400)?),
+            users_enabled: parse_or(vars, "USERS_ENABLED", false)?,
+            quota_daily: parse_optional(vars, "QUOTA_DAILY")?,
+            quota_monthly: parse_optional(vars, "QUOTA_MONTHLY")?,
+            database_url: optional(vars, "DATABASE_URL"),
+            database_max_connections: parse_or(
+                vars,
+                "DATABASE_MAX_CONNECTIONS",
+                DEFAULT_DATABASE_MAX_CONNECTIONS,
+            )?,
+        };
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn api_keys_enabled(&self) -> bool {
+        !self.client_api_keys.is_empty() || self.database_url.is_some()
+    }
+
+    pub fn jwt_enabled(&self) -> bool {
+        self.jwt_secret.is_some() || self.jwt_jwks_url.is_some()
+    }
+
+    pub fn oauth_enabled(&self) -> bool {
+        self.oauth_google_client_id.is_some() || self.oauth_github_client_id.is_some()
+    }
+
+    pub fn ip_filter_enabled(&self) -> bool {
+        !self.ip_allowlist.is_empty()
+            || !self.ip_denylist.is_empty()
+            || !self.trusted_proxies.is_empty()
+    }
+
+    /// Google is never called, the mock or recordings answer instead.
+    pub fn offline(&self) -> bool {
+        self.mock_providers || self.cassette_mode == CassetteMode::Replay
+    }
+
+    pub fn quotas_enabled(&self) -> bool {
+        self.quota_daily.is_some() || self.quota_monthly.is_some()
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.upstream_timeout.is_zero() {
+            return Err(ConfigError::Invalid {
+                key: "UPSTREAM_TIMEOUT_MS",
+                value: "0".into(),
+            });
+        }
+        if self.connect_timeout.is_zero() {
+            return Err(ConfigError::Invalid {
+                key: "UPSTREAM_CONNECT_TIMEOUT_MS",
+                value: "0".into(),
+            });
+        }
+        if self.places_timeout.is_zero() {
+            return Err(ConfigError::Invalid {
+                key: "PLACES_TIMEOUT_MS",
+                value: "0".into(),
+            });
+        }
+        if self.routes_timeout.is_zero() {
+            return Err(ConfigError::Invalid {
+                key: "ROUTES_TIMEOUT_MS",
+                value: "0".into(),
+            });
+        }
+        if self.retry_max_attempts == 0 {
+            return Err(ConfigError::Invalid {
+                key: "RETRY_MAX_ATTEMPTS",
+                value: "0".into(),
+            });
+        }
+        if self.breaker_failure_threshold == 0 {
+            return Err(ConfigError::Invalid {
+                key: "BREAKER_FAILURE_THRESHOLD",
+                value: "0".into(),
+            });
+        }
+        if self.rate_limit_enabled && self.rate_limit_burst == 0 {
+            return Err(ConfigError::Invalid {
+                key: "RATE_LIMIT_BURST",
+                value: "0".into(),
+            });
+        }
+        if self.slow_request_threshold.is_zero() {
+            return Err(ConfigError::Invalid {
+                key: "SLOW_REQUEST_THRESHOLD_MS",
+                value: "0".into(),
+            });
+        }
+        if !(self.slow_request_alert_rate > 0.0 && self.slow_request_alert_rate < 1.0) {
+            return Err(ConfigError::Invalid {
+                key: "SLOW_REQUEST_ALERT_RATE",
+                value: self.slow_request_alert_rate.to_string(),
+            });
+        }
+        if self.slow_request_alert_window.is_zero() {
+            return Err(ConfigError::Invalid {
+                key: "SLOW_REQUEST_ALERT_WINDOW_SECS",
+                value: "0".into(),
+            });
+        }
+        if let Some(precision) = self.geohash_precision {
+            if !(1..=geohash::MAX_PRECISION).contains(&precision) {
+                return Err(ConfigError::Invalid {
+                    key: "GEOHASH_PRECISION",
+                    value: precision.to_string(),
+                });
+            }
+        }
+        if self.batch_max_items == 0 {
+            return Err(ConfigError::Invalid {
+                key: "BATCH_MAX_ITEMS",
+                value: "0".into(),
+            });
+        }
+        if self.batch_concurrency == 0 {
+            return Err(ConfigError::Invalid {
+                key: "BATCH_CONCURRENCY",
+                value: "0".into(),
+            });
+        }
+        if self.batch_timeout.is_zero() {
+            return Err(ConfigError::Invalid {
+                key: "BATCH_TIMEOUT_MS",
+                value: "0".into(),
+            });
+        }
+        if self.job_ttl.is_zero() {
+            return Err(ConfigError::Invalid {
+                key: "JOB_TTL_SECS",
+                value: "0".into(),
+            });
+        }
+        if !(self.place_dedupe_meters.is_finite() && self.place_dedupe_meters >= 0.0) {
+            return Err(ConfigError::Invalid {
+                key: "PLACE_DEDUPE_METERS",
+                value: self.place_dedupe_meters.to_string(),
+            });
+        }
+        if !(self.drive_cost_per_km.is_finite() && self.drive_cost_per_km >= 0.0) {
+            return Err(ConfigError::Invalid {
+                key: "DRIVE_COST_PER_KM",
+                value: self.drive_cost_per_km.to_string(),
+            });
+        }
+        if !(self.route_deviation_meters.is_finite() && self.route_deviation_meters > 0.0) {
+            return Err(ConfigError::Invalid {
+                key: "ROUTE_DEVIATION_METERS",
+                value: self.route_deviation_meters.to_string(),
+            });
+        }
+        if !(self.error_rate_alert_threshold > 0.0 && self.error_rate_alert_threshold < 1.0) {
+            return Err(ConfigError::Invalid {
+                key: "ERROR_RATE_ALERT_THRESHOLD",
+                value: self.error_rate_alert_threshold.to_string(),
+            });
+        }
+        if self.error_rate_alert_window.is_zero() {
+            return Err(ConfigError::Invalid {
+                key: "ERROR_RATE_ALERT_WINDOW_SECS",
+                value: "0".into(),
+            });
+        }
+        if self.rate_limit_enabled
+            && !(self.rate_limit_per_sec.is_finite() && self.rate_limit_per_sec > 0.0)
+        {
+            return Err(ConfigError::Invalid {
+                key: "RATE_LIMIT_PER_SEC",
+                value: self.rate_limit_per_sec.to_string(),
+            });
+        }
+        if let Some(origin) = self
+            .cors_allowed_origins
+            .iter()
+            .find(|o| HeaderValue::from_str(o).is_err())
+        {
+            return Err(ConfigError::Invalid {
+                key: "CORS_ALLOWED_ORIGINS",
+                value: origin.clone(),
+            });
+        }
+        if let Some(method) = self
+            .cors_allowed_methods
+            .iter()
+            .find(|m| Method::from_bytes(m.as_bytes()).is_err())
+        {
+            return Err(ConfigError::Invalid {
+                key: "CORS_ALLOWED_METHODS",
+                value: method.clone(),
+            });
+        }
+        if let Some(header) = self
+            .cors_allowed_headers
+            .iter()
+            .find(|h| HeaderName::from_bytes(h.as_bytes()).is_err())
+        {
+            return Err(ConfigError::Invalid {
+                key: "CORS_ALLOWED_HEADERS",
+                value: header.clone(),
+            });
+        }
+        // Without any source of credentials every request would be rejected
+        if self.api_auth_enabled
+            && !self.api_keys_enabled()
+            && !self.jwt_enabled()
+            && self.signing_clients.is_empty()
+        {
+            return Err(ConfigError::Missing("CLIENT_API_KEYS"));
+        }
+        if !self.signing_clients.is_empty() && self.signing_tolerance.is_zero() {
+            return Err(ConfigError::Invalid {
+                key: "SIGNING_TOLERANCE_SECS",
+                value: "0".into(),
+            });
+        }
+        if self.oauth_google_client_id.is_some() && self.oauth_google_client_secret.is_none() {
+            return Err(ConfigError::Missing("OAUTH_GOOGLE_CLIENT_SECRET"));
+        }
+        if self.oauth_github_client_id.is_some() && self.oauth_github_client_secret.is_none() {
+            return Err(ConfigError::Missing("OAUTH_GITHUB_CLIENT_SECRET"));
+        }
+        if self.oauth_enabled() && self.oauth_callback_url.is_none() {
+            return Err(ConfigError::Missing("OAUTH_CALLBACK_URL"));
+        }
+        // Sessions are HS256 tokens, verified like any other bearer token
+        if self.oauth_enabled() && self.jwt_secret.is_none() {
+            return Err(ConfigError::Missing("JWT_SECRET"));
+        }
+        // Accounts sign in with sessions, which are only accepted through API authentication
+        if self.users_enabled && self.jwt_secret.is_none() {
+            return Err(ConfigError::Missing("JWT_SECRET"));
+        }
+        if self.users_enabled && self.database_url.is_none() {
+            return Err(ConfigError::Missing("DATABASE_URL"));
+        }
+        if self.users_enabled && !self.api_auth_enabled {
+            return Err(ConfigError::Invalid {
+                key: "API_AUTH_ENABLED",
+                value: "false".into(),
+            });
+        }
+        if (self.oauth_enabled() || self.users_enabled) && self.session_ttl.is_zero() {
+            return Err(ConfigError::Invalid {
+                key: "SESSION_TTL_SECS",
+                value: "0".into(),
+            });
+        }
+        // Quotas are per client, without authentication there are no clients to count
+        if self.quotas_enabled() && !self.api_auth_enabled {
+            return Err(ConfigError::Invalid {
+                key: "API_AUTH_ENABLED",
+                value: "false".into(),
+            });
+        }
+        if self.secrets_backend == SecretsBackend::Env
+            && self.google_keys.is_empty()
+            && !self.offline()
+        {
+            return Err(ConfigError::Missing("GOOGLE_PLACES_KEY"));
+        }
+        // Those clients would have every request turned away
+        if !self.sandbox_clients.is_empty()
+            && self.google_sandbox_keys.is_empty()
+            && !self.offline()
+        {
+            return Err(ConfigError::Missing("GOOGLE_SANDBOX_KEYS"));
+        }
+        for (key, origin) in [
+            ("GOOGLE_PLACES_ORIGIN", &self.google_places_origin),
+            ("GOOGLE_ROUTES_ORIGIN", &self.google_routes_origin),
+        ] {
+            if url::Url::parse(origin).is_err() {
+                return Err(ConfigError::Invalid {
+                    key,
+                    value: origin.clone(),
+                });
+            }
+        }
+        // The mock answers every call, there would be nothing to record or replay
+        if self.mock_providers && self.cassette_mode != CassetteMode::Off {
+            return Err(ConfigError::Invalid {
+                key: "MOCK_PROVIDERS",
+                value: "true".into(),
+            });
+        }
+        if self.places_race_url.is_some() && self.places_race_keys.is_empty() {
+            return Err(ConfigError::Missing("PLACES_RACE_KEY"));
+        }
+        if self.secrets_backend != SecretsBackend::Env && self.secrets_name.is_none() {
+            return Err(ConfigError::Missing("SECRETS_NAME"));
+        }
+        if self.secrets_backend == SecretsBackend::Vault && self.vault_addr.is_none() {
+            return Err(ConfigError::Missing("VAULT_ADDR"));
+        }
+        if self.secrets_backend == SecretsBackend::Vault && self.vault_token.is_none() {
+            return Err(ConfigError::Missing("VAULT_TOKEN"));
+        }
+        if self.secrets_refresh.is_zero() {
+            return Err(ConfigError::Invalid {
+                key: "SECRETS_REFRESH_SECS",
+                value: "0".into(),
+            });
+        }
+        if self.feature_flags_refresh.is_zero() {
+            return Err(ConfigError::Invalid {
+                key: "FEATURE_FLAGS_REFRESH_SECS",
+                value: "0".into(),
+            });
+        }
+        // A zero cooldown would let a rate limited key be picked again immediately
+        if self.google_key_cooldown.is_zero() {
+            return Err(ConfigError::Invalid {
+                key: "GOOGLE_KEY_COOLDOWN_SECS",
+                value: "0".into(),
+            });
+        }
+        if self.tls_cert_path.is_some() && self.tls_key_path.is_none() {
+            return Err(ConfigError::Missing("TLS_KEY_PATH"));
+        }
+        if self.tls_key_path.is_some() && self.tls_cert_path.is_none() {
+            return Err(ConfigError::Missing("TLS_CERT_PATH"));
+        }
+        if self.audit_log == AuditSink::Database && self.database_url.is_none() {
+            return Err(ConfigError::Missing("DATABASE_URL"));
+        }
+        if self.audit_log == AuditSink::File && self.audit_log_path.is_none() {
+            return Err(ConfigError::Missing("AUDIT_LOG_PATH"));
+        }
+        if self.database_max_connections == 0 {
+            return Err(ConfigError::Invalid {
+                key: "DATABASE_MAX_CONNECTIONS",
+                value: "0".into(),
+            });
+        }
+        if self.cache_enabled && self.cache_max_entries == 0 {
+            return Err(ConfigError::Invalid {
+                key: "CACHE_MAX_ENTRIES",
+                value: "0".into(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Settings by name, as read from the environment.
+pub type Vars = HashMap<String, String>;
+
+/// The process environment. Variables that aren't valid unicode are left out, as if unset.
+pub fn environment() -> Vars {
+    env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+        .collect()
+}
+
+fn required(vars: &Vars, key: &'static str) -> Result<String, ConfigError> {
+    match vars.get(key) {
+        Some(v) if !v.trim().is_empty() => Ok(v.clone()),
+        _ => Err(ConfigError::Missing(key)),
+    }
+}
+
+fn optional(vars: &Vars, key: &'static str) -> Option<String> {
+    vars.get(key).filter(|v| !v.trim().is_empty()).cloned()
+}
+
+fn parse_optional<T: FromStr>(vars: &Vars, key: &'static str) -> Result<Option<T>, ConfigError> {
+    match optional(vars, key) {
+        Some(v) => v
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| ConfigError::Invalid { key, value: v }),
+        None => Ok(None),
+    }
+}
+
+fn parse_or<T: FromStr>(vars: &Vars, key: &'static str, default: T) -> Result<T, ConfigError> {
+    match vars.get(key) {
+        Some(v) => v.trim().parse().map_err(|_| ConfigError::Invalid {
+            key,
+            value: v.clone(),
+        }),
+        None => Ok(default),
+    }
+}
+
+// Comma separated list, empty entries are dropped
+fn list_or(vars: &Vars, key: &'static str, default: &[&str]) -> Vec<String> {
+    match vars.get(key) {
+        Some(v) => parse_list(v),
+        None => default.iter().map(|item| item.to_string()).collect(),
+    }
+}
+
+// CIDR ranges, a bare address stands for just itself
+fn cidr_list(vars: &Vars, key: &'static str) -> Result<Vec<IpNet>, ConfigError> {
+    list_or(vars, key, &[])
+        .into_iter()
+        .map(|item| {
+            item.parse::<IpNet>()
+                .or_else(|_| item.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|_| ConfigError::Invalid { key, value: item })
+        })
+        .collect()
+}
+
+// `sku=price` pairs on top of the list prices
+fn usage_prices(vars: &Vars) -> Result<HashMap<String, f64>, ConfigError> {
+    let mut prices: HashMap<String, f64> = DEFAULT_PRICES
+        .iter()
+        .map(|(sku, price)| (sku.to_string(), *price))
+        .collect();
+
+    for entry in list_or(vars, "USAGE_PRICES", &[]) {
+        let parsed = entry
+            .split_once('=')
+            .and_then(|(sku, price)| Some((sku.trim(), price.trim().parse::<f64>().ok()?)))
+            .filter(|(sku, price)| !sku.is_empty() && price.is_finite() && *price >= 0.0);
+        match parsed {
+            Some((sku, price)) => {
+                prices.insert(sku.to_owned(), price);
+            }
+            None => {
+                return Err(ConfigError::Invalid {
+                    key: "USAGE_PRICES",
+                    value: entry,
+                })
+            }
+        }
+    }
+
+    Ok(prices)
+}
+
+// `fuel=price` pairs, e.g. `diesel=1.72`
+fn fuel_prices(vars: &Vars) -> Result<HashMap<String, f64>, ConfigError> {
+    list_or(vars, "FUEL_PRICES", &[])
+        .into_iter()
+        .map(|entry| {
+            entry
+                .split_once('=')
+                .and_then(|(fuel, price)| Some((fuel.trim(), price.trim().parse::<f64>().ok()?)))
+                .filter(|(fuel, price)| !fuel.is_empty() && price.is_finite() && *price >= 0.0)
+                .map(|(fuel, price)| (fuel.to_lowercase(), price))
+                .ok_or(ConfigError::Invalid {
+                    key: "FUEL_PRICES",
+                    value: entry,
+                })
+        })
+        .collect()
+}
+
+// `/path=limit` pairs, e.g. `/routes/matrix=4`
+fn route_concurrency_limits(vars: &Vars) -> Result<HashMap<String, usize>, ConfigError> {
+    list_or(vars, "ROUTE_CONCURRENCY_LIMITS", &[])
+        .into_iter()
+        .map(|entry| {
+            entry
+                .split_once('=')
+                .and_then(|(path, limit)| Some((path.trim(), limit.trim().parse::<usize>().ok()?)))
+                .filter(|(path, _)| path.starts_with('/'))
+                .map(|(path, limit)| (path.to_owned(), limit))
+                .ok_or(ConfigError::Invalid {
+                    key: "ROUTE_CONCURRENCY_LIMITS",
+                    value: entry,
+                })
+        })
+        .collect()
+}
+
+// `flag=rollout` or `flag@identity=rollout` pairs
+fn feature_flags(vars: &Vars) -> Result<Rules, ConfigError> {
+    list_or(vars, "FEATURE_FLAGS", &[])
+        .into_iter()
+        .map(|entry| {
+            entry
+                .split_once('=')
+                .and_then(|(name, rollout)| flags::parse_rule(name, rollout))
+                .ok_or(ConfigError::Invalid {
+                    key: "FEATURE_FLAGS",
+                    value: entry,
+                })
+        })
+        .collect()
+}
+
+// `id:secret` pairs. Errors only echo the client id so secrets stay out of the logs
+fn signing_clients(vars: &Vars) -> Result<Vec<(String, String)>, ConfigError> {
+    list_or(vars, "SIGNING_CLIENTS", &[])
+        .into_iter()
+        .map(|entry| match entry.split_once(':') {
+            Some((id, secret)) if !id.is_empty() && !secret.is_empty() => {
+                Ok((id.to_owned(), secret.to_owned()))
+            }
+            _ => Err(ConfigError::Invalid {
+                key: "SIGNING_CLIENTS",
+                value: entry.split(':').next().unwrap_or_default().to_owned(),
+            }),
+        })
+        .collect()
+}
+
+/// Splits a comma separated setting, dropping blank items.
+pub fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(String::from)
+        .collect()
+}
@@ -0,0 +1,54 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+/// A client key as stored, only the SHA-256 of the key itself is kept.
+#[derive(Debug, FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+pub async fn insert(pool: &PgPool, name: &str, key_hash: &str) -> Result<ApiKey, sqlx::Error> {
+    sqlx::query_as::<_, ApiKey>(
+        "INSERT INTO api_keys (id, name, key_hash) VALUES ($1, $2, $3)
+         RETURNING id, name, created_at, revoked_at",
+    )
+    .bind(Uuid::new_v4())
+    .bind(name)
+    .bind(key_hash)
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn list(pool: &PgPool) -> Result<Vec<ApiKey>, sqlx::Error> {
+    sqlx::query_as::<_, ApiKey>(
+        "SELECT id, name, created_at, revoked_at FROM api_keys ORDER BY created_at DESC",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn is_active(pool: &PgPool, key_hash: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS (SELECT 1 FROM api_keys WHERE key_hash = $1 AND revoked_at IS NULL)",
+    )
+    .bind(key_hash)
+    .fetch_one(pool)
+    .await
+}
+
+/// Returns whether an active key was revoked.
+pub async fn revoke(pool: &PgPool, id: Uuid) -> Result<bool, sqlx::Error> {
+    let result =
+        sqlx::query("UPDATE api_keys SET revoked_at = now() WHERE id = $1 AND revoked_at IS NULL")
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,60 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{types::Json, FromRow, PgPool};
+use uuid::Uuid;
+
+/// One handled API request. Params are sanitized before they get here.
+#[derive(Clone, Debug, Deserialize, FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub id: Uuid,
+    pub at: DateTime<Utc>,
+    pub identity: Option<String>,
+    pub method: String,
+    pub endpoint: String,
+    pub params: Json<Value>,
+    pub provider: Option<String>,
+    pub status: i32,
+    pub latency_ms: i64,
+}
+
+pub async fn insert(pool: &PgPool, entry: &AuditEntry) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO audit_log
+            (id, at, identity, method, endpoint, params, provider, status, latency_ms)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+    )
+    .bind(entry.id)
+    .bind(entry.at)
+    .bind(&entry.identity)
+    .bind(&entry.method)
+    .bind(&entry.endpoint)
+    .bind(&entry.params)
+    .bind(&entry.provider)
+    .bind(entry.status)
+    .bind(entry.latency_ms)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Most recent entries first, optionally only those of one identity.
+pub async fn recent(
+    pool: &PgPool,
+    identity: Option<&str>,
+    limit: i64,
+) -> Result<Vec<AuditEntry>, sqlx::Error> {
+    sqlx::query_as::<_, AuditEntry>(
+        "SELECT id, at, identity, method, endpoint, params, provider, status, latency_ms
+         FROM audit_log
+         WHERE $1::text IS NULL OR identity = $1
+         ORDER BY at DESC
+         LIMIT $2",
+    )
+    .bind(identity)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
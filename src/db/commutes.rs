@@ -0,0 +1,389 @@
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+use super::trips::Coordinate;
+
+const COMMUTE_COLUMNS: &str = "id, owner, name, origin_latitude, origin_longitude, \
+    destination_latitude, destination_longitude, travel_mode, weekdays, window_start, \
+    window_end, time_zone, last_sampled_at, created_at";
+
+#[derive(Debug, FromRow)]
+struct CommuteRow {
+    id: Uuid,
+    owner: String,
+    name: String,
+    origin_latitude: f64,
+    origin_longitude: f64,
+    destination_latitude: f64,
+    destination_longitude: f64,
+    travel_mode: String,
+    weekdays: Vec<i16>,
+    window_start: NaiveTime,
+    window_end: NaiveTime,
+    time_zone: String,
+    last_sampled_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Commute {
+    pub id: Uuid,
+    pub owner: String,
+    pub name: String,
+    pub origin: Coordinate,
+    pub destination: Coordinate,
+    pub travel_mode: String,
+    /// ISO weekdays, 1 is Monday
+    pub weekdays: Vec<i16>,
+    pub window_start: NaiveTime,
+    pub window_end: NaiveTime,
+    pub time_zone: String,
+    pub last_sampled_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<CommuteRow> for Commute {
+    fn from(row: CommuteRow) -> Self {
+        Commute {
+            id: row.id,
+            owner: row.owner,
+            name: row.name,
+            origin: Coordinate {
+                latitude: row.origin_latitude,
+                longitude: row.origin_longitude,
+            },
+            destination: Coordinate {
+                latitude: row.destination_latitude,
+                longitude: row.destination_longitude,
+            },
+            travel_mode: row.travel_mode,
+            weekdays: row.weekdays,
+            window_start: row.window_start,
+            window_end: row.window_end,
+            time_zone: row.time_zone,
+            last_sampled_at: row.last_sampled_at,
+            created_at: row.created_at,
+        }
+    }
+}
+
+pub struct NewCommute {
+    pub owner: String,
+    pub name: String,
+    pub origin: Coordinate,
+    pub destination: Coordinate,
+    pub travel_mode: String,
+    pub weekdays: Vec<i16>,
+    pub window_start: NaiveTime,
+    pub window_end: NaiveTime,
+    pub time_zone: String,
+}
+
+/// One measurement of the commute's travel time.
+#[derive(Debug, FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Sample {
+    pub sampled_at: DateTime<Utc>,
+    pub duration_seconds: f64,
+    /// The same route without traffic, when the travel mode has traffic
+    pub static_duration_seconds: Option<f64>,
+    pub distance_meters: f64,
+}
+
+/// Travel times over a period, `None` percentiles when nothing was sampled.
+#[derive(Debug, FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SampleStats {
+    pub samples: i64,
+    pub min_duration_seconds: Option<f64>,
+    pub median_duration_seconds: Option<f64>,
+    pub p90_duration_seconds: Option<f64>,
+    pub max_duration_seconds: Option<f64>,
+    /// Average time lost to traffic
+    pub mean_delay_seconds: Option<f64>,
+}
+
+pub async fn insert(pool: &PgPool, commute: NewCommute) -> Result<Commute, sqlx::Error> {
+    let row = sqlx::query_as::<_, CommuteRow>(&format!(
+        "INSERT INTO commutes (id, owner, name, origin_latitude, origin_longitude,
+             destination_latitude, destination_longitude, travel_mode, weekdays, window_start,
+             window_end, time_zone)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+         RETURNING {}",
+        COMMUTE_COLUMNS
+    ))
+    .bind(Uuid::new_v4())
+    .bind(commute.owner)
+    .bind(commute.name)
+    .bind(commute.origin.latitude)
+    .bind(commute.origin.longitude)
+    .bind(commute.destination.latitude)
+    .bind(commute.destination.longitude)
+    .bind(commute.travel_mode)
+    .bind(commute.weekdays)
+    .bind(commute.window_start)
+    .bind(commute.window_end)
+    .bind(commute.time_zone)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.into())
+}
+
+pub async fn count(pool: &PgPool, owner: &str) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar::<_, i64>("SELECT count(*) FROM commutes WHERE owner = $1")
+        .bind(owner)
+        .fetch_one(pool)
+        .await
+}
+
+pub async fn list(pool: &PgPool, owner: &str) -> Result<Vec<Commute>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, CommuteRow>(&format!(
+        "SELECT {} FROM commutes WHERE owner = $1 ORDER BY created_at DESC",
+        COMMUTE_COLUMNS
+    ))
+    .bind(owner)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(Commute::from).collect())
+}
+
+pub async fn get(pool: &PgPool, id: Uuid, owner: &str) -> Result<Option<Commute>, sqlx::Error> {
+    let row = sqlx::query_as::<_, CommuteRow>(&format!(
+        "SELECT {} FROM commutes WHERE id = $1 AND owner = $2",
+        COMMUTE_COLUMNS
+    ))
+    .bind(id)
+    .bind(owner)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(Commute::from))
+}
+
+/// Returns whether a commute owned by `owner` was deleted.
+pub async fn delete(pool: &PgPool, id: Uuid, owner: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM commutes WHERE id = $1 AND owner = $2")
+        .bind(id)
+        .bind(owner)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Whether the database knows the IANA time zone `name`, windows are evaluated there.
+pub async fn is_time_zone(pool: &PgPool, name: &str) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM pg_timezone_names WHERE name = $1)")
+        .bind(name)
+        .fetch_one(pool)
+        .await
+}
+
+/// Claims up to `limit` commutes whose window is open in their time zone and that weren't
+/// sampled in the last `every`. Claimed rows are skipped by other instances.
+pub async fn claim_due(
+    pool: &PgPool,
+    every: Duration,
+    limit: i64,
+) -> Result<Vec<Commute>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, CommuteRow>(&format!(
+        "UPDATE commutes SET last_sampled_at = now()
+         WHERE id IN (
+             SELECT id FROM commutes
+             WHERE (last_sampled_at IS NULL
+                    OR last_sampled_at <= now() - make_interval(secs => $1))
+               AND EXTRACT(ISODOW FROM now() AT TIME ZONE time_zone)::SMALLINT = ANY (weekdays)
+               AND (now() AT TIME ZONE time_zone)::TIME BETWEEN window_start AND window_end
+             ORDER BY last_sampled_at NULLS FIRST
+             LIMIT $2
+             FOR UPDATE SKIP LOCKED
+         )
+         RETURNING {}",
+        COMMUTE_COLUMNS
+    ))
+    .bind(every.as_secs_f64())
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(Commute::from).collect())
+}
+
+pub async fn insert_sample(
+    pool: &PgPool,
+    commute_id: Uuid,
+    duration_seconds: f64,
+    static_duration_seconds: Option<f64>,
+    distance_meters: f64,
+) -> Result<Sample, sqlx::Error> {
+    sqlx::query_as::<_, Sample>(
+        "INSERT INTO commute_samples (commute_id, duration_seconds, static_duration_seconds,
+             distance_meters)
+         VALUES ($1, $2, $3, $4)
+         RETURNING sampled_at, duration_seconds, static_duration_seconds, distance_meters",
+    )
+    .bind(commute_id)
+    .bind(duration_seconds)
+    .bind(static_duration_seconds)
+    .bind(distance_meters)
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn latest_sample(pool: &PgPool, commute_id: Uuid) -> Result<Option<Sample>, sqlx::Error> {
+    sqlx::query_as::<_, Sample>(
+        "SELECT sampled_at, duration_seconds, static_duration_seconds, distance_meters
+         FROM commute_samples
+         WHERE commute_id = $1
+         ORDER BY sampled_at DESC
+         LIMIT 1",
+    )
+    .bind(commute_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Samples taken since `since`, newest first.
+pub async fn samples(
+    pool: &PgPool,
+    commute_id: Uuid,
+    since: DateTime<Utc>,
+) -> Result<Vec<Sample>, sqlx::Error> {
+    sqlx::query_as::<_, Sample>(
+        "SELECT sampled_at, duration_seconds, static_duration_seconds, distance_meters
+         FROM commute_samples
+         WHERE commute_id = $1 AND sampled_at >= $2
+         ORDER BY sampled_at DESC",
+    )
+    .bind(commute_id)
+    .bind(since)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn stats(
+    pool: &PgPool,
+    commute_id: Uuid,
+    since: DateTime<Utc>,
+) -> Result<SampleStats, sqlx::Error> {
+    sqlx::query_as::<_, SampleStats>(
+        "SELECT count(*) AS samples,
+             min(duration_seconds) AS min_duration_seconds,
+             percentile_cont(0.5) WITHIN GROUP (ORDER BY duration_seconds)
+                 AS median_duration_seconds,
+             percentile_cont(0.9) WITHIN GROUP (ORDER BY duration_seconds)
+                 AS p90_duration_seconds,
+             max(duration_seconds) AS max_duration_seconds,
+             avg(duration_seconds - static_duration_seconds) AS mean_delay_seconds
+         FROM commute_samples
+         WHERE commute_id = $1 AND sampled_at >= $2",
+    )
+    .bind(commute_id)
+    .bind(since)
+    .fetch_one(pool)
+    .await
+}
+
+/// Where to notify the owner when the commute's travel time moves away from usual.
+#[derive(Debug, FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommuteAlert {
+    pub id: Uuid,
+    pub commute_id: Uuid,
+    pub channel: String,
+    pub target: String,
+    #[serde(skip)]
+    pub secret: String,
+    pub threshold_percent: f64,
+    pub last_notified_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+const ALERT_COLUMNS: &str =
+    "id, commute_id, channel, target, secret, threshold_percent, last_notified_at, created_at";
+
+pub async fn insert_alert(
+    pool: &PgPool,
+    commute_id: Uuid,
+    channel: &str,
+    target: &str,
+    secret: &str,
+    threshold_percent: f64,
+) -> Result<CommuteAlert, sqlx::Error> {
+    sqlx::query_as::<_, CommuteAlert>(&format!(
+        "INSERT INTO commute_alerts (id, commute_id, channel, target, secret, threshold_percent)
+         VALUES ($1, $2, $3, $4, $5, $6)
+         RETURNING {}",
+        ALERT_COLUMNS
+    ))
+    .bind(Uuid::new_v4())
+    .bind(commute_id)
+    .bind(channel)
+    .bind(target)
+    .bind(secret)
+    .bind(threshold_percent)
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn alerts(pool: &PgPool, commute_id: Uuid) -> Result<Vec<CommuteAlert>, sqlx::Error> {
+    sqlx::query_as::<_, CommuteAlert>(&format!(
+        "SELECT {} FROM commute_alerts WHERE commute_id = $1 ORDER BY created_at",
+        ALERT_COLUMNS
+    ))
+    .bind(commute_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Returns whether the alert existed on that commute.
+pub async fn delete_alert(pool: &PgPool, id: Uuid, commute_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM commute_alerts WHERE id = $1 AND commute_id = $2")
+        .bind(id)
+        .bind(commute_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Claims the alerts of the commute set off by a `deviation_percent` change and not
+/// notified in the last `cooldown`.
+pub async fn claim_alerts(
+    pool: &PgPool,
+    commute_id: Uuid,
+    deviation_percent: f64,
+    cooldown: Duration,
+) -> Result<Vec<CommuteAlert>, sqlx::Error> {
+    sqlx::query_as::<_, CommuteAlert>(&format!(
+        "UPDATE commute_alerts SET last_notified_at = now()
+         WHERE commute_id = $1 AND threshold_percent <= $2
+           AND (last_notified_at IS NULL
+                OR last_notified_at <= now() - make_interval(secs => $3))
+         RETURNING {}",
+        ALERT_COLUMNS
+    ))
+    .bind(commute_id)
+    .bind(deviation_percent)
+    .bind(cooldown.as_secs_f64())
+    .fetch_all(pool)
+    .await
+}
+
+/// Today's end of the commute window, in the commute's time zone.
+pub async fn window_end_today(pool: &PgPool, id: Uuid) -> Result<DateTime<Utc>, sqlx::Error> {
+    sqlx::query_scalar::<_, DateTime<Utc>>(
+        "SELECT ((now() AT TIME ZONE time_zone)::DATE + window_end) AT TIME ZONE time_zone
+         FROM commutes WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_one(pool)
+    .await
+}
@@ -0,0 +1,94 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{types::Json, FromRow, PgPool};
+use uuid::Uuid;
+
+use crate::geo::fence::Shape;
+
+#[derive(Debug, FromRow)]
+struct GeofenceRow {
+    id: Uuid,
+    name: String,
+    shape: Json<Shape>,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Geofence {
+    pub id: Uuid,
+    pub name: String,
+    pub shape: Shape,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<GeofenceRow> for Geofence {
+    fn from(row: GeofenceRow) -> Self {
+        Geofence {
+            id: row.id,
+            name: row.name,
+            shape: row.shape.0,
+            created_at: row.created_at,
+        }
+    }
+}
+
+pub async fn insert(
+    pool: &PgPool,
+    owner: &str,
+    name: &str,
+    shape: Shape,
+) -> Result<Geofence, sqlx::Error> {
+    let row = sqlx::query_as::<_, GeofenceRow>(
+        "INSERT INTO geofences (id, owner, name, shape)
+         VALUES ($1, $2, $3, $4)
+         RETURNING id, name, shape, created_at",
+    )
+    .bind(Uuid::new_v4())
+    .bind(owner)
+    .bind(name)
+    .bind(Json(shape))
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.into())
+}
+
+pub async fn list(pool: &PgPool, owner: &str) -> Result<Vec<Geofence>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, GeofenceRow>(
+        "SELECT id, name, shape, created_at
+         FROM geofences
+         WHERE owner = $1
+         ORDER BY created_at DESC",
+    )
+    .bind(owner)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(Geofence::from).collect())
+}
+
+pub async fn get(pool: &PgPool, id: Uuid, owner: &str) -> Result<Option<Geofence>, sqlx::Error> {
+    let row = sqlx::query_as::<_, GeofenceRow>(
+        "SELECT id, name, shape, created_at
+         FROM geofences
+         WHERE id = $1 AND owner = $2",
+    )
+    .bind(id)
+    .bind(owner)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(Geofence::from))
+}
+
+/// Returns whether a fence owned by `owner` was deleted.
+pub async fn delete(pool: &PgPool, id: Uuid, owner: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM geofences WHERE id = $1 AND owner = $2")
+        .bind(id)
+        .bind(owner)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,92 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+#[derive(Debug, FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub id: Uuid,
+    pub query: String,
+    pub chosen_place_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+pub async fn insert(pool: &PgPool, id: Uuid, owner: &str, query: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO search_history (id, owner, query) VALUES ($1, $2, $3)")
+        .bind(id)
+        .bind(owner)
+        .bind(query)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn list(
+    pool: &PgPool,
+    owner: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<HistoryEntry>, sqlx::Error> {
+    sqlx::query_as::<_, HistoryEntry>(
+        "SELECT id, query, chosen_place_id, created_at
+         FROM search_history
+         WHERE owner = $1
+         ORDER BY created_at DESC
+         LIMIT $2 OFFSET $3",
+    )
+    .bind(owner)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await
+}
+
+/// Returns whether an entry owned by `owner` was updated.
+pub async fn set_choice(
+    pool: &PgPool,
+    owner: &str,
+    id: Uuid,
+    place_id: &str,
+) -> Result<bool, sqlx::Error> {
+    let result =
+        sqlx::query("UPDATE search_history SET chosen_place_id = $3 WHERE id = $1 AND owner = $2")
+            .bind(id)
+            .bind(owner)
+            .bind(place_id)
+            .execute(pool)
+            .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn delete_all(pool: &PgPool, owner: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM search_history WHERE owner = $1")
+        .bind(owner)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// The `limit` queries searched most often since `since` across every owner, normalized the
+/// way the cache keys them.
+pub async fn popular(
+    pool: &PgPool,
+    since: DateTime<Utc>,
+    limit: i64,
+) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar::<_, String>(
+        "SELECT lower(regexp_replace(btrim(query), '\\s+', ' ', 'g')) AS normalized
+         FROM search_history
+         WHERE created_at >= $1
+         GROUP BY normalized
+         ORDER BY count(*) DESC, normalized
+         LIMIT $2",
+    )
+    .bind(since)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
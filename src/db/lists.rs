@@ -0,0 +1,373 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+use super::saved_places::SavedPlace;
+
+/// What someone may do with a list, ordered from least to most access.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(Role::Viewer),
+            "editor" => Ok(Role::Editor),
+            "owner" => Ok(Role::Owner),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A list as seen by one identity, `role` is theirs.
+#[derive(Debug, FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaceList {
+    pub id: Uuid,
+    pub name: String,
+    pub role: String,
+    pub version: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl PlaceList {
+    pub fn role(&self) -> Option<Role> {
+        Role::from_str(&self.role).ok()
+    }
+}
+
+#[derive(Debug, FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListMember {
+    pub member: String,
+    pub role: String,
+    pub joined_at: DateTime<Utc>,
+}
+
+/// Outcome of an edit guarded by the list version.
+pub enum Edit {
+    Applied {
+        version: i64,
+    },
+    /// The list moved past the version the client expected
+    Stale,
+    /// Nothing to remove
+    Missing,
+}
+
+#[derive(Debug, FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListedPlace {
+    #[sqlx(flatten)]
+    #[serde(flatten)]
+    pub place: SavedPlace,
+    pub list_id: Uuid,
+    pub tags: Vec<String>,
+    pub added_by: Option<String>,
+    pub added_at: DateTime<Utc>,
+}
+
+const LISTED_PLACE_COLUMNS: &str = "p.id, p.place_id, p.name, p.latitude, p.longitude, p.notes, \
+    p.created_at, i.list_id, i.tags, i.added_by, i.added_at";
+
+// The owner's role is implied, everyone else's comes from the membership
+const PLACE_LIST_COLUMNS: &str = "l.id, l.name, \
+    CASE WHEN l.owner = $2 THEN 'owner' ELSE m.role END AS role, l.version, l.created_at";
+
+/// Returns `None` when the owner already has a list with that name.
+pub async fn create(
+    pool: &PgPool,
+    owner: &str,
+    name: &str,
+) -> Result<Option<PlaceList>, sqlx::Error> {
+    sqlx::query_as::<_, PlaceList>(
+        "INSERT INTO place_lists (id, owner, name) VALUES ($1, $2, $3)
+         ON CONFLICT (owner, name) DO NOTHING
+         RETURNING id, name, 'owner' AS role, version, created_at",
+    )
+    .bind(Uuid::new_v4())
+    .bind(owner)
+    .bind(name)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Lists `member` owns or was invited to.
+pub async fn list(pool: &PgPool, member: &str) -> Result<Vec<PlaceList>, sqlx::Error> {
+    sqlx::query_as::<_, PlaceList>(&format!(
+        "SELECT {} FROM place_lists l
+         LEFT JOIN place_list_members m ON m.list_id = l.id AND m.member = $2
+         WHERE l.owner = $1 OR m.member IS NOT NULL
+         ORDER BY l.name",
+        PLACE_LIST_COLUMNS
+    ))
+    .bind(member)
+    .bind(member)
+    .fetch_all(pool)
+    .await
+}
+
+/// The list when `member` owns it or was invited to it.
+pub async fn get(pool: &PgPool, id: Uuid, member: &str) -> Result<Option<PlaceList>, sqlx::Error> {
+    sqlx::query_as::<_, PlaceList>(&format!(
+        "SELECT {} FROM place_lists l
+         LEFT JOIN place_list_members m ON m.list_id = l.id AND m.member = $2
+         WHERE l.id = $1 AND (l.owner = $2 OR m.member IS NOT NULL)",
+        PLACE_LIST_COLUMNS
+    ))
+    .bind(id)
+    .bind(member)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Returns whether a list owned by `owner` was deleted.
+pub async fn delete(pool: &PgPool, id: Uuid, owner: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM place_lists WHERE id = $1 AND owner = $2")
+        .bind(id)
+        .bind(owner)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+// Bumping the version locks the list row, so edits to one list apply one at a time and
+// an `expected` version is checked against the latest committed one
+async fn bump_version(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    list_id: Uuid,
+    expected: Option<i64>,
+) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar::<_, i64>(
+        "UPDATE place_lists SET version = version + 1
+         WHERE id = $1 AND ($2::BIGINT IS NULL OR version = $2)
+         RETURNING version",
+    )
+    .bind(list_id)
+    .bind(expected)
+    .fetch_optional(&mut **tx)
+    .await
+}
+
+/// Adds a saved place to a list, replacing its tags if it is already there.
+pub async fn upsert_item(
+    pool: &PgPool,
+    list_id: Uuid,
+    saved_place_id: Uuid,
+    tags: &[String],
+    added_by: &str,
+    expected: Option<i64>,
+) -> Result<Edit, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let Some(version) = bump_version(&mut tx, list_id, expected).await? else {
+        return Ok(Edit::Stale);
+    };
+    sqlx::query(
+        "INSERT INTO place_list_items (list_id, saved_place_id, tags, added_by)
+         VALUES ($1, $2, $3, $4)
+         ON CONFLICT (list_id, saved_place_id) DO UPDATE SET tags = EXCLUDED.tags",
+    )
+    .bind(list_id)
+    .bind(saved_place_id)
+    .bind(tags)
+    .bind(added_by)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(Edit::Applied { version })
+}
+
+pub async fn remove_item(
+    pool: &PgPool,
+    list_id: Uuid,
+    saved_place_id: Uuid,
+    expected: Option<i64>,
+) -> Result<Edit, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let Some(version) = bump_version(&mut tx, list_id, expected).await? else {
+        return Ok(Edit::Stale);
+    };
+    let result =
+        sqlx::query("DELETE FROM place_list_items WHERE list_id = $1 AND saved_place_id = $2")
+            .bind(list_id)
+            .bind(saved_place_id)
+            .execute(&mut *tx)
+            .await?;
+    if result.rows_affected() == 0 {
+        // Rolled back on drop, nothing changed so the version stays
+        return Ok(Edit::Missing);
+    }
+    tx.commit().await?;
+
+    Ok(Edit::Applied { version })
+}
+
+/// The owner first, then members in the order they joined.
+pub async fn members(pool: &PgPool, list_id: Uuid) -> Result<Vec<ListMember>, sqlx::Error> {
+    sqlx::query_as::<_, ListMember>(
+        "SELECT owner AS member, 'owner' AS role, created_at AS joined_at
+         FROM place_lists WHERE id = $1
+         UNION ALL
+         (SELECT member, role, joined_at FROM place_list_members
+          WHERE list_id = $1 ORDER BY joined_at)",
+    )
+    .bind(list_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Returns `None` when `member` isn't a member of the list.
+pub async fn update_member(
+    pool: &PgPool,
+    list_id: Uuid,
+    member: &str,
+    role: Role,
+) -> Result<Option<ListMember>, sqlx::Error> {
+    sqlx::query_as::<_, ListMember>(
+        "UPDATE place_list_members SET role = $3 WHERE list_id = $1 AND member = $2
+         RETURNING member, role, joined_at",
+    )
+    .bind(list_id)
+    .bind(member)
+    .bind(role.as_str())
+    .fetch_optional(pool)
+    .await
+}
+
+/// Returns whether `member` was a member of the list.
+pub async fn remove_member(
+    pool: &PgPool,
+    list_id: Uuid,
+    member: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM place_list_members WHERE list_id = $1 AND member = $2")
+        .bind(list_id)
+        .bind(member)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn create_invite(
+    pool: &PgPool,
+    token_hash: &str,
+    list_id: Uuid,
+    role: Role,
+    expires_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO place_list_invites (token_hash, list_id, role, expires_at)
+         VALUES ($1, $2, $3, $4)",
+    )
+    .bind(token_hash)
+    .bind(list_id)
+    .bind(role.as_str())
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Spends the invite and makes `member` a member of its list. Accepting never lowers a
+/// role the member already has. Returns `None` for unknown, used or expired invites.
+pub async fn accept_invite(
+    pool: &PgPool,
+    token_hash: &str,
+    member: &str,
+) -> Result<Option<PlaceList>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let invite = sqlx::query_as::<_, (Uuid, String)>(
+        "DELETE FROM place_list_invites WHERE token_hash = $1 AND expires_at > now()
+         RETURNING list_id, role",
+    )
+    .bind(token_hash)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some((list_id, role)) = invite else {
+        return Ok(None);
+    };
+
+    // The owner accepting their own invite just spends it
+    sqlx::query(
+        "INSERT INTO place_list_members (list_id, member, role)
+         SELECT $1, $2, $3 FROM place_lists WHERE id = $1 AND owner <> $2
+         ON CONFLICT (list_id, member) DO UPDATE
+         SET role = CASE WHEN place_list_members.role = 'editor' THEN 'editor'
+                         ELSE EXCLUDED.role END",
+    )
+    .bind(list_id)
+    .bind(member)
+    .bind(role)
+    .execute(&mut *tx)
+    .await?;
+    let list = sqlx::query_as::<_, PlaceList>(&format!(
+        "SELECT {} FROM place_lists l
+         LEFT JOIN place_list_members m ON m.list_id = l.id AND m.member = $2
+         WHERE l.id = $1",
+        PLACE_LIST_COLUMNS
+    ))
+    .bind(list_id)
+    .bind(member)
+    .fetch_optional(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(list)
+}
+
+pub async fn items(pool: &PgPool, list_id: Uuid) -> Result<Vec<ListedPlace>, sqlx::Error> {
+    sqlx::query_as::<_, ListedPlace>(&format!(
+        "SELECT {} FROM place_list_items i
+         JOIN saved_places p ON p.id = i.saved_place_id
+         WHERE i.list_id = $1
+         ORDER BY i.added_at DESC",
+        LISTED_PLACE_COLUMNS
+    ))
+    .bind(list_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Places tagged with `tag` across every list owned by `owner`.
+pub async fn items_by_tag(
+    pool: &PgPool,
+    owner: &str,
+    tag: &str,
+) -> Result<Vec<ListedPlace>, sqlx::Error> {
+    sqlx::query_as::<_, ListedPlace>(&format!(
+        "SELECT {} FROM place_list_items i
+         JOIN saved_places p ON p.id = i.saved_place_id
+         JOIN place_lists l ON l.id = i.list_id
+         WHERE l.owner = $1 AND $2 = ANY (i.tags)
+         ORDER BY i.added_at DESC",
+        LISTED_PLACE_COLUMNS
+    ))
+    .bind(owner)
+    .bind(tag)
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,32 @@
+pub mod api_keys;
+pub mod audit;
+pub mod commutes;
+pub mod geofences;
+pub mod history;
+pub mod lists;
+pub mod saved_places;
+pub mod shared_routes;
+pub mod trips;
+pub mod upstream_usage;
+pub mod usage;
+pub mod users;
+
+use sqlx::{migrate::MigrateError, postgres::PgPoolOptions, PgPool};
+
+pub async fn connect(url: &str, max_connections: u32) -> Result<PgPool, sqlx::Error> {
+    PgPoolOptions::new()
+        .max_connections(max_connections)
+        .connect(url)
+        .await
+}
+
+pub async fn ping(pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT 1").execute(pool).await?;
+
+    Ok(())
+}
+
+/// Applies the migrations embedded from `migrations/` at build time.
+pub async fn migrate(pool: &PgPool) -> Result<(), MigrateError> {
+    sqlx::migrate!().run(pool).await
+}
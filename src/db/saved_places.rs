@@ -0,0 +1,130 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+use super::trips::Coordinate;
+
+#[derive(Debug, FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedPlace {
+    pub id: Uuid,
+    pub place_id: String,
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub notes: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl SavedPlace {
+    pub fn location(&self) -> Coordinate {
+        Coordinate {
+            latitude: self.latitude,
+            longitude: self.longitude,
+        }
+    }
+}
+
+pub struct NewSavedPlace {
+    pub owner: String,
+    pub place_id: String,
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub notes: Option<String>,
+}
+
+pub async fn insert(pool: &PgPool, place: NewSavedPlace) -> Result<SavedPlace, sqlx::Error> {
+    sqlx::query_as::<_, SavedPlace>(
+        "INSERT INTO saved_places (id, owner, place_id, name, latitude, longitude, notes)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         RETURNING id, place_id, name, latitude, longitude, notes, created_at",
+    )
+    .bind(Uuid::new_v4())
+    .bind(place.owner)
+    .bind(place.place_id)
+    .bind(place.name)
+    .bind(place.latitude)
+    .bind(place.longitude)
+    .bind(place.notes)
+    .fetch_one(pool)
+    .await
+}
+
+pub async fn list(pool: &PgPool, owner: &str) -> Result<Vec<SavedPlace>, sqlx::Error> {
+    sqlx::query_as::<_, SavedPlace>(
+        "SELECT id, place_id, name, latitude, longitude, notes, created_at
+         FROM saved_places
+         WHERE owner = $1
+         ORDER BY created_at DESC",
+    )
+    .bind(owner)
+    .fetch_all(pool)
+    .await
+}
+
+/// At most `limit` of the owner's places inside the box between the two corners.
+pub async fn within(
+    pool: &PgPool,
+    owner: &str,
+    south_west: Coordinate,
+    north_east: Coordinate,
+    limit: i64,
+) -> Result<Vec<SavedPlace>, sqlx::Error> {
+    sqlx::query_as::<_, SavedPlace>(
+        "SELECT id, place_id, name, latitude, longitude, notes, created_at
+         FROM saved_places
+         WHERE owner = $1
+           AND latitude BETWEEN $2 AND $3 AND longitude BETWEEN $4 AND $5
+         LIMIT $6",
+    )
+    .bind(owner)
+    .bind(south_west.latitude)
+    .bind(north_east.latitude)
+    .bind(south_west.longitude)
+    .bind(north_east.longitude)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn get(pool: &PgPool, id: Uuid, owner: &str) -> Result<Option<SavedPlace>, sqlx::Error> {
+    sqlx::query_as::<_, SavedPlace>(
+        "SELECT id, place_id, name, latitude, longitude, notes, created_at
+         FROM saved_places
+         WHERE id = $1 AND owner = $2",
+    )
+    .bind(id)
+    .bind(owner)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Returns whether a place owned by `owner` was deleted.
+pub async fn delete(pool: &PgPool, id: Uuid, owner: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM saved_places WHERE id = $1 AND owner = $2")
+        .bind(id)
+        .bind(owner)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Places saved before they had owners, which no caller can see until they're claimed.
+pub async fn count_unowned(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT count(*) FROM saved_places WHERE owner IS NULL")
+        .fetch_one(pool)
+        .await
+}
+
+/// Gives every unowned place to `owner`, returning how many there were.
+pub async fn claim_unowned(pool: &PgPool, owner: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("UPDATE saved_places SET owner = $1 WHERE owner IS NULL")
+        .bind(owner)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
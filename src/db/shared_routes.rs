@@ -0,0 +1,63 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+
+const SHARED_ROUTE_COLUMNS: &str =
+    "slug, title, travel_mode, encoded_polyline, distance_meters, duration, created_at";
+
+/// A route as shared, without who shared it.
+#[derive(Debug, FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedRoute {
+    pub slug: String,
+    pub title: Option<String>,
+    pub travel_mode: String,
+    pub encoded_polyline: String,
+    pub distance_meters: Option<f64>,
+    pub duration: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct NewSharedRoute {
+    pub owner: Option<String>,
+    pub title: Option<String>,
+    pub travel_mode: String,
+    pub encoded_polyline: String,
+    pub distance_meters: Option<f64>,
+    pub duration: Option<String>,
+}
+
+/// Returns `None` when the slug is taken.
+pub async fn insert(
+    pool: &PgPool,
+    slug: &str,
+    route: &NewSharedRoute,
+) -> Result<Option<SharedRoute>, sqlx::Error> {
+    sqlx::query_as::<_, SharedRoute>(&format!(
+        "INSERT INTO shared_routes (slug, owner, title, travel_mode, encoded_polyline,
+             distance_meters, duration)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         ON CONFLICT (slug) DO NOTHING
+         RETURNING {}",
+        SHARED_ROUTE_COLUMNS
+    ))
+    .bind(slug)
+    .bind(&route.owner)
+    .bind(&route.title)
+    .bind(&route.travel_mode)
+    .bind(&route.encoded_polyline)
+    .bind(route.distance_meters)
+    .bind(&route.duration)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn get(pool: &PgPool, slug: &str) -> Result<Option<SharedRoute>, sqlx::Error> {
+    sqlx::query_as::<_, SharedRoute>(&format!(
+        "SELECT {} FROM shared_routes WHERE slug = $1",
+        SHARED_ROUTE_COLUMNS
+    ))
+    .bind(slug)
+    .fetch_optional(pool)
+    .await
+}
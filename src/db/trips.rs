@@ -0,0 +1,345 @@
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, FromRow, PgPool};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::geo::plus_code;
+
+const TRIP_COLUMNS: &str = "id, owner, name, origin_latitude, origin_longitude, \
+    destination_latitude, destination_longitude, waypoints, travel_mode, encoded_polyline, \
+    distance_meters, duration, recurrence_weekdays, recurrence_time, recurrence_time_zone, \
+    next_occurrence_at, route_departure_at, created_at, updated_at";
+
+/// A point, sent as `latitude` and `longitude`, as a full `plusCode`, or as the plus code
+/// alone.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Validate)]
+#[serde(try_from = "CoordinateInput")]
+pub struct Coordinate {
+    #[validate(range(min = -90.0, max = 90.0, message = "must be within [-90, 90]"))]
+    pub latitude: f64,
+    #[validate(range(min = -180.0, max = 180.0, message = "must be within [-180, 180]"))]
+    pub longitude: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CoordinateInput {
+    PlusCode(String),
+    #[serde(rename_all = "camelCase")]
+    Fields {
+        latitude: Option<f64>,
+        longitude: Option<f64>,
+        plus_code: Option<String>,
+    },
+}
+
+impl TryFrom<CoordinateInput> for Coordinate {
+    type Error = String;
+
+    fn try_from(input: CoordinateInput) -> Result<Self, Self::Error> {
+        match input {
+            CoordinateInput::PlusCode(code) => plus_code::resolve(None, None, Some(&code)),
+            CoordinateInput::Fields {
+                latitude,
+                longitude,
+                plus_code,
+            } => plus_code::resolve(latitude, longitude, plus_code.as_deref()),
+        }
+    }
+}
+
+#[derive(Debug, FromRow)]
+struct TripRow {
+    id: Uuid,
+    owner: String,
+    name: String,
+    origin_latitude: f64,
+    origin_longitude: f64,
+    destination_latitude: f64,
+    destination_longitude: f64,
+    waypoints: Json<Vec<Coordinate>>,
+    travel_mode: String,
+    encoded_polyline: String,
+    distance_meters: Option<f64>,
+    duration: Option<String>,
+    recurrence_weekdays: Option<Vec<i16>>,
+    recurrence_time: Option<NaiveTime>,
+    recurrence_time_zone: Option<String>,
+    next_occurrence_at: Option<DateTime<Utc>>,
+    route_departure_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, FromRow)]
+struct DueTripRow {
+    #[sqlx(flatten)]
+    trip: TripRow,
+    occurrence: DateTime<Utc>,
+}
+
+/// When a trip repeats, in the local time of `timeZone`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recurrence {
+    /// ISO weekdays, 1 is Monday
+    pub weekdays: Vec<i16>,
+    pub time: NaiveTime,
+    pub time_zone: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Trip {
+    pub id: Uuid,
+    pub owner: String,
+    pub name: String,
+    pub origin: Coordinate,
+    pub destination: Coordinate,
+    pub waypoints: Vec<Coordinate>,
+    pub travel_mode: String,
+    pub encoded_polyline: String,
+    pub distance_meters: Option<f64>,
+    pub duration: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<Recurrence>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_occurrence_at: Option<DateTime<Utc>>,
+    /// The departure the stored route was computed for ahead of an occurrence
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route_departure_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<TripRow> for Trip {
+    fn from(row: TripRow) -> Self {
+        Trip {
+            id: row.id,
+            owner: row.owner,
+            name: row.name,
+            origin: Coordinate {
+                latitude: row.origin_latitude,
+                longitude: row.origin_longitude,
+            },
+            destination: Coordinate {
+                latitude: row.destination_latitude,
+                longitude: row.destination_longitude,
+            },
+            waypoints: row.waypoints.0,
+            travel_mode: row.travel_mode,
+            encoded_polyline: row.encoded_polyline,
+            distance_meters: row.distance_meters,
+            duration: row.duration,
+            recurrence: match (
+                row.recurrence_weekdays,
+                row.recurrence_time,
+                row.recurrence_time_zone,
+            ) {
+                (Some(weekdays), Some(time), Some(time_zone)) => Some(Recurrence {
+                    weekdays,
+                    time,
+                    time_zone,
+                }),
+                _ => None,
+            },
+            next_occurrence_at: row.next_occurrence_at,
+            route_departure_at: row.route_departure_at,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+pub struct NewTrip {
+    pub owner: String,
+    pub name: String,
+    pub origin: Coordinate,
+    pub destination: Coordinate,
+    pub waypoints: Vec<Coordinate>,
+    pub travel_mode: String,
+    pub encoded_polyline: String,
+    pub distance_meters: Option<f64>,
+    pub duration: Option<String>,
+}
+
+pub async fn insert(pool: &PgPool, trip: NewTrip) -> Result<Trip, sqlx::Error> {
+    let row = sqlx::query_as::<_, TripRow>(&format!(
+        "INSERT INTO trips (id, owner, name, origin_latitude, origin_longitude,
+             destination_latitude, destination_longitude, waypoints, travel_mode,
+             encoded_polyline, distance_meters, duration)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+         RETURNING {}",
+        TRIP_COLUMNS
+    ))
+    .bind(Uuid::new_v4())
+    .bind(trip.owner)
+    .bind(trip.name)
+    .bind(trip.origin.latitude)
+    .bind(trip.origin.longitude)
+    .bind(trip.destination.latitude)
+    .bind(trip.destination.longitude)
+    .bind(Json(trip.waypoints))
+    .bind(trip.travel_mode)
+    .bind(trip.encoded_polyline)
+    .bind(trip.distance_meters)
+    .bind(trip.duration)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.into())
+}
+
+pub async fn list_by_owner(pool: &PgPool, owner: &str) -> Result<Vec<Trip>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, TripRow>(&format!(
+        "SELECT {} FROM trips WHERE owner = $1 ORDER BY created_at DESC",
+        TRIP_COLUMNS
+    ))
+    .bind(owner)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(Trip::from).collect())
+}
+
+pub async fn get(pool: &PgPool, id: Uuid, owner: &str) -> Result<Option<Trip>, sqlx::Error> {
+    let row = sqlx::query_as::<_, TripRow>(&format!(
+        "SELECT {} FROM trips WHERE id = $1 AND owner = $2",
+        TRIP_COLUMNS
+    ))
+    .bind(id)
+    .bind(owner)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(Trip::from))
+}
+
+/// Stores a freshly computed route for an existing trip, with the departure it was computed
+/// for when that isn't now.
+pub async fn update_route(
+    pool: &PgPool,
+    id: Uuid,
+    owner: &str,
+    encoded_polyline: &str,
+    distance_meters: Option<f64>,
+    duration: Option<&str>,
+    departure_at: Option<DateTime<Utc>>,
+) -> Result<Option<Trip>, sqlx::Error> {
+    let row = sqlx::query_as::<_, TripRow>(&format!(
+        "UPDATE trips
+         SET encoded_polyline = $3, distance_meters = $4, duration = $5,
+             route_departure_at = $6, updated_at = now()
+         WHERE id = $1 AND owner = $2
+         RETURNING {}",
+        TRIP_COLUMNS
+    ))
+    .bind(id)
+    .bind(owner)
+    .bind(encoded_polyline)
+    .bind(distance_meters)
+    .bind(duration)
+    .bind(departure_at)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(Trip::from))
+}
+
+/// Returns whether a trip owned by `owner` was deleted.
+pub async fn delete(pool: &PgPool, id: Uuid, owner: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM trips WHERE id = $1 AND owner = $2")
+        .bind(id)
+        .bind(owner)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Makes the trip repeat and schedules its next occurrence.
+pub async fn set_recurrence(
+    pool: &PgPool,
+    id: Uuid,
+    owner: &str,
+    weekdays: &[i16],
+    time: NaiveTime,
+    time_zone: &str,
+) -> Result<Option<Trip>, sqlx::Error> {
+    let row = sqlx::query_as::<_, TripRow>(&format!(
+        "UPDATE trips
+         SET recurrence_weekdays = $3, recurrence_time = $4, recurrence_time_zone = $5,
+             next_occurrence_at = next_occurrence($3, $4, $5, now()), updated_at = now()
+         WHERE id = $1 AND owner = $2
+         RETURNING {}",
+        TRIP_COLUMNS
+    ))
+    .bind(id)
+    .bind(owner)
+    .bind(weekdays)
+    .bind(time)
+    .bind(time_zone)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(Trip::from))
+}
+
+pub async fn clear_recurrence(
+    pool: &PgPool,
+    id: Uuid,
+    owner: &str,
+) -> Result<Option<Trip>, sqlx::Error> {
+    let row = sqlx::query_as::<_, TripRow>(&format!(
+        "UPDATE trips
+         SET recurrence_weekdays = NULL, recurrence_time = NULL, recurrence_time_zone = NULL,
+             next_occurrence_at = NULL, updated_at = now()
+         WHERE id = $1 AND owner = $2
+         RETURNING {}",
+        TRIP_COLUMNS
+    ))
+    .bind(id)
+    .bind(owner)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(Trip::from))
+}
+
+/// Claims up to `limit` recurring trips with an occurrence within `lead`, returning each
+/// with that occurrence and moving it on to the following one. Claimed rows are skipped by
+/// other instances.
+pub async fn claim_due(
+    pool: &PgPool,
+    lead: Duration,
+    limit: i64,
+) -> Result<Vec<(Trip, DateTime<Utc>)>, sqlx::Error> {
+    let rows = sqlx::query_as::<_, DueTripRow>(&format!(
+        "WITH due AS (
+             SELECT id AS due_id, next_occurrence_at AS occurrence FROM trips
+             WHERE next_occurrence_at <= now() + make_interval(secs => $1)
+             ORDER BY next_occurrence_at
+             LIMIT $2
+             FOR UPDATE SKIP LOCKED
+         )
+         UPDATE trips
+         SET next_occurrence_at = next_occurrence(recurrence_weekdays, recurrence_time,
+             recurrence_time_zone, greatest(due.occurrence, now()))
+         FROM due
+         WHERE trips.id = due.due_id
+         RETURNING {}, due.occurrence",
+        TRIP_COLUMNS
+    ))
+    .bind(lead.as_secs_f64())
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| (Trip::from(row.trip), row.occurrence))
+        .collect())
+}
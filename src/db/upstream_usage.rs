@@ -0,0 +1,28 @@
+use chrono::NaiveDate;
+use sqlx::PgPool;
+
+pub async fn increment(pool: &PgPool, day: NaiveDate, sku: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO upstream_usage (day, sku, calls) VALUES ($1, $2, 1)
+         ON CONFLICT (day, sku) DO UPDATE SET calls = upstream_usage.calls + 1",
+    )
+    .bind(day)
+    .bind(sku)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Calls per day and SKU since `from`, oldest first.
+pub async fn since(
+    pool: &PgPool,
+    from: NaiveDate,
+) -> Result<Vec<(NaiveDate, String, i64)>, sqlx::Error> {
+    sqlx::query_as::<_, (NaiveDate, String, i64)>(
+        "SELECT day, sku, calls FROM upstream_usage WHERE day >= $1 ORDER BY day, sku",
+    )
+    .bind(from)
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,47 @@
+use sqlx::PgPool;
+
+/// Counts one request against each period and returns the new totals.
+pub async fn increment(
+    pool: &PgPool,
+    identity: &str,
+    periods: &[String],
+) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    sqlx::query_as::<_, (String, i64)>(
+        "INSERT INTO client_usage (identity, period, count)
+         SELECT $1, period, 1 FROM unnest($2::text[]) AS period
+         ON CONFLICT (identity, period) DO UPDATE SET count = client_usage.count + 1
+         RETURNING period, count",
+    )
+    .bind(identity)
+    .bind(periods)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn counts(
+    pool: &PgPool,
+    identity: &str,
+    periods: &[String],
+) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    sqlx::query_as::<_, (String, i64)>(
+        "SELECT period, count FROM client_usage WHERE identity = $1 AND period = ANY($2)",
+    )
+    .bind(identity)
+    .bind(periods)
+    .fetch_all(pool)
+    .await
+}
+
+/// Counts of every client in the periods, for the admin overview.
+pub async fn all_counts(
+    pool: &PgPool,
+    periods: &[String],
+) -> Result<Vec<(String, String, i64)>, sqlx::Error> {
+    sqlx::query_as::<_, (String, String, i64)>(
+        "SELECT identity, period, count FROM client_usage WHERE period = ANY($1)
+         ORDER BY identity",
+    )
+    .bind(periods)
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,106 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+use uuid::Uuid;
+
+const USER_COLUMNS: &str = "id, email, display_name, created_at, updated_at";
+
+/// A user's profile, the password hash is only read when logging in.
+#[derive(Debug, FromRow, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    pub id: Uuid,
+    pub email: String,
+    pub display_name: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, FromRow)]
+pub struct Credentials {
+    pub id: Uuid,
+    pub password_hash: String,
+}
+
+/// Returns `None` when the email is already registered, in any casing.
+pub async fn insert(
+    pool: &PgPool,
+    email: &str,
+    password_hash: &str,
+    display_name: Option<&str>,
+) -> Result<Option<User>, sqlx::Error> {
+    sqlx::query_as::<_, User>(&format!(
+        "INSERT INTO users (id, email, password_hash, display_name) VALUES ($1, $2, $3, $4)
+         ON CONFLICT (lower(email)) DO NOTHING
+         RETURNING {}",
+        USER_COLUMNS
+    ))
+    .bind(Uuid::new_v4())
+    .bind(email)
+    .bind(password_hash)
+    .bind(display_name)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn credentials(pool: &PgPool, email: &str) -> Result<Option<Credentials>, sqlx::Error> {
+    sqlx::query_as::<_, Credentials>(
+        "SELECT id, password_hash FROM users WHERE lower(email) = lower($1)",
+    )
+    .bind(email)
+    .fetch_optional(pool)
+    .await
+}
+
+pub async fn get(pool: &PgPool, id: Uuid) -> Result<Option<User>, sqlx::Error> {
+    sqlx::query_as::<_, User>(&format!("SELECT {} FROM users WHERE id = $1", USER_COLUMNS))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn update_profile(
+    pool: &PgPool,
+    id: Uuid,
+    display_name: Option<&str>,
+) -> Result<Option<User>, sqlx::Error> {
+    sqlx::query_as::<_, User>(&format!(
+        "UPDATE users SET display_name = $2, updated_at = now() WHERE id = $1 RETURNING {}",
+        USER_COLUMNS
+    ))
+    .bind(id)
+    .bind(display_name)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Deletes the user along with everything `owner`, their identity, owns. Returns whether
+/// the user existed.
+pub async fn delete(pool: &PgPool, id: Uuid, owner: &str) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    // List items go with their lists and places
+    for table in [
+        "place_lists",
+        "commutes",
+        "saved_places",
+        "trips",
+        "search_history",
+        "geofences",
+    ] {
+        sqlx::query(&format!("DELETE FROM {} WHERE owner = $1", table))
+            .bind(owner)
+            .execute(&mut *tx)
+            .await?;
+    }
+    sqlx::query("DELETE FROM place_list_members WHERE member = $1")
+        .bind(owner)
+        .execute(&mut *tx)
+        .await?;
+    let result = sqlx::query("DELETE FROM users WHERE id = $1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(result.rows_affected() > 0)
+}
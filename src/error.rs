@@ -0,0 +1,276 @@
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::{collections::BTreeMap, fmt, time::Duration};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::{ValidationError, ValidationErrors, ValidationErrorsKind};
+
+use crate::{
+    api::version::{camel_case, ApiVersion},
+    middleware::{current_api_version, current_request_id},
+};
+
+const GENERIC_MESSAGE: &str = "Something went wrong. Try again later";
+
+#[derive(Clone, Debug)]
+pub enum AppError {
+    UpstreamError(String),
+    ParseError(String),
+    Validation(String),
+    InvalidFields(Vec<FieldError>),
+    Timeout,
+    RateLimited { retry_after: Duration },
+    Overloaded { retry_after: Duration },
+    Unavailable,
+    NotFound(String),
+    Unauthorized,
+    Forbidden,
+    PreconditionFailed(String),
+    Database(String),
+}
+
+/// A request field that failed validation, `field` is the path as the client sent it.
+#[derive(Clone, Debug, Serialize, ToSchema)]
+pub struct FieldError {
+    field: String,
+    /// The violated constraint, e.g. `length`, `range` or `rfc3339`
+    code: String,
+    message: String,
+    /// Bounds of the constraint, e.g. `{"min": 1, "max": 256}`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    #[schema(value_type = Object)]
+    params: BTreeMap<String, Value>,
+}
+
+impl FieldError {
+    fn new(field: String, error: &ValidationError) -> Self {
+        // The rejected value is left out, it may be large or sensitive
+        let params: BTreeMap<String, Value> = error
+            .params
+            .iter()
+            .filter(|(key, _)| *key != "value")
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect();
+        let message = match &error.message {
+            Some(message) => message.to_string(),
+            None => describe(&error.code, &params),
+        };
+
+        FieldError {
+            field,
+            code: error.code.to_string(),
+            message,
+            params,
+        }
+    }
+}
+
+// For constraints declared without a message
+fn describe(code: &str, params: &BTreeMap<String, Value>) -> String {
+    let bounds = match (params.get("min"), params.get("max")) {
+        (Some(min), Some(max)) => format!(" within [{}, {}]", min, max),
+        (Some(min), None) => format!(" at least {}", min),
+        (None, Some(max)) => format!(" at most {}", max),
+        (None, None) => String::new(),
+    };
+    match code {
+        "length" => format!("length must be{}", bounds),
+        "range" => format!("must be{}", bounds),
+        "required" => "is required".into(),
+        "email" => "must be an email address".into(),
+        "url" => "must be a URL".into(),
+        code => format!("failed the {} check", code),
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorBody {
+    code: &'static str,
+    message: String,
+    request_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<Vec<FieldError>>,
+}
+
+/// Body of every error response.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    error: ErrorBody,
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::UpstreamError(_) => StatusCode::BAD_GATEWAY,
+            AppError::ParseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Validation(_) | AppError::InvalidFields(_) => StatusCode::BAD_REQUEST,
+            AppError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            AppError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Overloaded { .. } | AppError::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden => StatusCode::FORBIDDEN,
+            AppError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            AppError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::UpstreamError(_) => "UPSTREAM_ERROR",
+            AppError::ParseError(_) => "PARSE_ERROR",
+            AppError::Validation(_) | AppError::InvalidFields(_) => "VALIDATION_ERROR",
+            AppError::Timeout => "TIMEOUT",
+            AppError::RateLimited { .. } => "RATE_LIMITED",
+            AppError::Overloaded { .. } => "OVERLOADED",
+            AppError::Unavailable => "UPSTREAM_UNAVAILABLE",
+            AppError::NotFound(_) => "NOT_FOUND",
+            AppError::Unauthorized => "UNAUTHORIZED",
+            AppError::Forbidden => "FORBIDDEN",
+            AppError::PreconditionFailed(_) => "PRECONDITION_FAILED",
+            AppError::Database(_) => "DATABASE_ERROR",
+        }
+    }
+
+    // Upstream, parse and database details stay in the logs, clients only get a generic message
+    pub fn message(&self) -> String {
+        match self {
+            AppError::UpstreamError(_) | AppError::ParseError(_) | AppError::Database(_) => {
+                GENERIC_MESSAGE.into()
+            }
+            AppError::Validation(m) => m.clone(),
+            AppError::InvalidFields(fields) => match fields.as_slice() {
+                [only] => format!("{} {}", only.field, only.message),
+                fields => format!("{} fields are invalid", fields.len()),
+            },
+            AppError::Timeout => "Upstream provider took too long to respond".into(),
+            AppError::RateLimited { .. } => "Too many requests".into(),
+            AppError::Overloaded { .. } => "The service is overloaded. Try again shortly".into(),
+            AppError::Unavailable => "Upstream provider is unavailable. Try again later".into(),
+            AppError::NotFound(m) => m.clone(),
+            AppError::Unauthorized => "Missing or invalid credentials".into(),
+            AppError::Forbidden => "Access denied".into(),
+            AppError::PreconditionFailed(m) => m.clone(),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::UpstreamError(detail)
+            | AppError::ParseError(detail)
+            | AppError::Database(detail) => {
+                write!(f, "{}: {}", self.code(), detail)
+            }
+            _ => write!(f, "{}: {}", self.code(), self.message()),
+        }
+    }
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            AppError::Timeout
+        } else if e.is_decode() {
+            AppError::ParseError(e.to_string())
+        } else {
+            AppError::UpstreamError(e.to_string())
+        }
+    }
+}
+
+impl From<ValidationErrors> for AppError {
+    fn from(errors: ValidationErrors) -> Self {
+        let mut fields = Vec::new();
+        collect_field_errors(&errors, current_api_version(), "", &mut fields);
+        fields.sort_by(|a, b| a.field.cmp(&b.field));
+
+        AppError::InvalidFields(fields)
+    }
+}
+
+// Nested structs and lists become dotted and indexed paths, e.g. `waypoints[2].latitude`.
+// The derive names fields as declared, v2 renames them to match the wire while v1 keeps
+// the names its clients have always been given
+fn collect_field_errors(
+    errors: &ValidationErrors,
+    version: ApiVersion,
+    prefix: &str,
+    out: &mut Vec<FieldError>,
+) {
+    for (field, kind) in errors.errors() {
+        let field = match version {
+            ApiVersion::V1 => field.to_string(),
+            ApiVersion::V2 => camel_case(field),
+        };
+        let path = if prefix.is_empty() {
+            field
+        } else {
+            format!("{}.{}", prefix, field)
+        };
+
+        match kind {
+            ValidationErrorsKind::Field(errors) => {
+                out.extend(errors.iter().map(|e| FieldError::new(path.clone(), e)));
+            }
+            ValidationErrorsKind::Struct(nested) => {
+                collect_field_errors(nested, version, &path, out)
+            }
+            ValidationErrorsKind::List(items) => {
+                for (index, nested) in items {
+                    let path = format!("{}[{}]", path, index);
+                    collect_field_errors(nested, version, &path, out);
+                }
+            }
+        }
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        tracing::error!("database error: {}", e);
+        AppError::Database(e.to_string())
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let code = self.code();
+        let message = self.message();
+        let status = self.status();
+        let (details, retry_after) = match self {
+            AppError::InvalidFields(fields) => (Some(fields), None),
+            AppError::RateLimited { retry_after } | AppError::Overloaded { retry_after } => {
+                (None, Some(retry_after))
+            }
+            _ => (None, None),
+        };
+        let body = ErrorResponse {
+            error: ErrorBody {
+                code,
+                message,
+                // Outside a request, e.g. in a rejection from a background task
+                request_id: current_request_id().unwrap_or_else(|| Uuid::new_v4().to_string()),
+                details,
+            },
+        };
+
+        let mut response = (status, Json(body)).into_response();
+        if let Some(retry_after) = retry_after {
+            // Retry-After is whole seconds, round up so clients don't retry too early
+            let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, secs.max(1).into());
+        }
+
+        response
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use tokio::sync::Mutex;
+
+use crate::error::AppError;
+
+const MESSAGING_SCOPE: &str = "https://www.googleapis.com/auth/firebase.messaging";
+const SEND_URL: &str = "https://fcm.googleapis.com/v1/projects";
+const JWT_BEARER_GRANT: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
+// Google's longest assertion lifetime
+const ASSERTION_TTL_SECS: i64 = 3600;
+// Tokens are replaced this long before they expire, so none runs out mid-send
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+// The fields of a service account key file that are used
+#[derive(Deserialize)]
+struct ServiceAccount {
+    project_id: String,
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+struct AccessToken {
+    value: String,
+    expires_at: Instant,
+}
+
+/// Firebase Cloud Messaging HTTP v1, authenticated as the service account of
+/// `FCM_SERVICE_ACCOUNT_PATH`.
+pub struct Fcm {
+    send_url: String,
+    client_email: String,
+    token_uri: String,
+    signing_key: EncodingKey,
+    token: Mutex<Option<AccessToken>>,
+}
+
+impl Fcm {
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let file = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let account: ServiceAccount = serde_json::from_str(&file).map_err(|e| e.to_string())?;
+        let signing_key =
+            EncodingKey::from_rsa_pem(account.private_key.as_bytes()).map_err(|e| e.to_string())?;
+
+        Ok(Fcm {
+            send_url: format!("{}/{}/messages:send", SEND_URL, account.project_id),
+            client_email: account.client_email,
+            token_uri: account.token_uri,
+            signing_key,
+            token: Mutex::new(None),
+        })
+    }
+
+    // Held while refreshing, so concurrent sends wait for one new token
+    async fn access_token(&self, client: &Client) -> Result<String, AppError> {
+        let mut token = self.token.lock().await;
+        if let Some(token) = token.as_ref() {
+            if token.expires_at > Instant::now() + TOKEN_REFRESH_MARGIN {
+                return Ok(token.value.clone());
+            }
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let assertion = encode(
+            &Header::new(Algorithm::RS256),
+            &json!({
+                "iss": self.client_email,
+                "scope": MESSAGING_SCOPE,
+                "aud": self.token_uri,
+                "iat": now,
+                "exp": now + ASSERTION_TTL_SECS,
+            }),
+            &self.signing_key,
+        )
+        .map_err(|e| AppError::ParseError(e.to_string()))?;
+        let response = client
+            .post(&self.token_uri)
+            .form(&[
+                ("grant_type", JWT_BEARER_GRANT),
+                ("assertion", assertion.as_str()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<TokenResponse>()
+            .await?;
+
+        let value = response.access_token.clone();
+        *token = Some(AccessToken {
+            value: response.access_token,
+            expires_at: Instant::now() + Duration::from_secs(response.expires_in),
+        });
+
+        Ok(value)
+    }
+
+    /// Sends a notification to the device with `token`. FCM only takes strings as data,
+    /// other values of `data` are sent as their JSON.
+    pub async fn send(
+        &self,
+        client: &Client,
+        token: &str,
+        title: &str,
+        body: &str,
+        data: Value,
+    ) -> Result<(), AppError> {
+        let data: Map<String, Value> = data
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(key, value)| {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                (key.clone(), Value::String(value))
+            })
+            .collect();
+        let access_token = self.access_token(client).await?;
+
+        client
+            .post(&self.send_url)
+            .bearer_auth(access_token)
+            .json(&json!({
+                "message": {
+                    "token": token,
+                    "notification": { "title": title, "body": body },
+                    "data": data,
+                },
+            }))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Duration,
+};
+
+use redis::aio::ConnectionManager;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{config::Config, error::AppError, identity::Identity};
+
+// Hash of `flag` or `flag@identity` fields, next to the cache entries
+const REDIS_KEY: &str = "multi-map:flags";
+
+/// Features that can be turned on and off while running, for everyone or per caller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Flag {
+    /// Race `PLACES_RACE_URL` against Google on place searches
+    ProviderFanOut,
+    /// Serve the last good copy when the provider fails
+    StaleIfError,
+}
+
+impl Flag {
+    pub const ALL: [Flag; 2] = [Flag::ProviderFanOut, Flag::StaleIfError];
+
+    fn name(self) -> &'static str {
+        match self {
+            Flag::ProviderFanOut => "provider_fan_out",
+            Flag::StaleIfError => "stale_if_error",
+        }
+    }
+}
+
+impl FromStr for Flag {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Flag::ALL
+            .into_iter()
+            .find(|flag| flag.name() == s)
+            .ok_or(())
+    }
+}
+
+/// Who a flag is on for: everyone, no one, or a stable share of callers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rollout {
+    On,
+    Off,
+    Percent(u8),
+}
+
+impl FromStr for Rollout {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "on" => Ok(Rollout::On),
+            "off" => Ok(Rollout::Off),
+            _ => s
+                .strip_suffix('%')
+                .and_then(|percent| percent.parse().ok())
+                .filter(|percent| *percent <= 100)
+                .map(Rollout::Percent)
+                .ok_or(()),
+        }
+    }
+}
+
+impl fmt::Display for Rollout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rollout::On => f.write_str("on"),
+            Rollout::Off => f.write_str("off"),
+            Rollout::Percent(percent) => write!(f, "{}%", percent),
+        }
+    }
+}
+
+impl Rollout {
+    // A caller always lands in the same bucket of a flag, so raising the share only adds callers
+    fn includes(self, flag: Flag, identity: Option<&Identity>) -> bool {
+        match self {
+            Rollout::On => true,
+            Rollout::Off => false,
+            Rollout::Percent(percent) => {
+                let caller = identity.map_or("", |identity| identity.0.as_str());
+                let digest = Sha256::digest(format!("{}:{}", flag.name(), caller).as_bytes());
+                u16::from_be_bytes([digest[0], digest[1]]) % 100 < u16::from(percent)
+            }
+        }
+    }
+}
+
+/// A flag for everyone, or for one caller when the identity is set.
+pub type RuleKey = (Flag, Option<String>);
+pub type Rules = HashMap<RuleKey, Rollout>;
+
+/// Parses `flag=rollout` or `flag@identity=rollout`, as in `FEATURE_FLAGS` and in Redis.
+pub fn parse_rule(name: &str, rollout: &str) -> Option<(RuleKey, Rollout)> {
+    let (flag, identity) = match name.trim().split_once('@') {
+        Some((flag, identity)) if !identity.is_empty() => (flag, Some(identity.to_owned())),
+        Some(_) => return None,
+        None => (name.trim(), None),
+    };
+
+    Some(((flag.parse().ok()?, identity), rollout.trim().parse().ok()?))
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleView {
+    flag: Flag,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    identity: Option<String>,
+    rollout: String,
+    source: &'static str,
+}
+
+/// Flag rules from `FEATURE_FLAGS`, overridden by those set through `/admin/flags` or in
+/// Redis. With `REDIS_URL` set the overrides live in Redis and every instance sees them,
+/// otherwise they only last as long as the process. A rule for the caller wins over the
+/// rule for everyone, and flags without any rule are on.
+pub struct Flags {
+    config: RwLock<Rules>,
+    overrides: RwLock<Rules>,
+    conn: Option<ConnectionManager>,
+}
+
+impl Flags {
+    pub async fn from_config(config: &Config) -> Self {
+        let conn = match &config.redis_url {
+            Some(url) => match redis::Client::open(url.as_str()) {
+                Ok(client) => ConnectionManager::new(client)
+                    .await
+                    .inspect_err(|e| tracing::warn!("redis unavailable for feature flags: {}", e))
+                    .ok(),
+                Err(e) => {
+                    tracing::warn!("redis unavailable for feature flags: {}", e);
+                    None
+                }
+            },
+            None => None,
+        };
+        let flags = Flags {
+            config: RwLock::new(config.feature_flags.clone()),
+            overrides: RwLock::new(Rules::new()),
+            conn,
+        };
+        flags.refresh().await;
+
+        flags
+    }
+
+    /// Whether `flag` is on for the caller, anonymous callers only see rules for everyone.
+    pub fn enabled(&self, flag: Flag, identity: Option<&Identity>) -> bool {
+        let caller = identity.map(|identity| (flag, Some(identity.0.clone())));
+        let everyone = (flag, None);
+        let overrides = self.overrides.read().unwrap();
+        let config = self.config.read().unwrap();
+
+        let rollout = caller
+            .iter()
+            .chain([&everyone])
+            .find_map(|key| overrides.get(key).or_else(|| config.get(key)));
+        rollout.is_none_or(|rollout| rollout.includes(flag, identity))
+    }
+
+    /// Replaces the rules from the configuration, on reload.
+    pub fn set_config_rules(&self, rules: Rules) {
+        *self.config.write().unwrap() = rules;
+    }
+
+    /// Sets the override of a rule, or removes it with `None`.
+    pub async fn set_override(
+        &self,
+        key: RuleKey,
+        rollout: Option<Rollout>,
+    ) -> Result<(), AppError> {
+        if let Some(mut conn) = self.conn.clone() {
+            let field = match &key.1 {
+                Some(identity) => format!("{}@{}", key.0.name(), identity),
+                None => key.0.name().to_owned(),
+            };
+            let mut command = redis::cmd(if rollout.is_some() { "HSET" } else { "HDEL" });
+            command.arg(REDIS_KEY).arg(field);
+            if let Some(rollout) = rollout {
+                command.arg(rollout.to_string());
+            }
+            command
+                .query_async::<_, ()>(&mut conn)
+                .await
+                .map_err(|e| AppError::UpstreamError(format!("redis: {}", e)))?;
+        }
+
+        let mut overrides = self.overrides.write().unwrap();
+        match rollout {
+            Some(rollout) => overrides.insert(key, rollout),
+            None => overrides.remove(&key),
+        };
+
+        Ok(())
+    }
+
+    /// Every rule in place, overrides first.
+    pub fn rules(&self) -> Vec<RuleView> {
+        let overrides = self.overrides.read().unwrap();
+        let config = self.config.read().unwrap();
+        let mut views: Vec<RuleView> = [(&*overrides, "override"), (&*config, "config")]
+            .into_iter()
+            .flat_map(|(rules, source)| {
+                rules
+                    .iter()
+                    .map(move |((flag, identity), rollout)| RuleView {
+                        flag: *flag,
+                        identity: identity.clone(),
+                        rollout: rollout.to_string(),
+                        source,
+                    })
+            })
+            .collect();
+        views.sort_by_key(|view| {
+            (
+                view.flag.name(),
+                view.identity.clone(),
+                view.source != "override",
+            )
+        });
+
+        views
+    }
+
+    // A field that doesn't parse is skipped, the rest still apply
+    async fn refresh(&self) {
+        let Some(mut conn) = self.conn.clone() else {
+            return;
+        };
+        let fields = match redis::cmd("HGETALL")
+            .arg(REDIS_KEY)
+            .query_async::<_, HashMap<String, String>>(&mut conn)
+            .await
+        {
+            Ok(fields) => fields,
+            Err(e) => {
+                tracing::warn!("failed to load feature flags from redis: {}", e);
+                return;
+            }
+        };
+        let rules = fields
+            .iter()
+            .filter_map(|(name, rollout)| {
+                let rule = parse_rule(name, rollout);
+                if rule.is_none() {
+                    tracing::warn!(field = %name, value = %rollout, "invalid feature flag in redis");
+                }
+                rule
+            })
+            .collect();
+        *self.overrides.write().unwrap() = rules;
+    }
+
+    /// Loads the Redis rules again every `every`, so changes there apply within that time.
+    pub fn spawn_refresh(self: &Arc<Self>, every: Duration) {
+        if self.conn.is_none() {
+            return;
+        }
+        let flags = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            // Loaded at startup already
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match flags.upgrade() {
+                    Some(flags) => flags.refresh().await,
+                    None => break,
+                }
+            }
+        });
+    }
+}
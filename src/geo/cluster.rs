@@ -0,0 +1,55 @@
+use std::collections::BTreeMap;
+
+use crate::db::trips::Coordinate;
+
+use super::mercator;
+
+const TILE_PIXELS: f64 = 256.0;
+
+/// Points drawn as one marker.
+#[derive(Clone, Debug)]
+pub struct Cluster {
+    pub centroid: Coordinate,
+    /// Indexes into the clustered points
+    pub members: Vec<usize>,
+}
+
+// Pixel position on the Web Mercator map at `zoom`
+fn to_pixels(point: Coordinate, zoom: u8) -> (f64, f64) {
+    let size = TILE_PIXELS * f64::from(1u32 << zoom);
+    let (x, y) = mercator(point);
+    (x * size, y * size)
+}
+
+/// Groups points falling in the same `cell_pixels` wide square of the map at `zoom`.
+/// Cheaper than distance based clustering and stable while panning, as the grid doesn't
+/// depend on the viewport.
+pub fn grid(points: &[Coordinate], zoom: u8, cell_pixels: f64) -> Vec<Cluster> {
+    let mut cells: BTreeMap<(i64, i64), Vec<usize>> = BTreeMap::new();
+    for (index, point) in points.iter().enumerate() {
+        let (x, y) = to_pixels(*point, zoom);
+        let cell = (
+            (x / cell_pixels).floor() as i64,
+            (y / cell_pixels).floor() as i64,
+        );
+        cells.entry(cell).or_default().push(index);
+    }
+
+    cells
+        .into_values()
+        .map(|members| {
+            let count = members.len() as f64;
+            let (latitude, longitude) = members.iter().fold((0.0, 0.0), |(lat, lng), &i| {
+                (lat + points[i].latitude, lng + points[i].longitude)
+            });
+
+            Cluster {
+                centroid: Coordinate {
+                    latitude: latitude / count,
+                    longitude: longitude / count,
+                },
+                members,
+            }
+        })
+        .collect()
+}
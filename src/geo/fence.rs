@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+use crate::db::trips::Coordinate;
+
+use super::{destination, haversine};
+
+// Enough for a circle to look round on a map
+const CIRCLE_VERTICES: usize = 64;
+
+/// Area of a geofence.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(
+    tag = "type",
+    rename_all = "lowercase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Shape {
+    Circle {
+        center: Coordinate,
+        radius_meters: f64,
+    },
+    /// The last vertex joins back to the first
+    Polygon { vertices: Vec<Coordinate> },
+}
+
+impl Shape {
+    pub fn contains(&self, point: Coordinate) -> bool {
+        match self {
+            Shape::Circle {
+                center,
+                radius_meters,
+            } => haversine(*center, point) <= *radius_meters,
+            Shape::Polygon { vertices } => in_polygon(vertices, point),
+        }
+    }
+
+    /// Vertices of the shape, circles approximated by a polygon.
+    pub fn outline(&self) -> Vec<Coordinate> {
+        match self {
+            Shape::Circle {
+                center,
+                radius_meters,
+            } => (0..CIRCLE_VERTICES)
+                .map(|i| {
+                    let bearing = i as f64 * 360.0 / CIRCLE_VERTICES as f64;
+                    destination(*center, bearing, *radius_meters)
+                })
+                .collect(),
+            Shape::Polygon { vertices } => vertices.clone(),
+        }
+    }
+}
+
+// Even-odd rule with a ray going east, latitude and longitude taken as flat coordinates.
+// Close enough for fences the size of a city, edges crossing the antimeridian aren't handled
+fn in_polygon(vertices: &[Coordinate], point: Coordinate) -> bool {
+    let Some(count) = vertices.len().checked_sub(1) else {
+        return false;
+    };
+
+    let mut inside = false;
+    let previous = vertices.iter().cycle().skip(count);
+    for (a, b) in vertices.iter().zip(previous) {
+        if (a.latitude > point.latitude) != (b.latitude > point.latitude) {
+            let crossing = a.longitude
+                + (point.latitude - a.latitude) / (b.latitude - a.latitude)
+                    * (b.longitude - a.longitude);
+            if point.longitude < crossing {
+                inside = !inside;
+            }
+        }
+    }
+
+    inside
+}
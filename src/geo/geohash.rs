@@ -0,0 +1,43 @@
+use crate::db::trips::Coordinate;
+
+const BASE32: &[u8; 32] = b"0123456789bcdefghjkmnpqrstuvwxyz";
+// 12 characters already pin a point down to a few centimeters
+pub const MAX_PRECISION: usize = 12;
+
+/// Geohash of `precision` characters. Points sharing a prefix are close to each other,
+/// so truncated hashes make cheap buckets.
+pub fn encode(point: Coordinate, precision: usize) -> String {
+    let mut latitude = (-90.0, 90.0);
+    let mut longitude = (-180.0, 180.0);
+    let mut hash = String::with_capacity(precision);
+    let mut bits = 0;
+    let mut chunk = 0usize;
+    // Bits alternate between longitude and latitude, longitude first
+    let mut even = true;
+
+    while hash.len() < precision.min(MAX_PRECISION) {
+        let (range, value) = if even {
+            (&mut longitude, point.longitude)
+        } else {
+            (&mut latitude, point.latitude)
+        };
+        let mid = (range.0 + range.1) / 2.0;
+        chunk <<= 1;
+        if value >= mid {
+            chunk |= 1;
+            range.0 = mid;
+        } else {
+            range.1 = mid;
+        }
+        even = !even;
+
+        bits += 1;
+        if bits == 5 {
+            hash.push(BASE32[chunk] as char);
+            bits = 0;
+            chunk = 0;
+        }
+    }
+
+    hash
+}
@@ -0,0 +1,211 @@
+pub mod cluster;
+pub mod fence;
+pub mod geohash;
+pub mod mvt;
+pub mod plus_code;
+pub mod polyline;
+
+use std::f64::consts::PI;
+
+use crate::db::trips::Coordinate;
+
+// Mean Earth radius, good to about 0.5% anywhere on the globe
+const EARTH_RADIUS_METERS: f64 = 6_371_008.8;
+// WGS 84 ellipsoid, for Vincenty's formulae
+const WGS84_SEMI_MAJOR_AXIS: f64 = 6_378_137.0;
+const WGS84_FLATTENING: f64 = 1.0 / 298.257_223_563;
+const VINCENTY_MAX_ITERATIONS: usize = 200;
+// Web Mercator stops short of the poles
+const MAX_MERCATOR_LATITUDE: f64 = 85.051_128_78;
+
+/// Great circle distance in meters.
+pub fn haversine(a: Coordinate, b: Coordinate) -> f64 {
+    let (lat1, lat2) = (a.latitude.to_radians(), b.latitude.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lng = (b.longitude - a.longitude).to_radians();
+
+    let h = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lng / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_METERS * h.sqrt().asin()
+}
+
+/// Distance in meters on the WGS 84 ellipsoid, accurate to a millimeter. `None` for
+/// nearly antipodal points, where the iteration doesn't converge.
+pub fn vincenty(a: Coordinate, b: Coordinate) -> Option<f64> {
+    let f = WGS84_FLATTENING;
+    let major = WGS84_SEMI_MAJOR_AXIS;
+    let minor = (1.0 - f) * major;
+
+    let l = (b.longitude - a.longitude).to_radians();
+    let u1 = ((1.0 - f) * a.latitude.to_radians().tan()).atan();
+    let u2 = ((1.0 - f) * b.latitude.to_radians().tan()).atan();
+    let (sin_u1, cos_u1) = u1.sin_cos();
+    let (sin_u2, cos_u2) = u2.sin_cos();
+
+    let mut lambda = l;
+    for _ in 0..VINCENTY_MAX_ITERATIONS {
+        let (sin_lambda, cos_lambda) = lambda.sin_cos();
+        let sin_sigma = ((cos_u2 * sin_lambda).powi(2)
+            + (cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda).powi(2))
+        .sqrt();
+        if sin_sigma == 0.0 {
+            return Some(0.0);
+        }
+        let cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_lambda;
+        let sigma = sin_sigma.atan2(cos_sigma);
+        let sin_alpha = cos_u1 * cos_u2 * sin_lambda / sin_sigma;
+        let cos_sq_alpha = 1.0 - sin_alpha * sin_alpha;
+        // Zero along the equator
+        let cos_2sigma_m = if cos_sq_alpha == 0.0 {
+            0.0
+        } else {
+            cos_sigma - 2.0 * sin_u1 * sin_u2 / cos_sq_alpha
+        };
+        let c = f / 16.0 * cos_sq_alpha * (4.0 + f * (4.0 - 3.0 * cos_sq_alpha));
+
+        let previous = lambda;
+        lambda = l
+            + (1.0 - c)
+                * f
+                * sin_alpha
+                * (sigma
+                    + c * sin_sigma
+                        * (cos_2sigma_m + c * cos_sigma * (-1.0 + 2.0 * cos_2sigma_m.powi(2))));
+
+        if (lambda - previous).abs() < 1e-12 {
+            let u_sq = cos_sq_alpha * (major.powi(2) - minor.powi(2)) / minor.powi(2);
+            let big_a =
+                1.0 + u_sq / 16384.0 * (4096.0 + u_sq * (-768.0 + u_sq * (320.0 - 175.0 * u_sq)));
+            let big_b = u_sq / 1024.0 * (256.0 + u_sq * (-128.0 + u_sq * (74.0 - 47.0 * u_sq)));
+            let delta_sigma = big_b
+                * sin_sigma
+                * (cos_2sigma_m
+                    + big_b / 4.0
+                        * (cos_sigma * (-1.0 + 2.0 * cos_2sigma_m.powi(2))
+                            - big_b / 6.0
+                                * cos_2sigma_m
+                                * (-3.0 + 4.0 * sin_sigma.powi(2))
+                                * (-3.0 + 4.0 * cos_2sigma_m.powi(2))));
+
+            return Some(minor * big_a * (sigma - delta_sigma));
+        }
+    }
+
+    None
+}
+
+/// Point reached after `distance` meters on a great circle setting off at `bearing`
+/// degrees clockwise from north.
+pub fn destination(start: Coordinate, bearing: f64, distance: f64) -> Coordinate {
+    let angle = distance / EARTH_RADIUS_METERS;
+    let bearing = bearing.to_radians();
+    let lat1 = start.latitude.to_radians();
+
+    let lat2 = (lat1.sin() * angle.cos() + lat1.cos() * angle.sin() * bearing.cos()).asin();
+    let d_lng =
+        (bearing.sin() * angle.sin() * lat1.cos()).atan2(angle.cos() - lat1.sin() * lat2.sin());
+
+    Coordinate {
+        latitude: lat2.to_degrees(),
+        // Back into [-180, 180)
+        longitude: (start.longitude + d_lng.to_degrees() + 540.0).rem_euclid(360.0) - 180.0,
+    }
+}
+
+/// Compass bearing in degrees, [0, 360), to set off from `a` towards `b` on a great circle.
+pub fn initial_bearing(a: Coordinate, b: Coordinate) -> f64 {
+    let (lat1, lat2) = (a.latitude.to_radians(), b.latitude.to_radians());
+    let d_lng = (b.longitude - a.longitude).to_radians();
+
+    let y = d_lng.sin() * lat2.cos();
+    let x = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lng.cos();
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+/// South-west and north-east corners of the smallest box holding every point. Paths
+/// crossing the antimeridian get the box going the long way round.
+pub fn bounding_box(path: &[Coordinate]) -> Option<(Coordinate, Coordinate)> {
+    let first = *path.first()?;
+    Some(path.iter().fold((first, first), |(low, high), c| {
+        (
+            Coordinate {
+                latitude: low.latitude.min(c.latitude),
+                longitude: low.longitude.min(c.longitude),
+            },
+            Coordinate {
+                latitude: high.latitude.max(c.latitude),
+                longitude: high.longitude.max(c.longitude),
+            },
+        )
+    }))
+}
+
+/// Web Mercator position scaled to [0, 1] on both axes, `y` growing southwards.
+pub fn mercator(point: Coordinate) -> (f64, f64) {
+    let latitude = point
+        .latitude
+        .clamp(-MAX_MERCATOR_LATITUDE, MAX_MERCATOR_LATITUDE)
+        .to_radians();
+
+    let x = (point.longitude + 180.0) / 360.0;
+    let y = (1.0 - (latitude.tan() + 1.0 / latitude.cos()).ln() / PI) / 2.0;
+    (x, y)
+}
+
+pub fn path_length(path: &[Coordinate]) -> f64 {
+    path.windows(2).map(|w| haversine(w[0], w[1])).sum()
+}
+
+/// Where a point sits relative to a path.
+#[derive(Clone, Copy, Debug)]
+pub struct Projection {
+    /// Distance from the point to the closest point of the path
+    pub off_path_meters: f64,
+    /// Distance along the path up to that closest point
+    pub along_path_meters: f64,
+}
+
+/// Projects `point` onto the closest segment of `path`. Segments are treated as straight
+/// lines on a local flat projection, which is accurate for the short segments of a route.
+pub fn project(path: &[Coordinate], point: Coordinate) -> Option<Projection> {
+    if let [only] = path {
+        return Some(Projection {
+            off_path_meters: haversine(*only, point),
+            along_path_meters: 0.0,
+        });
+    }
+
+    let mut best: Option<Projection> = None;
+    let mut travelled = 0.0;
+    for segment in path.windows(2) {
+        let (start, end) = (segment[0], segment[1]);
+        let length = haversine(start, end);
+
+        // Meters east and north of the segment start
+        let scale = start.latitude.to_radians().cos();
+        let to_xy = |c: Coordinate| {
+            (
+                (c.longitude - start.longitude).to_radians() * scale * EARTH_RADIUS_METERS,
+                (c.latitude - start.latitude).to_radians() * EARTH_RADIUS_METERS,
+            )
+        };
+        let (ex, ey) = to_xy(end);
+        let (px, py) = to_xy(point);
+        let squared = ex * ex + ey * ey;
+        let t = if squared == 0.0 {
+            0.0
+        } else {
+            ((px * ex + py * ey) / squared).clamp(0.0, 1.0)
+        };
+        let off = ((px - t * ex).powi(2) + (py - t * ey).powi(2)).sqrt();
+
+        if best.is_none_or(|b| off < b.off_path_meters) {
+            best = Some(Projection {
+                off_path_meters: off,
+                along_path_meters: travelled + t * length,
+            });
+        }
+        travelled += length;
+    }
+
+    best
+}
@@ -0,0 +1,299 @@
+use std::{cmp::Ordering, f64::consts::PI};
+
+use prost::Message;
+
+use crate::db::trips::Coordinate;
+
+use super::mercator;
+
+/// Tile coordinates run from 0 to this on both axes.
+pub const EXTENT: u32 = 4096;
+// Geometry reaches this far past the tile edges, so strokes and labels aren't cut off
+const BUFFER: f64 = 64.0;
+pub const MAX_ZOOM: u8 = 22;
+const VERSION: u32 = 2;
+
+const MOVE_TO: u32 = 1;
+const LINE_TO: u32 = 2;
+const CLOSE_PATH: u32 = 7;
+
+// Messages of the Mapbox Vector Tile specification 2.1, limited to what we write
+#[derive(Clone, PartialEq, Message)]
+struct Tile {
+    #[prost(message, repeated, tag = "3")]
+    layers: Vec<Layer>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Layer {
+    #[prost(uint32, required, tag = "15")]
+    version: u32,
+    #[prost(string, required, tag = "1")]
+    name: String,
+    #[prost(message, repeated, tag = "2")]
+    features: Vec<Feature>,
+    #[prost(string, repeated, tag = "3")]
+    keys: Vec<String>,
+    #[prost(message, repeated, tag = "4")]
+    values: Vec<Value>,
+    #[prost(uint32, optional, tag = "5")]
+    extent: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Feature {
+    #[prost(uint32, repeated, packed = "true", tag = "2")]
+    tags: Vec<u32>,
+    #[prost(enumeration = "GeomType", optional, tag = "3")]
+    r#type: Option<i32>,
+    #[prost(uint32, repeated, packed = "true", tag = "4")]
+    geometry: Vec<u32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Value {
+    #[prost(string, optional, tag = "1")]
+    string_value: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+enum GeomType {
+    Unknown = 0,
+    Point = 1,
+    Polygon = 3,
+}
+
+/// A tile of the Web Mercator pyramid.
+#[derive(Clone, Copy, Debug)]
+pub struct TileId {
+    pub z: u8,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl TileId {
+    /// `None` unless the tile exists at that zoom level.
+    pub fn new(z: u8, x: u32, y: u32) -> Option<Self> {
+        if z > MAX_ZOOM || x >= 1 << z || y >= 1 << z {
+            return None;
+        }
+
+        Some(TileId { z, x, y })
+    }
+
+    /// South-west and north-east corners, including the buffer around the tile.
+    pub fn bounds(&self) -> (Coordinate, Coordinate) {
+        let tiles = f64::from(1u32 << self.z);
+        let margin = BUFFER / f64::from(EXTENT);
+        let corner = |x: f64, y: f64| Coordinate {
+            latitude: (PI * (1.0 - 2.0 * y / tiles)).sinh().atan().to_degrees(),
+            longitude: x / tiles * 360.0 - 180.0,
+        };
+
+        let x = f64::from(self.x);
+        let y = f64::from(self.y);
+        (
+            corner(x - margin, y + 1.0 + margin),
+            corner(x + 1.0 + margin, y - margin),
+        )
+    }
+
+    fn project(&self, point: Coordinate) -> (f64, f64) {
+        let tiles = f64::from(1u32 << self.z);
+        let (x, y) = mercator(point);
+        (
+            (x * tiles - f64::from(self.x)) * f64::from(EXTENT),
+            (y * tiles - f64::from(self.y)) * f64::from(EXTENT),
+        )
+    }
+}
+
+/// One named layer of a tile.
+pub struct LayerBuilder {
+    tile: TileId,
+    layer: Layer,
+}
+
+impl LayerBuilder {
+    pub fn new(tile: TileId, name: &str) -> Self {
+        LayerBuilder {
+            tile,
+            layer: Layer {
+                version: VERSION,
+                name: name.into(),
+                extent: Some(EXTENT),
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn point(&mut self, point: Coordinate, properties: &[(&str, &str)]) {
+        let (x, y) = self.tile.project(point);
+        if !in_buffer(x) || !in_buffer(y) {
+            return;
+        }
+
+        let tags = self.tags(properties);
+        self.layer.features.push(Feature {
+            tags,
+            r#type: Some(GeomType::Point as i32),
+            geometry: vec![
+                command(MOVE_TO, 1),
+                zigzag(x.round() as i64),
+                zigzag(y.round() as i64),
+            ],
+        });
+    }
+
+    /// Adds the polygon with the given outer ring, clipped to the tile. Skipped when
+    /// nothing of it is left.
+    pub fn polygon(&mut self, ring: &[Coordinate], properties: &[(&str, &str)]) {
+        let projected: Vec<(f64, f64)> = ring.iter().map(|c| self.tile.project(*c)).collect();
+        let mut points: Vec<(i64, i64)> = clip(projected)
+            .into_iter()
+            .map(|(x, y)| (x.round() as i64, y.round() as i64))
+            .collect();
+        points.dedup();
+        if points.len() > 1 && points.first() == points.last() {
+            points.pop();
+        }
+        if points.len() < 3 {
+            return;
+        }
+        // Outer rings must have a positive area in tile coordinates, i.e. run clockwise
+        match signed_area(&points).cmp(&0) {
+            Ordering::Less => points.reverse(),
+            Ordering::Equal => return,
+            Ordering::Greater => {}
+        }
+
+        let mut geometry = Vec::with_capacity(points.len() * 2 + 3);
+        let mut cursor = (0, 0);
+        for (i, &(x, y)) in points.iter().enumerate() {
+            match i {
+                0 => geometry.push(command(MOVE_TO, 1)),
+                1 => geometry.push(command(LINE_TO, points.len() as u32 - 1)),
+                _ => {}
+            }
+            geometry.push(zigzag(x - cursor.0));
+            geometry.push(zigzag(y - cursor.1));
+            cursor = (x, y);
+        }
+        geometry.push(command(CLOSE_PATH, 1));
+
+        let tags = self.tags(properties);
+        self.layer.features.push(Feature {
+            tags,
+            r#type: Some(GeomType::Polygon as i32),
+            geometry,
+        });
+    }
+
+    // Keys and values are stored once per layer, features refer to them by index
+    fn tags(&mut self, properties: &[(&str, &str)]) -> Vec<u32> {
+        let mut tags = Vec::with_capacity(properties.len() * 2);
+        for (key, value) in properties {
+            let key = match self.layer.keys.iter().position(|k| k == key) {
+                Some(index) => index,
+                None => {
+                    self.layer.keys.push((*key).into());
+                    self.layer.keys.len() - 1
+                }
+            };
+            let value = match self
+                .layer
+                .values
+                .iter()
+                .position(|v| v.string_value.as_deref() == Some(*value))
+            {
+                Some(index) => index,
+                None => {
+                    self.layer.values.push(Value {
+                        string_value: Some((*value).into()),
+                    });
+                    self.layer.values.len() - 1
+                }
+            };
+            tags.push(key as u32);
+            tags.push(value as u32);
+        }
+
+        tags
+    }
+}
+
+/// Encodes the layers as one tile. Empty layers are left out.
+pub fn encode(layers: Vec<LayerBuilder>) -> Vec<u8> {
+    Tile {
+        layers: layers
+            .into_iter()
+            .map(|l| l.layer)
+            .filter(|l| !l.features.is_empty())
+            .collect(),
+    }
+    .encode_to_vec()
+}
+
+fn in_buffer(value: f64) -> bool {
+    (-BUFFER..=f64::from(EXTENT) + BUFFER).contains(&value)
+}
+
+fn command(id: u32, count: u32) -> u32 {
+    (id & 0x7) | (count << 3)
+}
+
+// Clipping keeps values well within 32 bits
+fn zigzag(value: i64) -> u32 {
+    ((value << 1) ^ (value >> 63)) as u32
+}
+
+fn signed_area(points: &[(i64, i64)]) -> i64 {
+    let previous = points.iter().cycle().skip(points.len() - 1);
+    points
+        .iter()
+        .zip(previous)
+        .map(|(&(x1, y1), &(x0, y0))| x0 * y1 - x1 * y0)
+        .sum()
+}
+
+// A side of the clip rectangle: the coordinate it bounds, the bound and the side kept
+type ClipEdge = (fn(&(f64, f64)) -> f64, f64, bool);
+
+// Sutherland-Hodgman against the buffered tile, which keeps coordinates small at high zoom
+fn clip(ring: Vec<(f64, f64)>) -> Vec<(f64, f64)> {
+    let (low, high) = (-BUFFER, f64::from(EXTENT) + BUFFER);
+    let edges: [ClipEdge; 4] = [
+        (|p| p.0, low, true),
+        (|p| p.0, high, false),
+        (|p| p.1, low, true),
+        (|p| p.1, high, false),
+    ];
+
+    let mut ring = ring;
+    for (axis, limit, keep_above) in edges {
+        let inside = |p: &(f64, f64)| (axis(p) >= limit) == keep_above || axis(p) == limit;
+        let Some(&last) = ring.last() else {
+            break;
+        };
+
+        let mut clipped = Vec::with_capacity(ring.len() + 4);
+        let mut previous = last;
+        for &current in &ring {
+            if inside(&current) != inside(&previous) {
+                let t = (limit - axis(&previous)) / (axis(&current) - axis(&previous));
+                clipped.push((
+                    previous.0 + t * (current.0 - previous.0),
+                    previous.1 + t * (current.1 - previous.1),
+                ));
+            }
+            if inside(&current) {
+                clipped.push(current);
+            }
+            previous = current;
+        }
+        ring = clipped;
+    }
+
+    ring
+}
@@ -0,0 +1,133 @@
+use crate::db::trips::Coordinate;
+
+const ALPHABET: &[u8; 20] = b"23456789CFGHJMPQRVWX";
+const SEPARATOR: char = '+';
+const SEPARATOR_POSITION: usize = 8;
+const PADDING: char = '0';
+// Digits go in latitude, longitude pairs down to 1/8000 of a degree, then each one splits
+// the area into a grid of 5 rows by 4 columns
+const PAIR_DIGITS: usize = 10;
+const PAIR_PRECISION: f64 = 8000.0;
+const MAX_DIGITS: usize = 15;
+const GRID_ROWS: usize = 5;
+const GRID_COLUMNS: usize = 4;
+
+/// Open Location Code of a point, e.g. `849VCWC8+R9`. The 10 digits cover about 14 by 14
+/// meters.
+pub fn encode(point: Coordinate) -> String {
+    encode_with_length(point, PAIR_DIGITS)
+}
+
+/// Code of `length` digits, from 10 up to 15. Each digit past 10 narrows the area to a
+/// twentieth, 11 digits being about 3 by 3 meters.
+pub fn encode_with_length(point: Coordinate, length: usize) -> String {
+    let grid_digits = length.clamp(PAIR_DIGITS, MAX_DIGITS) - PAIR_DIGITS;
+    let rows = GRID_ROWS.pow(grid_digits as u32) as i64;
+    let columns = GRID_COLUMNS.pow(grid_digits as u32) as i64;
+    let latitude = point.latitude.clamp(-90.0, 90.0) + 90.0;
+    let longitude = (point.longitude + 180.0).rem_euclid(360.0);
+    // The north pole would fall in a row of its own, it goes in the last one
+    let mut latitude =
+        ((latitude * PAIR_PRECISION * rows as f64).floor() as i64).min(180 * 8000 * rows - 1);
+    let mut longitude =
+        ((longitude * PAIR_PRECISION * columns as f64).floor() as i64) % (360 * 8000 * columns);
+
+    let mut digits = vec![0u8; PAIR_DIGITS + grid_digits];
+    for digit in digits[PAIR_DIGITS..].iter_mut().rev() {
+        let row = (latitude % GRID_ROWS as i64) as usize;
+        let column = (longitude % GRID_COLUMNS as i64) as usize;
+        *digit = ALPHABET[row * GRID_COLUMNS + column];
+        latitude /= GRID_ROWS as i64;
+        longitude /= GRID_COLUMNS as i64;
+    }
+    for pair in digits[..PAIR_DIGITS].chunks_mut(2).rev() {
+        pair[0] = ALPHABET[(latitude % 20) as usize];
+        pair[1] = ALPHABET[(longitude % 20) as usize];
+        latitude /= 20;
+        longitude /= 20;
+    }
+
+    let mut code = String::with_capacity(digits.len() + 1);
+    for (i, digit) in digits.into_iter().enumerate() {
+        if i == SEPARATOR_POSITION {
+            code.push(SEPARATOR);
+        }
+        code.push(digit as char);
+    }
+
+    code
+}
+
+/// Center of the area of a full plus code, padded ones like `849V0000+` included. Short
+/// codes such as `CWC8+R9` need a reference point to recover and give `None`, as does
+/// anything that isn't a plus code.
+pub fn decode(code: &str) -> Option<Coordinate> {
+    let code = code.trim().to_ascii_uppercase();
+    let (head, tail) = code.split_once(SEPARATOR)?;
+    if head.len() != SEPARATOR_POSITION || tail.len() == 1 || tail.contains(SEPARATOR) {
+        return None;
+    }
+    // Padding drops whole pairs, and nothing follows the separator then
+    let digits = match head.find(PADDING) {
+        Some(start) => {
+            let padded = head[start..].chars().all(|c| c == PADDING);
+            if start == 0 || start % 2 == 1 || !padded || !tail.is_empty() {
+                return None;
+            }
+            head[..start].to_owned()
+        }
+        None => format!("{}{}", head, tail),
+    };
+    if digits.len() > MAX_DIGITS {
+        return None;
+    }
+    let values = digits
+        .bytes()
+        .map(|b| ALPHABET.iter().position(|a| *a == b))
+        .collect::<Option<Vec<_>>>()?;
+    // The first pair only goes up to 180 and 360 degrees
+    if values[0] >= 9 || values[1] >= 18 {
+        return None;
+    }
+
+    let (mut south, mut west) = (-90.0, -180.0);
+    let mut size = 400.0;
+    for pair in values[..values.len().min(PAIR_DIGITS)].chunks(2) {
+        size /= 20.0;
+        south += pair[0] as f64 * size;
+        west += pair[1] as f64 * size;
+    }
+    let (mut height, mut width) = (size, size);
+    for value in values.iter().skip(PAIR_DIGITS) {
+        height /= GRID_ROWS as f64;
+        width /= GRID_COLUMNS as f64;
+        south += (value / GRID_COLUMNS) as f64 * height;
+        west += (value % GRID_COLUMNS) as f64 * width;
+    }
+
+    Some(Coordinate {
+        latitude: (south + height / 2.0).min(90.0),
+        longitude: west + width / 2.0,
+    })
+}
+
+/// A point given as `latitude` and `longitude`, or else as a full plus code.
+pub fn resolve(
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    plus_code: Option<&str>,
+) -> Result<Coordinate, String> {
+    match (latitude, longitude, plus_code) {
+        (Some(latitude), Some(longitude), _) => Ok(Coordinate {
+            latitude,
+            longitude,
+        }),
+        (None, None, Some(code)) => decode(code).ok_or_else(|| {
+            format!(
+                "plusCode {} is not a full plus code, e.g. 849VCWC8+R9",
+                code
+            )
+        }),
+        _ => Err("latitude and longitude, or a plusCode, are required".into()),
+    }
+}
@@ -0,0 +1,77 @@
+use crate::db::trips::Coordinate;
+
+// Google encodes coordinates with 5 decimal places
+const PRECISION: f64 = 1e5;
+
+/// Decodes a Google encoded polyline, `None` if it is malformed.
+pub fn decode(encoded: &str) -> Option<Vec<Coordinate>> {
+    let bytes = encoded.as_bytes();
+    let mut index = 0;
+    let mut points = Vec::new();
+    let (mut latitude, mut longitude) = (0i64, 0i64);
+
+    while index < bytes.len() {
+        latitude = latitude.checked_add(next_value(bytes, &mut index)?)?;
+        longitude = longitude.checked_add(next_value(bytes, &mut index)?)?;
+        points.push(Coordinate {
+            latitude: latitude as f64 / PRECISION,
+            longitude: longitude as f64 / PRECISION,
+        });
+    }
+
+    Some(points)
+}
+
+/// Encodes a path the way Google does, the inverse of [`decode`].
+pub fn encode(path: &[Coordinate]) -> String {
+    let mut encoded = String::new();
+    let (mut latitude, mut longitude) = (0i64, 0i64);
+
+    for point in path {
+        let (lat, lng) = (
+            (point.latitude * PRECISION).round() as i64,
+            (point.longitude * PRECISION).round() as i64,
+        );
+        push_value(&mut encoded, lat - latitude);
+        push_value(&mut encoded, lng - longitude);
+        (latitude, longitude) = (lat, lng);
+    }
+
+    encoded
+}
+
+fn push_value(encoded: &mut String, value: i64) {
+    let mut value = if value < 0 { !(value << 1) } else { value << 1 };
+    while value >= 0x20 {
+        encoded.push(char::from((0x20 | (value & 0x1f)) as u8 + 63));
+        value >>= 5;
+    }
+    encoded.push(char::from(value as u8 + 63));
+}
+
+// Values are zigzag encoded in 5 bit chunks, least significant first, offset by 63
+fn next_value(bytes: &[u8], index: &mut usize) -> Option<i64> {
+    let mut result = 0i64;
+    let mut shift = 0;
+
+    loop {
+        let byte = *bytes.get(*index)?;
+        *index += 1;
+        if !(63..=126).contains(&byte) || shift > 60 {
+            return None;
+        }
+
+        let chunk = i64::from(byte - 63);
+        result |= (chunk & 0x1f) << shift;
+        shift += 5;
+        if chunk < 0x20 {
+            break;
+        }
+    }
+
+    Some(if result & 1 == 1 {
+        !(result >> 1)
+    } else {
+        result >> 1
+    })
+}
@@ -0,0 +1,48 @@
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+use sha2::{Digest, Sha256};
+
+use crate::error::AppError;
+
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// Stable identifier of the caller. API key callers are identified by the key's hash so
+/// raw keys are never stored next to user data, token callers by their user id claim and
+/// signing clients by their client id.
+#[derive(Clone, Debug)]
+pub struct Identity(pub String);
+
+impl Identity {
+    pub fn from_api_key(key: &str) -> Self {
+        Identity(format!("{:x}", Sha256::digest(key.as_bytes())))
+    }
+
+    // Prefixed so a user id can never collide with a key hash
+    pub fn from_user_id(user_id: &str) -> Self {
+        Identity(format!("user:{}", user_id))
+    }
+
+    pub fn from_signing_client(client_id: &str) -> Self {
+        Identity(format!("client:{}", client_id))
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Identity {
+    type Rejection = AppError;
+
+    // Authentication middleware stores the verified identity, without it the key is
+    // taken from the header as is
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(identity) = parts.extensions.get::<Identity>() {
+            return Ok(identity.clone());
+        }
+
+        parts
+            .headers
+            .get(API_KEY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.is_empty())
+            .map(Identity::from_api_key)
+            .ok_or(AppError::Unauthorized)
+    }
+}
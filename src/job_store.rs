@@ -0,0 +1,239 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use moka::future::Cache as MokaCache;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::error::AppError;
+
+// Finished jobs are rare enough that this only bounds abuse
+const MAX_JOBS: u64 = 10_000;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ItemError {
+    code: &'static str,
+    message: String,
+}
+
+impl From<&AppError> for ItemError {
+    fn from(e: &AppError) -> Self {
+        ItemError {
+            code: e.code(),
+            message: e.message(),
+        }
+    }
+}
+
+/// Outcome of one item of a batch. `status` is the HTTP status the item would have had
+/// as a request of its own.
+#[derive(Clone, Debug, Serialize)]
+pub struct ItemResult {
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ItemError>,
+}
+
+impl ItemResult {
+    pub fn new(outcome: Result<Value, AppError>) -> Self {
+        match outcome {
+            Ok(result) => ItemResult {
+                status: 200,
+                result: Some(result),
+                error: None,
+            },
+            Err(e) => ItemResult {
+                status: e.status().as_u16(),
+                result: None,
+                error: Some(ItemError::from(&e)),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum JobEvent {
+    Item {
+        index: usize,
+        #[serde(flatten)]
+        item: ItemResult,
+        completed: usize,
+        total: usize,
+    },
+    Done {
+        completed: usize,
+        failed: usize,
+        total: usize,
+    },
+}
+
+impl JobEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            JobEvent::Item { .. } => "item",
+            JobEvent::Done { .. } => "done",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct JobState {
+    events: Vec<JobEvent>,
+    // Dropped once the job is done, which ends every live subscription
+    live: Option<broadcast::Sender<JobEvent>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobSnapshot {
+    id: Uuid,
+    kind: &'static str,
+    created_at: DateTime<Utc>,
+    done: bool,
+    completed: usize,
+    total: usize,
+    /// By item index, `null` while the item is still running
+    items: Vec<Option<ItemResult>>,
+}
+
+/// A batch running in the background. Its events are kept so late subscribers replay
+/// everything before following along.
+#[derive(Debug)]
+pub struct Job {
+    pub id: Uuid,
+    pub kind: &'static str,
+    pub owner: Option<String>,
+    pub total: usize,
+    created_at: DateTime<Utc>,
+    state: Mutex<JobState>,
+}
+
+impl Job {
+    pub fn record(&self, index: usize, item: ItemResult) {
+        let mut state = self.state.lock().unwrap();
+        let completed = state.events.len() + 1;
+        self.publish(
+            &mut state,
+            JobEvent::Item {
+                index,
+                item,
+                completed,
+                total: self.total,
+            },
+        );
+
+        if completed == self.total {
+            let failed = state
+                .events
+                .iter()
+                .filter(|e| matches!(e, JobEvent::Item { item, .. } if item.error.is_some()))
+                .count();
+            self.publish(
+                &mut state,
+                JobEvent::Done {
+                    completed,
+                    failed,
+                    total: self.total,
+                },
+            );
+            state.live = None;
+        }
+    }
+
+    fn publish(&self, state: &mut JobState, event: JobEvent) {
+        if let Some(live) = &state.live {
+            // Nobody listening is fine
+            let _ = live.send(event.clone());
+        }
+        state.events.push(event);
+    }
+
+    /// Events so far and, unless the job is done, a receiver for the ones still to come.
+    /// Taken under one lock, so nothing is missed or seen twice.
+    pub fn subscribe(&self) -> (Vec<JobEvent>, Option<broadcast::Receiver<JobEvent>>) {
+        let state = self.state.lock().unwrap();
+
+        (
+            state.events.clone(),
+            state.live.as_ref().map(broadcast::Sender::subscribe),
+        )
+    }
+
+    pub fn snapshot(&self) -> JobSnapshot {
+        let state = self.state.lock().unwrap();
+        let mut items = vec![None; self.total];
+        let mut completed = 0;
+        for event in &state.events {
+            if let JobEvent::Item { index, item, .. } = event {
+                items[*index] = Some(item.clone());
+                completed += 1;
+            }
+        }
+
+        JobSnapshot {
+            id: self.id,
+            kind: self.kind,
+            created_at: self.created_at,
+            done: state.live.is_none(),
+            completed,
+            total: self.total,
+            items,
+        }
+    }
+}
+
+/// Batch jobs of this instance, kept for `ttl` after they start.
+pub struct Jobs {
+    jobs: MokaCache<Uuid, Arc<Job>>,
+}
+
+impl Jobs {
+    pub fn new(ttl: Duration) -> Self {
+        Jobs {
+            jobs: MokaCache::builder()
+                .max_capacity(MAX_JOBS)
+                .time_to_live(ttl)
+                .build(),
+        }
+    }
+
+    pub async fn create(
+        &self,
+        kind: &'static str,
+        owner: Option<String>,
+        total: usize,
+    ) -> Arc<Job> {
+        // Room for every event, so a slow subscriber never lags behind
+        let (live, _) = broadcast::channel(total + 1);
+        let job = Arc::new(Job {
+            id: Uuid::new_v4(),
+            kind,
+            owner,
+            total,
+            created_at: Utc::now(),
+            state: Mutex::new(JobState {
+                events: Vec::with_capacity(total + 1),
+                live: Some(live),
+            }),
+        });
+        self.jobs.insert(job.id, job.clone()).await;
+
+        job
+    }
+
+    /// Jobs are only visible to the caller that started them.
+    pub async fn get(&self, id: Uuid, owner: Option<&str>) -> Option<Arc<Job>> {
+        self.jobs
+            .get(&id)
+            .await
+            .filter(|job| job.owner.as_deref() == owner)
+    }
+}
@@ -1,56 +1,1021 @@
+mod alerts;
 mod api;
+mod audit;
+mod cache;
+mod config;
+mod db;
+mod error;
+mod fcm;
+mod flags;
+mod geo;
+mod identity;
+mod job_store;
+mod middleware;
+mod oauth;
+mod reload;
+mod secrets;
+mod seed;
+mod session;
+mod telemetry;
+mod tls;
+mod upstream;
+mod usage;
+mod what3words;
 
-use std::env;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
 
-use api::{get_places, get_routes};
+use alerts::Webhook;
+use api::{
+    admin, along, auth,
+    batch::{self, BatchSettings},
+    cli::{self, Cli},
+    cluster, commute_alerts, commutes, compare, departure, distance,
+    docs::ApiDoc,
+    fuel::FuelPrices,
+    geofences, get_places, get_routes,
+    graphql::{self, GraphQlSchema},
+    health::{self, Readiness},
+    history, isochrone, jobs, kml, lists, matrix, meet, metrics, mock, planner,
+    prewarm::{self, PrewarmSettings},
+    quota,
+    race::RaceProvider,
+    saved_places, share, tiles,
+    tracking::{self, TrackingSettings},
+    trips, users,
+    version::ApiVersion,
+    w3w, warmup, ProviderUrls,
+};
+use audit::{AuditLog, AuditSink};
 use axum::{
-    http::StatusCode,
-    routing::{get, post},
+    middleware::{from_fn, from_fn_with_state},
+    routing::{delete, get, post, put, MethodRouter},
     Router,
 };
-use dotenvy::dotenv;
+use cache::Cache;
+use clap::Parser;
+use config::Config;
+use fcm::Fcm;
+use flags::Flags;
+use job_store::Jobs;
+use middleware::{
+    current_environment, ApiKeys, Authenticator, ConcurrencyLimits, Environment, ErrorBudget,
+    IpFilter, JwtVerifier, LoadShedder, Quotas, RateLimiter, RequestSigning, Sandbox,
+    SlowRequestAlert, SlowRequests,
+};
+use oauth::OAuth;
+use reload::{DotEnv, Reloadable, Reloader};
 use reqwest::Client;
-use tower_http::trace::TraceLayer;
-use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt};
+use secrets::SecretStore;
+use session::Sessions;
+use sqlx::PgPool;
+use tower_http::{compression::CompressionLayer, trace::TraceLayer};
+use upstream::{
+    CassetteMode, CircuitBreaker, Coalescer, EndpointMetrics, KeyPool, RetryPolicy, UpstreamMetrics,
+};
+use usage::UsageTracker;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+use what3words::What3Words;
 
-fn context() -> Client {
-    reqwest::Client::new()
+const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+fn context(config: &Config) -> Client {
+    // The total timeout also bounds reading the body, reqwest 0.11 has no separate one
+    let mut builder = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .timeout(config.upstream_timeout)
+        .connect_timeout(config.connect_timeout)
+        .pool_max_idle_per_host(config.pool_max_idle_per_host)
+        .pool_idle_timeout(config.pool_idle_timeout)
+        .tcp_keepalive((!config.tcp_keepalive.is_zero()).then_some(config.tcp_keepalive))
+        .tcp_nodelay(true);
+    // Otherwise HTTP/2 is still used wherever TLS negotiates it
+    if config.http2_prior_knowledge {
+        builder = builder.http2_prior_knowledge();
+    }
+
+    builder.build().expect("failed to build reqwest client")
 }
 
 #[derive(Clone)]
 pub struct AppState {
     client_reqwest: Client,
-    google_key: String,
+    urls: Arc<ProviderUrls>,
+    google_keys: Arc<KeyPool>,
+    sandbox_keys: Option<Arc<KeyPool>>,
+    retry_policy: RetryPolicy,
+    places_breaker: Arc<CircuitBreaker>,
+    routes_breaker: Arc<CircuitBreaker>,
+    places_metrics: Arc<EndpointMetrics>,
+    routes_metrics: Arc<EndpointMetrics>,
+    places_race: Option<Arc<RaceProvider>>,
+    inflight: Arc<Coalescer>,
+    upstream_metrics: Arc<UpstreamMetrics>,
+    usage: Arc<UsageTracker>,
+    audit: Option<Arc<AuditLog>>,
+    readiness: Arc<Readiness>,
+    alerts: Option<Arc<Webhook>>,
+    graphql: GraphQlSchema,
+    tracking: TrackingSettings,
+    batch: BatchSettings,
+    jobs: Arc<Jobs>,
+    geohash_precision: Option<usize>,
+    place_dedupe_meters: f64,
+    drive_cost_per_km: f64,
+    fuel_prices: Arc<FuelPrices>,
+    fcm: Option<Arc<Fcm>>,
+    cache: Option<Arc<dyn Cache>>,
+    stale_cache: Option<Arc<dyn Cache>>,
+    db: Option<PgPool>,
+    oauth: Option<Arc<OAuth>>,
+    sessions: Option<Arc<Sessions>>,
+    quotas: Option<Arc<Quotas>>,
+    flags: Arc<Flags>,
+    reloader: Reloader,
+    what3words: Option<Arc<What3Words>>,
+}
+
+impl AppState {
+    /// Google keys of the environment the current request runs in.
+    fn keys(&self) -> &KeyPool {
+        match (current_environment(), &self.sandbox_keys) {
+            (Environment::Sandbox, Some(keys)) => keys,
+            _ => &self.google_keys,
+        }
+    }
 }
 
 #[tokio::main]
 async fn main() {
-    dotenv().expect(".env file not found");
+    let command = Cli::parse().command();
+    if let Some(code) = cli::run_offline(&command) {
+        std::process::exit(code);
+    }
 
-    let google_key = env::var("GOOGLE_PLACES_KEY").expect(".env file not found");
+    // .env is optional, the environment itself can provide every setting
+    let dotenv = DotEnv::load();
 
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env().unwrap_or_else(|_| {
-                "multi_map_backend=debug,tower_http=debug,axum::rejection=trace".into()
-            }),
-        )
-        .with(fmt::layer())
-        .init();
+    // Logging is configured from the settings, so errors here go straight to stderr
+    let mut config = match Config::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    // One-off commands print their result to stdout, logs go to stderr then
+    telemetry::init(&config, !command.serves());
+    let secrets = SecretStore::from_config(&config, context(&config))
+        .await
+        .map(Arc::new);
+    if let Some(store) = &secrets {
+        match store.fetch().await {
+            Ok(loaded) => {
+                if let Some(keys) = loaded.get(secrets::GOOGLE_KEYS) {
+                    config.google_keys = config::parse_list(keys);
+                }
+            }
+            Err(e) => {
+                tracing::error!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    if config.offline() {
+        // Keys aren't looked at offline, a placeholder keeps the key pool working as usual
+        if config.google_keys.is_empty() {
+            config.google_keys = vec!["offline".into()];
+        }
+        if config.google_sandbox_keys.is_empty() {
+            config.google_sandbox_keys = vec!["offline".into()];
+        }
+        if config.places_race_url.take().is_some() {
+            tracing::warn!("PLACES_RACE_URL is ignored while offline");
+        }
+        if config.what3words_key.take().is_some() {
+            tracing::warn!("WHAT3WORDS_API_KEY is ignored while offline");
+        }
+    }
+    if config.google_keys.is_empty() {
+        tracing::error!("no Google key configured");
+        std::process::exit(1);
+    }
+    tracing::debug!(
+        "places enabled: {}, routes enabled: {}, cache enabled: {} (redis: {}, ttl {:?}, max entries {})",
+        config.places_enabled,
+        config.routes_enabled,
+        config.cache_enabled,
+        config.redis_url.is_some(),
+        config.cache_ttl,
+        config.cache_max_entries
+    );
 
+    // One-off commands only call the provider, they don't connect or migrate
+    let db = match &config.database_url {
+        Some(url) if command.serves() => {
+            Some(connect_database(url, config.database_max_connections).await)
+        }
+        _ => None,
+    };
+    if matches!(&command, cli::Command::Serve(args) if args.seed) {
+        let Some(pool) = &db else {
+            tracing::error!("--seed needs DATABASE_URL");
+            std::process::exit(1);
+        };
+        match seed::run(pool).await {
+            Ok(true) => tracing::info!(
+                password = seed::PASSWORD,
+                "seeded sample users, saved places and trips"
+            ),
+            Ok(false) => tracing::info!("sample data is already there, not seeding"),
+            Err(e) => {
+                tracing::error!(error = %e, "failed to seed the database");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let places_breaker = Arc::new(CircuitBreaker::new(
+        "google-places",
+        config.breaker_failure_threshold,
+        config.breaker_open_duration,
+    ));
+    let routes_breaker = Arc::new(CircuitBreaker::new(
+        "google-routes",
+        config.breaker_failure_threshold,
+        config.breaker_open_duration,
+    ));
+    let upstream_metrics = Arc::new(UpstreamMetrics::default());
+    let places_race = config.places_race_url.clone().map(|url| {
+        let breaker = Arc::new(CircuitBreaker::new(
+            "race-places",
+            config.breaker_failure_threshold,
+            config.breaker_open_duration,
+        ));
+        Arc::new(RaceProvider::new(
+            url,
+            KeyPool::new(
+                &config.places_race_keys,
+                config.google_key_rotation,
+                config.google_key_cooldown,
+            ),
+            breaker.clone(),
+            upstream_metrics.register("race", "places", breaker),
+        ))
+    });
+    let client_reqwest = context(&config);
+    let google = ProviderUrls::google(&config.google_places_origin, &config.google_routes_origin);
+    let urls = if config.mock_providers {
+        match mock::serve().await {
+            Ok(addr) => {
+                tracing::warn!(%addr, "answering with mock places and routes, Google is never called");
+                ProviderUrls::local(addr)
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "failed to start the mock provider");
+                std::process::exit(1);
+            }
+        }
+    } else if config.cassette_mode != CassetteMode::Off {
+        match upstream::cassette::serve(
+            config.cassette_mode,
+            config.cassette_dir.clone(),
+            client_reqwest.clone(),
+            google.upstreams(),
+        )
+        .await
+        {
+            Ok(addr) => {
+                tracing::warn!(%addr, mode = ?config.cassette_mode, dir = %config.cassette_dir.display(), "upstream calls go through the cassette proxy");
+                ProviderUrls::local(addr)
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "failed to start the cassette proxy");
+                std::process::exit(1);
+            }
+        }
+    } else {
+        google
+    };
+    let alerts = config.alert_webhook_url.clone().map(|url| {
+        Arc::new(Webhook::new(
+            client_reqwest.clone(),
+            url,
+            config.alert_cooldown,
+        ))
+    });
+    let what3words = config.what3words_key.clone().map(|key| {
+        Arc::new(What3Words::new(
+            client_reqwest.clone(),
+            &config.what3words_origin,
+            key,
+        ))
+    });
+    let fcm = config
+        .fcm_service_account_path
+        .as_deref()
+        .map(|path| match Fcm::from_file(path) {
+            Ok(fcm) => Arc::new(fcm),
+            Err(e) => {
+                tracing::error!(
+                    "failed to read FCM service account {}: {}",
+                    path.display(),
+                    e
+                );
+                std::process::exit(1);
+            }
+        });
+    let (reloader, reload_requests) = Reloader::channel();
     let state = AppState {
-        client_reqwest: context(),
-        google_key,
+        client_reqwest,
+        urls: Arc::new(urls),
+        google_keys: Arc::new(KeyPool::new(
+            &config.google_keys,
+            config.google_key_rotation,
+            config.google_key_cooldown,
+        )),
+        sandbox_keys: (!config.google_sandbox_keys.is_empty()).then(|| {
+            Arc::new(KeyPool::new(
+                &config.google_sandbox_keys,
+                config.google_key_rotation,
+                config.google_key_cooldown,
+            ))
+        }),
+        retry_policy: RetryPolicy {
+            max_attempts: config.retry_max_attempts,
+            base_delay: config.retry_base_delay,
+            max_delay: config.retry_max_delay,
+        },
+        places_breaker: places_breaker.clone(),
+        routes_breaker: routes_breaker.clone(),
+        places_metrics: upstream_metrics.register("google", "places", places_breaker),
+        routes_metrics: upstream_metrics.register("google", "routes", routes_breaker),
+        places_race,
+        inflight: Arc::new(Coalescer::default()),
+        upstream_metrics,
+        usage: Arc::new(UsageTracker::new(config.usage_prices.clone(), db.clone())),
+        audit: audit_log(&config, db.as_ref()).await,
+        readiness: Arc::new(Readiness::new(
+            config.readiness_cache,
+            config.places_enabled || config.routes_enabled,
+            config.redis_url.is_some() && (config.cache_enabled || config.stale_if_error_enabled),
+        )),
+        alerts,
+        batch: BatchSettings {
+            max_items: config.batch_max_items,
+            concurrency: config.batch_concurrency,
+        },
+        jobs: Arc::new(Jobs::new(config.job_ttl)),
+        geohash_precision: config.geohash_precision,
+        place_dedupe_meters: config.place_dedupe_meters,
+        drive_cost_per_km: config.drive_cost_per_km,
+        fuel_prices: Arc::new(FuelPrices::new(
+            config.fuel_prices.clone(),
+            config.fuel_currency.clone(),
+        )),
+        fcm,
+        tracking: TrackingSettings {
+            deviation_meters: config.route_deviation_meters,
+            reroute_interval: config.reroute_interval,
+        },
+        graphql: graphql::schema(graphql::Features {
+            places: config.places_enabled,
+            routes: config.routes_enabled,
+        }),
+        cache: cache::build(&config).await,
+        stale_cache: cache::build_stale(&config).await,
+        oauth: OAuth::from_config(&config).map(Arc::new),
+        sessions: config
+            .users_enabled
+            .then(|| Sessions::from_config(&config))
+            .flatten()
+            .map(Arc::new),
+        quotas: config.quotas_enabled().then(|| {
+            Arc::new(Quotas::new(
+                config.quota_daily,
+                config.quota_monthly,
+                db.clone(),
+            ))
+        }),
+        flags: Arc::new(Flags::from_config(&config).await),
+        reloader,
+        what3words,
+        db,
+    };
+    if !command.serves() {
+        let code = cli::run(command, &state).await;
+        telemetry::shutdown();
+        std::process::exit(code);
+    }
+
+    if !config.upstream_warmup_interval.is_zero() {
+        warmup::spawn_warmup(state.clone(), config.upstream_warmup_interval);
+    }
+    if let Some(pool) = state.db.clone() {
+        if config.routes_enabled && !config.commute_sample_interval.is_zero() {
+            commutes::spawn_monitor(state.clone(), pool.clone(), config.commute_sample_interval);
+        }
+        if config.routes_enabled && !config.trip_precompute_lead.is_zero() {
+            trips::spawn_precompute(state.clone(), pool.clone(), config.trip_precompute_lead);
+        }
+        if config.places_enabled
+            && state.cache.is_some()
+            && config.cache_prewarm_top > 0
+            && !config.cache_ttl.is_zero()
+        {
+            let settings = PrewarmSettings {
+                top: config.cache_prewarm_top,
+                window: config.cache_prewarm_window,
+                cache_ttl: config.cache_ttl,
+            };
+            prewarm::spawn_prewarm(state.clone(), pool, settings);
+        }
+    }
+    state.flags.spawn_refresh(config.feature_flags_refresh);
+    if let Some(store) = secrets.clone() {
+        store.spawn_refresh(config.secrets_refresh, state.google_keys.clone());
+    }
+
+    let auth = if config.api_auth_enabled {
+        Some(authenticator(&config, &state).await)
+    } else {
+        None
+    };
+    let rate_limiter = config.rate_limit_enabled.then(|| {
+        let limiter = Arc::new(RateLimiter::new(
+            config.rate_limit_burst,
+            config.rate_limit_per_sec,
+        ));
+        limiter.spawn_eviction(Duration::from_secs(60));
+        limiter
+    });
+    #[cfg(not(feature = "grpc"))]
+    if config.grpc_bind_addr.is_some() {
+        tracing::error!("GRPC_BIND_ADDR needs a build with the grpc feature");
+        std::process::exit(1);
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(addr) = config.grpc_bind_addr {
+        // Without API authentication the listener would spend the Google key for anyone
+        if auth.is_none() && !addr.ip().is_loopback() {
+            tracing::error!(
+                "GRPC_BIND_ADDR {} is not a loopback address, which needs API_AUTH_ENABLED",
+                addr
+            );
+            std::process::exit(1);
+        }
+        api::grpc::spawn(
+            addr,
+            state.clone(),
+            config.places_enabled,
+            config.routes_enabled,
+            config.routes_timeout,
+            auth.clone(),
+            rate_limiter.clone(),
+        );
+    }
+    Reloadable {
+        rate_limiter: rate_limiter.clone(),
+        cache: state.cache.clone(),
+        stale_cache: state.stale_cache.clone(),
+        google_keys: state.google_keys.clone(),
+        secrets,
+        sandbox_keys: state.sandbox_keys.clone(),
+        flags: state.flags.clone(),
+    }
+    .spawn(dotenv, reload_requests);
+    let router = router(&config, state, auth, rate_limiter);
+
+    if let (Some(cert), Some(key)) = (config.tls_cert_path, config.tls_key_path) {
+        tls::serve(config.bind_addr, router, cert, key).await;
+    } else {
+        let listener = tokio::net::TcpListener::bind(config.bind_addr)
+            .await
+            .unwrap();
+        tracing::debug!("listening on {}", listener.local_addr().unwrap());
+
+        axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .unwrap();
+    }
+
+    telemetry::shutdown();
+    tracing::debug!("server shut down");
+}
+
+// Persistence is opt-in, but once configured the service refuses to start without it
+async fn connect_database(url: &str, max_connections: u32) -> PgPool {
+    let pool = match db::connect(url, max_connections).await {
+        Ok(pool) => pool,
+        Err(e) => {
+            tracing::error!("failed to connect to database: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = db::migrate(&pool).await {
+        tracing::error!("failed to run database migrations: {}", e);
+        std::process::exit(1);
+    }
+    match db::saved_places::count_unowned(&pool).await {
+        Ok(0) => {}
+        Ok(count) => tracing::warn!(
+            "{} saved places predate accounts and are hidden until claimed through \
+             POST /admin/saved-places/claim",
+            count
+        ),
+        Err(e) => tracing::warn!("failed to count unowned saved places: {}", e),
+    }
+
+    pool
+}
+
+async fn audit_log(config: &Config, db: Option<&PgPool>) -> Option<Arc<AuditLog>> {
+    let log = match (config.audit_log, &config.audit_log_path) {
+        (AuditSink::Database, _) => AuditLog::database(db?.clone()),
+        (AuditSink::File, Some(path)) => match AuditLog::file(path.clone()).await {
+            Ok(log) => log,
+            Err(e) => {
+                tracing::error!("failed to open audit log {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        },
+        _ => return None,
     };
-    let router = Router::new()
-        .route("/health-check", get(|| async { (StatusCode::OK, "OK") }))
-        .route("/places", post(get_places))
-        .route("/routes", post(get_routes))
-        .with_state(state)
-        .layer(TraceLayer::new_for_http());
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    tracing::debug!("listening on {}", listener.local_addr().unwrap());
+    Some(Arc::new(log))
+}
+
+async fn authenticator(config: &Config, state: &AppState) -> Arc<Authenticator> {
+    let jwt = if config.jwt_enabled() {
+        let verifier = JwtVerifier::new(
+            config.jwt_secret.as_deref(),
+            config.jwt_jwks_url.as_deref(),
+            config.jwt_issuer.clone(),
+            config.jwt_audience.clone(),
+            config.jwt_user_claim.clone(),
+            state.client_reqwest.clone(),
+        );
+        verifier.preload().await;
+        Some(verifier)
+    } else {
+        None
+    };
+
+    Arc::new(Authenticator {
+        api_keys: config
+            .api_keys_enabled()
+            .then(|| ApiKeys::new(&config.client_api_keys, state.db.clone())),
+        jwt,
+        signing: (!config.signing_clients.is_empty())
+            .then(|| RequestSigning::new(&config.signing_clients, config.signing_tolerance)),
+    })
+}
+
+// Routes calling an upstream provider get a time budget and count against client quotas
+fn upstream_route(
+    route: MethodRouter<AppState>,
+    budget: Duration,
+    quotas: Option<&Arc<Quotas>>,
+) -> MethodRouter<AppState> {
+    let route = route.layer(from_fn_with_state(budget, middleware::timeout));
+    match quotas {
+        Some(quotas) => route.layer(from_fn_with_state(
+            quotas.clone(),
+            middleware::enforce_quota,
+        )),
+        None => route,
+    }
+}
+
+fn router(
+    config: &Config,
+    state: AppState,
+    auth: Option<Arc<Authenticator>>,
+    rate_limiter: Option<Arc<RateLimiter<IpAddr>>>,
+) -> Router {
+    let quotas = state.quotas.as_ref();
+    let limits = ConcurrencyLimits::new(config.concurrency_limit, &config.route_concurrency_limits);
+    let mut api = Router::new()
+        .route("/geo/distance", post(distance::measure))
+        .route("/places/cluster", post(cluster::cluster_places));
+    if config.places_enabled {
+        api = api.route(
+            "/places",
+            upstream_route(
+                limits.route("/places", post(get_places)),
+                config.places_timeout,
+                quotas,
+            ),
+        );
+        api = api.route(
+            "/places/batch",
+            upstream_route(
+                limits.route("/places/batch", post(batch::search_batch)),
+                config.batch_timeout,
+                quotas,
+            ),
+        );
+    }
+    if config.routes_enabled {
+        api = api.route(
+            "/routes",
+            upstream_route(
+                limits.route("/routes", post(get_routes)),
+                config.routes_timeout,
+                quotas,
+            ),
+        );
+        api = api.route(
+            "/routes/batch",
+            upstream_route(
+                limits.route("/routes/batch", post(batch::route_batch)),
+                config.batch_timeout,
+                quotas,
+            ),
+        );
+        api = api.route(
+            "/routes/matrix",
+            upstream_route(
+                limits.route("/routes/matrix", post(matrix::route_matrix)),
+                config.routes_timeout,
+                quotas,
+            ),
+        );
+        api = api.route(
+            "/routes/compare",
+            upstream_route(
+                limits.route("/routes/compare", post(compare::compare_routes)),
+                config.routes_timeout,
+                quotas,
+            ),
+        );
+        api = api.route(
+            "/routes/best-departure",
+            upstream_route(
+                limits.route("/routes/best-departure", post(departure::best_departure)),
+                config.batch_timeout,
+                quotas,
+            ),
+        );
+        api = api.route(
+            "/isochrone",
+            upstream_route(
+                limits.route("/isochrone", post(isochrone::isochrone)),
+                config.routes_timeout,
+                quotas,
+            ),
+        );
+        if config.places_enabled {
+            api = api.route(
+                "/routes/places-along",
+                upstream_route(
+                    limits.route("/routes/places-along", post(along::places_along_route)),
+                    config.places_timeout,
+                    quotas,
+                ),
+            );
+            api = api.route(
+                "/meet",
+                upstream_route(
+                    limits.route("/meet", post(meet::meet)),
+                    config.batch_timeout,
+                    quotas,
+                ),
+            );
+        }
+        api = api.route(
+            "/trips/plan",
+            upstream_route(
+                limits.route("/trips/plan", post(planner::plan_trip)),
+                config.batch_timeout,
+                quotas,
+            ),
+        );
+    }
+    let graphql_enabled =
+        config.graphql_enabled && (config.places_enabled || config.routes_enabled);
+    if graphql_enabled {
+        api = api.route(
+            "/graphql",
+            upstream_route(
+                limits.route("/graphql", post(graphql::execute)),
+                config.routes_timeout,
+                quotas,
+            ),
+        );
+    }
+    if state.db.is_some() {
+        api = api
+            .route(
+                "/saved-places",
+                post(saved_places::create_saved_place).get(saved_places::list_saved_places),
+            )
+            .route(
+                "/saved-places/:id",
+                get(saved_places::get_saved_place).delete(saved_places::delete_saved_place),
+            )
+            .route("/trips", post(trips::create_trip).get(trips::list_trips))
+            .route(
+                "/trips/:id",
+                get(trips::get_trip).delete(trips::delete_trip),
+            )
+            .route(
+                "/trips/:id/recurrence",
+                put(trips::set_recurrence).delete(trips::clear_recurrence),
+            )
+            .route(
+                "/history",
+                get(history::list_history).delete(history::delete_history),
+            )
+            .route("/history/:id/choice", put(history::record_choice))
+            .route("/lists", post(lists::create_list).get(lists::list_lists))
+            .route("/lists/:id", delete(lists::delete_list))
+            .route("/lists/:id/places", get(lists::list_places))
+            .route("/lists/:id/export.kml", get(kml::export_list))
+            .route(
+                "/lists/:id/places/:place_id",
+                put(lists::add_place).delete(lists::remove_place),
+            )
+            .route("/lists/:id/members", get(lists::list_members))
+            .route(
+                "/lists/:id/members/:member",
+                put(lists::update_member).delete(lists::remove_member),
+            )
+            .route("/lists/:id/invites", post(lists::create_invite))
+            .route("/invites/:token/accept", post(lists::accept_invite))
+            .route("/tags/:tag/places", get(lists::places_by_tag))
+            .route(
+                "/geofences",
+                post(geofences::create_geofence).get(geofences::list_geofences),
+            )
+            .route("/geofences/check", post(geofences::check_points))
+            .route("/mvt/:z/:x/:y", get(tiles::vector_tile))
+            .route("/routes/share", post(share::share_route))
+            .route(
+                "/commutes",
+                post(commutes::create_commute).get(commutes::list_commutes),
+            )
+            .route(
+                "/commutes/:id",
+                get(commutes::get_commute).delete(commutes::delete_commute),
+            )
+            .route("/commutes/:id/stats", get(commutes::commute_stats))
+            .route(
+                "/commutes/:id/alerts",
+                post(commute_alerts::create_alert).get(commute_alerts::list_alerts),
+            )
+            .route(
+                "/commutes/:id/alerts/:alert_id",
+                delete(commute_alerts::delete_alert),
+            )
+            .route(
+                "/geofences/:id",
+                get(geofences::get_geofence).delete(geofences::delete_geofence),
+            )
+            .route(
+                "/trips/:id/recompute",
+                upstream_route(post(trips::recompute_trip), config.routes_timeout, quotas),
+            );
+    }
+    if state.sessions.is_some() {
+        api = api.route(
+            "/users/me",
+            get(users::get_profile)
+                .patch(users::update_profile)
+                .delete(users::delete_account),
+        );
+    }
+    // Job results only live in this instance's memory
+    if config.places_enabled {
+        api = api.route(
+            "/jobs/places",
+            upstream_route(
+                limits.route("/jobs/places", post(jobs::create_places_job)),
+                config.places_timeout,
+                quotas,
+            ),
+        );
+    }
+    if config.routes_enabled {
+        api = api.route(
+            "/jobs/routes",
+            upstream_route(
+                limits.route("/jobs/routes", post(jobs::create_routes_job)),
+                config.routes_timeout,
+                quotas,
+            ),
+        );
+    }
+    if config.places_enabled || config.routes_enabled {
+        api = api
+            .route("/jobs/:id", get(jobs::get_job))
+            .route("/jobs/:id/events", get(jobs::job_events));
+    }
+    // Rerouting uses the routes provider, so live tracking goes with it
+    if state.db.is_some() && config.routes_enabled {
+        api = api.route("/ws/routes/:trip_id", get(tracking::track_route));
+    }
+    if quotas.is_some() {
+        api = api.route("/quota", get(quota::get_quota));
+    }
+    // Added after the layer, the endpoint takes the words themselves
+    if let Some(what3words) = state.what3words.clone() {
+        api = api
+            .route_layer(from_fn_with_state(
+                what3words,
+                middleware::resolve_what3words,
+            ))
+            .route(
+                "/w3w/resolve",
+                upstream_route(get(w3w::resolve), config.places_timeout, quotas),
+            );
+    }
+    if let Some(limit) = limits.global() {
+        api = api.route_layer(limit);
+    }
+    let sandbox = Sandbox::new(&config.sandbox_clients, state.sandbox_keys.is_some());
+    api = api.route_layer(from_fn_with_state(
+        Arc::new(sandbox),
+        middleware::select_environment,
+    ));
+    // Layers added later run first, so rate limiting happens before credentials are checked
+    if let Some(auth) = auth {
+        api = api.route_layer(from_fn_with_state(auth, middleware::authenticate));
+    }
+    if let Some(limiter) = rate_limiter {
+        api = api.route_layer(from_fn_with_state(limiter, middleware::rate_limit_by_ip));
+    }
+    // Outermost so rejected requests are audited too
+    if let Some(audit) = state.audit.clone() {
+        api = api.route_layer(from_fn_with_state(audit, middleware::audit));
+    }
+
+    let mut router = Router::new()
+        .route(
+            "/health-check",
+            limits.route("/health-check", get(health::livez)),
+        )
+        .route("/livez", limits.route("/livez", get(health::livez)))
+        .route("/readyz", limits.route("/readyz", get(health::readyz)));
+    // Each version nests the routes it serves. A breaking change ships as a new version
+    // whose handlers branch on ApiVersion, leaving the older prefixes untouched
+    for version in ApiVersion::ALL {
+        router = router.nest(
+            version.prefix(),
+            api.clone()
+                .layer(from_fn_with_state(version, middleware::api_version)),
+        );
+    }
+    if config.legacy_routes_enabled {
+        router = router.merge(api.layer(from_fn(middleware::deprecated_unversioned)));
+    }
+    if config.metrics_enabled {
+        router = router.route("/metrics", get(metrics::prometheus));
+    }
+    if config.docs_enabled {
+        router = router.merge(SwaggerUi::new("/docs").url("/openapi.json", ApiDoc::openapi()));
+    }
+    if state.oauth.is_some() {
+        router = router
+            .route("/auth/login", get(auth::login))
+            .route("/auth/callback", get(auth::callback));
+    }
+    // Shared links are public, whoever has one can open it
+    if state.db.is_some() {
+        router = router.route("/r/:slug", get(share::get_shared_route));
+    }
+    // Outside the API so signing up and in need no credentials
+    if state.sessions.is_some() {
+        router = router
+            .route("/users", post(users::register))
+            .route("/users/login", post(users::login));
+    }
+    if let Some(token) = &config.admin_token {
+        let mut admin_router = Router::new()
+            .route("/admin/providers", get(admin::provider_status))
+            .route("/admin/usage", get(admin::usage_report))
+            .route("/admin/audit", get(admin::audit_log))
+            .route("/admin/quotas", get(admin::quota_overview))
+            .route("/admin/flags", get(admin::feature_flags))
+            .route(
+                "/admin/flags/:flag",
+                put(admin::set_feature_flag).delete(admin::remove_feature_flag),
+            )
+            .route("/admin/reload", post(admin::reload))
+            .route("/admin/cache/stats", get(admin::cache_stats))
+            .route("/admin/cache/invalidate", post(admin::invalidate_cache))
+            .route("/admin/cache", delete(admin::flush_cache));
+        if state.db.is_some() {
+            admin_router = admin_router
+                .route(
+                    "/admin/api-keys",
+                    post(admin::create_api_key).get(admin::list_api_keys),
+                )
+                .route("/admin/api-keys/:id", delete(admin::revoke_api_key))
+                .route("/admin/saved-places/claim", post(admin::claim_saved_places));
+        }
+        let admin_router = admin_router.route_layer(from_fn_with_state(
+            Arc::<str>::from(token.as_str()),
+            middleware::require_admin_token,
+        ));
+        router = router.merge(admin_router);
+    }
+
+    let alerts = state.alerts.clone();
+    let mut router = router.with_state(state);
+    // Wraps every route so blocked clients never reach the rate limiter or handlers
+    if config.ip_filter_enabled() {
+        let filter = Arc::new(IpFilter::new(
+            config.ip_allowlist.clone(),
+            config.ip_denylist.clone(),
+            config.trusted_proxies.clone(),
+        ));
+        router = router.layer(from_fn_with_state(filter, middleware::filter_ip));
+    }
+    // Outside the rate limiter so preflight requests are answered without using a token
+    if let Some(cors) = middleware::cors_layer(config) {
+        router = router.layer(cors);
+    }
+    if config.compression_enabled {
+        router = router.layer(CompressionLayer::new());
+    }
+
+    if let Some(webhook) = &alerts {
+        let budget = ErrorBudget::new(
+            webhook.clone(),
+            config.error_rate_alert_threshold,
+            config.error_rate_alert_min_requests,
+            config.error_rate_alert_window,
+        );
+        router = router.layer(from_fn_with_state(
+            Arc::new(budget),
+            middleware::track_error_budget,
+        ));
+    }
+    let slow = SlowRequests::new(
+        config.slow_request_threshold,
+        alerts.map(|webhook| {
+            SlowRequestAlert::new(
+                webhook,
+                config.slow_request_alert_rate,
+                config.slow_request_alert_min_requests,
+                config.slow_request_alert_window,
+            )
+        }),
+    );
+
+    if config.load_shed_max_in_flight > 0 || !config.load_shed_max_lag.is_zero() {
+        let shedder = Arc::new(LoadShedder::new(
+            config.load_shed_max_in_flight,
+            config.load_shed_max_lag,
+        ));
+        if !config.load_shed_max_lag.is_zero() {
+            shedder.spawn_lag_probe();
+        }
+        router = router.layer(from_fn_with_state(shedder, middleware::shed_load));
+    }
+
+    // The request id is assigned first so the request span can carry it
+    router
+        .layer(from_fn_with_state(
+            Arc::new(slow),
+            middleware::log_slow_requests,
+        ))
+        .layer(TraceLayer::new_for_http().make_span_with(telemetry::request_span))
+        .layer(from_fn(middleware::request_id))
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
 
-    axum::serve(listener, router).await.unwrap();
+    tracing::debug!("shutdown signal received, draining connections");
 }
@@ -0,0 +1,47 @@
+use std::{collections::HashSet, time::Duration};
+
+use moka::future::Cache as MokaCache;
+use sqlx::PgPool;
+
+use crate::{db, identity::Identity};
+
+// Revoking a stored key takes effect within this long
+const VERIFIED_TTL: Duration = Duration::from_secs(30);
+const VERIFIED_MAX_ENTRIES: u64 = 10_000;
+
+/// Client keys accepted by the API: keys from config plus active keys stored in the
+/// database. Both are compared by SHA-256, which is also the caller's identity.
+pub struct ApiKeys {
+    configured: HashSet<String>,
+    db: Option<PgPool>,
+    verified: MokaCache<String, ()>,
+}
+
+impl ApiKeys {
+    pub fn new(keys: &[String], db: Option<PgPool>) -> Self {
+        ApiKeys {
+            configured: keys.iter().map(|k| Identity::from_api_key(k).0).collect(),
+            db,
+            verified: MokaCache::builder()
+                .max_capacity(VERIFIED_MAX_ENTRIES)
+                .time_to_live(VERIFIED_TTL)
+                .build(),
+        }
+    }
+
+    pub async fn is_valid(&self, key_hash: &str) -> Result<bool, sqlx::Error> {
+        if self.configured.contains(key_hash) || self.verified.contains_key(key_hash) {
+            return Ok(true);
+        }
+
+        let Some(pool) = &self.db else {
+            return Ok(false);
+        };
+        let active = db::api_keys::is_active(pool, key_hash).await?;
+        if active {
+            self.verified.insert(key_hash.to_owned(), ()).await;
+        }
+
+        Ok(active)
+    }
+}
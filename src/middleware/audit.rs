@@ -0,0 +1,45 @@
+use std::{sync::Arc, time::Instant};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use chrono::Utc;
+use sqlx::types::Json;
+use uuid::Uuid;
+
+use crate::{
+    audit::{self, AuditLog},
+    db::audit::AuditEntry,
+    identity::Identity,
+};
+
+/// Records every API request, including those rejected by authentication or rate limiting.
+/// The caller's identity is read from the response, where authentication leaves it.
+pub async fn audit(State(log): State<Arc<AuditLog>>, req: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let at = Utc::now();
+    let method = req.method().to_string();
+    let endpoint = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| req.uri().path().to_owned(), |p| p.as_str().to_owned());
+    let params = audit::sanitize_params(req.uri().query());
+
+    let (response, provider) = audit::with_provider(next.run(req)).await;
+
+    log.record(AuditEntry {
+        id: Uuid::new_v4(),
+        at,
+        identity: response.extensions().get::<Identity>().map(|i| i.0.clone()),
+        method,
+        endpoint,
+        params: Json(params),
+        provider: provider.map(str::to_owned),
+        status: i32::from(response.status().as_u16()),
+        latency_ms: started.elapsed().as_millis() as i64,
+    });
+
+    response
+}
@@ -0,0 +1,87 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{header::AUTHORIZATION, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    error::AppError,
+    identity::{Identity, API_KEY_HEADER},
+};
+
+use super::{api_key::ApiKeys, jwt::JwtVerifier, signing::RequestSigning};
+
+/// Accepted client credentials: API keys, JWT bearer tokens and signed requests, each
+/// only when configured.
+pub struct Authenticator {
+    pub api_keys: Option<ApiKeys>,
+    pub jwt: Option<JwtVerifier>,
+    pub signing: Option<RequestSigning>,
+}
+
+impl Authenticator {
+    async fn identify(&self, headers: &HeaderMap) -> Result<Identity, AppError> {
+        let bearer = headers
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        let api_key = headers.get(API_KEY_HEADER).and_then(|v| v.to_str().ok());
+
+        self.identify_credentials(bearer, api_key).await
+    }
+
+    /// Identity of a bearer token or else an API key, for interfaces that carry them
+    /// outside of HTTP headers, like gRPC metadata. Signed requests aren't covered.
+    pub async fn identify_credentials(
+        &self,
+        bearer: Option<&str>,
+        api_key: Option<&str>,
+    ) -> Result<Identity, AppError> {
+        if let (Some(token), Some(jwt)) = (bearer, &self.jwt) {
+            return jwt.verify(token).await.map_err(|e| {
+                tracing::debug!("rejected bearer token: {}", e);
+                AppError::Unauthorized
+            });
+        }
+
+        let api_key = api_key.filter(|v| !v.is_empty());
+        if let (Some(key), Some(api_keys)) = (api_key, &self.api_keys) {
+            let identity = Identity::from_api_key(key);
+            if api_keys.is_valid(&identity.0).await? {
+                return Ok(identity);
+            }
+        }
+
+        Err(AppError::Unauthorized)
+    }
+}
+
+/// Rejects unauthenticated requests and stores the caller's identity in the request
+/// extensions for the handlers, and in the response's for the audit log.
+pub async fn authenticate(
+    State(auth): State<Arc<Authenticator>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    // Signatures cover the body, so only signed requests are buffered
+    let (mut req, identity) = match &auth.signing {
+        Some(signing) if RequestSigning::is_signed(&req) => signing.verify(req).await,
+        _ => {
+            let identity = auth.identify(req.headers()).await;
+            (req, identity)
+        }
+    };
+
+    match identity {
+        Ok(identity) => {
+            req.extensions_mut().insert(identity.clone());
+            let mut response = next.run(req).await;
+            response.extensions_mut().insert(identity);
+            response
+        }
+        Err(e) => e.into_response(),
+    }
+}
@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+
+use axum::routing::MethodRouter;
+use tower::limit::GlobalConcurrencyLimitLayer;
+
+/// Caps on requests handled at once. Requests over a cap wait for a slot rather than
+/// being turned away, load shedding is what turns them away.
+pub struct ConcurrencyLimits {
+    global: Option<GlobalConcurrencyLimitLayer>,
+    routes: HashMap<String, GlobalConcurrencyLimitLayer>,
+}
+
+impl ConcurrencyLimits {
+    /// A limit of zero is no limit. Route limits are keyed by the path as routed, e.g.
+    /// `/routes/matrix`, and shared by every API version.
+    pub fn new(global: usize, routes: &HashMap<String, usize>) -> Self {
+        ConcurrencyLimits {
+            global: (global > 0).then(|| GlobalConcurrencyLimitLayer::new(global)),
+            routes: routes
+                .iter()
+                .filter(|(_, limit)| **limit > 0)
+                .map(|(path, limit)| (path.clone(), GlobalConcurrencyLimitLayer::new(*limit)))
+                .collect(),
+        }
+    }
+
+    /// The limit shared by every API route.
+    pub fn global(&self) -> Option<GlobalConcurrencyLimitLayer> {
+        self.global.clone()
+    }
+
+    /// Applies the limit configured for `path`, if any.
+    pub fn route<S>(&self, path: &str, route: MethodRouter<S>) -> MethodRouter<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        match self.routes.get(path) {
+            Some(limit) => route.layer(limit.clone()),
+            None => route,
+        }
+    }
+}
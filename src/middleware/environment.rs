@@ -0,0 +1,101 @@
+use std::{collections::HashSet, future::Future, str::FromStr, sync::Arc};
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{error::AppError, identity::Identity};
+
+pub const ENVIRONMENT_HEADER: &str = "X-Environment";
+
+/// Which provider keys a request uses, and where its usage is counted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Environment {
+    #[default]
+    Production,
+    Sandbox,
+}
+
+impl FromStr for Environment {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "production" => Ok(Environment::Production),
+            "sandbox" => Ok(Environment::Sandbox),
+            _ => Err(()),
+        }
+    }
+}
+
+tokio::task_local! {
+    static ENVIRONMENT: Environment;
+}
+
+/// Environment of the request being handled, production outside of one.
+pub fn current_environment() -> Environment {
+    ENVIRONMENT
+        .try_with(|environment| *environment)
+        .unwrap_or_default()
+}
+
+/// Carries the current environment into work spawned for the request, which would
+/// otherwise run as production.
+pub fn in_current_environment<F: Future>(work: F) -> impl Future<Output = F::Output> {
+    ENVIRONMENT.scope(current_environment(), work)
+}
+
+/// Who is sent to the sandbox whatever they ask for, and whether there is one.
+#[derive(Debug)]
+pub struct Sandbox {
+    clients: HashSet<String>,
+    configured: bool,
+}
+
+impl Sandbox {
+    pub fn new(clients: &[String], configured: bool) -> Self {
+        Sandbox {
+            clients: clients.iter().cloned().collect(),
+            configured,
+        }
+    }
+}
+
+/// Runs the request in the environment named by `X-Environment`, or in the sandbox for the
+/// clients listed in `SANDBOX_CLIENTS`, so test traffic can't reach the production keys.
+/// Runs after authentication, which identifies the caller.
+pub async fn select_environment(
+    State(sandbox): State<Arc<Sandbox>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let requested = match req.headers().get(ENVIRONMENT_HEADER) {
+        Some(value) => match value.to_str().ok().and_then(|v| v.parse().ok()) {
+            Some(environment) => environment,
+            None => {
+                return AppError::Validation(format!(
+                    "{} must be production or sandbox",
+                    ENVIRONMENT_HEADER
+                ))
+                .into_response()
+            }
+        },
+        None => Environment::Production,
+    };
+    let sandboxed = req
+        .extensions()
+        .get::<Identity>()
+        .is_some_and(|identity| sandbox.clients.contains(&identity.0));
+    let environment = if sandboxed {
+        Environment::Sandbox
+    } else {
+        requested
+    };
+    if environment == Environment::Sandbox && !sandbox.configured {
+        return AppError::Validation("No sandbox keys are configured".into()).into_response();
+    }
+
+    ENVIRONMENT.scope(environment, next.run(req)).await
+}
@@ -0,0 +1,135 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use serde_json::json;
+
+use crate::alerts::Webhook;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Counts {
+    requests: u64,
+    errors: u64,
+}
+
+#[derive(Debug)]
+struct Window {
+    started: Instant,
+    current: Counts,
+    previous: Counts,
+}
+
+impl Window {
+    fn new(now: Instant) -> Self {
+        Window {
+            started: now,
+            current: Counts::default(),
+            previous: Counts::default(),
+        }
+    }
+
+    fn advance(&mut self, now: Instant, length: Duration) {
+        let elapsed = now.duration_since(self.started);
+        if elapsed >= length * 2 {
+            *self = Window::new(now);
+        } else if elapsed >= length {
+            self.previous = self.current;
+            self.current = Counts::default();
+            self.started += length;
+        }
+    }
+
+    // The previous window counts for the part of it still inside the rolling window
+    fn rolling(&self, now: Instant, length: Duration) -> (f64, f64) {
+        let weight = 1.0 - now.duration_since(self.started).as_secs_f64() / length.as_secs_f64();
+        (
+            self.current.requests as f64 + self.previous.requests as f64 * weight,
+            self.current.errors as f64 + self.previous.errors as f64 * weight,
+        )
+    }
+}
+
+/// Rolling 5xx rate per endpoint, alerting through the webhook when it goes over `threshold`.
+pub struct ErrorBudget {
+    webhook: Arc<Webhook>,
+    threshold: f64,
+    min_requests: u64,
+    window: Duration,
+    endpoints: Mutex<HashMap<String, Window>>,
+}
+
+impl ErrorBudget {
+    pub fn new(webhook: Arc<Webhook>, threshold: f64, min_requests: u64, window: Duration) -> Self {
+        ErrorBudget {
+            webhook,
+            threshold,
+            min_requests,
+            window,
+            endpoints: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn record(&self, endpoint: &str, error: bool) {
+        let now = Instant::now();
+        let (requests, errors) = {
+            let mut endpoints = self.endpoints.lock().unwrap();
+            let window = endpoints
+                .entry(endpoint.to_owned())
+                .or_insert_with(|| Window::new(now));
+            window.advance(now, self.window);
+            window.current.requests += 1;
+            window.current.errors += u64::from(error);
+            window.rolling(now, self.window)
+        };
+
+        let rate = errors / requests;
+        if error && requests >= self.min_requests as f64 && rate > self.threshold {
+            // Per endpoint, so one failing endpoint doesn't mute alerts for the others
+            self.webhook.fire(
+                &format!("error_rate:{}", endpoint),
+                format!(
+                    "{} error rate is {:.1}% over the last {:?} (threshold {:.1}%)",
+                    endpoint,
+                    rate * 100.0,
+                    self.window,
+                    self.threshold * 100.0
+                ),
+                json!({
+                    "endpoint": endpoint,
+                    "errorRate": rate,
+                    "threshold": self.threshold,
+                    "requests": requests.round() as u64,
+                    "errors": errors.round() as u64,
+                    "windowSecs": self.window.as_secs(),
+                }),
+            );
+        }
+    }
+}
+
+/// Counts server errors per route. Requests matching no route aren't tracked, so arbitrary
+/// paths can't grow the table.
+pub async fn track_error_budget(
+    State(budget): State<Arc<ErrorBudget>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let endpoint = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| format!("{} {}", req.method(), p.as_str()));
+
+    let response = next.run(req).await;
+    if let Some(endpoint) = endpoint {
+        budget.record(&endpoint, response.status().is_server_error());
+    }
+
+    response
+}
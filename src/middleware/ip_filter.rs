@@ -0,0 +1,89 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ipnet::IpNet;
+
+use crate::error::AppError;
+
+const FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+
+/// Address of the client after looking through trusted proxies.
+#[derive(Clone, Copy, Debug)]
+pub struct ClientIp(pub IpAddr);
+
+/// Allow and deny lists of CIDR ranges. Denied ranges win, and an empty allowlist lets
+/// every address not denied through.
+#[derive(Debug)]
+pub struct IpFilter {
+    allow: Vec<IpNet>,
+    deny: Vec<IpNet>,
+    trusted_proxies: Vec<IpNet>,
+}
+
+impl IpFilter {
+    pub fn new(allow: Vec<IpNet>, deny: Vec<IpNet>, trusted_proxies: Vec<IpNet>) -> Self {
+        IpFilter {
+            allow,
+            deny,
+            trusted_proxies,
+        }
+    }
+
+    // X-Forwarded-For is only believed for hops added by our own proxies. Walking from the
+    // right, the first address that isn't a trusted proxy is the client
+    fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let mut client = peer;
+        let forwarded = headers
+            .get_all(FORWARDED_FOR_HEADER)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .collect::<Vec<_>>();
+
+        for hop in forwarded.iter().rev() {
+            if !is_in(&self.trusted_proxies, client) {
+                break;
+            }
+            match hop.trim().parse() {
+                Ok(ip) => client = ip,
+                Err(_) => break,
+            }
+        }
+
+        client
+    }
+
+    fn permits(&self, ip: IpAddr) -> bool {
+        !is_in(&self.deny, ip) && (self.allow.is_empty() || is_in(&self.allow, ip))
+    }
+}
+
+fn is_in(ranges: &[IpNet], ip: IpAddr) -> bool {
+    ranges.iter().any(|range| range.contains(&ip))
+}
+
+/// Rejects clients outside the allowed ranges and records the resolved client address
+/// for the layers after it.
+pub async fn filter_ip(
+    State(filter): State<Arc<IpFilter>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let ip = filter.client_ip(addr.ip(), req.headers());
+    if !filter.permits(ip) {
+        tracing::info!("rejected request from {}", ip);
+        return AppError::Forbidden.into_response();
+    }
+
+    req.extensions_mut().insert(ClientIp(ip));
+    next.run(req).await
+}
@@ -0,0 +1,150 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use reqwest::Client;
+use serde_json::Value;
+
+use crate::identity::Identity;
+
+// Tokens signed with an unknown kid trigger a JWKS refetch at most this often
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+struct Jwks {
+    url: String,
+    client: Client,
+    keys: RwLock<HashMap<String, DecodingKey>>,
+    last_refresh: Mutex<Option<Instant>>,
+}
+
+impl Jwks {
+    async fn refresh(&self) -> Result<(), String> {
+        *self.last_refresh.lock().unwrap() = Some(Instant::now());
+
+        let set = self
+            .client
+            .get(&self.url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| e.to_string())?
+            .json::<JwkSet>()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let keys: HashMap<String, DecodingKey> = set
+            .keys
+            .iter()
+            .filter_map(|jwk| {
+                let kid = jwk.common.key_id.clone()?;
+                let key = DecodingKey::from_jwk(jwk).ok()?;
+                Some((kid, key))
+            })
+            .collect();
+        tracing::debug!("loaded {} keys from {}", keys.len(), self.url);
+        *self.keys.write().unwrap() = keys;
+
+        Ok(())
+    }
+
+    fn refresh_allowed(&self) -> bool {
+        match *self.last_refresh.lock().unwrap() {
+            Some(at) => at.elapsed() >= MIN_REFRESH_INTERVAL,
+            None => true,
+        }
+    }
+
+    async fn key(&self, kid: &str) -> Option<DecodingKey> {
+        if let Some(key) = self.keys.read().unwrap().get(kid).cloned() {
+            return Some(key);
+        }
+
+        // Providers rotate keys, so an unknown kid may just mean our copy is outdated
+        if self.refresh_allowed() {
+            if let Err(e) = self.refresh().await {
+                tracing::warn!("failed to refresh JWKS from {}: {}", self.url, e);
+            }
+        }
+
+        self.keys.read().unwrap().get(kid).cloned()
+    }
+}
+
+/// Verifies HS256 tokens with a shared secret and RS256 tokens against a JWKS endpoint.
+pub struct JwtVerifier {
+    secret: Option<DecodingKey>,
+    jwks: Option<Jwks>,
+    issuer: Option<String>,
+    audience: Option<String>,
+    user_claim: String,
+}
+
+impl JwtVerifier {
+    pub fn new(
+        secret: Option<&str>,
+        jwks_url: Option<&str>,
+        issuer: Option<String>,
+        audience: Option<String>,
+        user_claim: String,
+        client: Client,
+    ) -> Self {
+        JwtVerifier {
+            secret: secret.map(|s| DecodingKey::from_secret(s.as_bytes())),
+            jwks: jwks_url.map(|url| Jwks {
+                url: url.to_owned(),
+                client,
+                keys: RwLock::new(HashMap::new()),
+                last_refresh: Mutex::new(None),
+            }),
+            issuer,
+            audience,
+            user_claim,
+        }
+    }
+
+    /// Loads the JWKS up front so the first requests don't pay for the fetch.
+    pub async fn preload(&self) {
+        if let Some(jwks) = &self.jwks {
+            if let Err(e) = jwks.refresh().await {
+                tracing::warn!("failed to load JWKS from {}: {}", jwks.url, e);
+            }
+        }
+    }
+
+    pub async fn verify(&self, token: &str) -> Result<Identity, String> {
+        let header = decode_header(token).map_err(|e| e.to_string())?;
+
+        let key = match header.alg {
+            Algorithm::HS256 => self.secret.clone().ok_or("HS256 is not configured")?,
+            Algorithm::RS256 => {
+                let jwks = self.jwks.as_ref().ok_or("RS256 is not configured")?;
+                let kid = header.kid.as_deref().ok_or("token has no kid")?;
+                jwks.key(kid).await.ok_or("unknown kid")?
+            }
+            alg => return Err(format!("unsupported algorithm {:?}", alg)),
+        };
+
+        let mut validation = Validation::new(header.alg);
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        let data = decode::<HashMap<String, Value>>(token, &key, &validation)
+            .map_err(|e| e.to_string())?;
+        let user = data
+            .claims
+            .get(&self.user_claim)
+            .and_then(|v| v.as_str())
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| format!("token has no {} claim", self.user_claim))?;
+
+        Ok(Identity::from_user_id(user))
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::error::AppError;
+
+// How often the event loop lag is measured
+const LAG_PROBE_INTERVAL: Duration = Duration::from_millis(250);
+// Low priority requests are turned away this much below the limits
+const LOW_PRIORITY_SHARE: f64 = 0.8;
+const RETRY_AFTER: Duration = Duration::from_secs(1);
+// Fan out to many upstream calls each, so they're the first to go
+const LOW_PRIORITY_PATHS: [&str; 9] = [
+    "/places/batch",
+    "/routes/batch",
+    "/routes/matrix",
+    "/routes/compare",
+    "/routes/best-departure",
+    "/isochrone",
+    "/trips/plan",
+    "/jobs/places",
+    "/jobs/routes",
+];
+// Never turned away, so probes and operators see the overload instead of adding to it
+const CRITICAL_PATHS: [&str; 5] = ["/livez", "/readyz", "/health-check", "/metrics", "/admin"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Priority {
+    Critical,
+    Normal,
+    Low,
+}
+
+fn priority(path: &str) -> Priority {
+    if CRITICAL_PATHS.iter().any(|p| path.starts_with(p)) {
+        Priority::Critical
+    } else if LOW_PRIORITY_PATHS.iter().any(|p| path.ends_with(p)) {
+        Priority::Low
+    } else {
+        Priority::Normal
+    }
+}
+
+/// Turns requests away with 503 once too many are in flight or the event loop falls
+/// behind, low priority ones first. A limit of zero is never reached.
+pub struct LoadShedder {
+    max_in_flight: usize,
+    max_lag: Duration,
+    in_flight: AtomicUsize,
+    lag_micros: AtomicU64,
+}
+
+// Counts a request as in flight until its response is produced or it's dropped
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl LoadShedder {
+    pub fn new(max_in_flight: usize, max_lag: Duration) -> Self {
+        LoadShedder {
+            max_in_flight,
+            max_lag,
+            in_flight: AtomicUsize::new(0),
+            lag_micros: AtomicU64::new(0),
+        }
+    }
+
+    /// Measures how late a timer fires, which is how long ready tasks wait for a worker.
+    pub fn spawn_lag_probe(self: &Arc<Self>) {
+        let shedder = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let started = Instant::now();
+                tokio::time::sleep(LAG_PROBE_INTERVAL).await;
+                let lag = started.elapsed().saturating_sub(LAG_PROBE_INTERVAL);
+                shedder
+                    .lag_micros
+                    .store(lag.as_micros() as u64, Ordering::Relaxed);
+            }
+        });
+    }
+
+    fn overloaded(&self, in_flight: usize, priority: Priority) -> bool {
+        let share = match priority {
+            Priority::Critical => return false,
+            Priority::Normal => 1.0,
+            Priority::Low => LOW_PRIORITY_SHARE,
+        };
+        let lag = Duration::from_micros(self.lag_micros.load(Ordering::Relaxed));
+        let too_many =
+            self.max_in_flight > 0 && in_flight as f64 > self.max_in_flight as f64 * share;
+        let too_slow = !self.max_lag.is_zero() && lag > self.max_lag.mul_f64(share);
+
+        too_many || too_slow
+    }
+}
+
+pub async fn shed_load(
+    State(shedder): State<Arc<LoadShedder>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let in_flight = shedder.in_flight.fetch_add(1, Ordering::Relaxed) + 1;
+    let _in_flight = InFlight(&shedder.in_flight);
+
+    let priority = priority(req.uri().path());
+    if shedder.overloaded(in_flight, priority) {
+        tracing::warn!(path = %req.uri().path(), in_flight, "shedding load");
+        return AppError::Overloaded {
+            retry_after: RETRY_AFTER,
+        }
+        .into_response();
+    }
+
+    next.run(req).await
+}
@@ -0,0 +1,41 @@
+mod admin;
+mod api_key;
+mod audit;
+mod auth;
+mod concurrency;
+mod cors;
+mod environment;
+mod error_budget;
+mod ip_filter;
+mod jwt;
+mod load_shed;
+mod quota;
+mod rate_limit;
+mod request_id;
+mod signing;
+mod slow_request;
+mod timeout;
+mod version;
+mod what3words;
+
+pub use admin::require_admin_token;
+pub use api_key::ApiKeys;
+pub use audit::audit;
+pub use auth::{authenticate, Authenticator};
+pub use concurrency::ConcurrencyLimits;
+pub use cors::cors_layer;
+pub use environment::{
+    current_environment, in_current_environment, select_environment, Environment, Sandbox,
+};
+pub use error_budget::{track_error_budget, ErrorBudget};
+pub use ip_filter::{filter_ip, IpFilter};
+pub use jwt::JwtVerifier;
+pub use load_shed::{shed_load, LoadShedder};
+pub use quota::{enforce_quota, ClientQuota, QuotaStatus, Quotas};
+pub use rate_limit::{rate_limit_by_ip, RateLimiter};
+pub use request_id::{current_request_id, request_id, REQUEST_ID_HEADER};
+pub use signing::RequestSigning;
+pub use slow_request::{log_slow_requests, record_upstream_call, SlowRequestAlert, SlowRequests};
+pub use timeout::timeout;
+pub use version::{api_version, current_api_version, deprecated_unversioned};
+pub use what3words::resolve_what3words;
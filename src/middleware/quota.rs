@@ -0,0 +1,267 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::{db, error::AppError, identity::Identity};
+
+#[derive(Debug, Default)]
+struct Counters {
+    day: String,
+    day_count: i64,
+    month: String,
+    month_count: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaPeriod {
+    limit: u64,
+    used: u64,
+    remaining: u64,
+    resets_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClientQuota {
+    identity: String,
+    #[serde(flatten)]
+    status: QuotaStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuotaStatus {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    daily: Option<QuotaPeriod>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    monthly: Option<QuotaPeriod>,
+}
+
+/// Daily and monthly request allowances per client, in UTC calendar periods. Counts are
+/// kept in the database when one is configured so they survive restarts and are shared
+/// between instances.
+#[derive(Debug)]
+pub struct Quotas {
+    daily: Option<u64>,
+    monthly: Option<u64>,
+    db: Option<PgPool>,
+    memory: Mutex<HashMap<String, Counters>>,
+}
+
+impl Quotas {
+    pub fn new(daily: Option<u64>, monthly: Option<u64>, db: Option<PgPool>) -> Self {
+        Quotas {
+            daily,
+            monthly,
+            db,
+            memory: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Counts a request for `identity`, or returns how long until its quota resets.
+    pub async fn consume(&self, identity: &str) -> Result<(), AppError> {
+        let now = Utc::now();
+        let (day_count, month_count) = match &self.db {
+            Some(pool) => {
+                let periods = periods(now);
+                let counts = db::usage::increment(pool, identity, &periods).await?;
+                totals(&periods, &counts)
+            }
+            None => {
+                let mut memory = self.memory.lock().unwrap();
+                let counters = memory.entry(identity.to_owned()).or_default();
+                counters.roll(now);
+                counters.day_count += 1;
+                counters.month_count += 1;
+                (counters.day_count, counters.month_count)
+            }
+        };
+
+        let reset = if exceeded(self.monthly, month_count) {
+            Some(next_month(now))
+        } else if exceeded(self.daily, day_count) {
+            Some(next_day(now))
+        } else {
+            None
+        };
+        match reset {
+            Some(at) => Err(AppError::RateLimited {
+                retry_after: (at - now).to_std().unwrap_or_default(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn status(&self, identity: &str) -> Result<QuotaStatus, AppError> {
+        let now = Utc::now();
+        let (day_count, month_count) = match &self.db {
+            Some(pool) => {
+                let periods = periods(now);
+                let counts = db::usage::counts(pool, identity, &periods).await?;
+                totals(&periods, &counts)
+            }
+            None => {
+                let mut memory = self.memory.lock().unwrap();
+                match memory.get_mut(identity) {
+                    Some(counters) => {
+                        counters.roll(now);
+                        (counters.day_count, counters.month_count)
+                    }
+                    None => (0, 0),
+                }
+            }
+        };
+
+        Ok(self.status_of(day_count, month_count, now))
+    }
+
+    /// Status of every client that made a request this month.
+    pub async fn overview(&self) -> Result<Vec<ClientQuota>, AppError> {
+        let now = Utc::now();
+        let mut counts: Vec<(String, (i64, i64))> = match &self.db {
+            Some(pool) => {
+                let periods = periods(now);
+                let mut by_identity: HashMap<String, (i64, i64)> = HashMap::new();
+                for (identity, period, count) in db::usage::all_counts(pool, &periods).await? {
+                    let totals = by_identity.entry(identity).or_default();
+                    if period == periods[0] {
+                        totals.0 = count;
+                    } else {
+                        totals.1 = count;
+                    }
+                }
+                by_identity.into_iter().collect()
+            }
+            None => {
+                let mut memory = self.memory.lock().unwrap();
+                memory
+                    .iter_mut()
+                    .map(|(identity, counters)| {
+                        counters.roll(now);
+                        (identity.clone(), (counters.day_count, counters.month_count))
+                    })
+                    .filter(|(_, (_, month_count))| *month_count > 0)
+                    .collect()
+            }
+        };
+        counts.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(counts
+            .into_iter()
+            .map(|(identity, (day_count, month_count))| ClientQuota {
+                identity,
+                status: self.status_of(day_count, month_count, now),
+            })
+            .collect())
+    }
+
+    fn status_of(&self, day_count: i64, month_count: i64, now: DateTime<Utc>) -> QuotaStatus {
+        QuotaStatus {
+            daily: self
+                .daily
+                .map(|limit| QuotaPeriod::new(limit, day_count, next_day(now))),
+            monthly: self
+                .monthly
+                .map(|limit| QuotaPeriod::new(limit, month_count, next_month(now))),
+        }
+    }
+}
+
+impl Counters {
+    // Counts from a previous period don't carry over
+    fn roll(&mut self, now: DateTime<Utc>) {
+        let day = now.format("%Y-%m-%d").to_string();
+        let month = now.format("%Y-%m").to_string();
+        if self.day != day {
+            self.day = day;
+            self.day_count = 0;
+        }
+        if self.month != month {
+            self.month = month;
+            self.month_count = 0;
+        }
+    }
+}
+
+impl QuotaPeriod {
+    fn new(limit: u64, used: i64, resets_at: DateTime<Utc>) -> Self {
+        let used = used.max(0) as u64;
+        QuotaPeriod {
+            limit,
+            used,
+            remaining: limit.saturating_sub(used),
+            resets_at,
+        }
+    }
+}
+
+fn periods(now: DateTime<Utc>) -> Vec<String> {
+    vec![
+        now.format("day:%Y-%m-%d").to_string(),
+        now.format("month:%Y-%m").to_string(),
+    ]
+}
+
+fn totals(periods: &[String], counts: &[(String, i64)]) -> (i64, i64) {
+    let count = |period: &String| {
+        counts
+            .iter()
+            .find(|(p, _)| p == period)
+            .map_or(0, |(_, c)| *c)
+    };
+    (count(&periods[0]), count(&periods[1]))
+}
+
+fn exceeded(limit: Option<u64>, count: i64) -> bool {
+    limit.is_some_and(|limit| count > limit as i64)
+}
+
+fn next_day(now: DateTime<Utc>) -> DateTime<Utc> {
+    let tomorrow = now.date_naive().succ_opt().expect("date out of range");
+    tomorrow.and_hms_opt(0, 0, 0).unwrap().and_utc()
+}
+
+fn next_month(now: DateTime<Utc>) -> DateTime<Utc> {
+    let (year, month) = match now.month() {
+        12 => (now.year() + 1, 1),
+        m => (now.year(), m + 1),
+    };
+    NaiveDate::from_ymd_opt(year, month, 1)
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .expect("date out of range")
+        .and_utc()
+}
+
+/// Counts the request against the caller's quota. Runs after authentication, requests
+/// without an identity are not counted.
+pub async fn enforce_quota(
+    State(quotas): State<Arc<Quotas>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(identity) = req.extensions().get::<Identity>().cloned() else {
+        return next.run(req).await;
+    };
+
+    match quotas.consume(&identity.0).await {
+        Ok(()) => next.run(req).await,
+        // Quotas protect the budget, they shouldn't take the API down with the database
+        Err(AppError::Database(e)) => {
+            tracing::warn!("quota check failed, allowing request: {}", e);
+            next.run(req).await
+        }
+        Err(e) => {
+            tracing::debug!("quota exceeded for {}", identity.0);
+            e.into_response()
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::error::AppError;
+
+use super::ip_filter::ClientIp;
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Limits {
+    burst: f64,
+    refill_per_sec: f64,
+}
+
+/// Token bucket limiter, one bucket per key: `burst` requests up front,
+/// refilled at `refill_per_sec` tokens per second.
+#[derive(Debug)]
+pub struct RateLimiter<K> {
+    limits: RwLock<Limits>,
+    buckets: Mutex<HashMap<K, Bucket>>,
+}
+
+impl<K: Eq + Hash> RateLimiter<K> {
+    pub fn new(burst: u32, refill_per_sec: f64) -> Self {
+        RateLimiter {
+            limits: RwLock::new(Limits {
+                burst: burst as f64,
+                refill_per_sec,
+            }),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Changes the limits in place. Buckets keep their tokens, down to the new burst.
+    pub fn set_limits(&self, burst: u32, refill_per_sec: f64) {
+        *self.limits.write().unwrap() = Limits {
+            burst: burst as f64,
+            refill_per_sec,
+        };
+    }
+
+    /// Takes a token for `key`, or returns how long to wait until one is available.
+    pub fn check(&self, key: K) -> Result<(), Duration> {
+        let Limits {
+            burst,
+            refill_per_sec,
+        } = *self.limits.read().unwrap();
+        let mut buckets = self.buckets.lock().unwrap();
+        let now = Instant::now();
+
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: burst,
+            last_refill: now,
+        });
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * refill_per_sec).min(burst);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - bucket.tokens) / refill_per_sec,
+            ))
+        }
+    }
+
+    // Buckets idle long enough to be full again are equivalent to fresh ones
+    fn evict_idle(&self) {
+        let limits = *self.limits.read().unwrap();
+        let full_after = Duration::from_secs_f64(limits.burst / limits.refill_per_sec);
+        let now = Instant::now();
+
+        self.buckets
+            .lock()
+            .unwrap()
+            .retain(|_, bucket| now.duration_since(bucket.last_refill) < full_after);
+    }
+}
+
+impl<K: Eq + Hash + Send + 'static> RateLimiter<K> {
+    pub fn spawn_eviction(self: &Arc<Self>, every: Duration) {
+        let limiter = Arc::downgrade(self);
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            loop {
+                interval.tick().await;
+                match limiter.upgrade() {
+                    Some(limiter) => limiter.evict_idle(),
+                    None => break,
+                }
+            }
+        });
+    }
+}
+
+pub async fn rate_limit_by_ip(
+    State(limiter): State<Arc<RateLimiter<IpAddr>>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next,
+) -> Response {
+    // Behind a trusted proxy the peer is the proxy, not the client
+    let ip = req
+        .extensions()
+        .get::<ClientIp>()
+        .map_or(addr.ip(), |client| client.0);
+
+    match limiter.check(ip) {
+        Ok(()) => next.run(req).await,
+        Err(retry_after) => {
+            tracing::debug!("rate limited {} for {:?}", ip, retry_after);
+            AppError::RateLimited { retry_after }.into_response()
+        }
+    }
+}
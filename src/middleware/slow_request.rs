@@ -0,0 +1,159 @@
+use std::{
+    cell::RefCell,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use serde_json::json;
+
+use crate::alerts::Webhook;
+
+struct UpstreamCall {
+    provider: &'static str,
+    elapsed: Duration,
+    retries: u32,
+}
+
+tokio::task_local! {
+    static UPSTREAM_CALLS: RefCell<Vec<UpstreamCall>>;
+}
+
+/// Notes an upstream call made for the current request, for the slow request breakdown.
+pub fn record_upstream_call(provider: &'static str, elapsed: Duration, retries: u32) {
+    let _ = UPSTREAM_CALLS.try_with(|calls| {
+        calls.borrow_mut().push(UpstreamCall {
+            provider,
+            elapsed,
+            retries,
+        })
+    });
+}
+
+#[derive(Debug)]
+struct Window {
+    started: Instant,
+    requests: u64,
+    slow: u64,
+}
+
+/// Fires when more than `rate` of the requests in a window were slow.
+pub struct SlowRequestAlert {
+    webhook: Arc<Webhook>,
+    rate: f64,
+    min_requests: u64,
+    window: Duration,
+    current: Mutex<Window>,
+}
+
+impl SlowRequestAlert {
+    pub fn new(webhook: Arc<Webhook>, rate: f64, min_requests: u64, window: Duration) -> Self {
+        SlowRequestAlert {
+            webhook,
+            rate,
+            min_requests,
+            window,
+            current: Mutex::new(Window {
+                started: Instant::now(),
+                requests: 0,
+                slow: 0,
+            }),
+        }
+    }
+
+    fn record(&self, slow: bool, threshold: Duration) {
+        let (requests, slow_requests) = {
+            let mut current = self.current.lock().unwrap();
+            if current.started.elapsed() >= self.window {
+                *current = Window {
+                    started: Instant::now(),
+                    requests: 0,
+                    slow: 0,
+                };
+            }
+            current.requests += 1;
+            current.slow += u64::from(slow);
+            (current.requests, current.slow)
+        };
+
+        // Small samples make for noisy rates
+        let rate = slow_requests as f64 / requests as f64;
+        if slow && requests >= self.min_requests && rate > self.rate {
+            self.webhook.fire(
+                "slow_requests",
+                format!(
+                    "{} of the last {} requests took longer than {:?}",
+                    slow_requests, requests, threshold
+                ),
+                json!({
+                    "slowRequests": slow_requests,
+                    "requests": requests,
+                    "rate": rate,
+                    "thresholdMs": threshold.as_millis() as u64,
+                    "windowSecs": self.window.as_secs(),
+                }),
+            );
+        }
+    }
+}
+
+pub struct SlowRequests {
+    threshold: Duration,
+    alert: Option<SlowRequestAlert>,
+}
+
+impl SlowRequests {
+    pub fn new(threshold: Duration, alert: Option<SlowRequestAlert>) -> Self {
+        SlowRequests { threshold, alert }
+    }
+}
+
+/// Warns about requests slower than the threshold, with the time spent on each upstream call.
+pub async fn log_slow_requests(
+    State(slow): State<Arc<SlowRequests>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let method = req.method().clone();
+    let path = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or_else(|| req.uri().path().to_owned(), |p| p.as_str().to_owned());
+
+    let (response, calls) = UPSTREAM_CALLS
+        .scope(RefCell::new(Vec::new()), async move {
+            let response = next.run(req).await;
+            (response, UPSTREAM_CALLS.with(|calls| calls.take()))
+        })
+        .await;
+    let elapsed = started.elapsed();
+    let is_slow = elapsed >= slow.threshold;
+
+    if is_slow {
+        let upstream_ms: u128 = calls.iter().map(|c| c.elapsed.as_millis()).sum();
+        let breakdown = calls
+            .iter()
+            .map(|c| format!("{}={}ms/{}r", c.provider, c.elapsed.as_millis(), c.retries))
+            .collect::<Vec<_>>()
+            .join(",");
+        tracing::warn!(
+            method = %method,
+            path,
+            status = response.status().as_u16(),
+            latency_ms = elapsed.as_millis() as u64,
+            upstream_ms = upstream_ms as u64,
+            upstream_calls = breakdown,
+            "slow request"
+        );
+    }
+    if let Some(alert) = &slow.alert {
+        alert.record(is_slow, slow.threshold);
+    }
+
+    response
+}
@@ -0,0 +1,22 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::error::AppError;
+
+// Dropping the handler future also cancels any in-flight upstream request
+pub async fn timeout(State(budget): State<Duration>, req: Request, next: Next) -> Response {
+    let path = req.uri().path().to_owned();
+
+    match tokio::time::timeout(budget, next.run(req)).await {
+        Ok(response) => response,
+        Err(_) => {
+            tracing::warn!("{} exceeded its time budget of {:?}", path, budget);
+            AppError::Timeout.into_response()
+        }
+    }
+}
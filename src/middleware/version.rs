@@ -0,0 +1,49 @@
+use axum::{
+    extract::{Request, State},
+    http::{header::LINK, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::api::version::ApiVersion;
+
+const DEPRECATION_HEADER: HeaderName = HeaderName::from_static("deprecation");
+
+tokio::task_local! {
+    static API_VERSION: ApiVersion;
+}
+
+/// Version of the request being handled, v1 outside of one.
+pub fn current_api_version() -> ApiVersion {
+    API_VERSION.try_with(|version| *version).unwrap_or_default()
+}
+
+/// Tags requests with the version of the router that matched them.
+pub async fn api_version(
+    State(version): State<ApiVersion>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    req.extensions_mut().insert(version);
+    API_VERSION.scope(version, next.run(req)).await
+}
+
+/// Unprefixed paths are served as v1 for clients predating versioning, marked deprecated
+/// with a link to their versioned successor.
+pub async fn deprecated_unversioned(mut req: Request, next: Next) -> Response {
+    req.extensions_mut().insert(ApiVersion::V1);
+    let successor = format!(
+        "<{}{}>; rel=\"successor-version\"",
+        ApiVersion::V1.prefix(),
+        req.uri().path()
+    );
+
+    let mut response = API_VERSION.scope(ApiVersion::V1, next.run(req)).await;
+    let headers = response.headers_mut();
+    headers.insert(DEPRECATION_HEADER, HeaderValue::from_static("true"));
+    if let Ok(link) = HeaderValue::from_str(&successor) {
+        headers.insert(LINK, link);
+    }
+
+    response
+}
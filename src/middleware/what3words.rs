@@ -0,0 +1,114 @@
+use std::sync::Arc;
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+        uri::{PathAndQuery, Uri},
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+
+use crate::{
+    error::AppError,
+    what3words::{address_words, What3Words},
+};
+
+// Same as axum's default body limit
+const MAX_BODY: usize = 2 * 1024 * 1024;
+
+/// Replaces what3words addresses such as `///index.home.raft` with the plus code of their
+/// square, which every location accepts. Addresses are looked for in query parameters and
+/// in every string of a JSON body. Runs after authentication, so anonymous requests can't
+/// spend the what3words quota.
+pub async fn resolve_what3words(
+    State(what3words): State<Arc<What3Words>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    match rewrite(&what3words, req).await {
+        Ok(req) => next.run(req).await,
+        Err(e) => e.into_response(),
+    }
+}
+
+async fn rewrite(what3words: &What3Words, req: Request) -> Result<Request, AppError> {
+    let (mut parts, body) = req.into_parts();
+    if let Some(uri) = rewrite_query(what3words, &parts.uri).await? {
+        parts.uri = uri;
+    }
+
+    let is_json = parts
+        .headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if !is_json {
+        return Ok(Request::from_parts(parts, body));
+    }
+    let body = to_bytes(body, MAX_BODY)
+        .await
+        .map_err(|_| AppError::Validation("Invalid request".into()))?;
+    // Bodies that don't parse are left for the handler to reject
+    let parsed = body
+        .windows(3)
+        .any(|w| w == b"///")
+        .then(|| serde_json::from_slice::<Value>(&body).ok())
+        .flatten();
+    let Some(mut value) = parsed else {
+        return Ok(Request::from_parts(parts, Body::from(body)));
+    };
+
+    for address in addresses(&mut value) {
+        *address = what3words.resolve(address).await?.plus_code;
+    }
+    let body = serde_json::to_vec(&value).map_err(|e| AppError::ParseError(e.to_string()))?;
+    parts.headers.remove(CONTENT_LENGTH);
+
+    Ok(Request::from_parts(parts, Body::from(body)))
+}
+
+// `None` when there is no address in the query
+async fn rewrite_query(what3words: &What3Words, uri: &Uri) -> Result<Option<Uri>, AppError> {
+    let Some(query) = uri.query() else {
+        return Ok(None);
+    };
+    let mut pairs: Vec<(String, String)> = url::form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+    if !pairs
+        .iter()
+        .any(|(_, value)| address_words(value).is_some())
+    {
+        return Ok(None);
+    }
+
+    for (_, value) in pairs.iter_mut() {
+        if address_words(value).is_some() {
+            *value = what3words.resolve(value).await?.plus_code;
+        }
+    }
+    let query = url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(&pairs)
+        .finish();
+    let path_and_query = PathAndQuery::try_from(format!("{}?{}", uri.path(), query))
+        .map_err(|_| AppError::Validation("Invalid request".into()))?;
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path_and_query);
+
+    Uri::from_parts(parts)
+        .map(Some)
+        .map_err(|_| AppError::Validation("Invalid request".into()))
+}
+
+fn addresses(value: &mut Value) -> Vec<&mut String> {
+    match value {
+        Value::String(s) if address_words(s).is_some() => vec![s],
+        Value::Array(items) => items.iter_mut().flat_map(addresses).collect(),
+        Value::Object(fields) => fields.values_mut().flat_map(addresses).collect(),
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,286 @@
+use std::{collections::HashMap, time::Duration};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use moka::future::Cache as MokaCache;
+use rand::{distributions::Alphanumeric, Rng};
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::{config::Config, error::AppError, session::Sessions};
+
+// Users have this long to finish signing in with the provider
+const LOGIN_TTL: Duration = Duration::from_secs(600);
+const MAX_PENDING_LOGINS: u64 = 10_000;
+const STATE_LENGTH: usize = 32;
+const VERIFIER_LENGTH: usize = 64;
+// Ties the state to the browser that started the login
+const STATE_COOKIE: &str = "oauth_state";
+// GitHub rejects API requests without a user agent
+const USER_AGENT: &str = "multi-map-backend";
+
+struct Provider {
+    authorize_url: &'static str,
+    token_url: &'static str,
+    userinfo_url: &'static str,
+    scope: &'static str,
+    /// Field of the userinfo response holding the provider's stable user id
+    id_field: &'static str,
+    client_id: String,
+    client_secret: String,
+}
+
+#[derive(Clone)]
+struct PendingLogin {
+    provider: String,
+    verifier: String,
+    redirect: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+/// A session token issued after a completed login, plus where to send it.
+pub struct Session {
+    pub token: String,
+    pub expires_in: u64,
+    pub redirect: Option<String>,
+}
+
+/// The provider URL to send the user to, and the cookie binding the login to their browser.
+pub struct LoginStart {
+    pub url: String,
+    pub cookie: String,
+}
+
+/// Authorization code flow (with PKCE) against Google and GitHub, ending in a session JWT.
+pub struct OAuth {
+    providers: HashMap<&'static str, Provider>,
+    callback_url: String,
+    app_redirects: Vec<String>,
+    pending: MokaCache<String, PendingLogin>,
+    sessions: Sessions,
+}
+
+impl OAuth {
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let mut providers = HashMap::new();
+        if let (Some(id), Some(secret)) = (
+            &config.oauth_google_client_id,
+            &config.oauth_google_client_secret,
+        ) {
+            providers.insert(
+                "google",
+                Provider {
+                    authorize_url: "https://accounts.google.com/o/oauth2/v2/auth",
+                    token_url: "https://oauth2.googleapis.com/token",
+                    userinfo_url: "https://openidconnect.googleapis.com/v1/userinfo",
+                    scope: "openid email",
+                    id_field: "sub",
+                    client_id: id.clone(),
+                    client_secret: secret.clone(),
+                },
+            );
+        }
+        if let (Some(id), Some(secret)) = (
+            &config.oauth_github_client_id,
+            &config.oauth_github_client_secret,
+        ) {
+            providers.insert(
+                "github",
+                Provider {
+                    authorize_url: "https://github.com/login/oauth/authorize",
+                    token_url: "https://github.com/login/oauth/access_token",
+                    userinfo_url: "https://api.github.com/user",
+                    scope: "read:user",
+                    id_field: "id",
+                    client_id: id.clone(),
+                    client_secret: secret.clone(),
+                },
+            );
+        }
+        if providers.is_empty() {
+            return None;
+        }
+
+        Some(OAuth {
+            providers,
+            callback_url: config.oauth_callback_url.clone()?,
+            app_redirects: config.oauth_app_redirect_urls.clone(),
+            pending: MokaCache::builder()
+                .max_capacity(MAX_PENDING_LOGINS)
+                .time_to_live(LOGIN_TTL)
+                .build(),
+            sessions: Sessions::from_config(config)?,
+        })
+    }
+
+    /// Starts a login and returns the provider URL to send the user to, with the state
+    /// cookie to set on the redirect.
+    pub async fn authorize_url(
+        &self,
+        provider_name: &str,
+        redirect: Option<String>,
+    ) -> Result<LoginStart, AppError> {
+        let provider = self
+            .providers
+            .get(provider_name)
+            .ok_or_else(|| AppError::NotFound("Unknown login provider".into()))?;
+        if let Some(redirect) = &redirect {
+            if !self.app_redirects.contains(redirect) {
+                return Err(AppError::Validation("redirect is not allowed".into()));
+            }
+        }
+
+        let state = random_string(STATE_LENGTH);
+        let verifier = random_string(VERIFIER_LENGTH);
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+
+        let url = Url::parse_with_params(
+            provider.authorize_url,
+            &[
+                ("response_type", "code"),
+                ("client_id", provider.client_id.as_str()),
+                ("redirect_uri", self.callback_url.as_str()),
+                ("scope", provider.scope),
+                ("state", state.as_str()),
+                ("code_challenge", challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )
+        .map_err(|e| AppError::ParseError(e.to_string()))?;
+
+        let cookie = self.state_cookie(&state, LOGIN_TTL.as_secs());
+        self.pending
+            .insert(
+                state,
+                PendingLogin {
+                    provider: provider_name.to_owned(),
+                    verifier,
+                    redirect,
+                },
+            )
+            .await;
+
+        Ok(LoginStart {
+            url: url.into(),
+            cookie,
+        })
+    }
+
+    /// Expires the state cookie once the login is over.
+    pub fn clear_state_cookie(&self) -> String {
+        self.state_cookie("", 0)
+    }
+
+    // Lax is sent on the provider's top-level redirect back, not on cross-site subrequests
+    fn state_cookie(&self, state: &str, max_age: u64) -> String {
+        let callback = Url::parse(&self.callback_url).ok();
+        let path = callback.as_ref().map_or("/", |url| url.path());
+        let secure = callback.as_ref().is_some_and(|url| url.scheme() == "https");
+
+        format!(
+            "{}={}; Max-Age={}; Path={}; HttpOnly; SameSite=Lax{}",
+            STATE_COOKIE,
+            state,
+            max_age,
+            path,
+            if secure { "; Secure" } else { "" }
+        )
+    }
+
+    /// Completes a login: exchanges the code, looks up the user and issues a session.
+    /// `cookie` is the `Cookie` header of the callback, which must carry the state the
+    /// login was started with, so a callback link can't be finished in another browser.
+    pub async fn complete(
+        &self,
+        client: &Client,
+        cookie: Option<&str>,
+        state: &str,
+        code: &str,
+    ) -> Result<Session, AppError> {
+        if cookie_value(cookie, STATE_COOKIE) != Some(state) {
+            tracing::info!("login callback without the matching state cookie");
+            return Err(AppError::Unauthorized);
+        }
+        // Each state is usable once, replayed callbacks are rejected
+        let login = self
+            .pending
+            .remove(state)
+            .await
+            .ok_or(AppError::Unauthorized)?;
+        let provider = &self.providers[login.provider.as_str()];
+
+        let tokens = client
+            .post(provider.token_url)
+            .header("Accept", "application/json")
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", self.callback_url.as_str()),
+                ("client_id", provider.client_id.as_str()),
+                ("client_secret", provider.client_secret.as_str()),
+                ("code_verifier", login.verifier.as_str()),
+            ])
+            .send()
+            .await?
+            .error_for_status()
+            .map_err(|e| {
+                tracing::warn!("{} code exchange failed: {}", login.provider, e);
+                AppError::Unauthorized
+            })?
+            .json::<TokenResponse>()
+            .await?;
+
+        let userinfo = client
+            .get(provider.userinfo_url)
+            .bearer_auth(&tokens.access_token)
+            .header("User-Agent", USER_AGENT)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+        let user_id = match &userinfo[provider.id_field] {
+            Value::String(id) if !id.is_empty() => id.clone(),
+            Value::Number(id) => id.to_string(),
+            _ => {
+                return Err(AppError::ParseError(format!(
+                    "{} userinfo has no {}",
+                    login.provider, provider.id_field
+                )))
+            }
+        };
+
+        let token = self
+            .sessions
+            .issue(&format!("{}:{}", login.provider, user_id))?;
+        tracing::info!("issued session for {} user", login.provider);
+
+        Ok(Session {
+            token,
+            expires_in: self.sessions.ttl().as_secs(),
+            redirect: login.redirect,
+        })
+    }
+}
+
+fn cookie_value<'a>(header: Option<&'a str>, name: &str) -> Option<&'a str> {
+    header?
+        .split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+        .filter(|value| !value.is_empty())
+}
+
+fn random_string(length: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(length)
+        .map(char::from)
+        .collect()
+}
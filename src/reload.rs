@@ -0,0 +1,179 @@
+use std::{net::IpAddr, sync::Arc};
+
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{
+    cache::Cache,
+    config::{self, parse_list, Config, Vars},
+    flags::Flags,
+    middleware::RateLimiter,
+    secrets::{SecretStore, SecretsBackend, GOOGLE_KEYS},
+    telemetry,
+    upstream::KeyPool,
+};
+
+type Reply = oneshot::Sender<Result<(), String>>;
+
+/// Asks the reload task to reload, as SIGHUP does, and waits for the outcome.
+#[derive(Clone)]
+pub struct Reloader(mpsc::Sender<Reply>);
+
+impl Reloader {
+    /// The reloader and the requests `Reloadable::spawn` answers.
+    pub fn channel() -> (Self, mpsc::Receiver<Reply>) {
+        let (sender, receiver) = mpsc::channel(1);
+        (Reloader(sender), receiver)
+    }
+
+    pub async fn reload(&self) -> Result<(), String> {
+        let (reply, outcome) = oneshot::channel();
+        self.0
+            .send(reply)
+            .await
+            .map_err(|_| "reloading is not running".to_owned())?;
+
+        outcome
+            .await
+            .unwrap_or_else(|_| Err("reloading stopped".into()))
+    }
+}
+
+/// The `.env` file, read again on reload. Variables set in the environment itself still
+/// win over it, as at startup.
+pub struct DotEnv {
+    environment: Vars,
+}
+
+impl DotEnv {
+    /// Loads `.env` into the environment when there is one, remembering the environment
+    /// from before.
+    pub fn load() -> Self {
+        let environment = config::environment();
+        // Still before any task is spawned, reloads read the file through `vars` instead
+        dotenvy::dotenv().ok();
+
+        DotEnv { environment }
+    }
+
+    // The environment never changes after startup, so reloads read the file into a map.
+    // Lines taken out of it since are unset again
+    fn vars(&self) -> Vars {
+        let mut vars = self.environment.clone();
+        let Ok(lines) = dotenvy::dotenv_iter() else {
+            return vars;
+        };
+        for line in lines {
+            match line {
+                Ok((name, value)) => {
+                    vars.entry(name).or_insert(value);
+                }
+                Err(e) => tracing::warn!(error = %e, "skipped a line of .env"),
+            }
+        }
+
+        vars
+    }
+}
+
+/// What the settings that change without a restart apply to. Everything else, addresses,
+/// backends and what is enabled, is only read at startup.
+pub struct Reloadable {
+    pub rate_limiter: Option<Arc<RateLimiter<IpAddr>>>,
+    pub cache: Option<Arc<dyn Cache>>,
+    pub stale_cache: Option<Arc<dyn Cache>>,
+    pub google_keys: Arc<KeyPool>,
+    pub secrets: Option<Arc<SecretStore>>,
+    pub sandbox_keys: Option<Arc<KeyPool>>,
+    pub flags: Arc<Flags>,
+}
+
+impl Reloadable {
+    fn apply(&self, vars: &Vars, config: &Config) {
+        telemetry::reload_filter(vars);
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.set_limits(config.rate_limit_burst, config.rate_limit_per_sec);
+        }
+        if let Some(cache) = &self.cache {
+            cache.set_ttl(config.cache_ttl);
+        }
+        if let Some(cache) = &self.stale_cache {
+            cache.set_ttl(config.stale_ttl);
+        }
+        self.flags.set_config_rules(config.feature_flags.clone());
+        // Keys from a secrets manager are refreshed from there, offline there are none
+        if config.secrets_backend == SecretsBackend::Env && !config.offline() {
+            self.google_keys.replace(&config.google_keys);
+        }
+        // A sandbox is set up or removed with a restart
+        if let Some(keys) = &self.sandbox_keys {
+            if !config.google_sandbox_keys.is_empty() && !config.offline() {
+                keys.replace(&config.google_sandbox_keys);
+            }
+        }
+    }
+
+    // Keys from a secrets manager are fetched again, the refresh would only get them later
+    async fn reload(&self, dotenv: &DotEnv) -> Result<(), String> {
+        let vars = dotenv.vars();
+        let config =
+            Config::from_vars(&vars).map_err(|e| format!("invalid configuration: {}", e))?;
+        self.apply(&vars, &config);
+
+        if let Some(store) = &self.secrets {
+            let secrets = store.fetch().await.map_err(|e| e.to_string())?;
+            match secrets.get(GOOGLE_KEYS).map(|v| parse_list(v)) {
+                Some(keys) if !keys.is_empty() => self.google_keys.replace(&keys),
+                _ => return Err(format!("the secret has no {}", GOOGLE_KEYS)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads `.env` and the environment again on SIGHUP or through the `Reloader`, and
+    /// applies the log filter, rate limits, cache TTLs, feature flags and Google keys.
+    /// In-flight requests finish with the settings they started with, an invalid
+    /// configuration is logged and leaves everything as is.
+    pub fn spawn(self, dotenv: DotEnv, mut requests: mpsc::Receiver<Reply>) {
+        let mut hangups = hangups();
+
+        tokio::spawn(async move {
+            loop {
+                let reply = tokio::select! {
+                    Some(()) = hangups.recv() => None,
+                    Some(reply) = requests.recv() => Some(reply),
+                    else => break,
+                };
+                let outcome = self.reload(&dotenv).await;
+                match &outcome {
+                    Ok(()) => tracing::info!("reloaded configuration"),
+                    Err(e) => tracing::error!("not reloaded: {}", e),
+                }
+                if let Some(reply) = reply {
+                    // The caller may have given up waiting
+                    let _ = reply.send(outcome);
+                }
+            }
+        });
+    }
+}
+
+// SIGHUP, forwarded so the reload task can wait on it and on requests alike
+fn hangups() -> mpsc::Receiver<()> {
+    let (sender, receiver) = mpsc::channel(1);
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangup = signal(SignalKind::hangup()).expect("failed to install SIGHUP handler");
+        while hangup.recv().await.is_some() {
+            if sender.send(()).await.is_err() {
+                break;
+            }
+        }
+    });
+    #[cfg(not(unix))]
+    drop(sender);
+
+    receiver
+}
@@ -0,0 +1,203 @@
+use std::{collections::HashMap, fmt, str::FromStr, sync::Arc, time::Duration};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::Client;
+use serde_json::Value;
+
+use crate::{
+    config::{parse_list, Config},
+    upstream::KeyPool,
+};
+
+/// Secret holding the Google keys, comma separated like the environment variable.
+pub const GOOGLE_KEYS: &str = "GOOGLE_PLACES_KEY";
+
+const VAULT_TOKEN_HEADER: &str = "X-Vault-Token";
+const GCP_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+const GCP_SECRETS_URL: &str = "https://secretmanager.googleapis.com/v1";
+
+/// Where provider keys come from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecretsBackend {
+    Env,
+    Vault,
+    Aws,
+    Gcp,
+}
+
+impl FromStr for SecretsBackend {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "env" => Ok(SecretsBackend::Env),
+            "vault" => Ok(SecretsBackend::Vault),
+            "aws" => Ok(SecretsBackend::Aws),
+            "gcp" => Ok(SecretsBackend::Gcp),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SecretsError(String);
+
+impl fmt::Display for SecretsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to load secrets: {}", self.0)
+    }
+}
+
+impl std::error::Error for SecretsError {}
+
+impl From<reqwest::Error> for SecretsError {
+    fn from(e: reqwest::Error) -> Self {
+        SecretsError(e.to_string())
+    }
+}
+
+enum Backend {
+    Vault { addr: String, token: String },
+    Aws(aws_sdk_secretsmanager::Client),
+    Gcp,
+}
+
+/// A secret in an external manager holding a JSON object of named values, e.g.
+/// `{"GOOGLE_PLACES_KEY": "key-a,key-b"}`.
+pub struct SecretStore {
+    backend: Backend,
+    name: String,
+    client: Client,
+}
+
+impl SecretStore {
+    /// Returns `None` when secrets come from the environment.
+    pub async fn from_config(config: &Config, client: Client) -> Option<Self> {
+        let backend = match config.secrets_backend {
+            SecretsBackend::Env => return None,
+            SecretsBackend::Vault => Backend::Vault {
+                addr: config.vault_addr.clone()?.trim_end_matches('/').to_owned(),
+                token: config.vault_token.clone()?,
+            },
+            SecretsBackend::Aws => {
+                let aws = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+                Backend::Aws(aws_sdk_secretsmanager::Client::new(&aws))
+            }
+            SecretsBackend::Gcp => Backend::Gcp,
+        };
+
+        Some(SecretStore {
+            backend,
+            name: config.secrets_name.clone()?,
+            client,
+        })
+    }
+
+    pub async fn fetch(&self) -> Result<HashMap<String, String>, SecretsError> {
+        let document = match &self.backend {
+            Backend::Vault { addr, token } => self.fetch_vault(addr, token).await?,
+            Backend::Aws(client) => {
+                let output = client
+                    .get_secret_value()
+                    .secret_id(&self.name)
+                    .send()
+                    .await
+                    .map_err(|e| SecretsError(e.to_string()))?;
+                let text = output
+                    .secret_string()
+                    .ok_or_else(|| SecretsError("secret has no string value".into()))?;
+                parse_json(text.as_bytes())?
+            }
+            Backend::Gcp => self.fetch_gcp().await?,
+        };
+
+        let Value::Object(entries) = document else {
+            return Err(SecretsError("secret is not a JSON object".into()));
+        };
+        Ok(entries
+            .into_iter()
+            .filter_map(|(k, v)| v.as_str().map(|v| (k, v.to_owned())))
+            .collect())
+    }
+
+    async fn fetch_vault(&self, addr: &str, token: &str) -> Result<Value, SecretsError> {
+        let body = self
+            .client
+            .get(format!("{}/v1/{}", addr, self.name))
+            .header(VAULT_TOKEN_HEADER, token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+
+        // KV version 2 nests the values one level deeper than version 1
+        let data = &body["data"];
+        Ok(match &data["data"] {
+            Value::Object(_) => data["data"].clone(),
+            _ => data.clone(),
+        })
+    }
+
+    async fn fetch_gcp(&self) -> Result<Value, SecretsError> {
+        // Credentials of the attached service account, from the metadata server
+        let token = self
+            .client
+            .get(GCP_TOKEN_URL)
+            .header("Metadata-Flavor", "Google")
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+        let access_token = token["access_token"]
+            .as_str()
+            .ok_or_else(|| SecretsError("metadata server returned no token".into()))?;
+
+        let secret = self
+            .client
+            .get(format!(
+                "{}/{}/versions/latest:access",
+                GCP_SECRETS_URL, self.name
+            ))
+            .bearer_auth(access_token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<Value>()
+            .await?;
+        let payload = secret["payload"]["data"]
+            .as_str()
+            .ok_or_else(|| SecretsError("secret has no payload".into()))?;
+        let decoded = STANDARD
+            .decode(payload)
+            .map_err(|e| SecretsError(e.to_string()))?;
+
+        parse_json(&decoded)
+    }
+
+    /// Reloads the secret every `every` and hands new Google keys to the pool. Failed
+    /// refreshes keep the keys already loaded.
+    pub fn spawn_refresh(self: Arc<Self>, every: Duration, keys: Arc<KeyPool>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            // The first tick completes immediately and startup already loaded the secret
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match self.fetch().await {
+                    Ok(secrets) => match secrets.get(GOOGLE_KEYS).map(|v| parse_list(v)) {
+                        Some(google_keys) if !google_keys.is_empty() => keys.replace(&google_keys),
+                        _ => tracing::warn!("refreshed secret has no {}", GOOGLE_KEYS),
+                    },
+                    Err(e) => tracing::warn!("{}", e),
+                }
+            }
+        });
+    }
+}
+
+fn parse_json(bytes: &[u8]) -> Result<Value, SecretsError> {
+    serde_json::from_slice(bytes).map_err(|e| SecretsError(e.to_string()))
+}
@@ -0,0 +1,120 @@
+use sqlx::PgPool;
+
+use crate::{
+    api::users::hash_password,
+    db::{
+        saved_places::{self, NewSavedPlace},
+        trips::{self, Coordinate, NewTrip},
+        users,
+    },
+    error::AppError,
+    geo,
+    identity::Identity,
+};
+
+// Everyone signs in with this, the data is for local development and demos only
+pub const PASSWORD: &str = "demo-password";
+const USERS: [(&str, &str); 2] = [
+    ("demo@example.com", "Demo User"),
+    ("alex@example.com", "Alex Example"),
+];
+// Name, made up place id and location, around Lisbon
+const PLACES: [(&str, &str, f64, f64); 5] = [
+    (
+        "Praça do Comércio",
+        "ChIJB8PS_ntzGQ0RWBgVc4ls5Aw",
+        38.7075,
+        -9.1364,
+    ),
+    (
+        "Castelo de São Jorge",
+        "ChIJ8cqxMnp0GQ0Rhz5F3Zxx16w",
+        38.7139,
+        -9.1335,
+    ),
+    (
+        "Torre de Belém",
+        "ChIJs1yMZbnLHg0RNpwvBNLhtL8",
+        38.6916,
+        -9.2160,
+    ),
+    (
+        "LX Factory",
+        "ChIJt0RpjmvKHg0R7tsMAEzbaZc",
+        38.7034,
+        -9.1784,
+    ),
+    (
+        "Oceanário de Lisboa",
+        "ChIJYfbJdqpyGQ0RS6-nl2Nsytc",
+        38.7636,
+        -9.0937,
+    ),
+];
+// Indices into PLACES, driven straight between them
+const TRIPS: [(&str, &[usize]); 2] = [
+    ("Riverside tour", &[0, 3, 2]),
+    ("Castle to the aquarium", &[1, 4]),
+];
+// A city average for the seeded durations
+const DRIVE_METERS_PER_SECOND: f64 = 30.0 / 3.6;
+
+/// Adds sample users, each with the same saved places and trips. Does nothing and returns
+/// false when the first sample user exists already, so seeding twice is harmless.
+pub async fn run(pool: &PgPool) -> Result<bool, AppError> {
+    let password_hash = hash_password(PASSWORD.into()).await?;
+
+    for (index, (email, display_name)) in USERS.into_iter().enumerate() {
+        let Some(user) = users::insert(pool, email, &password_hash, Some(display_name)).await?
+        else {
+            if index == 0 {
+                return Ok(false);
+            }
+            continue;
+        };
+        let owner = Identity::from_user_id(&user.id.to_string()).0;
+
+        for (name, place_id, latitude, longitude) in PLACES {
+            saved_places::insert(
+                pool,
+                NewSavedPlace {
+                    owner: owner.clone(),
+                    place_id: place_id.into(),
+                    name: name.into(),
+                    latitude,
+                    longitude,
+                    notes: None,
+                },
+            )
+            .await?;
+        }
+
+        for (name, stops) in TRIPS {
+            let path: Vec<Coordinate> = stops
+                .iter()
+                .map(|&i| Coordinate {
+                    latitude: PLACES[i].2,
+                    longitude: PLACES[i].3,
+                })
+                .collect();
+            let distance = geo::path_length(&path);
+            trips::insert(
+                pool,
+                NewTrip {
+                    owner: owner.clone(),
+                    name: name.into(),
+                    origin: path[0],
+                    destination: path[path.len() - 1],
+                    waypoints: path[1..path.len() - 1].to_vec(),
+                    travel_mode: "DRIVE".into(),
+                    encoded_polyline: geo::polyline::encode(&path),
+                    distance_meters: Some(distance.round()),
+                    duration: Some(format!("{}s", (distance / DRIVE_METERS_PER_SECOND).round())),
+                },
+            )
+            .await?;
+        }
+    }
+
+    Ok(true)
+}
@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use jsonwebtoken::{encode, EncodingKey, Header};
+use serde_json::json;
+
+use crate::{config::Config, error::AppError};
+
+/// Issues session JWTs signed with `JWT_SECRET`, so the regular bearer authentication
+/// accepts them.
+pub struct Sessions {
+    signing_key: EncodingKey,
+    issuer: Option<String>,
+    audience: Option<String>,
+    user_claim: String,
+    ttl: Duration,
+}
+
+impl Sessions {
+    pub fn from_config(config: &Config) -> Option<Self> {
+        Some(Sessions {
+            signing_key: EncodingKey::from_secret(config.jwt_secret.as_ref()?.as_bytes()),
+            issuer: config.jwt_issuer.clone(),
+            audience: config.jwt_audience.clone(),
+            user_claim: config.jwt_user_claim.clone(),
+            ttl: config.session_ttl,
+        })
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn issue(&self, user_id: &str) -> Result<String, AppError> {
+        let now = chrono::Utc::now().timestamp();
+        let mut claims = json!({
+            "sub": user_id,
+            "iat": now,
+            "exp": now + self.ttl.as_secs() as i64,
+        });
+        claims[&self.user_claim] = user_id.into();
+        if let Some(issuer) = &self.issuer {
+            claims["iss"] = issuer.as_str().into();
+        }
+        if let Some(audience) = &self.audience {
+            claims["aud"] = audience.as_str().into();
+        }
+
+        encode(&Header::default(), &claims, &self.signing_key)
+            .map_err(|e| AppError::ParseError(e.to_string()))
+    }
+}
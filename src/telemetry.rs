@@ -0,0 +1,208 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+};
+
+use axum::{extract::Request, http::HeaderMap};
+use opentelemetry::{
+    global,
+    propagation::{Extractor, Injector},
+    trace::TraceError,
+    KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{propagation::TraceContextPropagator, runtime, trace, Resource};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{
+    fmt::{self as subscriber_fmt, writer::BoxMakeWriter},
+    layer::SubscriberExt,
+    reload,
+    util::SubscriberInitExt,
+    EnvFilter, Registry,
+};
+
+use crate::{
+    config::{Config, Vars},
+    middleware::{current_request_id, REQUEST_ID_HEADER},
+};
+
+static REDACT_COORDINATES: AtomicBool = AtomicBool::new(false);
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A location as it should appear in logs, hidden when `LOG_REDACT_COORDINATES` is set.
+pub struct Coordinates<T>(pub T, pub T);
+
+impl<T: Display> Display for Coordinates<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if REDACT_COORDINATES.load(Ordering::Relaxed) {
+            f.write_str("[redacted]")
+        } else {
+            write!(f, "{},{}", self.0, self.1)
+        }
+    }
+}
+
+/// Sets up logging, plus span export over OTLP when an endpoint is configured. Logs go to
+/// stdout, or to stderr when stdout is for something else.
+pub fn init(config: &Config, stderr: bool) {
+    REDACT_COORDINATES.store(config.log_redact_coordinates, Ordering::Relaxed);
+
+    let otel = config.otlp_endpoint.as_deref().and_then(|endpoint| {
+        match tracer(endpoint, &config.otel_service_name) {
+            Ok(tracer) => Some(tracing_opentelemetry::layer().with_tracer(tracer)),
+            Err(e) => {
+                eprintln!("failed to set up OTLP export to {}: {}", endpoint, e);
+                None
+            }
+        }
+    });
+
+    let (filter, handle) = reload::Layer::new(filter(std::env::var(RUST_LOG).ok().as_deref()));
+    FILTER.set(handle).ok();
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            (config.log_format == LogFormat::Text)
+                .then(|| subscriber_fmt::layer().with_writer(writer(stderr))),
+        )
+        .with(
+            (config.log_format == LogFormat::Json)
+                .then(|| subscriber_fmt::layer().json().with_writer(writer(stderr))),
+        )
+        .with(otel)
+        .init();
+}
+
+const RUST_LOG: &str = EnvFilter::DEFAULT_ENV;
+
+// RUST_LOG, or the default levels
+fn filter(directives: Option<&str>) -> EnvFilter {
+    directives
+        .and_then(|directives| EnvFilter::try_new(directives).ok())
+        .unwrap_or_else(|| "multi_map_backend=debug,tower_http=debug,axum::rejection=trace".into())
+}
+
+/// Swaps in the filter of a RUST_LOG read again, for log levels changed while running.
+pub fn reload_filter(vars: &Vars) {
+    if let Some(handle) = FILTER.get() {
+        if let Err(e) = handle.reload(filter(vars.get(RUST_LOG).map(String::as_str))) {
+            tracing::warn!(error = %e, "failed to reload the log filter");
+        }
+    }
+}
+
+fn writer(stderr: bool) -> BoxMakeWriter {
+    if stderr {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    }
+}
+
+fn tracer(endpoint: &str, service_name: &str) -> Result<trace::Tracer, TraceError> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(
+            trace::config().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                service_name.to_owned(),
+            )])),
+        )
+        .install_batch(runtime::Tokio)
+}
+
+/// Flushes spans still waiting in the batch exporter.
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+/// Request span continuing the caller's trace when it sent a `traceparent`.
+pub fn request_span(req: &Request) -> Span {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let span = tracing::info_span!(
+        "request",
+        method = %req.method(),
+        uri = %req.uri(),
+        version = ?req.version(),
+        request_id,
+    );
+    let parent = global::get_text_map_propagator(|p| p.extract(&HeaderExtractor(req.headers())));
+    span.set_parent(parent);
+
+    span
+}
+
+/// Trace context and request id headers for an upstream request made within the current
+/// span, so provider-side logs can be matched to ours.
+pub fn upstream_headers() -> reqwest::header::HeaderMap {
+    let mut headers = reqwest::header::HeaderMap::new();
+    let context = Span::current().context();
+    global::get_text_map_propagator(|p| {
+        p.inject_context(&context, &mut HeaderInjector(&mut headers))
+    });
+    if let Some(id) = current_request_id() {
+        HeaderInjector(&mut headers).set(REQUEST_ID_HEADER.as_str(), id);
+    }
+
+    headers
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut reqwest::header::HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            reqwest::header::HeaderName::from_bytes(key.as_bytes()),
+            reqwest::header::HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use axum::Router;
+use axum_server::{tls_rustls::RustlsConfig, Handle};
+
+/// Serves HTTPS with the PEM certificate and key at the given paths. Sending SIGHUP reloads
+/// both files, so renewed certificates are picked up without dropping connections.
+pub async fn serve(addr: SocketAddr, router: Router, cert: PathBuf, key: PathBuf) {
+    let tls = match RustlsConfig::from_pem_file(&cert, &key).await {
+        Ok(tls) => tls,
+        Err(e) => {
+            tracing::error!("failed to load TLS certificate: {}", e);
+            std::process::exit(1);
+        }
+    };
+    #[cfg(unix)]
+    spawn_reload(tls.clone(), cert, key);
+
+    let handle = Handle::new();
+    let shutdown = handle.clone();
+    tokio::spawn(async move {
+        super::shutdown_signal().await;
+        shutdown.graceful_shutdown(None);
+    });
+
+    tracing::debug!("listening on {} with TLS", addr);
+    axum_server::bind_rustls(addr, tls)
+        .handle(handle)
+        .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+        .await
+        .unwrap();
+}
+
+#[cfg(unix)]
+fn spawn_reload(tls: RustlsConfig, cert: PathBuf, key: PathBuf) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    tokio::spawn(async move {
+        let mut hangup = signal(SignalKind::hangup()).expect("failed to install SIGHUP handler");
+        while hangup.recv().await.is_some() {
+            // A bad file keeps the previous certificate in place
+            match tls.reload_from_pem_file(&cert, &key).await {
+                Ok(()) => tracing::info!("reloaded TLS certificate"),
+                Err(e) => tracing::error!("failed to reload TLS certificate: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,101 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug)]
+enum BreakerState {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    HalfOpen { probe_started: Instant },
+}
+
+/// Opens after `failure_threshold` consecutive failures and rejects calls until
+/// `open_duration` has passed, then lets a single probe through to decide whether to close.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    name: &'static str,
+    failure_threshold: u32,
+    open_duration: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &'static str, failure_threshold: u32, open_duration: Duration) -> Self {
+        CircuitBreaker {
+            name,
+            failure_threshold,
+            open_duration,
+            state: Mutex::new(BreakerState::Closed { failures: 0 }),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn state_name(&self) -> &'static str {
+        match *self.state.lock().unwrap() {
+            BreakerState::Closed { .. } => "closed",
+            BreakerState::Open { .. } => "open",
+            BreakerState::HalfOpen { .. } => "half_open",
+        }
+    }
+
+    pub fn try_acquire(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+
+        match *state {
+            BreakerState::Closed { .. } => true,
+            BreakerState::Open { until } if now >= until => {
+                tracing::debug!("circuit {} half-open, sending probe", self.name);
+                *state = BreakerState::HalfOpen { probe_started: now };
+                true
+            }
+            BreakerState::Open { .. } => false,
+            // A probe that never reported back (e.g. its request was cancelled) must not
+            // keep the circuit stuck, so allow another one after the open duration
+            BreakerState::HalfOpen { probe_started }
+                if now.duration_since(probe_started) >= self.open_duration =>
+            {
+                *state = BreakerState::HalfOpen { probe_started: now };
+                true
+            }
+            BreakerState::HalfOpen { .. } => false,
+        }
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        if let BreakerState::HalfOpen { .. } = *state {
+            tracing::info!("circuit {} closed", self.name);
+        }
+        *state = BreakerState::Closed { failures: 0 };
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+
+        let failures = match *state {
+            BreakerState::Closed { failures } => failures + 1,
+            BreakerState::HalfOpen { .. } => self.failure_threshold,
+            BreakerState::Open { .. } => return,
+        };
+
+        if failures >= self.failure_threshold {
+            tracing::warn!(
+                "circuit {} opened for {:?} after {} consecutive failures",
+                self.name,
+                self.open_duration,
+                failures
+            );
+            *state = BreakerState::Open {
+                until: Instant::now() + self.open_duration,
+            };
+        } else {
+            *state = BreakerState::Closed { failures };
+        }
+    }
+}
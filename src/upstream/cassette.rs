@@ -0,0 +1,317 @@
+use std::{
+    io,
+    net::{Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+};
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Json, Router,
+};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::net::TcpListener;
+
+// Forwarded to the provider. The key is left out of recordings and of the file name
+const API_KEY_HEADER: &str = "X-Goog-Api-Key";
+const FIELD_MASK_HEADER: &str = "X-Goog-FieldMask";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CassetteMode {
+    Off,
+    /// Calls go through to the provider and every answer is written to disk
+    Record,
+    /// Answers come from disk, the provider is never called
+    Replay,
+}
+
+impl FromStr for CassetteMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(CassetteMode::Off),
+            "record" => Ok(CassetteMode::Record),
+            "replay" => Ok(CassetteMode::Replay),
+            _ => Err(()),
+        }
+    }
+}
+
+/// JSON bodies are kept as JSON so recordings can be read and edited, anything else as text.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Payload {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body_text: Option<String>,
+}
+
+impl Payload {
+    fn of(bytes: &[u8]) -> Self {
+        match serde_json::from_slice(bytes) {
+            Ok(body) => Payload {
+                body: Some(body),
+                body_text: None,
+            },
+            Err(_) if bytes.is_empty() => Payload::default(),
+            Err(_) => Payload {
+                body: None,
+                body_text: Some(String::from_utf8_lossy(bytes).into_owned()),
+            },
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        match (&self.body, &self.body_text) {
+            (Some(body), _) => serde_json::to_vec(body).unwrap_or_default(),
+            (None, Some(text)) => text.clone().into_bytes(),
+            (None, None) => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RecordedRequest {
+    method: String,
+    url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    field_mask: Option<String>,
+    #[serde(flatten)]
+    payload: Payload,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RecordedResponse {
+    status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    #[serde(flatten)]
+    payload: Payload,
+}
+
+/// One upstream call, a file of the cassette directory.
+#[derive(Debug, Deserialize, Serialize)]
+struct Recording {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+struct Cassette {
+    mode: CassetteMode,
+    dir: PathBuf,
+    client: Client,
+    // Local path and the URL it stands for. Paths ending in / take the rest of the path as is
+    upstreams: Vec<(String, String)>,
+}
+
+impl Cassette {
+    fn upstream_url(&self, uri: &Uri) -> Option<(String, String)> {
+        let path = uri.path();
+        self.upstreams.iter().find_map(|(local, upstream)| {
+            let url = match path.strip_prefix(local.as_str()) {
+                Some("") => upstream.clone(),
+                Some(rest) if local.ends_with('/') => format!("{}{}", upstream, rest),
+                _ => return None,
+            };
+            let url = match uri.query() {
+                Some(query) => format!("{}?{}", url, query),
+                None => url,
+            };
+            Some((local.trim_matches('/').replace('/', "-"), url))
+        })
+    }
+}
+
+/// Serves the upstream endpoints on a loopback port, recording what the provider answers
+/// or replaying earlier recordings. A recording is found again by the method, URL, field
+/// mask and body of the request, so replays need requests built exactly as when recording:
+/// searches and routes without a departure time replay, "now" departures don't.
+pub async fn serve(
+    mode: CassetteMode,
+    dir: PathBuf,
+    client: Client,
+    upstreams: Vec<(String, String)>,
+) -> io::Result<SocketAddr> {
+    if mode == CassetteMode::Record {
+        tokio::fs::create_dir_all(&dir).await?;
+    }
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+    let addr = listener.local_addr()?;
+    let cassette = Arc::new(Cassette {
+        mode,
+        dir,
+        client,
+        upstreams,
+    });
+    let app = Router::new().fallback(proxy).with_state(cassette);
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            tracing::error!(error = %e, "cassette proxy stopped");
+        }
+    });
+
+    Ok(addr)
+}
+
+// Google's error body, so the failure is translated like any other
+fn provider_error(status: StatusCode, code: &str, message: String) -> Response {
+    (
+        status,
+        Json(json!({
+            "error": { "code": status.as_u16(), "message": message, "status": code }
+        })),
+    )
+        .into_response()
+}
+
+fn file_name(
+    endpoint: &str,
+    method: &Method,
+    url: &str,
+    field_mask: Option<&str>,
+    body: &[u8],
+) -> String {
+    let mut hasher = Sha256::new();
+    for part in [method.as_str(), url, field_mask.unwrap_or_default()] {
+        hasher.update(part.as_bytes());
+        hasher.update(b"\n");
+    }
+    hasher.update(body);
+    format!("{}-{}.json", endpoint, hex::encode(&hasher.finalize()[..8]))
+}
+
+async fn proxy(
+    State(cassette): State<Arc<Cassette>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Some((endpoint, url)) = cassette.upstream_url(&uri) else {
+        return provider_error(
+            StatusCode::NOT_FOUND,
+            "NOT_FOUND",
+            format!("No upstream for {}", uri.path()),
+        );
+    };
+    let field_mask = headers.get(FIELD_MASK_HEADER).and_then(|v| v.to_str().ok());
+    let path = cassette
+        .dir
+        .join(file_name(&endpoint, &method, &url, field_mask, &body));
+
+    match cassette.mode {
+        CassetteMode::Replay => replay(path).await,
+        _ => record(&cassette, path, method, url, &headers, body).await,
+    }
+}
+
+async fn replay(path: PathBuf) -> Response {
+    let recording = match tokio::fs::read(&path).await {
+        Ok(bytes) => serde_json::from_slice::<Recording>(&bytes).map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    let recording = match recording {
+        Ok(recording) => recording,
+        Err(e) => {
+            tracing::warn!(error = %e, file = %path.display(), "no recording to replay");
+            return provider_error(
+                StatusCode::NOT_IMPLEMENTED,
+                "UNIMPLEMENTED",
+                format!("No recording at {}", path.display()),
+            );
+        }
+    };
+
+    let response = recording.response;
+    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::OK);
+    let content_type = response
+        .content_type
+        .unwrap_or_else(|| "application/json".into());
+    (
+        status,
+        [(header::CONTENT_TYPE, content_type)],
+        response.payload.bytes(),
+    )
+        .into_response()
+}
+
+async fn record(
+    cassette: &Cassette,
+    path: PathBuf,
+    method: Method,
+    url: String,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Response {
+    let mut request = cassette
+        .client
+        .request(method.as_str().parse().unwrap_or_default(), &url)
+        .body(body.clone());
+    for name in [
+        API_KEY_HEADER,
+        FIELD_MASK_HEADER,
+        header::CONTENT_TYPE.as_str(),
+    ] {
+        if let Some(value) = headers.get(name).and_then(|v| v.to_str().ok()) {
+            request = request.header(name, value);
+        }
+    }
+    let (status, content_type, answer) = match request.send().await {
+        Ok(response) => {
+            let status = response.status().as_u16();
+            let content_type = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_owned);
+            match response.bytes().await {
+                Ok(answer) => (status, content_type, answer),
+                Err(e) => {
+                    return provider_error(StatusCode::BAD_GATEWAY, "UNAVAILABLE", e.to_string())
+                }
+            }
+        }
+        Err(e) => return provider_error(StatusCode::BAD_GATEWAY, "UNAVAILABLE", e.to_string()),
+    };
+
+    let recording = Recording {
+        request: RecordedRequest {
+            method: method.to_string(),
+            url,
+            field_mask: headers
+                .get(FIELD_MASK_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_owned),
+            payload: Payload::of(&body),
+        },
+        response: RecordedResponse {
+            status,
+            content_type: content_type.clone(),
+            payload: Payload::of(&answer),
+        },
+    };
+    match serde_json::to_vec_pretty(&recording) {
+        Ok(json) => match tokio::fs::write(&path, json).await {
+            Ok(()) => tracing::debug!(file = %path.display(), status, "recorded upstream call"),
+            Err(e) => {
+                tracing::warn!(error = %e, file = %path.display(), "failed to write recording")
+            }
+        },
+        Err(e) => tracing::warn!(error = %e, "failed to serialize recording"),
+    }
+
+    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY);
+    let content_type = content_type.unwrap_or_else(|| "application/json".into());
+    (status, [(header::CONTENT_TYPE, content_type)], answer).into_response()
+}
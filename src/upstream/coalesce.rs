@@ -0,0 +1,86 @@
+use std::{
+    any::Any,
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+};
+
+use tokio::sync::watch;
+
+use crate::error::AppError;
+
+type Shared = Result<Arc<dyn Any + Send + Sync>, AppError>;
+type Flight = watch::Receiver<Option<Shared>>;
+
+/// Lets identical upstream calls in flight at the same time share one call. The first
+/// caller of a key makes the call, later ones wait for its outcome.
+#[derive(Default)]
+pub struct Coalescer {
+    flights: Mutex<HashMap<String, Flight>>,
+}
+
+// Ends the flight when the leading call completes or is dropped
+struct Landing<'a> {
+    coalescer: &'a Coalescer,
+    key: &'a str,
+}
+
+impl Drop for Landing<'_> {
+    fn drop(&mut self) {
+        self.coalescer.flights.lock().unwrap().remove(self.key);
+    }
+}
+
+impl Coalescer {
+    /// Runs `call` unless a call for `key` is already in flight, in which case its outcome
+    /// is shared instead. Waiters of a leading call that's dropped halfway make their own.
+    pub async fn run<T, F>(&self, key: &str, call: F) -> Result<T, AppError>
+    where
+        T: Clone + Send + Sync + 'static,
+        F: Future<Output = Result<T, AppError>>,
+    {
+        let leading = {
+            let mut flights = self.flights.lock().unwrap();
+            match flights.get(key) {
+                Some(flight) => Err(flight.clone()),
+                None => {
+                    let (sender, flight) = watch::channel(None);
+                    flights.insert(key.to_owned(), flight);
+                    Ok(sender)
+                }
+            }
+        };
+
+        let sender = match leading {
+            Ok(sender) => sender,
+            Err(mut flight) => {
+                let shared = match flight.wait_for(Option::is_some).await {
+                    Ok(outcome) => outcome.clone(),
+                    Err(_) => None,
+                };
+                return match shared {
+                    Some(Ok(value)) => match value.downcast_ref::<T>() {
+                        Some(value) => Ok(value.clone()),
+                        None => call.await,
+                    },
+                    Some(Err(e)) => Err(e),
+                    None => call.await,
+                };
+            }
+        };
+
+        let landing = Landing {
+            coalescer: self,
+            key,
+        };
+        let outcome = call.await;
+        drop(landing);
+        let shared = outcome
+            .clone()
+            .map(|value| Arc::new(value) as Arc<dyn Any + Send + Sync>);
+        // Nobody waiting is fine
+        let _ = sender.send(Some(shared));
+
+        outcome
+    }
+}
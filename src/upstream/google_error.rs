@@ -0,0 +1,83 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+use crate::error::AppError;
+
+// When Google asks for a pause without saying how long
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default, Deserialize)]
+struct Envelope {
+    #[serde(default)]
+    error: GoogleError,
+}
+
+/// Error body of Google's APIs, following google.rpc.Status.
+#[derive(Debug, Default, Deserialize)]
+struct GoogleError {
+    #[serde(default)]
+    message: String,
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    details: Vec<Detail>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Detail {
+    // Set on google.rpc.RetryInfo, e.g. "30s"
+    retry_delay: Option<String>,
+}
+
+impl GoogleError {
+    fn retry_after(&self) -> Duration {
+        self.details
+            .iter()
+            .filter_map(|d| {
+                d.retry_delay
+                    .as_deref()?
+                    .strip_suffix('s')?
+                    .parse::<f64>()
+                    .ok()
+            })
+            .find(|secs| secs.is_finite() && *secs >= 0.0)
+            .map_or(DEFAULT_RETRY_AFTER, Duration::from_secs_f64)
+    }
+}
+
+/// Turns an unsuccessful Google answer into the error the client gets: its own mistakes
+/// are 400s and 404s with Google's explanation, quota exhaustion a 429, anything else a
+/// provider failure.
+pub fn translate(provider: &str, status: StatusCode, body: &[u8]) -> AppError {
+    let error = serde_json::from_slice::<Envelope>(body)
+        .unwrap_or_default()
+        .error;
+    tracing::warn!(
+        %status,
+        provider,
+        google_status = %error.status,
+        message = %error.message,
+        "upstream request failed"
+    );
+
+    match (error.status.as_str(), status) {
+        ("INVALID_ARGUMENT" | "OUT_OF_RANGE" | "FAILED_PRECONDITION", _)
+        | ("", StatusCode::BAD_REQUEST) => AppError::Validation(if error.message.is_empty() {
+            "The provider rejected the request".into()
+        } else {
+            format!("The provider rejected the request: {}", error.message)
+        }),
+        ("NOT_FOUND", _) | ("", StatusCode::NOT_FOUND) => {
+            AppError::NotFound("Not found at the provider".into())
+        }
+        ("RESOURCE_EXHAUSTED", _) | ("", StatusCode::TOO_MANY_REQUESTS) => AppError::RateLimited {
+            retry_after: error.retry_after(),
+        },
+        ("DEADLINE_EXCEEDED", _) | ("", StatusCode::GATEWAY_TIMEOUT) => AppError::Timeout,
+        // Rejected keys and server errors aren't anything the client can fix
+        _ => AppError::UpstreamError(format!("{} returned {} {}", provider, status, error.status)),
+    }
+}
@@ -0,0 +1,182 @@
+use std::{
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+use reqwest::StatusCode;
+use serde_json::Value;
+
+const INVALID_KEY_REASON: &str = "API_KEY_INVALID";
+
+/// How requests are spread over the pool.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    /// Every request takes the next key in turn
+    RoundRobin,
+    /// Requests use the first usable key, later keys are only spares
+    Failover,
+}
+
+impl FromStr for Rotation {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "round_robin" => Ok(Rotation::RoundRobin),
+            "failover" => Ok(Rotation::Failover),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PooledKey {
+    value: String,
+    retired: AtomicBool,
+    cooling_until: Mutex<Option<Instant>>,
+}
+
+impl PooledKey {
+    fn new(value: String) -> Self {
+        PooledKey {
+            value,
+            retired: AtomicBool::new(false),
+            cooling_until: Mutex::new(None),
+        }
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    fn is_usable(&self, now: Instant) -> bool {
+        if self.retired.load(Ordering::Relaxed) {
+            return false;
+        }
+        match *self.cooling_until.lock().unwrap() {
+            Some(until) => until <= now,
+            None => true,
+        }
+    }
+}
+
+/// Provider API keys shared by all requests. Keys that hit their quota sit out a cooldown,
+/// keys the provider rejects as invalid are retired until they are replaced.
+#[derive(Debug)]
+pub struct KeyPool {
+    keys: RwLock<Vec<Arc<PooledKey>>>,
+    rotation: Rotation,
+    cooldown: Duration,
+    next: AtomicUsize,
+}
+
+impl KeyPool {
+    pub fn new(keys: &[String], rotation: Rotation, cooldown: Duration) -> Self {
+        KeyPool {
+            keys: RwLock::new(
+                keys.iter()
+                    .cloned()
+                    .map(PooledKey::new)
+                    .map(Arc::new)
+                    .collect(),
+            ),
+            rotation,
+            cooldown,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Swaps in a new set of keys. Keys present before and after keep their standing, so
+    /// a refresh doesn't bring back a key already known to be invalid.
+    pub fn replace(&self, keys: &[String]) {
+        let mut current = self.keys.write().unwrap();
+        let replaced: Vec<Arc<PooledKey>> = keys
+            .iter()
+            .map(|value| {
+                current
+                    .iter()
+                    .find(|k| &k.value == value)
+                    .cloned()
+                    .unwrap_or_else(|| Arc::new(PooledKey::new(value.clone())))
+            })
+            .collect();
+
+        if replaced.len() != current.len()
+            || replaced
+                .iter()
+                .zip(current.iter())
+                .any(|(a, b)| !Arc::ptr_eq(a, b))
+        {
+            tracing::info!("Google key pool now holds {} keys", replaced.len());
+        }
+        *current = replaced;
+    }
+
+    /// The key for the next request, or `None` when no key is usable right now.
+    pub fn pick(&self) -> Option<Arc<PooledKey>> {
+        let keys = self.keys.read().unwrap();
+        let count = keys.len();
+        let start = match self.rotation {
+            Rotation::RoundRobin => self.next.fetch_add(1, Ordering::Relaxed),
+            Rotation::Failover => 0,
+        };
+        let now = Instant::now();
+
+        (0..count)
+            .map(|offset| &keys[(start + offset) % count])
+            .find(|k| k.is_usable(now))
+            .cloned()
+    }
+
+    /// Updates the key's standing from the provider's answer. Returns whether the request
+    /// should be repeated with another key.
+    pub fn record(&self, key: &PooledKey, status: StatusCode, body: &[u8]) -> bool {
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            *key.cooling_until.lock().unwrap() = Some(Instant::now() + self.cooldown);
+            tracing::warn!(
+                "Google key {} is rate limited, resting it for {:?}",
+                masked(&key.value),
+                self.cooldown
+            );
+            return true;
+        }
+        if matches!(status, StatusCode::BAD_REQUEST | StatusCode::FORBIDDEN)
+            && error_reasons(body).any(|r| r == INVALID_KEY_REASON)
+        {
+            key.retired.store(true, Ordering::Relaxed);
+            tracing::error!("Google key {} is invalid, retiring it", masked(&key.value));
+            return true;
+        }
+
+        false
+    }
+}
+
+// Google reports the cause in error.details[].reason
+fn error_reasons(body: &[u8]) -> impl Iterator<Item = String> {
+    let details = serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|v| v["error"]["details"].as_array().cloned())
+        .unwrap_or_default();
+
+    details
+        .into_iter()
+        .filter_map(|d| d["reason"].as_str().map(str::to_owned))
+}
+
+// Enough of the key to tell pooled keys apart in the logs
+fn masked(key: &str) -> String {
+    let tail: String = key
+        .chars()
+        .rev()
+        .take(4)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    format!("...{}", tail)
+}
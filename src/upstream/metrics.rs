@@ -0,0 +1,212 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use super::CircuitBreaker;
+
+// Quantiles are computed over this many of the latest calls
+const LATENCY_WINDOW: usize = 1024;
+
+/// How an upstream call ended, as reported in the status breakdown.
+pub enum Outcome {
+    Status(u16),
+    /// No response, e.g. a connect error or timeout
+    Error,
+    /// Not sent because the circuit was open
+    Rejected,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    outcomes: BTreeMap<String, u64>,
+    retries: u64,
+    latency_sum: f64,
+    latency_count: u64,
+    latencies: VecDeque<f64>,
+}
+
+/// Call statistics of one upstream endpoint.
+#[derive(Debug)]
+pub struct EndpointMetrics {
+    provider: &'static str,
+    endpoint: &'static str,
+    breaker: Arc<CircuitBreaker>,
+    counters: Mutex<Counters>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LatencySummary {
+    p50: f64,
+    p95: f64,
+    p99: f64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EndpointStatus {
+    provider: &'static str,
+    endpoint: &'static str,
+    circuit: &'static str,
+    requests: u64,
+    retries: u64,
+    outcomes: BTreeMap<String, u64>,
+    latency_ms: Option<LatencySummary>,
+}
+
+impl EndpointMetrics {
+    pub fn record(&self, outcome: Outcome, retries: u32, elapsed: Duration) {
+        let label = match outcome {
+            Outcome::Status(status) => status.to_string(),
+            Outcome::Error => "error".into(),
+            Outcome::Rejected => "circuit_open".into(),
+        };
+        let mut counters = self.counters.lock().unwrap();
+
+        *counters.outcomes.entry(label).or_default() += 1;
+        counters.retries += u64::from(retries);
+        if !matches!(outcome, Outcome::Rejected) {
+            let secs = elapsed.as_secs_f64();
+            counters.latency_sum += secs;
+            counters.latency_count += 1;
+            if counters.latencies.len() == LATENCY_WINDOW {
+                counters.latencies.pop_front();
+            }
+            counters.latencies.push_back(secs);
+        }
+    }
+
+    fn quantiles(counters: &Counters) -> Option<[f64; 3]> {
+        if counters.latencies.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f64> = counters.latencies.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let at = |q: f64| sorted[((sorted.len() - 1) as f64 * q).round() as usize];
+
+        Some([at(0.5), at(0.95), at(0.99)])
+    }
+
+    pub fn status(&self) -> EndpointStatus {
+        let counters = self.counters.lock().unwrap();
+        let to_ms = |secs: f64| (secs * 1000.0 * 10.0).round() / 10.0;
+
+        EndpointStatus {
+            provider: self.provider,
+            endpoint: self.endpoint,
+            circuit: self.breaker.state_name(),
+            requests: counters.outcomes.values().sum(),
+            retries: counters.retries,
+            outcomes: counters.outcomes.clone(),
+            latency_ms: Self::quantiles(&counters).map(|[p50, p95, p99]| LatencySummary {
+                p50: to_ms(p50),
+                p95: to_ms(p95),
+                p99: to_ms(p99),
+            }),
+        }
+    }
+
+    fn labels(&self) -> String {
+        format!(
+            "provider=\"{}\",endpoint=\"{}\"",
+            self.provider, self.endpoint
+        )
+    }
+}
+
+/// All upstream endpoints the service calls, for the metrics and status endpoints.
+#[derive(Debug, Default)]
+pub struct UpstreamMetrics {
+    endpoints: Mutex<Vec<Arc<EndpointMetrics>>>,
+}
+
+impl UpstreamMetrics {
+    pub fn register(
+        &self,
+        provider: &'static str,
+        endpoint: &'static str,
+        breaker: Arc<CircuitBreaker>,
+    ) -> Arc<EndpointMetrics> {
+        let metrics = Arc::new(EndpointMetrics {
+            provider,
+            endpoint,
+            breaker,
+            counters: Mutex::new(Counters::default()),
+        });
+        self.endpoints.lock().unwrap().push(metrics.clone());
+
+        metrics
+    }
+
+    pub fn status(&self) -> Vec<EndpointStatus> {
+        self.endpoints
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|e| e.status())
+            .collect()
+    }
+
+    /// Prometheus text exposition of every endpoint.
+    pub fn render(&self) -> String {
+        let endpoints = self.endpoints.lock().unwrap();
+        let mut out = String::new();
+
+        // Exposition requires the samples of a metric to be grouped together
+        let _ = writeln!(out, "# TYPE upstream_requests_total counter");
+        for endpoint in endpoints.iter() {
+            let counters = endpoint.counters.lock().unwrap();
+            for (outcome, count) in &counters.outcomes {
+                let _ = writeln!(
+                    out,
+                    "upstream_requests_total{{{},outcome=\"{}\"}} {}",
+                    endpoint.labels(),
+                    outcome,
+                    count
+                );
+            }
+        }
+
+        let _ = writeln!(out, "# TYPE upstream_retries_total counter");
+        for endpoint in endpoints.iter() {
+            let counters = endpoint.counters.lock().unwrap();
+            let _ = writeln!(
+                out,
+                "upstream_retries_total{{{}}} {}",
+                endpoint.labels(),
+                counters.retries
+            );
+        }
+
+        let _ = writeln!(out, "# TYPE upstream_latency_seconds summary");
+        for endpoint in endpoints.iter() {
+            let counters = endpoint.counters.lock().unwrap();
+            let labels = endpoint.labels();
+            if let Some(quantiles) = EndpointMetrics::quantiles(&counters) {
+                for (q, value) in ["0.5", "0.95", "0.99"].iter().zip(quantiles) {
+                    let _ = writeln!(
+                        out,
+                        "upstream_latency_seconds{{{},quantile=\"{}\"}} {}",
+                        labels, q, value
+                    );
+                }
+            }
+            let _ = writeln!(
+                out,
+                "upstream_latency_seconds_sum{{{}}} {}",
+                labels, counters.latency_sum
+            );
+            let _ = writeln!(
+                out,
+                "upstream_latency_seconds_count{{{}}} {}",
+                labels, counters.latency_count
+            );
+        }
+
+        out
+    }
+}
@@ -0,0 +1,130 @@
+mod breaker;
+pub mod cassette;
+mod coalesce;
+pub mod google_error;
+mod keys;
+mod metrics;
+mod retry;
+
+pub use breaker::CircuitBreaker;
+pub use cassette::CassetteMode;
+pub use coalesce::Coalescer;
+pub use keys::{KeyPool, Rotation};
+pub use metrics::{EndpointMetrics, EndpointStatus, Outcome, UpstreamMetrics};
+pub use retry::{send_with_retry, RetryPolicy};
+
+use std::time::Instant;
+
+use bytes::Bytes;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use tracing::Instrument;
+
+use crate::{audit, error::AppError, middleware, telemetry};
+
+/// Sends an upstream request through the provider's circuit breaker and retry policy.
+/// Transport errors and 5xx responses count as failures for the breaker.
+pub async fn send(
+    breaker: &CircuitBreaker,
+    policy: &RetryPolicy,
+    metrics: &EndpointMetrics,
+    request: RequestBuilder,
+) -> Result<Response, AppError> {
+    let started = Instant::now();
+    if !breaker.try_acquire() {
+        metrics.record(Outcome::Rejected, 0, started.elapsed());
+        return Err(AppError::Unavailable);
+    }
+
+    audit::note_provider(breaker.name());
+    let span = tracing::info_span!("upstream", provider = breaker.name());
+    let request = span.in_scope(|| request.headers(telemetry::upstream_headers()));
+
+    let (result, retries) = send_with_retry(policy, request).instrument(span).await;
+    middleware::record_upstream_call(breaker.name(), started.elapsed(), retries);
+    match result {
+        Ok(response) => {
+            metrics.record(
+                Outcome::Status(response.status().as_u16()),
+                retries,
+                started.elapsed(),
+            );
+            if response.status().is_server_error() {
+                breaker.record_failure();
+            } else {
+                breaker.record_success();
+            }
+            Ok(response)
+        }
+        Err(e) => {
+            metrics.record(Outcome::Error, retries, started.elapsed());
+            breaker.record_failure();
+            Err(AppError::from(e))
+        }
+    }
+}
+
+/// Sends a request built for a key from the pool, moving on to another key when the
+/// provider rate limits or rejects the current one. Returns the last answer received.
+pub async fn send_with_keys<F>(
+    keys: &KeyPool,
+    breaker: &CircuitBreaker,
+    policy: &RetryPolicy,
+    metrics: &EndpointMetrics,
+    build: F,
+) -> Result<(StatusCode, Bytes), AppError>
+where
+    F: Fn(&str) -> RequestBuilder,
+{
+    let mut last = None;
+
+    // Every rotation takes the failing key out of the pool, so this ends
+    while let Some(key) = keys.pick() {
+        let response = send(breaker, policy, metrics, build(key.value())).await?;
+        let status = response.status();
+        let body = response.bytes().await?;
+
+        if !keys.record(&key, status, &body) {
+            return Ok((status, body));
+        }
+        last = Some((status, body));
+    }
+
+    last.ok_or_else(|| {
+        tracing::error!("no usable Google key left");
+        AppError::Unavailable
+    })
+}
+
+/// Like `send_with_keys`, but a successful answer comes back unread so its body can be
+/// streamed on. Unsuccessful answers are read and become the matching error.
+pub async fn stream_with_keys<F>(
+    keys: &KeyPool,
+    breaker: &CircuitBreaker,
+    policy: &RetryPolicy,
+    metrics: &EndpointMetrics,
+    build: F,
+) -> Result<Response, AppError>
+where
+    F: Fn(&str) -> RequestBuilder,
+{
+    let mut last = None;
+
+    while let Some(key) = keys.pick() {
+        let response = send(breaker, policy, metrics, build(key.value())).await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+        let body = response.bytes().await?;
+
+        if !keys.record(&key, status, &body) {
+            return Err(google_error::translate(breaker.name(), status, &body));
+        }
+        last = Some(google_error::translate(breaker.name(), status, &body));
+    }
+
+    Err(last.unwrap_or_else(|| {
+        tracing::error!("no usable Google key left");
+        AppError::Unavailable
+    }))
+}
@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use rand::Rng;
+use reqwest::{RequestBuilder, Response, StatusCode};
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    // Full jitter: a random delay between zero and the capped exponential backoff
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let millis = exp.as_millis() as u64;
+
+        Duration::from_millis(rand::thread_rng().gen_range(0..=millis))
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE
+    )
+}
+
+fn is_retryable_error(e: &reqwest::Error) -> bool {
+    e.is_connect()
+}
+
+/// Sends the request, retrying connect errors and 429/502/503 responses with jittered
+/// exponential backoff. Requests whose body can't be cloned are sent only once. Also
+/// returns how many retries were made.
+pub async fn send_with_retry(
+    policy: &RetryPolicy,
+    request: RequestBuilder,
+) -> (Result<Response, reqwest::Error>, u32) {
+    let mut attempt = 1;
+
+    loop {
+        let current = match request.try_clone() {
+            Some(cloned) if attempt < policy.max_attempts => cloned,
+            _ => return (request.send().await, attempt - 1),
+        };
+
+        match current.send().await {
+            Ok(response) if is_retryable_status(response.status()) => {
+                tracing::debug!(
+                    "upstream returned {}, retrying (attempt {}/{})",
+                    response.status(),
+                    attempt,
+                    policy.max_attempts
+                );
+            }
+            Err(e) if is_retryable_error(&e) => {
+                tracing::debug!(
+                    "upstream request failed: {}, retrying (attempt {}/{})",
+                    e,
+                    attempt,
+                    policy.max_attempts
+                );
+            }
+            result => return (result, attempt - 1),
+        }
+
+        tokio::time::sleep(policy.backoff(attempt - 1)).await;
+        attempt += 1;
+    }
+}
@@ -0,0 +1,190 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+use chrono::{Datelike, Days, NaiveDate, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgPool;
+
+use crate::{
+    db,
+    error::AppError,
+    middleware::{current_environment, Environment},
+};
+
+pub const PLACES_TEXT_SEARCH: &str = "places.text_search";
+pub const PLACE_DETAILS: &str = "places.details";
+pub const ROUTES_BASIC: &str = "routes.basic";
+pub const ROUTES_ADVANCED: &str = "routes.advanced";
+pub const ROUTES_PREFERRED: &str = "routes.preferred";
+pub const ROUTE_MATRIX_ELEMENT: &str = "routes.matrix_element";
+
+/// List prices in USD per call, overridable through `USAGE_PRICES`.
+pub const DEFAULT_PRICES: &[(&str, f64)] = &[
+    (PLACES_TEXT_SEARCH, 0.032),
+    (PLACE_DETAILS, 0.017),
+    (ROUTES_BASIC, 0.005),
+    (ROUTES_ADVANCED, 0.01),
+    (ROUTES_PREFERRED, 0.015),
+    (ROUTE_MATRIX_ELEMENT, 0.005),
+];
+
+// Sandbox calls are stored under their own SKUs, e.g. `sandbox.places.text_search`
+const SANDBOX_PREFIX: &str = "sandbox.";
+
+// Google bills more than 10 intermediate waypoints at the advanced rate
+const BASIC_MAX_INTERMEDIATES: usize = 10;
+
+/// The computeRoutes SKU a request body is billed under.
+pub fn routes_sku(body: &Value) -> &'static str {
+    let intermediates = body["intermediates"].as_array().map_or(0, Vec::len);
+    match body["routingPreference"].as_str() {
+        Some("TRAFFIC_AWARE_OPTIMAL") => ROUTES_PREFERRED,
+        Some("TRAFFIC_AWARE") => ROUTES_ADVANCED,
+        _ if intermediates > BASIC_MAX_INTERMEDIATES => ROUTES_ADVANCED,
+        _ => ROUTES_BASIC,
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SkuUsage {
+    calls: u64,
+    cost: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsagePeriod {
+    period: String,
+    skus: BTreeMap<String, SkuUsage>,
+    cost: f64,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Periods {
+    daily: Vec<UsagePeriod>,
+    monthly: Vec<UsagePeriod>,
+}
+
+/// Production usage, with the sandbox keys' usage apart so tests don't blur the bill.
+#[derive(Debug, Serialize)]
+pub struct UsageReport {
+    currency: &'static str,
+    daily: Vec<UsagePeriod>,
+    monthly: Vec<UsagePeriod>,
+    sandbox: Periods,
+}
+
+#[derive(Default)]
+struct PeriodTotals {
+    daily: BTreeMap<String, UsagePeriod>,
+    monthly: BTreeMap<String, UsagePeriod>,
+}
+
+impl PeriodTotals {
+    fn into_periods(self) -> Periods {
+        Periods {
+            daily: self.daily.into_values().collect(),
+            monthly: self.monthly.into_values().collect(),
+        }
+    }
+}
+
+/// Counts billable upstream calls per UTC day and SKU, in the database when one is
+/// configured, and prices them for the usage report.
+pub struct UsageTracker {
+    prices: HashMap<String, f64>,
+    db: Option<PgPool>,
+    memory: Mutex<BTreeMap<(NaiveDate, String), u64>>,
+}
+
+impl UsageTracker {
+    pub fn new(prices: HashMap<String, f64>, db: Option<PgPool>) -> Self {
+        UsageTracker {
+            prices,
+            db,
+            memory: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    // Recorded in the background so accounting never slows down a request
+    pub fn record(&self, sku: &'static str) {
+        let day = Utc::now().date_naive();
+        let sku = match current_environment() {
+            Environment::Production => sku.to_owned(),
+            Environment::Sandbox => format!("{}{}", SANDBOX_PREFIX, sku),
+        };
+
+        match &self.db {
+            Some(pool) => {
+                let pool = pool.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = db::upstream_usage::increment(&pool, day, &sku).await {
+                        tracing::error!(error = %e, sku = %sku, "failed to record upstream usage");
+                    }
+                });
+            }
+            None => {
+                *self.memory.lock().unwrap().entry((day, sku)).or_default() += 1;
+            }
+        }
+    }
+
+    /// Usage of the last `days` days, including today, and the full months they touch.
+    pub async fn report(&self, days: u64) -> Result<UsageReport, AppError> {
+        let today = Utc::now().date_naive();
+        let first_day = today
+            .checked_sub_days(Days::new(days.saturating_sub(1)))
+            .unwrap_or(NaiveDate::MIN);
+        let from = first_day.with_day(1).unwrap_or(first_day);
+
+        let rows: Vec<(NaiveDate, String, u64)> = match &self.db {
+            Some(pool) => db::upstream_usage::since(pool, from)
+                .await?
+                .into_iter()
+                .map(|(day, sku, calls)| (day, sku, calls.max(0) as u64))
+                .collect(),
+            None => self
+                .memory
+                .lock()
+                .unwrap()
+                .range((from, String::new())..)
+                .map(|((day, sku), calls)| (*day, sku.clone(), *calls))
+                .collect(),
+        };
+
+        let mut production = PeriodTotals::default();
+        let mut sandbox = PeriodTotals::default();
+        for (day, sku, calls) in rows {
+            let (totals, sku) = match sku.strip_prefix(SANDBOX_PREFIX) {
+                Some(sku) => (&mut sandbox, sku.to_owned()),
+                None => (&mut production, sku),
+            };
+            let price = self.prices.get(&sku).copied().unwrap_or_default();
+            let mut periods = vec![(&mut totals.monthly, day.format("%Y-%m").to_string())];
+            if day >= first_day {
+                periods.push((&mut totals.daily, day.format("%Y-%m-%d").to_string()));
+            }
+            for (periods, key) in periods {
+                let period = periods.entry(key.clone()).or_insert_with(|| UsagePeriod {
+                    period: key,
+                    skus: BTreeMap::new(),
+                    cost: 0.0,
+                });
+                let usage = period.skus.entry(sku.clone()).or_default();
+                usage.calls += calls;
+                usage.cost += calls as f64 * price;
+                period.cost += calls as f64 * price;
+            }
+        }
+
+        let production = production.into_periods();
+        Ok(UsageReport {
+            currency: "USD",
+            daily: production.daily,
+            monthly: production.monthly,
+            sandbox: sandbox.into_periods(),
+        })
+    }
+}
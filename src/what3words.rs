@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use moka::future::Cache as MokaCache;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::{db::trips::Coordinate, error::AppError, geo::plus_code};
+
+const CONVERT_PATH: &str = "/v3/convert-to-coordinates";
+// In a header rather than the query, so the key stays out of logged URLs
+const API_KEY_HEADER: &str = "X-Api-Key";
+const ADDRESS_PREFIX: &str = "///";
+// Squares never move, so conversions are kept for a day
+const CACHE_CAPACITY: u64 = 10_000;
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+// Squares are 3 by 3 meters, 11 digits keep them apart
+const PLUS_CODE_LENGTH: usize = 11;
+
+#[derive(Debug, Deserialize)]
+struct ConvertResponse {
+    words: String,
+    coordinates: LatLng,
+    country: Option<String>,
+    #[serde(rename = "nearestPlace")]
+    nearest_place: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LatLng {
+    lat: f64,
+    lng: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorDetail {
+    code: String,
+    message: String,
+}
+
+/// The square of a what3words address.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Square {
+    pub words: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Plus code of the square's center, accepted wherever a location is
+    pub plus_code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nearest_place: Option<String>,
+}
+
+/// Whether `words` are three words separated by dots, e.g. `index.home.raft`.
+pub fn is_words(words: &str) -> bool {
+    let is_word = |word: &str| !word.is_empty() && word.chars().all(char::is_alphabetic);
+
+    words.split('.').count() == 3 && words.split('.').all(is_word)
+}
+
+/// The three words of `///index.home.raft`, `None` for anything else.
+pub fn address_words(value: &str) -> Option<&str> {
+    let words = value.trim().strip_prefix(ADDRESS_PREFIX)?;
+
+    is_words(words).then_some(words)
+}
+
+/// Converts what3words addresses to coordinates through the what3words API.
+pub struct What3Words {
+    client: Client,
+    url: String,
+    key: String,
+    squares: MokaCache<String, Square>,
+}
+
+impl What3Words {
+    pub fn new(client: Client, origin: &str, key: String) -> Self {
+        What3Words {
+            client,
+            url: format!("{}{}", origin.trim_end_matches('/'), CONVERT_PATH),
+            key,
+            squares: MokaCache::builder()
+                .max_capacity(CACHE_CAPACITY)
+                .time_to_live(CACHE_TTL)
+                .build(),
+        }
+    }
+
+    /// The square of `words`, given with or without the leading `///`. Words that aren't
+    /// an address are a validation error.
+    pub async fn resolve(&self, words: &str) -> Result<Square, AppError> {
+        let words = words
+            .trim()
+            .trim_start_matches(ADDRESS_PREFIX)
+            .to_lowercase();
+        if let Some(square) = self.squares.get(&words).await {
+            return Ok(square);
+        }
+
+        let response = self
+            .client
+            .get(&self.url)
+            .query(&[("words", words.as_str())])
+            .header(API_KEY_HEADER, &self.key)
+            .send()
+            .await
+            .inspect_err(
+                |e| tracing::error!(error = %e, provider = "what3words", "upstream request failed"),
+            )?;
+        let status = response.status();
+        let body = response.bytes().await?;
+        if !status.is_success() {
+            return Err(translate(status, &body));
+        }
+
+        let converted = serde_json::from_slice::<ConvertResponse>(&body).map_err(|e| {
+            tracing::error!(error = %e, provider = "what3words", "failed to parse upstream response");
+            AppError::ParseError(e.to_string())
+        })?;
+        let center = Coordinate {
+            latitude: converted.coordinates.lat,
+            longitude: converted.coordinates.lng,
+        };
+        let square = Square {
+            words: converted.words,
+            latitude: center.latitude,
+            longitude: center.longitude,
+            plus_code: plus_code::encode_with_length(center, PLUS_CODE_LENGTH),
+            country: converted.country,
+            nearest_place: converted.nearest_place,
+        };
+        self.squares.insert(words, square.clone()).await;
+
+        Ok(square)
+    }
+}
+
+// Unknown words are the caller's mistake, a refused key is ours
+fn translate(status: StatusCode, body: &[u8]) -> AppError {
+    let detail = serde_json::from_slice::<ErrorResponse>(body).ok();
+    tracing::warn!(
+        %status,
+        code = detail.as_ref().map(|d| d.error.code.as_str()),
+        provider = "what3words",
+        "upstream returned an error"
+    );
+
+    match (status, detail) {
+        (StatusCode::BAD_REQUEST, Some(detail)) => AppError::Validation(format!(
+            "what3words rejected the address: {}",
+            detail.error.message
+        )),
+        (StatusCode::TOO_MANY_REQUESTS, _) => AppError::RateLimited {
+            retry_after: Duration::from_secs(1),
+        },
+        _ => AppError::UpstreamError(format!("what3words answered {}", status)),
+    }
+}
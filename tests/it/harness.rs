@@ -0,0 +1,129 @@
+use std::{
+    net::TcpListener,
+    process::{Child, Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use reqwest::StatusCode;
+use serde_json::Value;
+use wiremock::MockServer;
+
+pub const GOOGLE_KEY: &str = "test-key";
+pub const WHAT3WORDS_KEY: &str = "test-w3w-key";
+// Short enough for the timeout tests to stay quick
+pub const UPSTREAM_TIMEOUT: Duration = Duration::from_millis(500);
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The server binary running against `google`, which also stands in for what3words,
+/// killed when dropped.
+pub struct App {
+    process: Child,
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl App {
+    pub async fn start(google: &MockServer) -> App {
+        App::start_with(google, &[]).await
+    }
+
+    /// Like [`App::start`], with `env` set on top of the test defaults.
+    pub async fn start_with(google: &MockServer, env: &[(&str, &str)]) -> App {
+        // Taken and released right away, the server binds it again
+        let port = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("no free port")
+            .port();
+        let process = Command::new(env!("CARGO_BIN_EXE_multi-map-backend"))
+            .env_clear()
+            // Keeps a developer's .env out of the tests
+            .current_dir(std::env::temp_dir())
+            .env("BIND_ADDR", format!("127.0.0.1:{}", port))
+            .env("GOOGLE_PLACES_KEY", GOOGLE_KEY)
+            .env("GOOGLE_PLACES_ORIGIN", google.uri())
+            .env("GOOGLE_ROUTES_ORIGIN", google.uri())
+            .env("WHAT3WORDS_API_KEY", WHAT3WORDS_KEY)
+            .env("WHAT3WORDS_ORIGIN", google.uri())
+            .env("FUEL_PRICES", "diesel=1.5")
+            .env("API_AUTH_ENABLED", "false")
+            .env("CACHE_ENABLED", "false")
+            .env("STALE_IF_ERROR_ENABLED", "false")
+            .env("RETRY_MAX_ATTEMPTS", "1")
+            .env("UPSTREAM_WARMUP_INTERVAL_SECS", "0")
+            .env(
+                "UPSTREAM_TIMEOUT_MS",
+                UPSTREAM_TIMEOUT.as_millis().to_string(),
+            )
+            .envs(env.iter().copied())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("failed to start the server");
+
+        let mut app = App {
+            process,
+            base_url: format!("http://127.0.0.1:{}", port),
+            client: reqwest::Client::new(),
+        };
+        app.wait_until_live().await;
+
+        app
+    }
+
+    async fn wait_until_live(&mut self) {
+        let started = Instant::now();
+        while started.elapsed() < STARTUP_TIMEOUT {
+            if let Ok(Some(status)) = self.process.try_wait() {
+                panic!("server exited during startup with {}", status);
+            }
+            let live = self
+                .client
+                .get(format!("{}/livez", self.base_url))
+                .send()
+                .await
+                .is_ok_and(|response| response.status().is_success());
+            if live {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        panic!("server not live after {:?}", STARTUP_TIMEOUT);
+    }
+
+    /// A request to `path` to finish and send, for what `post` doesn't cover.
+    pub fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        self.client
+            .request(method, format!("{}{}", self.base_url, path))
+    }
+
+    pub async fn post_response(&self, path: &str, body: Value) -> reqwest::Response {
+        self.client
+            .post(format!("{}{}", self.base_url, path))
+            .json(&body)
+            .send()
+            .await
+            .expect("request to the server failed")
+    }
+
+    /// Posts `body` to `path`, returning the status and the JSON answer.
+    pub async fn post(&self, path: &str, body: Value) -> (StatusCode, Value) {
+        let response = self.post_response(path, body).await;
+        let status = response.status();
+
+        (status, response.json().await.expect("answer isn't JSON"))
+    }
+}
+
+impl Drop for App {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// Google's error body.
+pub fn google_error(code: u16, status: &str, message: &str) -> Value {
+    serde_json::json!({
+        "error": { "code": code, "message": message, "status": status }
+    })
+}
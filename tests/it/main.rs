@@ -0,0 +1,7 @@
+//! Runs the server against a fake Google served by wiremock. Each test starts its own
+//! server process, so breakers, key cooldowns and caches never leak between tests.
+
+mod harness;
+mod places;
+mod routes;
+mod tiles;
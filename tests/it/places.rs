@@ -0,0 +1,195 @@
+use serde_json::json;
+use wiremock::{
+    matchers::{header, header_exists, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::harness::{google_error, App, GOOGLE_KEY, UPSTREAM_TIMEOUT};
+
+const TEXT_SEARCH_PATH: &str = "/v1/places:searchText";
+
+fn search() -> serde_json::Value {
+    json!({ "textQuery": "coffee in Lisbon" })
+}
+
+#[tokio::test]
+async fn search_returns_google_places() {
+    let google = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(TEXT_SEARCH_PATH))
+        .and(header("X-Goog-Api-Key", GOOGLE_KEY))
+        .and(header_exists("X-Goog-FieldMask"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "places": [{
+                "id": "ChIJ1",
+                "formattedAddress": "Rua Augusta 1, Lisboa",
+                "displayName": { "text": "Café A", "languageCode": "pt" },
+                "location": { "latitude": 38.71, "longitude": -9.14 },
+                "rating": 4.5,
+            }]
+        })))
+        .expect(1)
+        .mount(&google)
+        .await;
+    let app = App::start(&google).await;
+
+    let (status, body) = app.post("/v2/places", search()).await;
+
+    assert_eq!(status, 200);
+    assert_eq!(body["places"][0]["id"], "ChIJ1");
+    assert_eq!(body["places"][0]["displayName"]["text"], "Café A");
+    assert_eq!(body["meta"]["stale"], false);
+}
+
+#[tokio::test]
+async fn places_rank_from_a_plus_code_and_carry_theirs() {
+    let google = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(TEXT_SEARCH_PATH))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "places": [{
+                "id": "ChIJ2",
+                "formattedAddress": "Avenida da Liberdade 200, Lisboa",
+                "displayName": { "text": "Café B" },
+                "location": { "latitude": 38.75, "longitude": -9.2 },
+            }, {
+                "id": "ChIJ1",
+                "formattedAddress": "Rua Augusta 1, Lisboa",
+                "displayName": { "text": "Café A" },
+                "location": { "latitude": 38.71053, "longitude": -9.14047 },
+            }]
+        })))
+        .mount(&google)
+        .await;
+    let app = App::start(&google).await;
+
+    let (status, body) = app
+        .post(
+            "/v2/places",
+            json!({
+                "textQuery": "coffee in Lisbon",
+                "rankBy": "distance",
+                "plusCode": "8CCGPV65+6R",
+            }),
+        )
+        .await;
+
+    assert_eq!(status, 200);
+    assert_eq!(body["places"][0]["id"], "ChIJ1");
+    assert_eq!(body["places"][0]["location"]["plusCode"], "8CCGPV65+6R");
+}
+
+#[tokio::test]
+async fn dry_run_returns_the_request_without_calling_google() {
+    let google = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(TEXT_SEARCH_PATH))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "places": [] })))
+        .expect(0)
+        .mount(&google)
+        .await;
+    let app = App::start(&google).await;
+
+    let (status, body) = app.post("/v2/places?dryRun=true", search()).await;
+
+    assert_eq!(status, 200);
+    assert_eq!(body["url"], format!("{}{}", google.uri(), TEXT_SEARCH_PATH));
+    assert_eq!(body["body"]["textQuery"], "coffee in Lisbon");
+    assert!(body["headers"]["X-Goog-FieldMask"].is_string());
+    assert!(body["headers"].get("X-Goog-Api-Key").is_none());
+}
+
+#[tokio::test]
+async fn malformed_answer_is_a_parse_error() {
+    let google = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(TEXT_SEARCH_PATH))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_raw("{\"places\": [{\"id\": 4", "application/json"),
+        )
+        .mount(&google)
+        .await;
+    let app = App::start(&google).await;
+
+    let (status, body) = app.post("/v2/places", search()).await;
+
+    assert_eq!(status, 500);
+    assert_eq!(body["error"]["code"], "PARSE_ERROR");
+}
+
+#[tokio::test]
+async fn rate_limited_search_is_a_429_with_retry_after() {
+    let google = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(TEXT_SEARCH_PATH))
+        .respond_with(ResponseTemplate::new(429).set_body_json(google_error(
+            429,
+            "RESOURCE_EXHAUSTED",
+            "Quota exceeded",
+        )))
+        .mount(&google)
+        .await;
+    let app = App::start(&google).await;
+
+    let response = app.post_response("/v2/places", search()).await;
+
+    assert_eq!(response.status(), 429);
+    assert!(response.headers().contains_key("retry-after"));
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "RATE_LIMITED");
+}
+
+#[tokio::test]
+async fn slow_search_times_out() {
+    let google = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(TEXT_SEARCH_PATH))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "places": [] }))
+                .set_delay(UPSTREAM_TIMEOUT * 4),
+        )
+        .mount(&google)
+        .await;
+    let app = App::start(&google).await;
+
+    let (status, body) = app.post("/v2/places", search()).await;
+
+    assert_eq!(status, 504);
+    assert_eq!(body["error"]["code"], "TIMEOUT");
+}
+
+#[tokio::test]
+async fn rejected_search_is_a_validation_error() {
+    let google = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(TEXT_SEARCH_PATH))
+        .respond_with(ResponseTemplate::new(400).set_body_json(google_error(
+            400,
+            "INVALID_ARGUMENT",
+            "Invalid text query",
+        )))
+        .mount(&google)
+        .await;
+    let app = App::start(&google).await;
+
+    let (status, body) = app.post("/v2/places", search()).await;
+
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["code"], "VALIDATION_ERROR");
+}
+
+#[tokio::test]
+async fn invalid_fields_are_named_as_each_version_names_them() {
+    let google = MockServer::start().await;
+    let app = App::start(&google).await;
+    let body = json!({ "textQuery": "coffee", "maxResults": 50 });
+
+    let (status, v1) = app.post("/v1/places", body.clone()).await;
+    assert_eq!(status, 400);
+    assert_eq!(v1["error"]["details"][0]["field"], "max_results");
+
+    let (status, v2) = app.post("/v2/places", body).await;
+    assert_eq!(status, 400);
+    assert_eq!(v2["error"]["details"][0]["field"], "maxResults");
+}
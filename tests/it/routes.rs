@@ -0,0 +1,382 @@
+use serde_json::{json, Value};
+use wiremock::{
+    matchers::{body_partial_json, header, method, path, query_param},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::harness::{google_error, App, GOOGLE_KEY, UPSTREAM_TIMEOUT, WHAT3WORDS_KEY};
+
+const COMPUTE_ROUTES_PATH: &str = "/directions/v2:computeRoutes";
+const COMPUTE_ROUTE_MATRIX_PATH: &str = "/distanceMatrix/v2:computeRouteMatrix";
+
+fn route_request() -> Value {
+    json!({
+        "originLocation": { "latitude": 38.7223, "longitude": -9.1393 },
+        "destinationLocation": { "latitude": 38.6979, "longitude": -9.2068 },
+        "departureTime": "2035-06-01T08:00:00Z",
+    })
+}
+
+#[tokio::test]
+async fn routes_are_computed_by_google() {
+    let google = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(COMPUTE_ROUTES_PATH))
+        .and(header("X-Goog-Api-Key", GOOGLE_KEY))
+        .and(body_partial_json(json!({
+            "travelMode": "DRIVE",
+            "departureTime": "2035-06-01T08:00:00Z",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "routes": [{
+                "distanceMeters": 7012,
+                "duration": "842s",
+                // (38.5, -120.2), (40.7, -120.95), (43.252, -126.453)
+                "polyline": { "encodedPolyline": "_p~iF~ps|U_ulLnnqC_mqNvxq`@" },
+            }]
+        })))
+        .expect(1)
+        .mount(&google)
+        .await;
+    let app = App::start(&google).await;
+
+    let (status, body) = app.post("/v2/routes", route_request()).await;
+
+    assert_eq!(status, 200);
+    assert_eq!(body["routes"][0]["duration"], "842s");
+    assert_eq!(body["routes"][0]["distanceMeters"], 7012.0);
+    // Filled in from the polyline when Google leaves it out
+    assert!(body["routes"][0]["viewport"]["low"].is_object());
+}
+
+#[tokio::test]
+async fn transit_routes_list_their_rides() {
+    let ride = |line: &str, from: &str, to: &str| {
+        json!({
+            "transitDetails": {
+                "stopDetails": {
+                    "departureStop": { "name": from },
+                    "departureTime": "2035-06-01T08:05:00Z",
+                    "arrivalStop": { "name": to },
+                    "arrivalTime": "2035-06-01T08:15:00Z",
+                },
+                "headsign": to,
+                "transitLine": { "nameShort": line, "vehicle": { "type": "BUS" } },
+                "stopCount": 4,
+            },
+        })
+    };
+    let google = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(COMPUTE_ROUTES_PATH))
+        .and(body_partial_json(json!({ "travelMode": "TRANSIT" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "routes": [{
+                "distanceMeters": 7012,
+                "duration": "1500s",
+                "polyline": { "encodedPolyline": "_p~iF~ps|U_ulLnnqC_mqNvxq`@" },
+                "legs": [{
+                    "steps": [
+                        { "travelMode": "WALK" },
+                        ride("728", "Cais do Sodré", "Belém"),
+                        ride("15E", "Belém", "Algés"),
+                    ],
+                }],
+            }]
+        })))
+        .expect(1)
+        .mount(&google)
+        .await;
+    let app = App::start(&google).await;
+
+    let mut request = route_request();
+    request["travelMode"] = json!("TRANSIT");
+    let (status, body) = app.post("/v2/routes", request).await;
+
+    assert_eq!(status, 200);
+    let transit = &body["routes"][0]["transit"];
+    assert_eq!(transit["transfers"], 1);
+    assert_eq!(transit["rides"][0]["lineShortName"], "728");
+    assert_eq!(transit["rides"][0]["departureStop"], "Cais do Sodré");
+    assert_eq!(transit["rides"][1]["vehicleType"], "BUS");
+    assert_eq!(transit["rides"][1]["arrivalStop"], "Algés");
+}
+
+#[tokio::test]
+async fn routes_carry_a_fuel_cost_from_the_price_table_and_their_tolls() {
+    let google = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(COMPUTE_ROUTES_PATH))
+        .and(body_partial_json(json!({ "extraComputations": ["TOLLS"] })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "routes": [{
+                "distanceMeters": 7012,
+                "duration": "842s",
+                "polyline": { "encodedPolyline": "_p~iF~ps|U_ulLnnqC_mqNvxq`@" },
+                "travelAdvisory": {
+                    "tollInfo": {
+                        "estimatedPrice": [
+                            { "currencyCode": "EUR", "units": "2", "nanos": 350000000 },
+                        ],
+                    },
+                },
+            }, {
+                "distanceMeters": 9120,
+                "duration": "901s",
+                "polyline": { "encodedPolyline": "_p~iF~ps|U_ulLnnqC" },
+            }]
+        })))
+        .expect(1)
+        .mount(&google)
+        .await;
+    let app = App::start(&google).await;
+
+    let (status, body) = app
+        .post(
+            "/v2/routes?fuelConsumption=6&fuelType=diesel",
+            route_request(),
+        )
+        .await;
+
+    assert_eq!(status, 200);
+    assert_eq!(
+        body["routes"][0]["fuelCost"],
+        json!({ "liters": 0.4, "currencyCode": "USD", "amount": 0.63 })
+    );
+    assert_eq!(
+        body["routes"][0]["tolls"],
+        json!([{ "currencyCode": "EUR", "amount": 2.35 }])
+    );
+    assert!(body["routes"][1].get("tolls").is_none());
+}
+
+#[tokio::test]
+async fn what3words_addresses_are_resolved_before_routing() {
+    let google = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/v3/convert-to-coordinates"))
+        .and(query_param("words", "filled.count.soap"))
+        .and(header("X-Api-Key", WHAT3WORDS_KEY))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "words": "filled.count.soap",
+            "coordinates": { "lat": 51.521251, "lng": -0.203586 },
+            "country": "GB",
+            "nearestPlace": "Bayswater, London",
+        })))
+        .expect(1)
+        .mount(&google)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(COMPUTE_ROUTES_PATH))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "routes": [{
+                "distanceMeters": 1200,
+                "duration": "300s",
+                "polyline": { "encodedPolyline": "_p~iF~ps|U_ulLnnqC_mqNvxq`@" },
+            }]
+        })))
+        .expect(1)
+        .mount(&google)
+        .await;
+    let app = App::start(&google).await;
+
+    let (status, body) = app
+        .post(
+            "/v2/routes",
+            json!({
+                "originLocation": "///filled.count.soap",
+                "destinationLocation": { "latitude": 51.5246, "longitude": -0.1340 },
+                "departureTime": "2035-06-01T08:00:00Z",
+            }),
+        )
+        .await;
+
+    assert_eq!(status, 200);
+    assert_eq!(body["routes"][0]["duration"], "300s");
+}
+
+#[tokio::test]
+async fn places_along_a_route_come_in_route_order() {
+    // (38.5, -120.2), (40.7, -120.95), (43.252, -126.453)
+    let polyline = "_p~iF~ps|U_ulLnnqC_mqNvxq`@";
+    let google = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/v1/places:searchText"))
+        .and(body_partial_json(json!({
+            "textQuery": "gas station",
+            "searchAlongRouteParameters": { "polyline": { "encodedPolyline": polyline } },
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "places": [{
+                "id": "late",
+                "formattedAddress": "Near the end",
+                "displayName": { "text": "Late" },
+                "location": { "latitude": 43.2, "longitude": -126.4 },
+            }, {
+                "id": "early",
+                "formattedAddress": "Near the start",
+                "displayName": { "text": "Early" },
+                "location": { "latitude": 38.6, "longitude": -120.25 },
+            }],
+            "routingSummaries": [
+                { "legs": [{ "duration": "9000s", "distanceMeters": 600000 }, { "duration": "60s", "distanceMeters": 1000 }] },
+                { "legs": [{ "duration": "600s", "distanceMeters": 12000 }, { "duration": "8500s", "distanceMeters": 590000 }] },
+            ],
+        })))
+        .expect(1)
+        .mount(&google)
+        .await;
+    let app = App::start(&google).await;
+
+    let (status, body) = app
+        .post(
+            "/v2/routes/places-along",
+            json!({ "encodedPolyline": polyline, "textQuery": "gas station" }),
+        )
+        .await;
+
+    assert_eq!(status, 200);
+    assert_eq!(body["stops"][0]["place"]["id"], "early");
+    assert_eq!(body["stops"][0]["fromStartSeconds"], 600.0);
+    assert_eq!(body["stops"][1]["place"]["id"], "late");
+    assert!(
+        body["stops"][1]["distanceAlongRouteMeters"]
+            .as_f64()
+            .unwrap()
+            > 500_000.0
+    );
+}
+
+#[tokio::test]
+async fn meeting_venues_are_ranked_by_the_longest_trip() {
+    let google = MockServer::start().await;
+    let venue_waypoint = json!({
+        "waypoint": { "location": { "latLng": { "latitude": 38.75, "longitude": -9.125 } } },
+    });
+    Mock::given(method("POST"))
+        .and(path(COMPUTE_ROUTE_MATRIX_PATH))
+        .and(body_partial_json(json!({ "destinations": [venue_waypoint] })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            { "condition": "ROUTE_EXISTS", "duration": "300s" },
+            { "originIndex": 1, "condition": "ROUTE_EXISTS", "duration": "1500s" },
+            { "destinationIndex": 1, "condition": "ROUTE_EXISTS", "duration": "700s" },
+            { "originIndex": 1, "destinationIndex": 1, "condition": "ROUTE_EXISTS", "duration": "800s" },
+        ])))
+        .expect(1)
+        .mount(&google)
+        .await;
+    // Only the centroid and the first point around it can be reached, the centroid fairly
+    Mock::given(method("POST"))
+        .and(path(COMPUTE_ROUTE_MATRIX_PATH))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            { "condition": "ROUTE_EXISTS", "duration": "900s" },
+            { "originIndex": 1, "condition": "ROUTE_EXISTS", "duration": "900s" },
+            { "destinationIndex": 1, "condition": "ROUTE_EXISTS", "duration": "300s" },
+            { "originIndex": 1, "destinationIndex": 1, "condition": "ROUTE_EXISTS", "duration": "2000s" },
+        ])))
+        .expect(1)
+        .mount(&google)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/v1/places:searchText"))
+        .and(body_partial_json(json!({
+            "textQuery": "coffee",
+            "locationBias": { "circle": { "center": { "latitude": 38.5, "longitude": -9.25 } } },
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "places": [{
+                "id": "far",
+                "formattedAddress": "Close to the first",
+                "displayName": { "text": "Far" },
+                "location": { "latitude": 38.75, "longitude": -9.125 },
+            }, {
+                "id": "fair",
+                "formattedAddress": "Halfway",
+                "displayName": { "text": "Fair" },
+                "location": { "latitude": 38.25, "longitude": -9.375 },
+            }],
+        })))
+        .expect(1)
+        .mount(&google)
+        .await;
+    let app = App::start(&google).await;
+
+    let (status, body) = app
+        .post(
+            "/v2/meet",
+            json!({
+                "origins": [
+                    { "latitude": 38.5, "longitude": -9.0 },
+                    { "latitude": 38.5, "longitude": -9.5 },
+                ],
+                "textQuery": "coffee",
+            }),
+        )
+        .await;
+
+    assert_eq!(status, 200);
+    assert_eq!(
+        body["midpoint"],
+        json!({ "latitude": 38.5, "longitude": -9.25 })
+    );
+    assert_eq!(body["venues"][0]["place"]["id"], "fair");
+    assert_eq!(body["venues"][0]["etaSeconds"], json!([700.0, 800.0]));
+    assert_eq!(body["venues"][0]["maxEtaSeconds"], 800.0);
+    assert_eq!(body["venues"][1]["place"]["id"], "far");
+}
+
+#[tokio::test]
+async fn malformed_routes_are_a_parse_error() {
+    let google = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(COMPUTE_ROUTES_PATH))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "routes": "none" })))
+        .mount(&google)
+        .await;
+    let app = App::start(&google).await;
+
+    let (status, body) = app.post("/v2/routes", route_request()).await;
+
+    assert_eq!(status, 500);
+    assert_eq!(body["error"]["code"], "PARSE_ERROR");
+}
+
+#[tokio::test]
+async fn rate_limited_routes_are_a_429() {
+    let google = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(COMPUTE_ROUTES_PATH))
+        .respond_with(ResponseTemplate::new(429).set_body_json(google_error(
+            429,
+            "RESOURCE_EXHAUSTED",
+            "Quota exceeded",
+        )))
+        .mount(&google)
+        .await;
+    let app = App::start(&google).await;
+
+    let (status, body) = app.post("/v2/routes", route_request()).await;
+
+    assert_eq!(status, 429);
+    assert_eq!(body["error"]["code"], "RATE_LIMITED");
+}
+
+#[tokio::test]
+async fn slow_routes_time_out() {
+    let google = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(COMPUTE_ROUTES_PATH))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "routes": [] }))
+                .set_delay(UPSTREAM_TIMEOUT * 4),
+        )
+        .mount(&google)
+        .await;
+    let app = App::start(&google).await;
+
+    let (status, body) = app.post("/v2/routes", route_request()).await;
+
+    assert_eq!(status, 504);
+    assert_eq!(body["error"]["code"], "TIMEOUT");
+}
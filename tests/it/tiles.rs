@@ -0,0 +1,227 @@
+use std::f64::consts::PI;
+
+use prost::Message;
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+use wiremock::MockServer;
+
+use crate::harness::App;
+
+// Tiles are built from the database, these tests are skipped without one
+const DATABASE_URL_VAR: &str = "TEST_DATABASE_URL";
+const EXTENT: f64 = 4096.0;
+const BUFFER: i64 = 64;
+
+const MOVE_TO: u32 = 1;
+const LINE_TO: u32 = 2;
+const CLOSE_PATH: u32 = 7;
+
+// What the server writes of the Mapbox Vector Tile specification 2.1
+#[derive(Clone, PartialEq, Message)]
+struct Tile {
+    #[prost(message, repeated, tag = "3")]
+    layers: Vec<Layer>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Layer {
+    #[prost(uint32, required, tag = "15")]
+    version: u32,
+    #[prost(string, required, tag = "1")]
+    name: String,
+    #[prost(message, repeated, tag = "2")]
+    features: Vec<Feature>,
+    #[prost(string, repeated, tag = "3")]
+    keys: Vec<String>,
+    #[prost(message, repeated, tag = "4")]
+    values: Vec<Value>,
+    #[prost(uint32, optional, tag = "5")]
+    extent: Option<u32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Feature {
+    #[prost(uint32, repeated, packed = "true", tag = "2")]
+    tags: Vec<u32>,
+    #[prost(int32, optional, tag = "3")]
+    r#type: Option<i32>,
+    #[prost(uint32, repeated, packed = "true", tag = "4")]
+    geometry: Vec<u32>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Value {
+    #[prost(string, optional, tag = "1")]
+    string_value: Option<String>,
+}
+
+impl Layer {
+    fn property<'a>(&'a self, feature: &Feature, key: &str) -> Option<&'a str> {
+        feature.tags.chunks(2).find_map(|pair| {
+            (self.keys[pair[0] as usize] == key)
+                .then(|| self.values[pair[1] as usize].string_value.as_deref())
+                .flatten()
+        })
+    }
+}
+
+/// One decoded geometry command with its absolute points.
+#[derive(Debug, PartialEq)]
+enum Command {
+    MoveTo(Vec<(i64, i64)>),
+    LineTo(Vec<(i64, i64)>),
+    ClosePath,
+}
+
+fn decode_geometry(geometry: &[u32]) -> Vec<Command> {
+    let unzigzag = |n: u32| i64::from((n >> 1) as i32 ^ -((n & 1) as i32));
+    let mut commands = Vec::new();
+    let mut cursor = (0, 0);
+    let mut words = geometry.iter().copied();
+    while let Some(word) = words.next() {
+        let (id, count) = (word & 7, word >> 3);
+        let mut points = || {
+            (0..count)
+                .map(|_| {
+                    cursor.0 += unzigzag(words.next().expect("missing x"));
+                    cursor.1 += unzigzag(words.next().expect("missing y"));
+                    cursor
+                })
+                .collect()
+        };
+        commands.push(match id {
+            MOVE_TO => Command::MoveTo(points()),
+            LINE_TO => Command::LineTo(points()),
+            CLOSE_PATH => Command::ClosePath,
+            other => panic!("unknown command {}", other),
+        });
+    }
+
+    commands
+}
+
+// Web Mercator y of `latitude` within tile row `y` at zoom `z`
+fn tile_y(latitude: f64, z: u8, y: u32) -> i64 {
+    let latitude = latitude.to_radians();
+    let mercator = (1.0 - (latitude.tan() + 1.0 / latitude.cos()).ln() / PI) / 2.0;
+    ((mercator * f64::from(1u32 << z) - f64::from(y)) * EXTENT).round() as i64
+}
+
+#[tokio::test]
+async fn vector_tiles_hold_the_callers_places_and_geofences() {
+    let Ok(database_url) = std::env::var(DATABASE_URL_VAR) else {
+        eprintln!("{} is unset, skipping", DATABASE_URL_VAR);
+        return;
+    };
+    // Each run is its own caller, so earlier runs' rows stay out of the tile
+    let api_key = format!("tiles-{}", uuid::Uuid::new_v4());
+    let google = MockServer::start().await;
+    let app = App::start_with(
+        &google,
+        &[
+            ("DATABASE_URL", database_url.as_str()),
+            ("API_AUTH_ENABLED", "true"),
+            ("CLIENT_API_KEYS", api_key.as_str()),
+        ],
+    )
+    .await;
+
+    let place = app
+        .request(Method::POST, "/v2/saved-places")
+        .header("X-Api-Key", &api_key)
+        .json(&json!({
+            "placeId": "ChIJequator",
+            "name": "Equator",
+            "latitude": 0.0,
+            "longitude": 90.0,
+        }))
+        .send()
+        .await
+        .expect("request to the server failed");
+    assert_eq!(place.status(), StatusCode::CREATED);
+    // Runs counterclockwise on the map, the tile has it the other way around
+    let fence = app
+        .request(Method::POST, "/v2/geofences")
+        .header("X-Api-Key", &api_key)
+        .json(&json!({
+            "name": "Gulf",
+            "shape": {
+                "type": "polygon",
+                "vertices": [
+                    { "latitude": 0.0, "longitude": -45.0 },
+                    { "latitude": 0.0, "longitude": 45.0 },
+                    { "latitude": 40.0, "longitude": 45.0 },
+                    { "latitude": 40.0, "longitude": -45.0 },
+                ],
+            },
+        }))
+        .send()
+        .await
+        .expect("request to the server failed");
+    assert_eq!(fence.status(), StatusCode::CREATED);
+
+    // The north-east quarter of the world, its bottom edge the equator
+    let response = app
+        .request(Method::GET, "/v2/mvt/1/1/0")
+        .header("X-Api-Key", &api_key)
+        .send()
+        .await
+        .expect("request to the server failed");
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["content-type"],
+        "application/vnd.mapbox-vector-tile"
+    );
+    let tile = Tile::decode(response.bytes().await.unwrap()).expect("not a vector tile");
+    let layer = |name: &str| {
+        tile.layers
+            .iter()
+            .find(|layer| layer.name == name)
+            .unwrap_or_else(|| panic!("no {} layer", name))
+    };
+
+    let places = layer("places");
+    assert_eq!(places.version, 2);
+    assert_eq!(places.extent, Some(4096));
+    assert_eq!(places.features.len(), 1);
+    let point = &places.features[0];
+    assert_eq!(point.r#type, Some(1));
+    assert_eq!(places.property(point, "placeId"), Some("ChIJequator"));
+    assert_eq!(places.property(point, "name"), Some("Equator"));
+    // Halfway across and on the bottom edge
+    assert_eq!(
+        decode_geometry(&point.geometry),
+        vec![Command::MoveTo(vec![(2048, 4096)])]
+    );
+
+    let geofences = layer("geofences");
+    assert_eq!(geofences.features.len(), 1);
+    let polygon = &geofences.features[0];
+    assert_eq!(polygon.r#type, Some(3));
+    assert_eq!(geofences.property(polygon, "name"), Some("Gulf"));
+    let commands = decode_geometry(&polygon.geometry);
+    let [Command::MoveTo(start), Command::LineTo(rest), Command::ClosePath] = &commands[..] else {
+        panic!("not a single ring: {:?}", commands);
+    };
+    let ring: Vec<(i64, i64)> = start.iter().chain(rest).copied().collect();
+    // Cut at the buffer left of the tile, longitude 45 is a quarter across
+    let north = tile_y(40.0, 1, 0);
+    let mut corners = ring.clone();
+    corners.sort();
+    assert_eq!(
+        corners,
+        vec![
+            (-BUFFER, north),
+            (-BUFFER, 4096),
+            (1024, north),
+            (1024, 4096)
+        ]
+    );
+    // Outer rings run clockwise in tile coordinates, a positive area
+    let area: i64 = ring
+        .iter()
+        .zip(ring.iter().cycle().skip(ring.len() - 1))
+        .map(|(&(x1, y1), &(x0, y0))| x0 * y1 - x1 * y0)
+        .sum();
+    assert!(area > 0, "counterclockwise ring {:?}", ring);
+}